@@ -0,0 +1,198 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+
+#include <Arduino.h>
+#include <WiFi.h>
+
+#include <TinyGPSPlus.h>
+
+extern "C"
+{
+#include "zenoh-pico.h"
+}
+
+// WiFi-specific parameters
+#define SSID "SSID"
+#define PASS "PASS"
+
+// Zenoh-specific parameters
+#define MODE "client"
+#define CONNECT ""
+
+// Vehicle-specific parameters
+#define VEHICLE_ID "beacon-1"
+#define VEHICLE_KIND "beacon"
+#define VEHICLE_COLOR "orange"
+#define KEYEXPR "demo/tracker/mobs/" VEHICLE_ID
+#define META_KEYEXPR "demo/tracker/meta/" VEHICLE_ID
+
+// When 1, positions are published in the compact form {id, position, speed},
+// the kind and color being registered as the vehicle's metadata, again every
+// META_PERIOD_MS for trackers started later.
+#define COMPACT 0
+#define META_PERIOD_MS 60000
+
+// GPS module (u-blox NEO-6M or any NMEA receiver) on UART2
+#define GPS_RX_PIN 16
+#define GPS_TX_PIN 17
+#define GPS_BAUD 9600
+
+// Publication parameters
+#define PERIOD_MS 1000
+// fixes older than this are not published
+#define MAX_FIX_AGE_MS 2000
+#define PAYLOAD_SIZE 192
+
+/* ------- Serialize Functions ---------- */
+// VehicleInfo as the tracker expects it. Coordinates keep 7 decimals (about
+// 1 cm), the speed is in m/s.
+int serialize_vehicle_info(double lat, double lng, double speed, char *buf, size_t size)
+{
+#if COMPACT == 1
+    return snprintf(buf, size,
+                    "{\"id\":\"%s\",\"position\":{\"lat\":%.7f,\"lng\":%.7f},\"speed\":%.2f}",
+                    VEHICLE_ID, lat, lng, speed);
+#else
+    return snprintf(buf, size,
+                    "{\"id\":\"%s\",\"kind\":\"%s\",\"color\":\"%s\",\"position\":{\"lat\":%.7f,\"lng\":%.7f},\"speed\":%.2f}",
+                    VEHICLE_ID, VEHICLE_KIND, VEHICLE_COLOR, lat, lng, speed);
+#endif
+}
+
+/* -------------------------------------- */
+
+TinyGPSPlus gps;
+HardwareSerial gps_serial(2);
+
+z_owned_session_t s;
+z_owned_publisher_t pub;
+
+void setup(void)
+{
+    // Initialize Serial for debug
+    Serial.begin(115200);
+    while (!Serial)
+    {
+        delay(1000);
+    }
+
+    // Set WiFi in STA mode and trigger attachment
+    WiFi.mode(WIFI_STA);
+    WiFi.begin(SSID, PASS);
+    while (WiFi.status() != WL_CONNECTED)
+    {
+        delay(1000);
+    }
+    Serial.printf("Connected to WiFi %s!\n", SSID);
+
+    // Initialize the GPS UART
+    gps_serial.begin(GPS_BAUD, SERIAL_8N1, GPS_RX_PIN, GPS_TX_PIN);
+
+    // Initialize Zenoh Session and other parameters
+    z_owned_config_t config = z_config_default();
+    zp_config_insert(z_config_loan(&config), Z_CONFIG_MODE_KEY, z_string_make(MODE));
+    if (strcmp(CONNECT, "") != 0)
+    {
+        zp_config_insert(z_config_loan(&config), Z_CONFIG_CONNECT_KEY, z_string_make(CONNECT));
+    }
+
+    // Open Zenoh session
+    Serial.println("Opening Zenoh Session...");
+    s = z_open(z_config_move(&config));
+    if (!z_session_check(&s))
+    {
+        Serial.println("Unable to open session!\n");
+        while (1)
+            ;
+    }
+    Serial.println("OK");
+
+    // Start the receive and the session lease loop for zenoh-pico
+    zp_start_read_task(z_session_loan(&s), NULL);
+    zp_start_lease_task(z_session_loan(&s), NULL);
+
+    // Declare Zenoh publisher
+    Serial.print("Declaring publisher for ");
+    Serial.print(KEYEXPR);
+    Serial.println("...");
+    pub = z_declare_publisher(z_session_loan(&s), z_keyexpr(KEYEXPR), NULL);
+    if (!z_publisher_check(&pub))
+    {
+        Serial.println("Unable to declare publisher for key expression!\n");
+        while (1)
+            ;
+    }
+    Serial.println("OK");
+    Serial.println("Zenoh setup finished!");
+}
+
+// Registers the slow changing part of the VehicleInfo
+void register_metadata()
+{
+    char meta[64];
+    int len = snprintf(meta, sizeof(meta), "{\"kind\":\"%s\",\"color\":\"%s\"}", VEHICLE_KIND, VEHICLE_COLOR);
+    if (z_put(z_session_loan(&s), z_keyexpr(META_KEYEXPR), (const uint8_t *)meta, len, NULL) < 0)
+    {
+        Serial.println("Unable to register the vehicle metadata");
+    }
+}
+
+unsigned long last_publication = 0;
+unsigned long last_registration = 0;
+bool registered = false;
+
+void loop()
+{
+#if COMPACT == 1
+    if (!registered || millis() - last_registration >= META_PERIOD_MS)
+    {
+        register_metadata();
+        last_registration = millis();
+        registered = true;
+    }
+#endif
+
+    // Feed the NMEA sentences received so far to the parser
+    while (gps_serial.available() > 0)
+    {
+        gps.encode(gps_serial.read());
+    }
+
+    if (millis() - last_publication < PERIOD_MS)
+    {
+        return;
+    }
+    last_publication = millis();
+
+    if (!gps.location.isValid() || gps.location.age() > MAX_FIX_AGE_MS)
+    {
+        // the tracker would keep the vehicle at its last position anyway
+        Serial.printf("No GPS fix (%u satellites) - Don't publish\n", gps.satellites.value());
+        return;
+    }
+
+    double speed = gps.speed.isValid() ? gps.speed.mps() : 0.0;
+    char buf[PAYLOAD_SIZE];
+    int len = serialize_vehicle_info(gps.location.lat(), gps.location.lng(), speed, buf, sizeof(buf));
+    if (len < 0 || len >= PAYLOAD_SIZE)
+    {
+        Serial.println("VehicleInfo does not fit in the payload buffer");
+        return;
+    }
+    Serial.println(buf);
+    if (z_publisher_put(z_publisher_loan(&pub), (const uint8_t *)buf, len, NULL) < 0)
+    {
+        Serial.println("Error while publishing data");
+    }
+}
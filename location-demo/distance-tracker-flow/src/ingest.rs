@@ -0,0 +1,68 @@
+use std::sync::Mutex;
+use serde::Deserialize;
+use zenoh::prelude::r#async::*;
+use zenoh::subscriber::Subscriber;
+use zenoh_flow_nodes::prelude::*;
+use tracing::warn;
+use distance_tracker::ingest::{self, Profiles, RawVehicle};
+use distance_tracker::VehicleInfo;
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "kebab-case")]
+struct Settings {
+    // demo/tracker/mobs/** by default
+    key: Option<String>,
+    zenoh_config: Option<String>,
+}
+
+// Subscribes to the positions and sends the valid ones on `positions`, the
+// compact payloads completed with the last known profile of their vehicle as
+// by the tracker.
+#[export_source]
+pub struct Ingest {
+    output: Output<VehicleInfo>,
+    subscriber: Subscriber<'static, flume::Receiver<Sample>>,
+    profiles: Mutex<Profiles>,
+}
+
+#[async_trait::async_trait]
+impl Source for Ingest {
+    async fn new(_context: Context, configuration: Configuration, mut outputs: Outputs) -> Result<Self> {
+        let settings: Settings = crate::settings(&configuration)?;
+        let key = settings.key.unwrap_or("demo/tracker/mobs/**".into());
+        let z = crate::open(settings.zenoh_config.as_deref()).await?.into_arc();
+        let subscriber = z.declare_subscriber(&key).res().await.map_err(|e| anyhow!("key {key}: {e}"))?;
+        let output = outputs.take("positions").ok_or_else(|| anyhow!("no output called 'positions'"))?.typed(crate::serialize);
+        Ok(Ingest { output, subscriber, profiles: Mutex::default() })
+    }
+}
+
+impl Ingest {
+    fn decode(&self, sample: &Sample) -> std::result::Result<VehicleInfo, String> {
+        let payload = zdemo_common::payload(sample)?;
+        let raw = RawVehicle::parse(&payload).map_err(|e| e.to_string())?;
+        raw.validate()?;
+        let (kind, color) = ingest::attached(sample);
+        let mut vi = VehicleInfo::default();
+        raw.write_to(&mut vi, self.profiles.lock().unwrap().resolve(&raw, (kind.as_deref(), color.as_deref()), None).0);
+        raw.write_telemetry(&mut vi.telemetry);
+        Ok(vi)
+    }
+}
+
+#[async_trait::async_trait]
+impl Node for Ingest {
+    async fn iteration(&self) -> Result<()> {
+        let sample = self.subscriber.recv_async().await.map_err(|e| anyhow!(e))?;
+        if sample.kind == SampleKind::Delete {
+            return Ok(());
+        }
+        match self.decode(&sample) {
+            Ok(vi) => self.output.send(vi, None).await,
+            Err(e) => {
+                warn!("Rejecting the position on {}: {e}", sample.key_expr);
+                Ok(())
+            },
+        }
+    }
+}
@@ -0,0 +1,56 @@
+// The stages of the distance tracker as zenoh-flow nodes: the `ingest` source
+// decodes the positions, the `rules` operator evaluates the alerting rules on
+// the fleet and the `publish` sink puts the alerts, so that a data flow can
+// spread them over several machines.
+use std::ops::Deref;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+#[cfg(any(feature = "ingest", feature = "publish"))]
+use zenoh::prelude::r#async::*;
+use zenoh_flow_nodes::prelude::*;
+
+#[cfg(feature = "ingest")]
+mod ingest;
+#[cfg(feature = "publish")]
+mod publish;
+#[cfg(feature = "rules")]
+mod rules;
+
+// An alert of the rules, to be put on `key`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Publication {
+    pub key: String,
+    pub value: serde_json::Value,
+}
+
+// The data crossing the links is JSON, whether the nodes run on the same
+// runtime or not, so that it can be followed on the Zenoh network.
+#[cfg(any(feature = "ingest", feature = "rules"))]
+pub(crate) fn serialize<T: Serialize>(buffer: &mut Vec<u8>, data: &T) -> Result<()> {
+    serde_json::to_writer(buffer, data).map_err(|e| anyhow!(e))
+}
+
+#[cfg(any(feature = "rules", feature = "publish"))]
+pub(crate) fn deserialize<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    serde_json::from_slice(bytes).map_err(|e| anyhow!(e))
+}
+
+// The settings of a node from the configuration of its descriptor, in which
+// every field is optional.
+pub(crate) fn settings<T: DeserializeOwned + Default>(configuration: &Configuration) -> Result<T> {
+    if configuration.is_null() {
+        return Ok(T::default());
+    }
+    serde_json::from_value(configuration.deref().clone()).map_err(|e| anyhow!("invalid configuration: {e}"))
+}
+
+// Opens a session with the Zenoh configuration file at `path`, or the default
+// configuration.
+#[cfg(any(feature = "ingest", feature = "publish"))]
+pub(crate) async fn open(path: Option<&str>) -> Result<Session> {
+    let config = match path {
+        Some(path) => Config::from_file(path).map_err(|e| anyhow!("{path}: {e}"))?,
+        None => Config::default(),
+    };
+    zenoh::open(config).res().await.map_err(|e| anyhow!("unable to open a zenoh session: {e}"))
+}
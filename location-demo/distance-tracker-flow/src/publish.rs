@@ -0,0 +1,44 @@
+use serde::Deserialize;
+use zenoh::prelude::r#async::*;
+use zenoh_flow_nodes::prelude::*;
+use zdemo_common::Compression;
+use crate::Publication;
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "kebab-case")]
+struct Settings {
+    // none, lz4 or zstd
+    compression: Option<String>,
+    zenoh_config: Option<String>,
+}
+
+// Puts the `alerts` it receives on their keys.
+#[export_sink]
+pub struct Publish {
+    input: Input<Publication>,
+    z: Session,
+    compression: Compression,
+}
+
+#[async_trait::async_trait]
+impl Sink for Publish {
+    async fn new(_context: Context, configuration: Configuration, mut inputs: Inputs) -> Result<Self> {
+        let settings: Settings = crate::settings(&configuration)?;
+        let compression = match settings.compression {
+            Some(c) => c.parse().map_err(|e| anyhow!("compression: {e}"))?,
+            None => Compression::None,
+        };
+        let z = crate::open(settings.zenoh_config.as_deref()).await?;
+        let input = inputs.take("alerts").ok_or_else(|| anyhow!("no input called 'alerts'"))?.typed(crate::deserialize);
+        Ok(Publish { input, z, compression })
+    }
+}
+
+#[async_trait::async_trait]
+impl Node for Publish {
+    async fn iteration(&self) -> Result<()> {
+        let (p, _) = self.input.recv().await?;
+        let value = zdemo_common::json_value_with(&p.value, self.compression);
+        self.z.put(&p.key, value).res().await.map_err(|e| anyhow!("unable to put the alert on {}: {e}", p.key))
+    }
+}
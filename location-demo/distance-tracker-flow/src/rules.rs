@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use serde::Deserialize;
+use zenoh_flow_nodes::prelude::*;
+use distance_tracker::geo::DistanceAlgo;
+use distance_tracker::rules::{Alert, Rules};
+use distance_tracker::vehicle::VehicleState;
+use distance_tracker::VehicleInfo;
+use crate::Publication;
+
+// The defaults of the tracker.
+#[derive(Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct Settings {
+    // m
+    min_distance: f64,
+    max_distance: f64,
+    distance_algo: DistanceAlgo,
+    cutin_range: f64,
+    // degrees per second
+    cutin_bearing_rate: f64,
+    // km/h of difference between the reported and the implied speeds
+    speed_tolerance: f64,
+    // ms between two evaluations of the rules
+    compute_period_ms: u64,
+    // ms after which the vehicles which stopped publishing are forgotten,
+    // never by default
+    vehicle_timeout_ms: Option<u64>,
+    // alerts are published on <alert-key>/<rule>
+    alert_key: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            min_distance: 10.0,
+            max_distance: 1000.0,
+            distance_algo: DistanceAlgo::Haversine,
+            cutin_range: 50.0,
+            cutin_bearing_rate: 5.0,
+            speed_tolerance: 5.0,
+            compute_period_ms: 500,
+            vehicle_timeout_ms: None,
+            alert_key: "demo/tracker/alert".into(),
+        }
+    }
+}
+
+struct Fleet {
+    rules: Rules,
+    map: HashMap<String, VehicleState>,
+    last_cycle: Option<Instant>,
+}
+
+// Keeps the fleet from the `positions` it receives and, every compute period,
+// evaluates the rules on it, sending the alerts raised on `alerts`. The cycles
+// are kept while no position arrives, as the tracker's compute loop, so that
+// silent vehicles and separations are still alerted on.
+#[export_operator]
+pub struct Evaluate {
+    input: Input<VehicleInfo>,
+    output: Output<Publication>,
+    fleet: Mutex<Fleet>,
+    speed_tolerance: f64,
+    period: Duration,
+    timeout: Option<Duration>,
+    alert_key: String,
+}
+
+#[async_trait::async_trait]
+impl Operator for Evaluate {
+    async fn new(_context: Context, configuration: Configuration, mut inputs: Inputs, mut outputs: Outputs) -> Result<Self> {
+        let s: Settings = crate::settings(&configuration)?;
+        if s.min_distance > s.max_distance {
+            bail!("min-distance {} is above max-distance {}", s.min_distance, s.max_distance);
+        }
+        let rules = Rules::new(s.min_distance, s.max_distance, s.distance_algo, s.cutin_range, s.cutin_bearing_rate);
+        Ok(Evaluate {
+            input: inputs.take("positions").ok_or_else(|| anyhow!("no input called 'positions'"))?.typed(crate::deserialize),
+            output: outputs.take("alerts").ok_or_else(|| anyhow!("no output called 'alerts'"))?.typed(crate::serialize),
+            fleet: Mutex::new(Fleet { rules, map: HashMap::new(), last_cycle: None }),
+            speed_tolerance: s.speed_tolerance,
+            period: Duration::from_millis(s.compute_period_ms),
+            timeout: s.vehicle_timeout_ms.map(Duration::from_millis),
+            alert_key: s.alert_key,
+        })
+    }
+}
+
+impl Evaluate {
+    // How long to wait for a position before the next compute cycle is due.
+    fn until_next_cycle(&self, now: Instant) -> Duration {
+        match self.fleet.lock().unwrap().last_cycle {
+            Some(l) => (l + self.period).saturating_duration_since(now),
+            None => self.period,
+        }
+    }
+
+    // Applies the position, if any, and, when a compute cycle is due, the rules.
+    fn apply(&self, vi: Option<&VehicleInfo>, now: Instant) -> Vec<Alert> {
+        let mut fleet = self.fleet.lock().unwrap();
+        let Fleet { rules, map, last_cycle } = &mut *fleet;
+        if let Some(vi) = vi {
+            match map.get_mut(&vi.id) {
+                Some(state) => state.update(vi, now, self.speed_tolerance, rules.max_speeds.get(vi.kind.as_str())),
+                None => { map.insert(vi.id.clone(), VehicleState::new(vi.clone(), now)); },
+            }
+        }
+        if last_cycle.is_some_and(|l| now.duration_since(l) < self.period) {
+            return vec![];
+        }
+        *last_cycle = Some(now);
+        if let Some(timeout) = self.timeout {
+            map.retain(|_, v| now.duration_since(v.seen()) < timeout);
+        }
+        rules.evaluate(map, now)
+    }
+
+    // The tracker's default key of the alert.
+    fn key(&self, alert: &Alert) -> String {
+        match alert {
+            Alert::Poi(pa) => format!("{}/poi/{}", self.alert_key, pa.poi),
+            Alert::Sector(sa) => format!("{}/sector/{}", self.alert_key, sa.sector),
+            _ => format!("{}/{}", self.alert_key, alert.name()),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Node for Evaluate {
+    async fn iteration(&self) -> Result<()> {
+        // the deadline of the next cycle stands in for a position when none
+        // arrives before it
+        let received = async_std::future::timeout(self.until_next_cycle(Instant::now()), self.input.recv()).await;
+        let vi = match received {
+            Ok(r) => Some(r?.0),
+            Err(_) => None,
+        };
+        for alert in self.apply(vi.as_deref(), Instant::now()) {
+            let publication = Publication { key: self.key(&alert), value: serde_json::to_value(&alert)? };
+            self.output.send(publication, None).await?;
+        }
+        Ok(())
+    }
+}
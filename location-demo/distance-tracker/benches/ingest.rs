@@ -0,0 +1,109 @@
+// Allocations and time per sample of the ingest hot path (decoding a position
+// and applying it to the vehicle map), compared with decoding through
+// serde_json::Value into an owned VehicleInfo as the tracker did before.
+//
+//     cargo bench --bench ingest
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use distance_tracker::advisory;
+use distance_tracker::ingest::{Downsampler, IngestMetrics, Profiles, RawVehicle};
+use distance_tracker::vehicle::VehicleState;
+use distance_tracker::VehicleInfo;
+
+const VEHICLES: usize = 1000;
+const ROUNDS: usize = 50;
+
+struct Counting;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn payloads() -> Vec<Vec<u8>> {
+    (0..ROUNDS).flat_map(|r| (0..VEHICLES).map(move |v| {
+        let (lat, lng) = (45.07 + v as f64 * 1e-4, 7.68 + r as f64 * 1e-5);
+        format!(r#"{{"id":"vehicle-{v}","kind":"car","color":"red","position":{{"lat":{lat},"lng":{lng}}},"speed":8.5}}"#).into_bytes()
+    })).collect()
+}
+
+// Same positions without the kind and color, given by the first round.
+fn compact(payloads: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    payloads.iter().enumerate().map(|(i, p)| match i < VEHICLES {
+        true => p.clone(),
+        false => String::from_utf8_lossy(p).replace(r#""kind":"car","color":"red","#, "").into_bytes(),
+    }).collect()
+}
+
+fn before(payloads: &[Vec<u8>], map: &mut HashMap<String, VehicleState>, now: Instant) {
+    for p in payloads {
+        let mut v: serde_json::Value = serde_json::from_slice(p).unwrap();
+        advisory::upgrade(&mut v);
+        let vi: VehicleInfo = serde_json::from_value(v).unwrap();
+        map.insert(vi.id.clone(), VehicleState::new(vi, now));
+    }
+}
+
+fn after(payloads: &[Vec<u8>], map: &mut HashMap<String, VehicleState>, limiter: &mut Downsampler, profiles: &mut Profiles, now: Instant) {
+    let mut metrics = IngestMetrics::default();
+    let mut vi = VehicleInfo::default();
+    for p in payloads {
+        let raw = RawVehicle::parse(p).unwrap();
+        raw.write_to(&mut vi, profiles.resolve(&raw, (None, None), None).0);
+        if limiter.offer(&vi, now, &mut metrics) {
+            match map.get_mut(&vi.id) {
+                Some(state) => state.update(&vi, now, 5.0, None),
+                None => { map.insert(vi.id.clone(), VehicleState::new(vi.clone(), now)); },
+            }
+        }
+    }
+}
+
+fn measure(name: &str, samples: usize, run: impl FnOnce()) {
+    let (allocations, start) = (ALLOCATIONS.load(Ordering::Relaxed), Instant::now());
+    run();
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    println!("{name:<32} {:>8.2} allocations/sample {:>8.0} ns/sample",
+        allocations as f64 / samples as f64, elapsed.as_nanos() as f64 / samples as f64);
+}
+
+fn main() {
+    let payloads = payloads();
+    let n = payloads.len();
+    let (warmup, rest) = payloads.split_at(VEHICLES);
+    let now = Instant::now();
+    println!("{VEHICLES} vehicles, {n} samples (first round, allocating the vehicles, excluded)");
+    let mut map = HashMap::new();
+    before(warmup, &mut map, now);
+    measure("serde_json::Value, owned", rest.len(), || before(rest, &mut map, now));
+    let (mut map, mut limiter, mut profiles) = (HashMap::new(), Downsampler::new(0.0), Profiles::default());
+    after(warmup, &mut map, &mut limiter, &mut profiles, now);
+    measure("borrowed, in place", rest.len(), || after(rest, &mut map, &mut limiter, &mut profiles, now));
+    let compact = compact(&payloads);
+    let (mut map, mut limiter, mut profiles) = (HashMap::new(), Downsampler::new(0.0), Profiles::default());
+    after(&compact[..VEHICLES], &mut map, &mut limiter, &mut profiles, now);
+    measure("borrowed, in place, compact", rest.len(), || after(&compact[VEHICLES..], &mut map, &mut limiter, &mut profiles, now));
+    // every sample but the first of each vehicle is held back
+    let (mut map, mut limiter, mut profiles) = (HashMap::new(), Downsampler::new(1.0), Profiles::default());
+    after(warmup, &mut map, &mut limiter, &mut profiles, now);
+    measure("borrowed, in place, downsampled", rest.len(), || after(rest, &mut map, &mut limiter, &mut profiles, now + Duration::from_millis(1)));
+}
@@ -0,0 +1,6 @@
+// Generates the gRPC service of proto/tracker.proto, with a bundled protoc so
+// that building needs none installed.
+fn main() {
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().unwrap());
+    tonic_build::compile_protos("proto/tracker.proto").unwrap();
+}
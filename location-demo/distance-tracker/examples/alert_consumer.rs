@@ -0,0 +1,49 @@
+// Minimal consumer of the tracker's alerts, surviving session losses and
+// tracker restarts:
+//
+//     cargo run --example alert_consumer -- --alert-key 'demo/tracker/alert/**'
+use std::time::Duration;
+use clap::Parser;
+use zenoh::prelude::r#async::*;
+use distance_tracker::client::{self, AlertStream, ClientEvent};
+
+#[derive(clap_derive::Parser)]
+struct AppArgs {
+    #[arg(long)]
+    alert_key: Option<String>,
+    #[arg(long)]
+    state_key: Option<String>,
+    /// Subscribe again after this long without alerts, 0 only when the subscription is lost.
+    #[arg(long)]
+    idle_resync_ms: Option<u64>,
+    #[arg(long)]
+    config: Option<String>
+}
+
+#[tokio::main]
+async fn main() {
+    let args = AppArgs::parse();
+    let config = match args.config {
+        Some(f) => Config::from_file(f).unwrap(),
+        None => Config::default()
+    };
+    let defaults = AlertStream::default();
+    let cfg = AlertStream {
+        alert_key: args.alert_key.unwrap_or(defaults.alert_key),
+        state_key: args.state_key.unwrap_or(defaults.state_key),
+        idle_resync: match args.idle_resync_ms {
+            Some(0) => None,
+            Some(ms) => Some(Duration::from_millis(ms)),
+            None => defaults.idle_resync,
+        },
+    };
+    let z = zdemo_common::open(config).await;
+    let mut events = client::resilient_alert_stream(z, cfg);
+    while let Some(event) = events.recv().await {
+        match event {
+            ClientEvent::Fleet(vehicles) => println!("FLEET: {} vehicles", vehicles.len()),
+            ClientEvent::Alert { key, alert } => println!("ALERT: {key} {alert}"),
+            ClientEvent::Resubscribing => println!("RESUBSCRIBING"),
+        }
+    }
+}
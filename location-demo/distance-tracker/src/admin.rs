@@ -0,0 +1,302 @@
+use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+use zenoh::prelude::r#async::*;
+use tracing::{error, info, warn};
+use crate::diag::{Diagnostics, Stage};
+use crate::record::unix_ms;
+use crate::rules::Rules;
+use crate::runtime_state::SharedRuntimeState;
+use crate::zones::{self, Zone};
+
+// Versions are ordered by time, then by the id of the instance that made the
+// change, so that every instance picks the same last writer.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    // unix ms
+    pub time: u64,
+    pub origin: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Versioned<T> {
+    pub version: Version,
+    pub value: T,
+}
+
+// The thresholds of the rules that can be changed at runtime.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct Thresholds {
+    pub min_distance: f64,
+    pub max_distance: f64,
+    pub red_min_distance: f64,
+    pub closing_speed: f64,
+    pub closing_floor: f64,
+    pub route_corridor: f64,
+    pub checkpoint_radius: f64,
+    pub cluster_range: f64,
+    pub light_radius: f64,
+}
+
+impl Thresholds {
+    pub fn of(r: &Rules) -> Self {
+        Thresholds {
+            min_distance: r.min_distance,
+            max_distance: r.max_distance,
+            red_min_distance: r.red_min_distance,
+            closing_speed: r.closing_speed,
+            closing_floor: r.closing_floor,
+            route_corridor: r.route_corridor,
+            checkpoint_radius: r.checkpoint_radius,
+            cluster_range: r.cluster_range,
+            light_radius: r.light_radius,
+        }
+    }
+
+    pub fn apply(&self, r: &mut Rules) {
+        r.min_distance = self.min_distance;
+        r.max_distance = self.max_distance;
+        r.red_min_distance = self.red_min_distance;
+        r.closing_speed = self.closing_speed;
+        r.closing_floor = self.closing_floor;
+        r.route_corridor = self.route_corridor;
+        r.checkpoint_radius = self.checkpoint_radius;
+        r.cluster_range = self.cluster_range;
+        r.light_radius = self.light_radius;
+    }
+
+    // Thresholds with the fields of `patch`, a JSON object, replaced.
+    fn patched(&self, patch: &[u8]) -> Result<Self, String> {
+        let serde_json::Value::Object(fields) = serde_json::from_slice(patch).map_err(|e| e.to_string())? else {
+            return Err("expecting an object with the thresholds to change".into());
+        };
+        let mut t = serde_json::to_value(self).unwrap();
+        t.as_object_mut().unwrap().extend(fields);
+        serde_json::from_value(t).map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigChange {
+    Thresholds(Thresholds),
+    Zones(Vec<Zone>),
+}
+
+// A change requested to this instance other than through Zenoh, e.g. over
+// HTTP, answered with the versioned value shared or the reason it is invalid.
+pub struct SetRequest {
+    pub section: String,
+    pub payload: Vec<u8>,
+    pub reply: oneshot::Sender<Result<Vec<u8>, String>>,
+}
+
+// Configuration shared by the instances on <prefix>/config/{rules,zones}.
+pub struct Shared {
+    prefix: String,
+    origin: String,
+    // thresholds changes are applied on top of these
+    thresholds: Thresholds,
+    rules: Option<Versioned<Thresholds>>,
+    zones: Option<Versioned<Vec<Zone>>>,
+    // saved to survive restarts
+    saved: Option<SharedRuntimeState>,
+}
+
+// The update in `payload`, unless `current` is at least as recent.
+fn newer<T: DeserializeOwned>(current: &Option<Versioned<T>>, payload: &[u8]) -> Result<Option<Versioned<T>>, String> {
+    let update: Versioned<T> = serde_json::from_slice(payload).map_err(|e| e.to_string())?;
+    Ok(current.as_ref().is_none_or(|c| update.version > c.version).then_some(update))
+}
+
+impl Shared {
+    // The configuration of instance `origin`, starting from `thresholds` and
+    // the versions `saved` holds.
+    pub fn new(prefix: String, origin: String, thresholds: Thresholds, saved: Option<SharedRuntimeState>) -> Self {
+        Shared { prefix, origin, thresholds, rules: None, zones: None, saved }
+    }
+
+    fn config_key(&self, section: &str) -> String {
+        format!("{}/config/{section}", self.prefix)
+    }
+
+    fn persist(&self) {
+        if let Some(saved) = &self.saved {
+            let mut saved = saved.lock().unwrap();
+            saved.rules = self.rules.clone();
+            saved.zones = self.zones.clone();
+        }
+    }
+
+    fn next_version(&self) -> Version {
+        let last = self.rules.iter().map(|v| v.version.time)
+            .chain(self.zones.iter().map(|v| v.version.time))
+            .max().unwrap_or(0);
+        Version { time: unix_ms().max(last + 1), origin: self.origin.clone() }
+    }
+
+    // A change published by an instance, applied if it is the most recent.
+    fn receive(&mut self, section: &str, payload: &[u8]) -> Result<Option<ConfigChange>, String> {
+        match section {
+            "rules" => Ok(newer(&self.rules, payload)?.map(|u| {
+                info!("CONFIG: rules version {} from {}", u.version.time, u.version.origin);
+                self.thresholds = u.value;
+                self.rules = Some(u);
+                self.persist();
+                ConfigChange::Thresholds(self.thresholds)
+            })),
+            "zones" => {
+                let Some(u) = newer::<Vec<Zone>>(&self.zones, payload)? else { return Ok(None) };
+                zones::check(&u.value)?;
+                info!("CONFIG: zones version {} from {}", u.version.time, u.version.origin);
+                let change = ConfigChange::Zones(u.value.clone());
+                self.zones = Some(u);
+                self.persist();
+                Ok(Some(change))
+            },
+            _ => Err(format!("unknown configuration section '{section}'")),
+        }
+    }
+
+    // A change requested by an operator to this instance, returned with the
+    // versioned value to publish to the others.
+    fn set(&mut self, section: &str, payload: &[u8]) -> Result<(ConfigChange, String, Vec<u8>), String> {
+        let version = self.next_version();
+        match section {
+            "rules" => {
+                let value = self.thresholds.patched(payload)?;
+                let v = Versioned { version, value };
+                let bs = serde_json::to_vec(&v).unwrap();
+                self.thresholds = value;
+                self.rules = Some(v);
+                self.persist();
+                Ok((ConfigChange::Thresholds(value), self.config_key(section), bs))
+            },
+            "zones" => {
+                let value: Vec<Zone> = serde_json::from_slice(payload).map_err(|e| e.to_string())?;
+                zones::check(&value)?;
+                let v = Versioned { version, value: value.clone() };
+                let bs = serde_json::to_vec(&v).unwrap();
+                self.zones = Some(v);
+                self.persist();
+                Ok((ConfigChange::Zones(value), self.config_key(section), bs))
+            },
+            _ => Err(format!("unknown configuration section '{section}'")),
+        }
+    }
+
+    fn sections(&self) -> Vec<(String, Vec<u8>)> {
+        let rules = self.rules.as_ref().map(|v| (self.config_key("rules"), serde_json::to_vec(v).unwrap()));
+        let zones = self.zones.as_ref().map(|v| (self.config_key("zones"), serde_json::to_vec(v).unwrap()));
+        rules.into_iter().chain(zones).collect()
+    }
+}
+
+// Applies a change requested to this instance and shares it with the others,
+// returning the versioned value.
+async fn set(z: &Session, shared: &mut Shared, tx: &UnboundedSender<ConfigChange>, section: &str, payload: &[u8]) -> Result<Vec<u8>, String> {
+    let (change, key, bs) = shared.set(section, payload)?;
+    info!("CONFIG: {section} set, sharing it on {key}");
+    let _ = tx.send(change);
+    if let Err(e) = zdemo_common::with_backoff(&format!("put on {key}"), 5, || {
+        z.put(&key, bs.clone()).encoding(Encoding::APP_JSON).res()
+    }).await {
+        error!("unable to share the configuration on {key}: {e}");
+    }
+    Ok(bs)
+}
+
+// Keeps the configuration in sync with the other instances: changes requested
+// on <prefix>/<origin>/set/<section> (or through `requests`) are versioned and
+// published on <prefix>/config/<section>, the most recent version published by
+// any instance wins. The current versions are returned by queries on
+// <prefix>/config/*, which is how instances catch up when they start, after
+// restoring the saved versions.
+pub async fn sync(z: Arc<Session>, mut shared: Shared, tx: UnboundedSender<ConfigChange>, mut requests: UnboundedReceiver<SetRequest>, diag: Diagnostics) {
+    let prefix = shared.prefix.clone();
+    let config_keys = format!("{prefix}/config/*");
+    let set_prefix = format!("{prefix}/{}/set/", shared.origin);
+    let restored = shared.saved.as_ref().map(|s| s.lock().unwrap().clone()).unwrap_or_default();
+    let queryable = z.declare_queryable(&config_keys).res().await.unwrap();
+    let keys = [config_keys.clone(), format!("{set_prefix}*")];
+    let (mut subs, mut rx) = zdemo_common::subscribe(&z, &keys).await;
+    let config_prefix = format!("{prefix}/config/");
+    let receive = |shared: &mut Shared, key: &str, payload: &[u8]| {
+        let section = key.strip_prefix(&config_prefix).unwrap_or(key);
+        match shared.receive(section, payload) {
+            Ok(Some(change)) => { let _ = tx.send(change); },
+            Ok(None) => {},
+            Err(e) => {
+                warn!("Ignoring configuration on {key}: {e}");
+                diag.report(Stage::Config, key, e, Some(payload));
+            },
+        }
+    };
+    let restored = [("rules", restored.rules.map(|v| serde_json::to_vec(&v).unwrap())), ("zones", restored.zones.map(|v| serde_json::to_vec(&v).unwrap()))];
+    for (section, payload) in restored.into_iter().filter_map(|(s, p)| Some((s, p?))) {
+        receive(&mut shared, &format!("{config_prefix}{section}"), &payload);
+    }
+    match z.get(&config_keys).res().await {
+        Ok(replies) => {
+            while let Ok(reply) = replies.recv_async().await {
+                if let Ok(sample) = reply.sample {
+                    match zdemo_common::payload(&sample) {
+                        Ok(payload) => receive(&mut shared, sample.key_expr.as_str(), &payload),
+                        Err(e) => {
+                            warn!("Ignoring configuration on {}: {e}", sample.key_expr);
+                            diag.report(Stage::Decode, sample.key_expr.as_str(), e, None);
+                        },
+                    }
+                }
+            }
+        },
+        Err(e) => warn!("unable to query the shared configuration: {e}"),
+    }
+    loop {
+        tokio::select! {
+            q = queryable.recv_async() => {
+                let Ok(query) = q else { break };
+                for (key, bs) in shared.sections() {
+                    let sample = Sample::new(KeyExpr::try_from(key).unwrap(), Value::from(bs).encoding(Encoding::APP_JSON));
+                    if let Err(e) = query.reply(Ok(sample)).res().await {
+                        warn!("Unable to reply to query on {config_keys}: {e}");
+                    }
+                }
+            },
+            Some(r) = requests.recv() => {
+                let result = set(&z, &mut shared, &tx, &r.section, &r.payload).await;
+                if let Err(e) = &result {
+                    warn!("Invalid configuration for {}: {e}", r.section);
+                }
+                let _ = r.reply.send(result);
+            },
+            s = rx.recv() => {
+                let Some(sample) = s else {
+                    warn!("configuration subscription lost, declaring it again");
+                    drop(subs);
+                    (subs, rx) = zdemo_common::subscribe(&z, &keys).await;
+                    continue;
+                };
+                let key = sample.key_expr.as_str();
+                let payload = match zdemo_common::payload(&sample) {
+                    Ok(p) => p,
+                    Err(e) => {
+                        warn!("Ignoring configuration on {key}: {e}");
+                        diag.report(Stage::Decode, key, e, None);
+                        continue;
+                    }
+                };
+                let Some(section) = key.strip_prefix(&set_prefix) else {
+                    receive(&mut shared, key, &payload);
+                    continue;
+                };
+                if let Err(e) = set(&z, &mut shared, &tx, section, &payload).await {
+                    warn!("Invalid configuration on {key}: {e}");
+                    diag.report(Stage::Config, key, e, Some(&payload));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use serde_json::{json, Value};
+use crate::ingest::PAYLOAD_VERSION;
+
+// Advisories are repeated at most this often per publisher and deprecation.
+const ADVISORY_INTERVAL: Duration = Duration::from_secs(60);
+
+// A deprecated format still accepted, and how to move away from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Deprecation {
+    pub format: &'static str,
+    pub migration: &'static str,
+}
+
+pub const POSITION_ARRAY: Deprecation = Deprecation {
+    format: "position as a [lat, lng] array",
+    migration: "publish the position as an object: \"position\": {\"lat\": .., \"lng\": ..}",
+};
+
+pub const TOP_LEVEL_COORDS: Deprecation = Deprecation {
+    format: "lat and lng fields at the top level",
+    migration: "move lat and lng into a position object: \"position\": {\"lat\": .., \"lng\": ..}",
+};
+
+// Rewrites a position payload using deprecated formats into the current one,
+// versioned, returning the deprecations found.
+pub fn upgrade(v: &mut Value) -> Vec<Deprecation> {
+    let mut found = vec![];
+    let Value::Object(fields) = v else { return found };
+    if let Some(Value::Array(coords)) = fields.get("position") {
+        if let [lat, lng] = coords.as_slice() {
+            let position = json!({ "lat": lat, "lng": lng });
+            fields.insert("position".into(), position);
+            found.push(POSITION_ARRAY);
+        }
+    }
+    if !fields.contains_key("position") && fields.contains_key("lat") && fields.contains_key("lng") {
+        let (lat, lng) = (fields.remove("lat").unwrap(), fields.remove("lng").unwrap());
+        fields.insert("position".into(), json!({ "lat": lat, "lng": lng }));
+        found.push(TOP_LEVEL_COORDS);
+    }
+    if !found.is_empty() {
+        fields.insert("v".into(), PAYLOAD_VERSION.into());
+    }
+    found
+}
+
+// Published on <advisory_key>/<publisher>.
+#[derive(Serialize, Debug)]
+pub struct Advisory<'a> {
+    pub publisher: &'a str,
+    // key the deprecated payload was received on
+    pub key: &'a str,
+    pub deprecated: &'static str,
+    pub migration: &'static str,
+    // the payload in the current format
+    pub example: &'a Value,
+}
+
+// Remembers which advisories were sent, so publishers are reminded instead of
+// being flooded at their publication rate.
+#[derive(Default)]
+pub struct Advisor {
+    sent: HashMap<(String, Deprecation), Instant>,
+}
+
+impl Advisor {
+    pub fn due(&mut self, publisher: &str, d: Deprecation, now: Instant) -> bool {
+        let last = self.sent.get(&(publisher.to_string(), d));
+        if last.is_some_and(|t| now.duration_since(*t) < ADVISORY_INTERVAL) {
+            return false;
+        }
+        self.sent.insert((publisher.to_string(), d), now);
+        true
+    }
+
+    // Forgets the advisories sent, so that publishers are advised again.
+    pub fn reset(&mut self) {
+        self.sent.clear();
+    }
+}
@@ -0,0 +1,70 @@
+use std::sync::Arc;
+use std::time::Duration;
+use clap::Parser;
+use zenoh::prelude::r#async::*;
+use tracing::warn;
+use distance_tracker::clock;
+use distance_tracker::federation::{Aggregator, RegionSummary};
+use distance_tracker::logging::{self, LogFormat};
+
+#[derive(clap_derive::Parser)]
+struct AppArgs {
+    /// Global key space the regional trackers publish their summaries on, as
+    /// <federation_key>/region/<region>. The merged summary is published on
+    /// <federation_key>/summary and returned by queries on it.
+    #[arg(long, default_value = "global/tracker")]
+    federation_key: String,
+    /// Period of the merged summary publication.
+    #[arg(long, default_value_t = 5000)]
+    period_ms: u64,
+    /// Number of pairs, the riskiest of all the regions, in the merged summary.
+    #[arg(long, default_value_t = 10)]
+    top: usize,
+    #[arg(long)]
+    config: Option<String>
+}
+
+// Merges the summaries of the regional trackers of a federation.
+#[tokio::main]
+async fn main() {
+    let args = AppArgs::parse();
+    logging::init(LogFormat::default());
+    let config = match args.config {
+        Some(f) => Config::from_file(f).unwrap(),
+        None => Config::default()
+    };
+    let z = Arc::new(zdemo_common::open(config).await);
+    let keys = [format!("{}/region/*", args.federation_key)];
+    let summary_key = KeyExpr::try_from(format!("{}/summary", args.federation_key)).unwrap_or_else(|e| panic!("--federation-key: {e}"));
+    let (mut subs, mut rx) = zdemo_common::subscribe(&z, &keys).await;
+    let queryable = z.declare_queryable(&summary_key).res().await.unwrap();
+    let mut aggregator = Aggregator::new(args.top);
+    let mut tick = tokio::time::interval(Duration::from_millis(args.period_ms.max(1)));
+    let merged = |aggregator: &Aggregator| Value::from(serde_json::to_vec(&aggregator.merge(clock::unix_ms())).unwrap()).encoding(Encoding::APP_JSON);
+    loop {
+        tokio::select! {
+            s = rx.recv() => {
+                let Some(sample) = s else {
+                    warn!("region subscription lost, declaring it again");
+                    drop(subs);
+                    (subs, rx) = zdemo_common::subscribe(&z, &keys).await;
+                    continue;
+                };
+                match zdemo_common::decode_json::<RegionSummary>(&sample) {
+                    Ok(summary) => aggregator.receive(summary, clock::unix_ms()),
+                    Err(e) => warn!("Ignoring the region summary on {}: {e}", sample.key_expr),
+                }
+            },
+            Ok(query) = queryable.recv_async() => {
+                if let Err(e) = query.reply(Ok(Sample::new(summary_key.clone(), merged(&aggregator)))).res().await {
+                    warn!("Unable to reply to query on {summary_key}: {e}");
+                }
+            },
+            _ = tick.tick() => {
+                if let Err(e) = z.put(&summary_key, merged(&aggregator)).res().await {
+                    warn!("Unable to publish the merged summary on {summary_key}: {e}");
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+use zenoh::prelude::{keyexpr, OwnedKeyExpr};
+use serde::Serialize;
+use tracing::warn;
+use crate::closing::ClosingAlert;
+use crate::cutin::CutInAlert;
+use crate::ingest::{Profiles, RawVehicle, COLOR_ATTACHMENT, KIND_ATTACHMENT};
+use crate::lights::LightStatus;
+use crate::record::Record;
+use crate::routes::{self, RouteDeviationAlert};
+use crate::rules::{Alert, Rules};
+use crate::sectors::SectorAlert;
+use crate::telemetry::BatteryAlert;
+use crate::tether::TetherAlert;
+use crate::poi::PoiAlert;
+use crate::vehicle::VehicleState;
+use crate::zones::SpeedAlert;
+use crate::{DistanceAlert, VehicleInfo};
+
+// Alerts are compared per rule and vehicles involved, not one by one: how many
+// times an alert repeats depends on where the compute cycles fall.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AlertId {
+    pub rule: String,
+    pub a: String,
+    pub b: String,
+}
+
+impl AlertId {
+    fn of(alert: &Alert) -> Self {
+        match alert {
+            Alert::Distance(da) => Self::distance(da),
+            Alert::CutIn(ca) => Self::cutin(ca),
+            Alert::Closing(ca) => Self::closing(ca),
+            Alert::Speed(sa) => Self::speed(sa),
+            Alert::Route(ra) => Self::route(ra),
+            Alert::Tether(ta) => Self::tether(ta),
+            Alert::Poi(pa) => Self::poi(pa),
+            Alert::Sector(sa) => Self::sector(sa),
+            Alert::Battery(ba) => Self::battery(ba),
+        }
+    }
+
+    fn distance(da: &DistanceAlert) -> Self {
+        // the order of the pair depends on the iteration order of the map
+        let (a, b) = if da.ida <= da.idb { (&da.ida, &da.idb) } else { (&da.idb, &da.ida) };
+        let rule = da.severity.clone().unwrap_or_else(|| da.kind.tag().to_string());
+        AlertId { rule, a: a.clone(), b: b.clone() }
+    }
+
+    fn cutin(ca: &CutInAlert) -> Self {
+        AlertId { rule: "CutIn".into(), a: ca.cutter.clone(), b: ca.victim.clone() }
+    }
+
+    fn closing(ca: &ClosingAlert) -> Self {
+        let (a, b) = if ca.ida <= ca.idb { (&ca.ida, &ca.idb) } else { (&ca.idb, &ca.ida) };
+        AlertId { rule: "Closing".into(), a: a.clone(), b: b.clone() }
+    }
+
+    fn speed(sa: &SpeedAlert) -> Self {
+        AlertId { rule: "Speed".into(), a: sa.id.clone(), b: sa.zone.clone() }
+    }
+
+    fn route(ra: &RouteDeviationAlert) -> Self {
+        AlertId { rule: "Route".into(), a: ra.id.clone(), b: String::new() }
+    }
+
+    fn tether(ta: &TetherAlert) -> Self {
+        AlertId { rule: "Tether".into(), a: ta.id.clone(), b: ta.group.clone() }
+    }
+
+    fn poi(pa: &PoiAlert) -> Self {
+        AlertId { rule: "Poi".into(), a: pa.id.clone(), b: pa.poi.clone() }
+    }
+
+    fn sector(sa: &SectorAlert) -> Self {
+        AlertId { rule: format!("Sector:{}", sa.sector), a: sa.id.clone(), b: sa.other.clone() }
+    }
+
+    fn battery(ba: &BatteryAlert) -> Self {
+        AlertId { rule: "Battery".into(), a: ba.id.clone(), b: String::new() }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub positions: usize,
+    pub cycles: usize,
+    // alert -> (times it fires with the current rules, times it was recorded)
+    pub alerts: BTreeMap<AlertId, (usize, usize)>,
+    // the alerts fired, as JSON lines of {t, rule, alert}, when sequenced
+    pub sequence: Vec<String>,
+}
+
+// An alert fired `t` ms into the recording.
+#[derive(Serialize)]
+struct Sequenced<'a> {
+    t: u64,
+    rule: &'static str,
+    alert: &'a Alert,
+}
+
+// Where the tracker that was recorded read and published its data.
+pub struct RecordedKeys {
+    pub positions: Vec<OwnedKeyExpr>,
+    // distance alerts are published on this key or below it, depending on the layout
+    pub distance_alerts: String,
+    pub cutin_alerts: String,
+    pub closing_alerts: String,
+    pub speed_alerts: String,
+    // routes are assigned on <routes>/<vehicle id>
+    pub routes: String,
+    pub route_alerts: String,
+    pub tether_alerts: String,
+    // POI alerts are published on <poi_alerts>/<poi id>
+    pub poi_alerts: String,
+    // sector alerts are published on <sector_alerts>/<sector name>
+    pub sector_alerts: String,
+    // traffic light states are published on <lights>/<name>
+    pub lights: String,
+}
+
+impl RecordedKeys {
+    pub fn new(positions: &[String], distance_alerts: &str, cutin_alerts: &str, speed_alerts: &str) -> Result<Self, String> {
+        let positions = positions.iter()
+            .map(|k| OwnedKeyExpr::new(k.as_str()).map_err(|e| format!("{k}: {e}")))
+            .collect::<Result<_, _>>()?;
+        Ok(RecordedKeys {
+            positions,
+            distance_alerts: distance_alerts.into(),
+            cutin_alerts: cutin_alerts.into(),
+            closing_alerts: String::new(),
+            speed_alerts: speed_alerts.into(),
+            routes: String::new(),
+            route_alerts: String::new(),
+            tether_alerts: String::new(),
+            poi_alerts: String::new(),
+            sector_alerts: String::new(),
+            lights: String::new(),
+        })
+    }
+
+    pub fn with_routes(mut self, routes: &str, route_alerts: &str) -> Self {
+        self.routes = routes.into();
+        self.route_alerts = route_alerts.into();
+        self
+    }
+
+    pub fn with_closing(mut self, closing_alerts: &str) -> Self {
+        self.closing_alerts = closing_alerts.into();
+        self
+    }
+
+    pub fn with_tethers(mut self, tether_alerts: &str) -> Self {
+        self.tether_alerts = tether_alerts.into();
+        self
+    }
+
+    pub fn with_pois(mut self, poi_alerts: &str) -> Self {
+        self.poi_alerts = poi_alerts.into();
+        self
+    }
+
+    pub fn with_sectors(mut self, sector_alerts: &str) -> Self {
+        self.sector_alerts = sector_alerts.into();
+        self
+    }
+
+    pub fn with_lights(mut self, lights: &str) -> Self {
+        self.lights = lights.into();
+        self
+    }
+
+    fn is_position(&self, key: &keyexpr) -> bool {
+        self.positions.iter().any(|k| k.intersects(key))
+    }
+
+    fn is_distance_alert(&self, key: &str) -> bool {
+        key == self.distance_alerts || key.strip_prefix(&self.distance_alerts).is_some_and(|s| s.starts_with('/'))
+    }
+}
+
+// Feeds the recorded positions through `rules`, running a compute cycle every
+// `period` of recorded time, and compares the result with the recorded alerts.
+// Everything runs on the calling thread, in recorded time: `sequenced` keeps
+// the alerts fired, sorted within each cycle, the same on every run.
+pub fn analyze(records: &[Record], keys: &RecordedKeys, rules: &mut Rules, period: Duration, speed_tolerance: f64, sequenced: bool) -> Report {
+    let mut report = Report::default();
+    let mut map = HashMap::<String, VehicleState>::new();
+    let mut profiles = Profiles::default();
+    let base = Instant::now();
+    let t0 = records.first().map(|r| r.t).unwrap_or(0);
+    let mut next_cycle = Duration::ZERO;
+    let cycle = |rules: &mut Rules, map: &HashMap<String, VehicleState>, at: Duration, report: &mut Report| {
+        report.cycles += 1;
+        let alerts = rules.evaluate(map, base + at);
+        for alert in &alerts {
+            report.alerts.entry(AlertId::of(alert)).or_default().0 += 1;
+        }
+        if sequenced {
+            let t = at.as_millis() as u64;
+            let mut lines: Vec<String> = alerts.iter().map(|alert| serde_json::to_string(&Sequenced { t, rule: alert.name(), alert }).unwrap()).collect();
+            lines.sort();
+            report.sequence.extend(lines);
+        }
+    };
+    for r in records {
+        let at = Duration::from_millis(r.t.saturating_sub(t0));
+        while next_cycle <= at {
+            cycle(rules, &map, next_cycle, &mut report);
+            next_cycle += period;
+        }
+        let payload = r.payload.to_bytes();
+        if !keys.routes.is_empty() && r.key.starts_with(&format!("{}/", keys.routes)) {
+            match routes::parse_update(&keys.routes, &r.key, r.delete, &payload) {
+                Ok(u) => rules.set_route(u),
+                Err(e) => warn!("Skipping route: {e}"),
+            }
+            continue;
+        }
+        if r.delete {
+            continue;
+        }
+        if !keys.lights.is_empty() && r.key.starts_with(&format!("{}/", keys.lights)) {
+            match serde_json::from_slice::<LightStatus>(&payload) {
+                Ok(l) => rules.set_light(l),
+                Err(e) => warn!("Skipping traffic light state: {e}"),
+            }
+            continue;
+        }
+        let Ok(key) = keyexpr::new(&r.key) else { continue };
+        if keys.is_position(key) {
+            if let Some(raw) = RawVehicle::parse(&payload).ok().filter(|raw| raw.validate().is_ok()) {
+                let attached = |k: &str| r.attachment.get(k).map(String::as_str);
+                let mut vi = VehicleInfo::default();
+                raw.write_to(&mut vi, profiles.resolve(&raw, (attached(KIND_ATTACHMENT), attached(COLOR_ATTACHMENT)), None).0);
+                raw.write_telemetry(&mut vi.telemetry);
+                vi.fleet = rules.fleets.of_key(key).map(|f| f.name.clone());
+                report.positions += 1;
+                let now = base + at;
+                match map.get_mut(&vi.id) {
+                    Some(state) => state.update(&vi, now, speed_tolerance, rules.max_speeds.get(vi.kind.as_str())),
+                    None => { map.insert(vi.id.clone(), VehicleState::new(vi, now)); }
+                }
+            }
+        } else if keys.is_distance_alert(&r.key) {
+            if let Ok(da) = serde_json::from_slice::<DistanceAlert>(&payload) {
+                report.alerts.entry(AlertId::distance(&da)).or_default().1 += 1;
+            }
+        } else if r.key == keys.cutin_alerts {
+            if let Ok(ca) = serde_json::from_slice::<CutInAlert>(&payload) {
+                report.alerts.entry(AlertId::cutin(&ca)).or_default().1 += 1;
+            }
+        } else if r.key == keys.closing_alerts {
+            if let Ok(ca) = serde_json::from_slice::<ClosingAlert>(&payload) {
+                report.alerts.entry(AlertId::closing(&ca)).or_default().1 += 1;
+            }
+        } else if r.key == keys.route_alerts {
+            if let Ok(ra) = serde_json::from_slice::<RouteDeviationAlert>(&payload) {
+                report.alerts.entry(AlertId::route(&ra)).or_default().1 += 1;
+            }
+        } else if r.key == keys.tether_alerts {
+            if let Ok(ta) = serde_json::from_slice::<TetherAlert>(&payload) {
+                report.alerts.entry(AlertId::tether(&ta)).or_default().1 += 1;
+            }
+        } else if !keys.poi_alerts.is_empty() && r.key.strip_prefix(keys.poi_alerts.as_str()).is_some_and(|rest| rest.starts_with('/')) {
+            if let Ok(pa) = serde_json::from_slice::<PoiAlert>(&payload) {
+                report.alerts.entry(AlertId::poi(&pa)).or_default().1 += 1;
+            }
+        } else if !keys.sector_alerts.is_empty() && r.key.strip_prefix(keys.sector_alerts.as_str()).is_some_and(|rest| rest.starts_with('/')) {
+            if let Ok(sa) = serde_json::from_slice::<SectorAlert>(&payload) {
+                report.alerts.entry(AlertId::sector(&sa)).or_default().1 += 1;
+            }
+        } else if r.key == keys.speed_alerts {
+            if let Ok(sa) = serde_json::from_slice::<SpeedAlert>(&payload) {
+                report.alerts.entry(AlertId::speed(&sa)).or_default().1 += 1;
+            }
+        }
+    }
+    if !records.is_empty() {
+        cycle(rules, &map, next_cycle, &mut report);
+    }
+    report
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use zenoh::prelude::r#async::*;
+use zenoh::queryable::Query;
+use tracing::warn;
+use crate::http::{AlertFilter, SharedHistory};
+
+// Alerts returned to a query on the recent key without `n`.
+pub const DEFAULT_RECENT: usize = 50;
+
+// What is asked of the recent alerts:
+//   ?n=<n>&since=<ms since the UNIX epoch>&id=<vehicle id>&severity=<severity>
+#[derive(Debug, Default)]
+pub struct RecentQuery {
+    pub n: usize,
+    pub since: u64,
+    pub filter: AlertFilter,
+}
+
+impl RecentQuery {
+    pub fn from_parameters(params: &HashMap<String, String>, default_n: usize) -> Result<Self, String> {
+        let n = match params.get("n") {
+            Some(s) => s.parse::<usize>().map_err(|e| format!("invalid n '{s}': {e}"))?,
+            None => default_n,
+        };
+        let since = match params.get("since") {
+            Some(s) => s.parse::<u64>().map_err(|e| format!("invalid since '{s}': {e}"))?,
+            None => 0,
+        };
+        let filter = AlertFilter { id: params.get("id").cloned(), severity: params.get("severity").cloned() };
+        Ok(RecentQuery { n, since, filter })
+    }
+}
+
+fn parameters(query: &Query, default_n: usize) -> Result<RecentQuery, String> {
+    query.selector().parameters_stringmap().map_err(|e| e.to_string()).and_then(|params| RecentQuery::from_parameters(&params, default_n))
+}
+
+// Answers the queries on `key` with the last alerts published, as a JSON array
+// of {ts, key, alert}, oldest first, so that late joining dashboards can show
+// what they missed. With a `cache` key expression, queries on it are answered
+// with the recent alerts published on the keys they select, each on its own
+// key, as a publication cache would, for the querying subscribers.
+pub async fn serve(z: Arc<Session>, key: String, cache: Option<String>, history: SharedHistory) {
+    let key = KeyExpr::try_from(key).unwrap();
+    let recent = z.declare_queryable(&key).res().await.unwrap();
+    let cache = match cache {
+        Some(c) => Some(z.declare_queryable(c).res().await.unwrap()),
+        None => None,
+    };
+    loop {
+        tokio::select! {
+            Ok(query) = recent.recv_async() => {
+                let reply = match parameters(&query, DEFAULT_RECENT) {
+                    Ok(q) => {
+                        let alerts = history.lock().unwrap().recent(q.n, q.since, &q.filter);
+                        Ok(Sample::new(key.clone(), Value::from(serde_json::to_vec(&alerts).unwrap()).encoding(Encoding::APP_JSON)))
+                    },
+                    Err(e) => {
+                        warn!("Invalid query {}: {e}", query.selector());
+                        Err(Value::from(e))
+                    },
+                };
+                if let Err(e) = query.reply(reply).res().await {
+                    warn!("Unable to reply to query on {key}: {e}");
+                }
+            },
+            Ok(query) = async { cache.as_ref().unwrap().recv_async().await }, if cache.is_some() => {
+                let replies = match parameters(&query, usize::MAX) {
+                    Ok(q) => history.lock().unwrap().recent(q.n, q.since, &q.filter).into_iter()
+                        .filter(|a| keyexpr::new(&a.key).is_ok_and(|k| query.key_expr().intersects(k)))
+                        .map(|a| Ok(Sample::new(KeyExpr::try_from(a.key).unwrap(), Value::from(serde_json::to_vec(&a.alert).unwrap()).encoding(Encoding::APP_JSON))))
+                        .collect(),
+                    Err(e) => {
+                        warn!("Invalid query {}: {e}", query.selector());
+                        vec![Err(Value::from(e))]
+                    },
+                };
+                for reply in replies {
+                    if let Err(e) = query.reply(reply).res().await {
+                        warn!("Unable to reply to query on {}: {e}", query.key_expr());
+                        break;
+                    }
+                }
+            },
+        }
+    }
+}
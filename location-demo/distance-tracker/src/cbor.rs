@@ -0,0 +1,64 @@
+use serde_json::Value;
+
+// Encodes a JSON value as CBOR (RFC 8949), numbers which are not integers as
+// doubles and objects as maps with text keys.
+pub fn encode(v: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    write(&mut out, v);
+    out
+}
+
+// The head of an item: its major type and its argument, in as few bytes as
+// possible.
+fn head(out: &mut Vec<u8>, major: u8, arg: u64) {
+    let major = major << 5;
+    match arg {
+        0..=23 => out.push(major | arg as u8),
+        24..=0xff => out.extend([major | 24, arg as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend((arg as u16).to_be_bytes());
+        },
+        0x1_0000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend((arg as u32).to_be_bytes());
+        },
+        _ => {
+            out.push(major | 27);
+            out.extend(arg.to_be_bytes());
+        },
+    }
+}
+
+fn write(out: &mut Vec<u8>, v: &Value) {
+    match v {
+        Value::Null => out.push(0xf6),
+        Value::Bool(b) => out.push(if *b { 0xf5 } else { 0xf4 }),
+        Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(u), _) => head(out, 0, u),
+            (None, Some(i)) => head(out, 1, !(i as u64)),
+            _ => {
+                out.push(0xfb);
+                out.extend(n.as_f64().unwrap().to_be_bytes());
+            },
+        },
+        Value::String(s) => {
+            head(out, 3, s.len() as u64);
+            out.extend(s.as_bytes());
+        },
+        Value::Array(items) => {
+            head(out, 4, items.len() as u64);
+            for item in items {
+                write(out, item);
+            }
+        },
+        Value::Object(fields) => {
+            head(out, 5, fields.len() as u64);
+            for (k, v) in fields {
+                head(out, 3, k.len() as u64);
+                out.extend(k.as_bytes());
+                write(out, v);
+            }
+        },
+    }
+}
@@ -0,0 +1,113 @@
+use std::sync::Arc;
+use std::time::Duration;
+use serde_json::Value;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use zenoh::prelude::r#async::*;
+use tracing::warn;
+use crate::query::{Page, MAX_PAGE_LIMIT};
+
+// Attempts at fetching the fleet before giving up until the next resubscription.
+const STATE_ATTEMPTS: u32 = 5;
+
+// What a consumer of the tracker's alerts gets from `resilient_alert_stream`.
+#[derive(Debug, Clone)]
+pub enum ClientEvent {
+    // The fleet as known by a tracker, fetched once subscribed and again after
+    // every resubscription, so that consumers catch up with what they missed.
+    Fleet(Vec<Value>),
+    Alert { key: String, alert: Value },
+    // The subscriptions were lost (or stayed idle for too long) and are being
+    // declared again; a new `Fleet` follows.
+    Resubscribing,
+}
+
+#[derive(Debug, Clone)]
+pub struct AlertStream {
+    // alert key expression of the trackers
+    pub alert_key: String,
+    // state queryable of the trackers, for the fleet
+    pub state_key: String,
+    // Subscriptions receiving nothing for this long are declared again, which
+    // recovers from session losses going unnoticed (a router restarting); None
+    // only resubscribes when they are closed.
+    pub idle_resync: Option<Duration>,
+}
+
+impl Default for AlertStream {
+    fn default() -> Self {
+        AlertStream {
+            alert_key: "demo/tracker/alert/**".into(),
+            state_key: "demo/tracker/state".into(),
+            idle_resync: Some(Duration::from_secs(30)),
+        }
+    }
+}
+
+// Subscribes to the tracker's alerts for as long as the receiver is kept,
+// handling what consumers tend to get wrong on their own: subscriptions
+// declared with backoff and declared again when lost, and the fleet fetched
+// from the state queryable after each (re)subscription.
+pub fn resilient_alert_stream(z: Arc<Session>, cfg: AlertStream) -> UnboundedReceiver<ClientEvent> {
+    let (tx, rx) = unbounded_channel();
+    tokio::spawn(run(z, cfg, tx));
+    rx
+}
+
+async fn run(z: Arc<Session>, cfg: AlertStream, tx: UnboundedSender<ClientEvent>) {
+    let keys = [cfg.alert_key.clone()];
+    loop {
+        let (subs, mut rx) = zdemo_common::subscribe(&z, &keys).await;
+        // subscribed first, so that no alert falls between the fleet and the stream
+        let fleet = zdemo_common::with_backoff("fetching the fleet", STATE_ATTEMPTS, || fleet(&z, &cfg.state_key)).await;
+        match fleet {
+            Ok(vehicles) => if tx.send(ClientEvent::Fleet(vehicles)).is_err() {
+                return;
+            },
+            Err(e) => warn!("unable to fetch the fleet on {}: {e}", cfg.state_key),
+        }
+        loop {
+            let sample = match cfg.idle_resync {
+                Some(idle) => match tokio::time::timeout(idle, rx.recv()).await {
+                    Ok(s) => s,
+                    Err(_) => {
+                        warn!("no alert for {idle:?}, subscribing again");
+                        None
+                    },
+                },
+                None => rx.recv().await,
+            };
+            let Some(sample) = sample else { break };
+            match zdemo_common::decode_json::<Value>(&sample) {
+                Ok(alert) => if tx.send(ClientEvent::Alert { key: sample.key_expr.to_string(), alert }).is_err() {
+                    return;
+                },
+                Err(e) => warn!("Unable to Deserialize alert on {}: {e}", sample.key_expr),
+            }
+        }
+        drop(subs);
+        if tx.send(ClientEvent::Resubscribing).is_err() {
+            return;
+        }
+    }
+}
+
+// Walks through the state pages of the first tracker replying.
+async fn fleet(z: &Session, state_key: &str) -> Result<Vec<Value>, String> {
+    let mut vehicles = vec![];
+    let mut token: Option<String> = None;
+    loop {
+        let selector = match &token {
+            Some(t) => format!("{state_key}?limit={MAX_PAGE_LIMIT}&token={t}"),
+            None => format!("{state_key}?limit={MAX_PAGE_LIMIT}"),
+        };
+        let replies = z.get(&selector).res().await.map_err(|e| e.to_string())?;
+        let reply = replies.recv_async().await.map_err(|_| "no tracker replied".to_string())?;
+        let sample = reply.sample.map_err(|e| e.to_string())?;
+        let page: Page<Value> = zdemo_common::decode_json(&sample).map_err(|e| e.to_string())?;
+        vehicles.extend(page.items);
+        match page.next {
+            Some(t) => token = Some(t),
+            None => return Ok(vehicles),
+        }
+    }
+}
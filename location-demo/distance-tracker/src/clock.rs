@@ -0,0 +1,58 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+// The clock rate limits, speeds and alert timings run on: the wall clock, or
+// the source timestamps (ts) of the positions, so that a replay faster than
+// real time, or a simulation, keeps them consistent with the data. Process
+// wide like the coordinate frame, set once at startup.
+#[derive(clap_derive::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ClockSource {
+    /// The wall clock
+    #[default]
+    Wall,
+    /// The latest source timestamp of the positions, standing still until they advance it
+    Data,
+}
+
+static DATA: AtomicBool = AtomicBool::new(false);
+static ORIGIN: OnceLock<Instant> = OnceLock::new();
+// ms since the UNIX epoch of the first and latest timestamps seen, 0 until one is
+static FIRST: AtomicU64 = AtomicU64::new(0);
+static LATEST: AtomicU64 = AtomicU64::new(0);
+
+pub fn set_source(source: ClockSource) {
+    DATA.store(source == ClockSource::Data, Ordering::Relaxed);
+    ORIGIN.get_or_init(Instant::now);
+}
+
+pub fn is_data() -> bool {
+    DATA.load(Ordering::Relaxed)
+}
+
+// Advances the data clock to `ts` (ms since the UNIX epoch), unless it is
+// already later: out of order timestamps never take it back.
+pub fn observe(ts: u64) {
+    if !is_data() {
+        return;
+    }
+    let _ = FIRST.compare_exchange(0, ts, Ordering::Relaxed, Ordering::Relaxed);
+    LATEST.fetch_max(ts, Ordering::Relaxed);
+}
+
+// The data clock maps the first timestamp to the start of the process.
+pub fn now() -> Instant {
+    if !is_data() {
+        return Instant::now();
+    }
+    let origin = *ORIGIN.get_or_init(Instant::now);
+    origin + Duration::from_millis(LATEST.load(Ordering::Relaxed).saturating_sub(FIRST.load(Ordering::Relaxed)))
+}
+
+// ms since the UNIX epoch, the wall clock until the first timestamp.
+pub fn unix_ms() -> u64 {
+    match LATEST.load(Ordering::Relaxed) {
+        ts if is_data() && ts > 0 => ts,
+        _ => crate::record::unix_ms(),
+    }
+}
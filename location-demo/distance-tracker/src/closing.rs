@@ -0,0 +1,36 @@
+use serde::{Serialize, Deserialize};
+use crate::geo;
+use crate::vehicle::VehicleState;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClosingAlert {
+    pub ida: String,
+    pub idb: String,
+    pub distance: f64,
+    // speed (m/s) at which the distance between the pair decreases
+    pub closing_speed: f64,
+    pub threshold: f64,
+}
+
+// Velocity (east, north) in m/s, None if the vehicle moves in an unknown direction.
+fn velocity(v: &VehicleState) -> Option<(f64, f64)> {
+    if v.effective_speed == 0.0 {
+        return Some((0.0, 0.0));
+    }
+    let h = v.heading?.to_radians();
+    Some((v.effective_speed * h.sin(), v.effective_speed * h.cos()))
+}
+
+// Rate (m/s) at which a and b approach each other, negative when they move apart.
+// The velocities are projected on the line joining them, so it does not depend
+// on when their positions were last sampled.
+pub fn closing_speed(a: &VehicleState, b: &VehicleState) -> Option<f64> {
+    let (va, vb) = (velocity(a)?, velocity(b)?);
+    let (e, n) = geo::enu(&a.info.position, &b.info.position);
+    let d = e.hypot(n);
+    if d == 0.0 {
+        return None;
+    }
+    // a moving towards b and b moving towards a both close the gap
+    Some(((va.0 - vb.0) * e + (va.1 - vb.1) * n) / d)
+}
@@ -0,0 +1,82 @@
+use std::collections::{BTreeMap, HashMap};
+use serde::{Serialize, Deserialize};
+use crate::angle_diff;
+use crate::vehicle::VehicleState;
+use crate::Position;
+
+// Vehicles heading in directions further apart than this are crossing, not
+// travelling together. Vehicles without a heading are grouped on proximity only.
+const MAX_HEADING_DIFF: f64 = 30.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Cluster {
+    // the smallest member id, stable as long as that vehicle stays in the group
+    pub id: String,
+    pub members: Vec<String>,
+    pub centroid: Position,
+}
+
+// Payload published after every compute cycle.
+#[derive(Serialize, Debug)]
+pub struct ClusterList<'a> {
+    pub clusters: &'a [Cluster],
+}
+
+// Union-find over the proximity graph of one compute cycle.
+#[derive(Default)]
+pub struct ClusterBuilder {
+    parent: HashMap<String, String>,
+}
+
+impl ClusterBuilder {
+    fn find(&mut self, id: &str) -> String {
+        self.parent.entry(id.to_string()).or_insert_with(|| id.to_string());
+        let mut root = id.to_string();
+        while let Some(p) = self.parent.get(&root).filter(|p| **p != root) {
+            root = p.clone();
+        }
+        // path compression
+        let mut cur = id.to_string();
+        while cur != root {
+            let next = self.parent.insert(cur, root.clone()).unwrap_or_else(|| root.clone());
+            cur = next;
+        }
+        root
+    }
+
+    pub fn link(&mut self, a: &VehicleState, b: &VehicleState, distance: f64, range: f64) {
+        let aligned = match (a.heading, b.heading) {
+            (Some(ha), Some(hb)) => angle_diff(ha, hb).abs() <= MAX_HEADING_DIFF,
+            _ => true,
+        };
+        if distance > range || !aligned {
+            return;
+        }
+        let (ra, rb) = (self.find(&a.info.id), self.find(&b.info.id));
+        if ra != rb {
+            let (root, child) = if ra < rb { (ra, rb) } else { (rb, ra) };
+            self.parent.insert(child, root);
+        }
+    }
+
+    // Groups of at least two vehicles, sorted by id.
+    pub fn build(mut self, map: &HashMap<String, VehicleState>) -> Vec<Cluster> {
+        let ids: Vec<String> = self.parent.keys().cloned().collect();
+        let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for id in ids {
+            let root = self.find(&id);
+            groups.entry(root).or_default().push(id);
+        }
+        groups.into_values()
+            .filter(|m| m.len() > 1)
+            .map(|mut members| {
+                members.sort();
+                let n = members.len() as f64;
+                let (lat, lng) = members.iter()
+                    .filter_map(|id| map.get(id))
+                    .fold((0.0, 0.0), |(lat, lng), vs| (lat + vs.info.position.lat, lng + vs.info.position.lng));
+                Cluster { id: members[0].clone(), centroid: Position { lat: lat / n, lng: lng / n }, members }
+            })
+            .collect()
+    }
+}
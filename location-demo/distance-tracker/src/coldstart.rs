@@ -0,0 +1,33 @@
+use std::time::{Duration, SystemTime};
+use zenoh::prelude::r#async::*;
+use tracing::{info, warn};
+
+// How long the storage has to reply at startup.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// The latest positions held by a Zenoh storage on `key`, fetched at startup so
+// that the fleet is known, and its alerts raised, before the vehicles publish
+// again. Positions stored more than `max_age` ago are those of vehicles gone
+// since, and are skipped; positions without timestamps are kept.
+pub async fn fetch(z: &Session, key: &str, max_age: Duration) -> Vec<Sample> {
+    let replies = match z.get(key).timeout(TIMEOUT).res().await {
+        Ok(replies) => replies,
+        Err(e) => {
+            warn!("COLD START: unable to get the positions on {key}: {e}");
+            return vec![];
+        }
+    };
+    let now = SystemTime::now();
+    let (mut samples, mut expired) = (Vec::new(), 0);
+    while let Ok(reply) = replies.recv_async().await {
+        let Ok(sample) = reply.sample else { continue };
+        let stored = sample.timestamp.map(|t| t.get_time().to_system_time());
+        if stored.is_some_and(|t| now.duration_since(t).is_ok_and(|age| age > max_age)) {
+            expired += 1;
+            continue;
+        }
+        samples.push(sample);
+    }
+    info!("COLD START: {} positions from {key}, {expired} older than {} s skipped", samples.len(), max_age.as_secs());
+    samples
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::time::Instant;
+use serde::{Serialize, Deserialize};
+use crate::angle_diff;
+use crate::vehicle::VehicleState;
+
+// Vehicles whose headings differ by more than this are crossing or oncoming,
+// not changing lane.
+const MAX_HEADING_DIFF: f64 = 45.0;
+// Half-width of the frontal sector in which a cut-in is considered.
+const FRONT_SECTOR: f64 = 60.0;
+
+#[derive (Serialize, Deserialize, Debug, Clone)]
+pub struct CutInAlert {
+    // Vehicle moving into the path of `victim`.
+    pub cutter: String,
+    pub victim: String,
+    pub distance: f64,
+    // Bearing of the cutter relative to the victim's heading, in degrees.
+    pub relative_bearing: f64,
+    // Rate at which the relative bearing closes on the victim's heading, deg/s.
+    pub bearing_rate: f64,
+}
+
+pub struct CutInDetector {
+    range: f64,
+    min_rate: f64,
+    // (victim, cutter) -> last relative bearing and when it was measured
+    last: HashMap<(String, String), (f64, Instant)>,
+}
+
+impl CutInDetector {
+    pub fn new(range: f64, min_rate: f64) -> Self {
+        CutInDetector { range, min_rate, last: HashMap::new() }
+    }
+
+    // Checks both a cutting into b and b cutting into a.
+    pub fn check(&mut self, a: &VehicleState, b: &VehicleState, distance: f64, now: Instant) -> Vec<CutInAlert> {
+        if distance > self.range {
+            self.last.remove(&(a.info.id.clone(), b.info.id.clone()));
+            self.last.remove(&(b.info.id.clone(), a.info.id.clone()));
+            return vec![];
+        }
+        [self.check_one(a, b, distance, now), self.check_one(b, a, distance, now)]
+            .into_iter().flatten().collect()
+    }
+
+    fn check_one(&mut self, victim: &VehicleState, cutter: &VehicleState, distance: f64, now: Instant) -> Option<CutInAlert> {
+        let (vh, ch) = (victim.heading?, cutter.heading?);
+        let relative_bearing = angle_diff(victim.info.position.bearing(&cutter.info.position), vh);
+        let key = (victim.info.id.clone(), cutter.info.id.clone());
+        let prev = self.last.insert(key, (relative_bearing, now));
+        if angle_diff(ch, vh).abs() > MAX_HEADING_DIFF || relative_bearing.abs() > FRONT_SECTOR {
+            return None;
+        }
+        let (prev_rb, t) = prev?;
+        let dt = now.duration_since(t).as_secs_f64();
+        if dt <= 0.0 {
+            return None;
+        }
+        // Positive when the cutter moves towards the victim's heading line.
+        let bearing_rate = (prev_rb.abs() - relative_bearing.abs()) / dt;
+        if bearing_rate >= self.min_rate && relative_bearing.signum() == prev_rb.signum() {
+            Some(CutInAlert {
+                cutter: cutter.info.id.clone(),
+                victim: victim.info.id.clone(),
+                distance,
+                relative_bearing,
+                bearing_rate,
+            })
+        } else {
+            None
+        }
+    }
+
+    // Forgets pairs involving vehicles that are no longer tracked.
+    pub fn retain(&mut self, alive: impl Fn(&str) -> bool) {
+        self.last.retain(|(v, c), _| alive(v) && alive(c));
+    }
+}
@@ -0,0 +1,357 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Json, Redirect, Response};
+use axum::routing::{get, post};
+use axum::Router;
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use zenoh::prelude::r#async::*;
+use tracing::{error, info, warn};
+use distance_tracker::dedup::AlertMerger;
+use distance_tracker::geohash::BoundingBox;
+use distance_tracker::admin::Versioned;
+use distance_tracker::ingest::{self, Profiles, RawVehicle};
+use distance_tracker::keys::{self, KeyUse};
+use distance_tracker::logging::{self, LogFormat};
+use distance_tracker::metadata::{self, MetaCache};
+use distance_tracker::record::unix_ms;
+use distance_tracker::zones::{self, Zone};
+use distance_tracker::VehicleInfo;
+use zdemo_common::Instance;
+
+const MAX_ALERTS: usize = 100;
+// Fields of the alert payloads naming the vehicles involved.
+const ALERT_ID_FIELDS: [&str; 4] = ["ida", "idb", "cutter", "victim"];
+const VIEW_PAGE: &str = include_str!("dashboard.html");
+const SNAPSHOT_PAGE: &str = include_str!("snapshot.html");
+const MAX_SNAPSHOTS: usize = 32;
+
+// One screen of the dashboard, served on /<name>.
+#[derive(Deserialize, Debug, Clone)]
+struct ViewConfig {
+    name: String,
+    // Namespace (or fleet) of the positions shown in this view.
+    #[serde(default = "default_key")]
+    key: String,
+    #[serde(default = "default_alert_key")]
+    alert_key: String,
+    // "lat_min,lng_min,lat_max,lng_max"
+    bbox: Option<String>,
+    kinds: Option<Vec<String>>,
+}
+
+fn default_key() -> String { "demo/tracker/mobs/**".into() }
+fn default_alert_key() -> String { "demo/tracker/alert/**".into() }
+
+#[derive(Serialize, Default)]
+struct ViewState {
+    instance: String,
+    vehicles: Vec<VehicleInfo>,
+    alerts: Vec<serde_json::Value>,
+    zones: Vec<Zone>,
+}
+
+// Speed zones of the trackers, shared by the views.
+type SharedZones = Arc<Mutex<Vec<Zone>>>;
+
+struct View {
+    instance: String,
+    cfg: ViewConfig,
+    bbox: Option<BoundingBox>,
+    vehicles: Mutex<HashMap<String, VehicleInfo>>,
+    alerts: Mutex<VecDeque<serde_json::Value>>,
+    // alerts of redundant trackers reporting the same incident are shown once
+    merger: Mutex<AlertMerger>,
+    zones: SharedZones,
+}
+
+impl View {
+    fn accepts(&self, vi: &VehicleInfo) -> bool {
+        let in_bbox = self.bbox.is_none_or(|bb| bb.contains(vi.position.lat, vi.position.lng));
+        let of_kind = self.cfg.kinds.as_ref().is_none_or(|ks| ks.iter().any(|k| k == vi.kind.as_str()));
+        in_bbox && of_kind
+    }
+
+    async fn on_position(&self, vi: VehicleInfo) {
+        let mut m = self.vehicles.lock().await;
+        if self.accepts(&vi) {
+            m.insert(vi.id.clone(), vi);
+        } else {
+            // vehicles leaving the view's area disappear from it
+            m.remove(&vi.id);
+        }
+    }
+
+    async fn on_alert(&self, key: &str, alert: serde_json::Value) {
+        let relevant = {
+            let m = self.vehicles.lock().await;
+            ALERT_ID_FIELDS.iter()
+                .filter_map(|f| alert.get(f).and_then(|v| v.as_str()))
+                .any(|id| m.contains_key(id))
+        };
+        if relevant && self.merger.lock().await.accept(key, &alert, Instant::now()) {
+            let mut a = self.alerts.lock().await;
+            if a.len() == MAX_ALERTS {
+                a.pop_front();
+            }
+            a.push_back(alert);
+        }
+    }
+
+    async fn state(&self) -> ViewState {
+        let mut vehicles: Vec<VehicleInfo> = self.vehicles.lock().await.values().cloned().collect();
+        vehicles.sort_by(|a, b| a.id.cmp(&b.id));
+        let alerts = self.alerts.lock().await.iter().rev().cloned().collect();
+        let zones = self.zones.lock().await.clone();
+        ViewState { instance: self.instance.clone(), vehicles, alerts, zones }
+    }
+}
+
+// Each view has its own subscriptions so that views on different namespaces
+// only receive their own traffic.
+async fn run_view(z: Arc<Session>, view: Arc<View>, meta: MetaCache) {
+    let keys = [view.cfg.key.clone(), view.cfg.alert_key.clone()];
+    let position_key = keyexpr::new(&view.cfg.key).unwrap();
+    // kind and color of the vehicles publishing compact positions
+    let mut profiles = Profiles::default();
+    let mut decode = |sample: &Sample| {
+        let payload = zdemo_common::payload(sample)?;
+        let raw = RawVehicle::parse(&payload).map_err(|e| e.to_string())?;
+        let (kind, color) = ingest::attached(sample);
+        let profile = meta.with_profile(&raw.id, |m| profiles.resolve(&raw, (kind.as_deref(), color.as_deref()), m).0);
+        let mut vi = VehicleInfo::default();
+        raw.write_to(&mut vi, profile);
+        Ok::<_, String>(vi)
+    };
+    loop {
+        let (subs, mut rx) = zdemo_common::subscribe(&z, &keys).await;
+        while let Some(sample) = rx.recv().await {
+            if position_key.intersects(&sample.key_expr) {
+                match decode(&sample) {
+                    Ok(vi) => view.on_position(vi).await,
+                    Err(e) => info!("[{}] Unable to Deserialize position on {}: {e}", view.cfg.name, sample.key_expr),
+                }
+            } else if let Ok(alert) = zdemo_common::decode_json::<serde_json::Value>(&sample) {
+                view.on_alert(sample.key_expr.as_str(), alert).await;
+            }
+        }
+        warn!("[{}] subscriptions lost, declaring them again", view.cfg.name);
+        drop(subs);
+    }
+}
+
+// Follows the zones shared by the trackers on `key`, starting from `zones`.
+async fn follow_zones(z: Arc<Session>, key: String, zones: SharedZones) {
+    let mut version = None;
+    let mut apply = |sample: &Sample| {
+        match zdemo_common::decode_json::<Versioned<Vec<Zone>>>(sample) {
+            Ok(u) if version.as_ref().is_none_or(|v| u.version > *v) => {
+                info!("ZONES: version {} from {}", u.version.time, u.version.origin);
+                version = Some(u.version);
+                Some(u.value)
+            },
+            Ok(_) => None,
+            Err(e) => {
+                warn!("Unable to Deserialize zones on {}: {e}", sample.key_expr);
+                None
+            },
+        }
+    };
+    let keys = [key];
+    let (mut subs, mut rx) = zdemo_common::subscribe(&z, &keys).await;
+    // zones changed before the dashboard started, kept by the trackers
+    match z.get(&keys[0]).res().await {
+        Ok(replies) => {
+            while let Ok(reply) = replies.recv_async().await {
+                if let Some(u) = reply.sample.ok().and_then(|s| apply(&s)) {
+                    *zones.lock().await = u;
+                }
+            }
+        },
+        Err(e) => warn!("unable to query the zones: {e}"),
+    }
+    loop {
+        while let Some(sample) = rx.recv().await {
+            if let Some(u) = apply(&sample) {
+                *zones.lock().await = u;
+            }
+        }
+        warn!("zones subscription lost, declaring it again");
+        drop(subs);
+        (subs, rx) = zdemo_common::subscribe(&z, &keys).await;
+    }
+}
+
+struct Dashboard {
+    views: HashMap<String, Arc<View>>,
+    // latest snapshots, by id
+    snapshots: Mutex<VecDeque<(String, String)>>,
+}
+
+type Views = Arc<Dashboard>;
+
+#[derive(Serialize)]
+struct Snapshot {
+    view: String,
+    // unix ms
+    taken: u64,
+    #[serde(flatten)]
+    state: ViewState,
+}
+
+async fn index(State(d): State<Views>) -> Html<String> {
+    let mut names: Vec<&String> = d.views.keys().collect();
+    names.sort();
+    let links: String = names.iter().map(|n| format!("<li><a href=\"/{n}\">{n}</a></li>")).collect();
+    Html(format!("<html><head><title>Zenoh Tracker Dashboard</title></head><body><h1>Views</h1><ul>{links}</ul></body></html>"))
+}
+
+async fn view_page(Path(name): Path<String>, State(d): State<Views>) -> Response {
+    match d.views.get(&name) {
+        Some(_) => Html(VIEW_PAGE.replace("{{VIEW}}", &name)).into_response(),
+        None => (StatusCode::NOT_FOUND, format!("no view named {name}")).into_response(),
+    }
+}
+
+async fn view_state(Path(name): Path<String>, State(d): State<Views>) -> Response {
+    match d.views.get(&name) {
+        Some(v) => Json(v.state().await).into_response(),
+        None => (StatusCode::NOT_FOUND, format!("no view named {name}")).into_response(),
+    }
+}
+
+// Captures the view in a static page, with its data inline, kept on
+// /snapshots/<id> where it can be shared.
+async fn take_snapshot(Path(name): Path<String>, State(d): State<Views>) -> Response {
+    let Some(v) = d.views.get(&name) else {
+        return (StatusCode::NOT_FOUND, format!("no view named {name}")).into_response();
+    };
+    let snapshot = Snapshot { view: name.clone(), taken: unix_ms(), state: v.state().await };
+    let id = format!("{name}-{}", snapshot.taken);
+    // the data must not close the script element it is inlined in
+    let data = serde_json::to_string(&snapshot).unwrap().replace("</", "<\\/");
+    let page = SNAPSHOT_PAGE.replace("{{VIEW}}", &name).replace("{{SNAPSHOT}}", &data);
+    let mut snapshots = d.snapshots.lock().await;
+    if snapshots.len() == MAX_SNAPSHOTS {
+        snapshots.pop_front();
+    }
+    snapshots.push_back((id.clone(), page));
+    info!("Snapshot of view {name} on /snapshots/{id}");
+    Redirect::to(&format!("/snapshots/{id}")).into_response()
+}
+
+async fn snapshot(Path(id): Path<String>, State(d): State<Views>) -> Response {
+    match d.snapshots.lock().await.iter().find(|(i, _)| *i == id) {
+        Some((_, page)) => Html(page.clone()).into_response(),
+        None => (StatusCode::NOT_FOUND, format!("no snapshot {id}, only the last {MAX_SNAPSHOTS} are kept")).into_response(),
+    }
+}
+
+#[derive(clap_derive::Parser)]
+struct AppArgs {
+    /// JSON file with the list of views, each {name, key, alert_key, bbox, kinds}.
+    #[arg(long)]
+    views: Option<String>,
+    #[arg(long)]
+    http_port: Option<u16>,
+    /// Alerts of an incident already reported by another tracker less than this ago are dropped (0 disables merging).
+    #[arg(long)]
+    merge_window_ms: Option<u64>,
+    /// Speed zones ({name, speed_limit, polygon}) shown on the map, until the trackers share others.
+    #[arg(long)]
+    speed_zones: Option<String>,
+    /// Zones shared by the trackers, see their --admin-key.
+    #[arg(long)]
+    zones_key: Option<String>,
+    /// Vehicle metadata, completing the kind and color of compact positions, see the trackers' --meta-key.
+    #[arg(long)]
+    meta_key: Option<String>,
+    /// Id added to every response, generated and persisted when not given.
+    #[arg(long)]
+    instance_id: Option<String>,
+    /// Format of the logs, filtered by RUST_LOG.
+    #[arg(long, value_enum)]
+    log_format: Option<LogFormat>,
+    #[arg(long)]
+    config: Option<String>
+}
+
+#[tokio::main]
+async fn main() {
+    let args = AppArgs::parse();
+    logging::init(args.log_format.unwrap_or_default());
+    let configs: Vec<ViewConfig> = match &args.views {
+        Some(f) => {
+            let s = std::fs::read_to_string(f).unwrap_or_else(|e| panic!("Unable to read {f}: {e}"));
+            serde_json::from_str(&s).unwrap_or_else(|e| panic!("Invalid views file {f}: {e}"))
+        },
+        None => vec![ViewConfig { name: "all".into(), key: default_key(), alert_key: default_alert_key(), bbox: None, kinds: None }]
+    };
+    let config = match args.config {
+        Some(f) => Config::from_file(f).unwrap(),
+        None => Config::default()
+    };
+    let port = args.http_port.unwrap_or(8080);
+    let merge_window = Duration::from_millis(args.merge_window_ms.unwrap_or(2000));
+    let speed_zones = match &args.speed_zones {
+        Some(f) => zones::load(f).unwrap_or_else(|e| panic!("--speed-zones: {e}")),
+        None => vec![]
+    };
+    let zones_key = args.zones_key.unwrap_or("demo/tracker/admin/config/zones".into());
+    let meta_key = args.meta_key.unwrap_or("demo/tracker/meta".into());
+    if let Err(errors) = keys::check_all([("--zones-key", zones_key.as_str(), KeyUse::Concrete), ("--meta-key", meta_key.as_str(), KeyUse::Concrete)]) {
+        for e in errors {
+            error!("{e}");
+        }
+        std::process::exit(2);
+    }
+    let instance = Instance::resolve("tracker-dashboard", args.instance_id);
+    for (k, v) in instance.resource_attributes() {
+        info!("{k}={v}");
+    }
+
+    let z = zdemo_common::open(config).await;
+    let zones: SharedZones = Arc::new(Mutex::new(speed_zones));
+    tokio::spawn(follow_zones(z.clone(), zones_key, zones.clone()));
+    let meta = MetaCache::default();
+    tokio::spawn(metadata::follow(z.clone(), meta_key, meta.clone(), None));
+    let mut views = HashMap::new();
+    for cfg in configs {
+        if views.contains_key(&cfg.name) {
+            panic!("duplicated view name {}", cfg.name);
+        }
+        if cfg.name == "snapshots" {
+            panic!("view name snapshots is reserved");
+        }
+        let origin = format!("view '{}'", cfg.name);
+        let checks = [(&*origin, cfg.key.as_str(), KeyUse::Select), (&*origin, cfg.alert_key.as_str(), KeyUse::Select)];
+        if let Err(errors) = keys::check_all(checks) {
+            for e in errors {
+                error!("{e}");
+            }
+            std::process::exit(2);
+        }
+        let bbox = cfg.bbox.as_ref().map(|s| s.parse::<BoundingBox>().unwrap_or_else(|e| panic!("view {}: {e}", cfg.name)));
+        let view = Arc::new(View { instance: instance.id.clone(), cfg, bbox, vehicles: Mutex::new(HashMap::new()), alerts: Mutex::new(VecDeque::new()), merger: Mutex::new(AlertMerger::new(merge_window)), zones: zones.clone() });
+        info!("View /{} on {}", view.cfg.name, view.cfg.key);
+        tokio::spawn(run_view(z.clone(), view.clone(), meta.clone()));
+        views.insert(view.cfg.name.clone(), view);
+    }
+    let app = Router::new()
+        .route("/", get(index))
+        .route("/:view", get(view_page))
+        .route("/:view/state", get(view_state))
+        .route("/:view/snapshot", post(take_snapshot))
+        .route("/snapshots/:id", get(snapshot))
+        .with_state(Arc::new(Dashboard { views, snapshots: Mutex::new(VecDeque::new()) }));
+    let listener = tokio::net::TcpListener::bind(("0.0.0.0", port)).await.unwrap();
+    info!("Dashboard listening on http://0.0.0.0:{port}");
+    axum::serve(listener, app)
+        .with_graceful_shutdown(zdemo_common::shutdown_signal())
+        .await
+        .unwrap();
+}
@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use serde_json::Value;
+
+// Fields of the alert payloads identifying the incident, besides the key.
+const PAIR_FIELDS: [&str; 2] = ["ida", "idb"];
+const ID_FIELDS: [&str; 5] = ["cutter", "victim", "id", "zone", "kind"];
+
+// Identity of the incident an alert is about, the same whichever tracker
+// replica published it: its key, the pair in id order (the order of ida and
+// idb is not significant) and the other identifying fields.
+pub fn incident(key: &str, alert: &Value) -> String {
+    let field = |f: &str| alert.get(f).and_then(|v| v.as_str()).unwrap_or("");
+    let (a, b) = (field(PAIR_FIELDS[0]), field(PAIR_FIELDS[1]));
+    let (a, b) = if a <= b { (a, b) } else { (b, a) };
+    let mut id = format!("{key}|{a}|{b}");
+    for f in ID_FIELDS {
+        id.push('|');
+        id.push_str(field(f));
+    }
+    id
+}
+
+struct Owner {
+    instance: String,
+    seen: Instant,
+}
+
+// Merges the alerts published by redundant trackers (active-active, or before
+// a leader is elected), so that consumers show every incident once: the first
+// replica reporting an incident owns it, and the same incident reported by
+// other replicas is dropped until the owner stays silent for `window`, when
+// the next replica reporting it takes over. Replicas are told apart by the
+// `instance` field of the alerts.
+pub struct AlertMerger {
+    window: Duration,
+    owners: HashMap<String, Owner>,
+    pruned: Instant,
+}
+
+impl AlertMerger {
+    pub fn new(window: Duration) -> Self {
+        AlertMerger { window, owners: HashMap::new(), pruned: Instant::now() }
+    }
+
+    // Whether `alert`, received on `key`, is to be shown: false for duplicates
+    // of an incident owned by another replica. A zero window merges nothing.
+    pub fn accept(&mut self, key: &str, alert: &Value, now: Instant) -> bool {
+        if self.window.is_zero() {
+            return true;
+        }
+        if now.duration_since(self.pruned) >= self.window {
+            let window = self.window;
+            self.owners.retain(|_, o| now.duration_since(o.seen) < window);
+            self.pruned = now;
+        }
+        let instance = alert.get("instance").and_then(|v| v.as_str()).unwrap_or("");
+        let owner = self.owners.entry(incident(key, alert))
+            .or_insert_with(|| Owner { instance: instance.to_string(), seen: now });
+        if owner.instance != instance {
+            if now.duration_since(owner.seen) < self.window {
+                return false;
+            }
+            owner.instance = instance.to_string();
+        }
+        owner.seen = now;
+        true
+    }
+}
@@ -0,0 +1,194 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+use zenoh::prelude::r#async::*;
+use tracing::{info, warn};
+use crate::keys::{self, KeyUse};
+use crate::record::{unix_ms, Payload};
+
+// Samples published under `from` are republished `delay` seconds later under
+// `to`, e.g. from demo/tracker/mobs/** to demo/spectator/mobs.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DelayStream {
+    pub from: String,
+    pub to: String,
+    // s
+    pub delay: f64,
+}
+
+impl DelayStream {
+    fn prefix(&self) -> &str {
+        self.from.strip_suffix("/**").unwrap_or(&self.from)
+    }
+
+    fn target(&self, key: &str) -> Option<String> {
+        key.strip_prefix(self.prefix())
+            .filter(|rest| rest.starts_with('/'))
+            .map(|rest| format!("{}{rest}", self.to))
+    }
+}
+
+pub fn load(path: &str) -> Result<Vec<DelayStream>, String> {
+    let s = std::fs::read_to_string(path).map_err(|e| format!("Unable to read {path}: {e}"))?;
+    let streams: Vec<DelayStream> = serde_json::from_str(&s).map_err(|e| format!("Invalid delay streams file {path}: {e}"))?;
+    for d in &streams {
+        if !d.from.ends_with("/**") {
+            return Err(format!("delay stream from '{}' must end with /**", d.from));
+        }
+        keys::check("delay stream from", d.prefix(), KeyUse::Concrete)?;
+        keys::check("delay stream to", &d.to, KeyUse::Concrete)?;
+        if d.delay.is_nan() || d.delay <= 0.0 {
+            return Err(format!("delay of the stream from '{}' must be positive", d.from));
+        }
+        let republished = keyexpr::new(&d.to).unwrap().join("**").unwrap();
+        if republished.intersects(keyexpr::new(&d.from).unwrap()) {
+            return Err(format!("delay stream to '{}' overlaps its source '{}'", d.to, d.from));
+        }
+    }
+    Ok(streams)
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Delayed {
+    // unix ms, wall clock so that the delay holds across restarts
+    due: u64,
+    // arrival order of samples due at the same time
+    seq: u64,
+    key: String,
+    #[serde(default)]
+    delete: bool,
+    encoding: String,
+    payload: Payload,
+}
+
+impl PartialEq for Delayed {
+    fn eq(&self, other: &Self) -> bool {
+        (self.due, self.seq) == (other.due, other.seq)
+    }
+}
+
+impl Eq for Delayed {}
+
+impl PartialOrd for Delayed {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Delayed {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.due, self.seq).cmp(&(other.due, other.seq))
+    }
+}
+
+// Delayed samples ordered by due time, saved to `path` so that samples still
+// held when the tracker stops are published after it restarts.
+pub struct DelayQueue {
+    heap: BinaryHeap<Reverse<Delayed>>,
+    seq: u64,
+    path: Option<PathBuf>,
+    dirty: bool,
+}
+
+pub type SharedQueue = Arc<Mutex<DelayQueue>>;
+
+impl DelayQueue {
+    pub fn open(path: Option<PathBuf>) -> Self {
+        let saved: Vec<Delayed> = path.as_ref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|s| serde_json::from_str(&s).map_err(|e| warn!("ignoring invalid delayed samples: {e}")).ok())
+            .unwrap_or_default();
+        let seq = saved.iter().map(|d| d.seq + 1).max().unwrap_or(0);
+        if !saved.is_empty() {
+            info!("Restored {} delayed samples", saved.len());
+        }
+        DelayQueue { heap: saved.into_iter().map(Reverse).collect(), seq, path, dirty: false }
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    fn push(&mut self, due: u64, key: String, sample: &Sample) {
+        let payload = Payload::from_bytes(&sample.payload.contiguous());
+        let delete = sample.kind == SampleKind::Delete;
+        self.heap.push(Reverse(Delayed { due, seq: self.seq, key, delete, encoding: sample.encoding.to_string(), payload }));
+        self.seq += 1;
+        self.dirty = true;
+    }
+
+    fn pop_due(&mut self, now: u64) -> Vec<Delayed> {
+        let mut due = vec![];
+        while self.heap.peek().is_some_and(|d| d.0.due <= now) {
+            due.push(self.heap.pop().unwrap().0);
+        }
+        self.dirty |= !due.is_empty();
+        due
+    }
+
+    // Writes the queue, if it changed, replacing the previous file atomically.
+    pub fn save(&mut self) {
+        let Some(path) = &self.path else { return };
+        if !self.dirty {
+            return;
+        }
+        let mut samples: Vec<&Delayed> = self.heap.iter().map(|d| &d.0).collect();
+        samples.sort();
+        let tmp = path.with_extension("tmp");
+        let r = path.parent().map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&tmp, serde_json::to_vec(&samples).unwrap()))
+            .and_then(|_| std::fs::rename(&tmp, path));
+        match r {
+            Ok(_) => self.dirty = false,
+            Err(e) => warn!("unable to save the delayed samples in {}: {e}", path.display()),
+        }
+    }
+}
+
+// Republishes the delay streams, saving the queue every second.
+pub async fn run(z: Arc<Session>, streams: Vec<DelayStream>, queue: SharedQueue) {
+    let keys: Vec<String> = streams.iter().map(|d| d.from.clone()).collect();
+    let (mut subs, mut rx) = zdemo_common::subscribe(&z, &keys).await;
+    let mut release = tokio::time::interval(Duration::from_millis(100));
+    let mut save = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        tokio::select! {
+            s = rx.recv() => {
+                let Some(sample) = s else {
+                    warn!("delay stream subscriptions lost, declaring them again");
+                    drop(subs);
+                    (subs, rx) = zdemo_common::subscribe(&z, &keys).await;
+                    continue;
+                };
+                let mut q = queue.lock().unwrap();
+                for d in &streams {
+                    if let Some(key) = d.target(sample.key_expr.as_str()) {
+                        q.push(unix_ms() + (d.delay * 1000.0) as u64, key, &sample);
+                    }
+                }
+            },
+            _ = release.tick() => {
+                let due = queue.lock().unwrap().pop_due(unix_ms());
+                for d in due {
+                    let res = if d.delete {
+                        z.delete(&d.key).res().await
+                    } else {
+                        z.put(&d.key, d.payload.to_bytes()).encoding(Encoding::from(d.encoding)).res().await
+                    };
+                    if let Err(e) = res {
+                        warn!("Unable to publish delayed sample on {}: {e}", d.key);
+                    }
+                }
+            },
+            _ = save.tick() => queue.lock().unwrap().save(),
+        }
+    }
+}
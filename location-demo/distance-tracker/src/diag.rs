@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use tokio::sync::mpsc::Sender;
+use zenoh::prelude::r#async::*;
+use tracing::warn;
+use crate::publish::Outgoing;
+
+// Diagnostics of a stage and key are published at most this often, the others
+// only being counted.
+const REPEAT_INTERVAL: Duration = Duration::from_secs(1);
+// Bytes of the offending payload included in a diagnostic.
+const PAYLOAD_EXCERPT: usize = 256;
+
+// Attachment entries of the dead letters, next to those of the original sample.
+pub const STAGE_ATTACHMENT: &str = "stage";
+pub const ERROR_ATTACHMENT: &str = "error";
+
+// Where the tracker gave up on a sample.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    // the payload could not be decoded (encoding, compression)
+    Decode,
+    // the payload is not a position
+    Position,
+    // the position is out of range
+    Validation,
+    // the position is not signed by its vehicle, and was rejected
+    Signature,
+    // the position jumped faster than its kind of vehicle can move
+    Outlier,
+    // the kind or color is invalid, and was ignored
+    Attribute,
+    Metadata,
+    Route,
+    Light,
+    Flag,
+    Config,
+    Subscription,
+    Handover,
+    Ack,
+}
+
+impl Stage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Decode => "decode",
+            Stage::Position => "position",
+            Stage::Validation => "validation",
+            Stage::Signature => "signature",
+            Stage::Outlier => "outlier",
+            Stage::Attribute => "attribute",
+            Stage::Metadata => "metadata",
+            Stage::Route => "route",
+            Stage::Light => "light",
+            Stage::Flag => "flag",
+            Stage::Config => "config",
+            Stage::Subscription => "subscription",
+            Stage::Handover => "handover",
+            Stage::Ack => "ack",
+        }
+    }
+}
+
+// Why the tracker ignored a sample, so that remote operators can tell what is
+// wrong with their publisher without access to the tracker's output.
+#[derive(Serialize, Debug)]
+pub struct Diagnostic {
+    pub stage: Stage,
+    // key of the offending sample
+    pub key: String,
+    pub error: String,
+    // beginning of the offending payload, as lossy UTF-8
+    pub payload: Option<String>,
+    // diagnostics of the same stage and key suppressed since the previous one
+    pub repeated: u64,
+}
+
+// (stage, key) -> (last published, suppressed since)
+type Published = HashMap<(Stage, String), (Instant, u64)>;
+
+// Publishes the diagnostics on a single key, through the tracker's publisher.
+// Reporting never waits, diagnostics are dropped when the publisher lags.
+#[derive(Clone)]
+pub struct Diagnostics {
+    key: String,
+    tx: Sender<Outgoing>,
+    last: Arc<Mutex<Published>>,
+}
+
+impl Diagnostics {
+    pub fn new(key: String, tx: Sender<Outgoing>) -> Self {
+        Diagnostics { key, tx, last: Arc::default() }
+    }
+
+    pub fn report(&self, stage: Stage, key: &str, error: impl Display, payload: Option<&[u8]>) {
+        let now = Instant::now();
+        let repeated = {
+            let mut last = self.last.lock().unwrap();
+            match last.get_mut(&(stage, key.to_string())) {
+                Some((at, suppressed)) if now.duration_since(*at) < REPEAT_INTERVAL => {
+                    *suppressed += 1;
+                    return;
+                },
+                Some((at, suppressed)) => {
+                    *at = now;
+                    std::mem::take(suppressed)
+                },
+                None => {
+                    last.insert((stage, key.to_string()), (now, 0));
+                    0
+                },
+            }
+        };
+        let payload = payload.map(|p| String::from_utf8_lossy(&p[..p.len().min(PAYLOAD_EXCERPT)]).into_owned());
+        let diagnostic = Diagnostic { stage, key: key.into(), error: error.to_string(), payload, repeated };
+        // diagnostics are not worth blocking the caller
+        let _ = self.tx.try_send(Outgoing::json(&self.key, &diagnostic));
+    }
+}
+
+// Republishes the rejected positions as received (payload, encoding and
+// attachments) on <key>/<original key>, with the stage and error attached, so
+// that they can be inspected in full, and replayed once the publisher is fixed.
+// Unlike diagnostics, every rejected sample is republished.
+#[derive(Clone)]
+pub struct DeadLetters {
+    z: Arc<Session>,
+    key: String,
+}
+
+impl DeadLetters {
+    pub fn new(z: Arc<Session>, key: String) -> Self {
+        DeadLetters { z, key }
+    }
+
+    pub async fn republish(&self, stage: Stage, sample: &Sample, error: impl Display) {
+        let key = format!("{}/{}", self.key, sample.key_expr);
+        let mut attachment = sample.attachment().cloned().unwrap_or_default();
+        attachment.insert(STAGE_ATTACHMENT, stage.as_str());
+        attachment.insert(ERROR_ATTACHMENT, error.to_string().as_str());
+        let value = Value::new(sample.payload.clone()).encoding(sample.encoding.clone());
+        if let Err(e) = self.z.put(&key, value).with_attachment(attachment).res().await {
+            warn!("Unable to republish the rejected sample on {key}: {e}");
+        }
+    }
+}
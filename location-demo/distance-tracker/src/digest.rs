@@ -0,0 +1,172 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use zenoh::prelude::r#async::*;
+use zdemo_common::Instance;
+use tracing::warn;
+use crate::display::{self, AlertState};
+use crate::rules::Alert;
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct PairStats {
+    pub ida: String,
+    pub idb: String,
+    // times the pair entered the alert state
+    pub alerts: u64,
+    // m, the shortest distance of its alerts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_distance: Option<f64>,
+    // ms spent raising alerts, and dangers among them
+    pub alert_ms: u64,
+    pub danger_ms: u64,
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct VehicleStats {
+    pub id: String,
+    pub alerts: u64,
+    pub alert_ms: u64,
+    pub danger_ms: u64,
+}
+
+// Published on the digest key, the riskiest first.
+#[derive(Serialize, Debug)]
+pub struct DigestReport {
+    // ms since the UNIX epoch the statistics are accumulated from
+    pub since: u64,
+    pub cycles: u64,
+    pub pairs: Vec<PairStats>,
+    pub vehicles: Vec<VehicleStats>,
+}
+
+// Alert statistics of the pairs and vehicles since the start. The time between
+// two cycles is spent in the states of the first one, the time computation is
+// suspended in none.
+type States = HashMap<String, AlertState>;
+
+pub struct Digest {
+    since: u64,
+    cycles: u64,
+    pairs: BTreeMap<String, PairStats>,
+    vehicles: BTreeMap<String, VehicleStats>,
+    // the states of the last cycle, by alert id for the pairs
+    last: Option<(Instant, States, States)>,
+}
+
+pub type SharedDigest = Arc<Mutex<Digest>>;
+
+// The vehicles of an alert between two of them, with their distance (m) when
+// the alert has one, and the state they are in.
+pub fn pair(a: &Alert) -> Option<(&String, &String, Option<f64>, AlertState)> {
+    match a {
+        Alert::Distance(da) => Some((&da.ida, &da.idb, Some(da.distance), if da.kind.is_danger() { AlertState::Danger } else { AlertState::Alert })),
+        Alert::CutIn(ca) => Some((&ca.cutter, &ca.victim, None, AlertState::Danger)),
+        Alert::Closing(ca) => Some((&ca.ida, &ca.idb, None, AlertState::Danger)),
+        Alert::Sector(sa) => Some((&sa.id, &sa.other, Some(sa.distance), if sa.danger { AlertState::Danger } else { AlertState::Alert })),
+        _ => None,
+    }
+}
+
+fn spend(state: AlertState, dt: u64, alert_ms: &mut u64, danger_ms: &mut u64) {
+    *alert_ms += dt;
+    if state == AlertState::Danger {
+        *danger_ms += dt;
+    }
+}
+
+impl Digest {
+    pub fn new(unix_ms: u64) -> Self {
+        Digest { since: unix_ms, cycles: 0, pairs: BTreeMap::new(), vehicles: BTreeMap::new(), last: None }
+    }
+
+    pub fn cycle(&mut self, alerts: &[Alert], now: Instant) {
+        self.cycles += 1;
+        let mut pairs = States::new();
+        for (ida, idb, distance, state) in alerts.iter().filter_map(pair) {
+            let id = crate::geometry::alert_id(ida, idb);
+            let p = self.pairs.entry(id.clone()).or_insert_with(|| {
+                let (a, b) = if ida <= idb { (ida, idb) } else { (idb, ida) };
+                PairStats { ida: a.clone(), idb: b.clone(), ..Default::default() }
+            });
+            if let Some(d) = distance {
+                p.min_distance = Some(p.min_distance.map_or(d, |m| m.min(d)));
+            }
+            let s = pairs.entry(id).or_insert(state);
+            *s = (*s).max(state);
+        }
+        let vehicles: States = display::states(alerts).into_iter().map(|(id, s)| (id.to_string(), s)).collect();
+        let (previous_pairs, previous_vehicles) = match self.last.take() {
+            Some((at, ps, vs)) => {
+                let dt = now.saturating_duration_since(at).as_millis() as u64;
+                for (id, s) in &ps {
+                    let p = self.pairs.get_mut(id).unwrap();
+                    spend(*s, dt, &mut p.alert_ms, &mut p.danger_ms);
+                }
+                for (id, s) in &vs {
+                    let v = self.vehicles.get_mut(id).unwrap();
+                    spend(*s, dt, &mut v.alert_ms, &mut v.danger_ms);
+                }
+                (ps, vs)
+            },
+            None => Default::default(),
+        };
+        for id in pairs.keys().filter(|id| !previous_pairs.contains_key(*id)) {
+            self.pairs.get_mut(id).unwrap().alerts += 1;
+        }
+        for id in vehicles.keys().filter(|id| !previous_vehicles.contains_key(*id)) {
+            self.vehicles.entry(id.clone()).or_insert_with(|| VehicleStats { id: id.clone(), ..Default::default() }).alerts += 1;
+        }
+        self.last = Some((now, pairs, vehicles));
+    }
+
+    // Computation was suspended: the time until the next cycle is not spent in
+    // any state.
+    pub fn suspend(&mut self) {
+        self.last = None;
+    }
+
+    // The states of the pairs raising alerts at the last cycle, by alert id,
+    // none while computation is suspended.
+    pub fn current(&self) -> BTreeMap<String, AlertState> {
+        self.last.as_ref().map(|(_, pairs, _)| pairs.iter().map(|(id, s)| (id.clone(), *s)).collect()).unwrap_or_default()
+    }
+
+    pub fn report(&self) -> DigestReport {
+        let mut pairs: Vec<PairStats> = self.pairs.values().cloned().collect();
+        pairs.sort_by_key(|p| Reverse((p.danger_ms, p.alert_ms, p.alerts)));
+        let mut vehicles: Vec<VehicleStats> = self.vehicles.values().cloned().collect();
+        vehicles.sort_by_key(|v| Reverse((v.danger_ms, v.alert_ms, v.alerts)));
+        DigestReport { since: self.since, cycles: self.cycles, pairs, vehicles }
+    }
+}
+
+fn value(digest: &SharedDigest, instance: &Instance) -> Value {
+    let value = instance.tag(serde_json::to_value(digest.lock().unwrap().report()).unwrap());
+    Value::from(value.to_string().into_bytes()).encoding(Encoding::APP_JSON)
+}
+
+// Answers the queries on `key` with the digest, and publishes it there every
+// `period`, unless zero.
+pub async fn serve(z: Arc<Session>, key: String, digest: SharedDigest, instance: Instance, period: Duration) {
+    let key = KeyExpr::try_from(key).unwrap();
+    let queryable = z.declare_queryable(&key).res().await.unwrap();
+    let mut tick = tokio::time::interval(period.max(Duration::from_millis(1)));
+    tick.tick().await;
+    loop {
+        tokio::select! {
+            Ok(query) = queryable.recv_async() => {
+                let sample = Sample::new(key.clone(), value(&digest, &instance));
+                if let Err(e) = query.reply(Ok(sample)).res().await {
+                    warn!("Unable to reply to query on {key}: {e}");
+                }
+            },
+            _ = tick.tick(), if !period.is_zero() => {
+                if let Err(e) = z.put(&key, value(&digest, &instance)).res().await {
+                    warn!("Unable to publish the alert digest on {key}: {e}");
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use serde::Serialize;
+use crate::rules::Alert;
+use crate::vehicle::{self, VehicleState};
+use crate::Position;
+
+// Icon and z-order of the known kinds, smaller vehicles being drawn on top of
+// larger ones. Other kinds get the generic marker.
+const KIND_STYLES: [(&str, &str, u32); 8] = [
+    ("beacon", "beacon", 0),
+    ("truck", "truck", 1),
+    ("bus", "bus", 1),
+    ("tow", "tow-truck", 1),
+    ("car", "car", 2),
+    ("bicycle", "bicycle", 3),
+    ("pedestrian", "pedestrian", 4),
+    ("drone", "drone", 5),
+];
+const DEFAULT_ICON: &str = "marker";
+const DEFAULT_Z_ORDER: u32 = 2;
+// vehicles with alerts are drawn on top of the others, dangers on top of all
+const ALERT_Z_ORDER: u32 = 10;
+// Leaflet's default marker color, for vehicles without a valid one
+const DEFAULT_COLOR: &str = "#3388ff";
+const ALERT_COLOR: &str = "#ffa500";
+const DANGER_COLOR: &str = "#ff0000";
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    Normal,
+    // raising alerts, speeding, off its route or near a point of interest
+    Alert,
+    // raising danger alerts, cut-ins, closing speeds or near a hazard
+    Danger,
+}
+
+// What frontends draw for a vehicle, published on <display_key>/<id> so that
+// they need no styling logic of their own.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Display {
+    pub id: String,
+    pub position: Position,
+    pub kind: String,
+    pub state: AlertState,
+    pub icon: &'static str,
+    // #rrggbb
+    pub color: String,
+    // higher is drawn on top
+    pub z_order: u32,
+    pub label: String,
+}
+
+// The alert state of the vehicles involved in the alerts of a cycle.
+pub fn states<'a>(alerts: &'a [Alert]) -> HashMap<&'a str, AlertState> {
+    let mut states: HashMap<&'a str, AlertState> = HashMap::new();
+    let mut raise = |ids: &[&'a str], state| {
+        for &id in ids {
+            let s = states.entry(id).or_insert(state);
+            *s = (*s).max(state);
+        }
+    };
+    for a in alerts {
+        match a {
+            Alert::Distance(da) => raise(&[&da.ida, &da.idb], if da.kind.is_danger() { AlertState::Danger } else { AlertState::Alert }),
+            Alert::CutIn(ca) => raise(&[&ca.cutter, &ca.victim], AlertState::Danger),
+            Alert::Closing(ca) => raise(&[&ca.ida, &ca.idb], AlertState::Danger),
+            Alert::Speed(sa) => raise(&[&sa.id], AlertState::Alert),
+            Alert::Route(ra) => raise(&[&ra.id], AlertState::Alert),
+            Alert::Tether(ta) => raise(&[&ta.id], AlertState::Alert),
+            Alert::Poi(pa) => raise(&[&pa.id], if pa.hazard { AlertState::Danger } else { AlertState::Alert }),
+            Alert::Sector(sa) => raise(&[&sa.id, &sa.other], if sa.danger { AlertState::Danger } else { AlertState::Alert }),
+            Alert::Battery(ba) => raise(&[&ba.id], AlertState::Alert),
+        }
+    }
+    states
+}
+
+pub fn display(v: &VehicleState, state: AlertState) -> Display {
+    let info = &v.info;
+    let (icon, z) = KIND_STYLES.iter().find(|(kind, _, _)| *kind == info.kind.as_str()).map_or((DEFAULT_ICON, DEFAULT_Z_ORDER), |(_, icon, z)| (*icon, *z));
+    let color = match state {
+        AlertState::Normal if !info.color.is_empty() => info.color.to_string(),
+        AlertState::Normal => DEFAULT_COLOR.into(),
+        AlertState::Alert => ALERT_COLOR.into(),
+        AlertState::Danger => DANGER_COLOR.into(),
+    };
+    let z_order = z + ALERT_Z_ORDER * state as u32;
+    let label = format!("{} · {:.0} km/h", info.id, v.effective_speed * 3.6);
+    Display { id: info.id.clone(), position: info.position, kind: info.kind.to_string(), state, icon, color, z_order, label }
+}
+
+// The displays last published, so that only those which changed are published
+// again.
+#[derive(Default)]
+pub struct Presenter(HashMap<String, Display>);
+
+impl Presenter {
+    // The displays of the live vehicles which changed since the last cycle,
+    // and the ids of the vehicles gone since, whose displays are deleted.
+    pub fn update(&mut self, map: &HashMap<String, VehicleState>, alerts: &[Alert]) -> (Vec<Display>, Vec<String>) {
+        let states = states(alerts);
+        let live = vehicle::live(map);
+        let gone: Vec<String> = self.0.keys().filter(|id| !live.contains_key(*id)).cloned().collect();
+        for id in &gone {
+            self.0.remove(id);
+        }
+        let mut changed = Vec::new();
+        for (id, v) in live.iter() {
+            let d = display(v, states.get(id.as_str()).copied().unwrap_or(AlertState::Normal));
+            if self.0.get(id) != Some(&d) {
+                self.0.insert(id.clone(), d.clone());
+                changed.push(d);
+            }
+        }
+        (changed, gone)
+    }
+}
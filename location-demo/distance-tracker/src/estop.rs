@@ -0,0 +1,70 @@
+use std::collections::{BTreeMap, BTreeSet};
+use serde::Serialize;
+use serde_json::Value;
+use tracing::{info, warn};
+use crate::publish::Outgoing;
+use crate::qos::Level;
+use crate::rules::Alert;
+use crate::{AlertKind, AlertLevel};
+
+// The default payload of the commands, {"id", "command", "with"}.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Command<'a> {
+    pub id: &'a str,
+    // stop or release
+    pub command: &'static str,
+    // the vehicles it is too close to, for stops
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub with: &'a [String],
+}
+
+// Stops the vehicles of the DangerMin pairs, publishing a command on
+// <key>/<id>/stop for each vehicle entering such a pair, and releases them
+// on <key>/<id>/release once they are in none. Vehicles stopped when the
+// tracker exits stay stopped.
+pub struct EmergencyStop {
+    key: String,
+    // replacing the default payloads
+    stop: Option<Value>,
+    release: Option<Value>,
+    // the stopped vehicles, with those they are too close to
+    held: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl EmergencyStop {
+    pub fn new(key: String, stop: Option<Value>, release: Option<Value>) -> Self {
+        EmergencyStop { key, stop, release, held: BTreeMap::new() }
+    }
+
+    // The commands following the alerts of a cycle, releases first, urgent.
+    pub fn update(&mut self, alerts: &[Alert]) -> Vec<Outgoing> {
+        let mut held: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        for a in alerts {
+            if let Alert::Distance(da) = a {
+                if da.kind == AlertKind::proximity(AlertLevel::Danger) {
+                    held.entry(da.ida.clone()).or_default().insert(da.idb.clone());
+                    held.entry(da.idb.clone()).or_default().insert(da.ida.clone());
+                }
+            }
+        }
+        let mut commands = Vec::new();
+        for id in self.held.keys().filter(|id| !held.contains_key(*id)) {
+            info!(target: "alert", rule = "estop", id, "RELEASE: {id}");
+            let payload = self.release.clone().unwrap_or_else(|| serde_json::to_value(Command { id, command: "release", with: &[] }).unwrap());
+            commands.push(Outgoing::json(format!("{}/{id}/release", self.key), &payload).level(Level::Danger));
+        }
+        for (id, with) in held.iter().filter(|(id, _)| !self.held.contains_key(*id)) {
+            let with: Vec<String> = with.iter().cloned().collect();
+            warn!(target: "alert", rule = "estop", id, "STOP: {id}, too close to {}", with.join(", "));
+            let payload = self.stop.clone().unwrap_or_else(|| serde_json::to_value(Command { id, command: "stop", with: &with }).unwrap());
+            commands.push(Outgoing::json(format!("{}/{id}/stop", self.key), &payload).level(Level::Danger));
+        }
+        self.held = held;
+        commands
+    }
+
+    // Releases every stopped vehicle.
+    pub fn reset(&mut self) -> Vec<Outgoing> {
+        self.update(&[])
+    }
+}
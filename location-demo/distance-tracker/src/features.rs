@@ -0,0 +1,78 @@
+use std::sync::Arc;
+use serde::Serialize;
+use tokio::sync::Mutex;
+use zenoh::prelude::r#async::*;
+use zdemo_common::Instance;
+use tracing::warn;
+use crate::rules::Rules;
+
+// An optional subsystem, `compiled` letting tooling tell the ones a build
+// leaves out, such as shared memory without the `shm` feature, apart.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Feature {
+    pub compiled: bool,
+    pub enabled: bool,
+}
+
+impl Feature {
+    fn built(enabled: bool) -> Self {
+        Feature { compiled: true, enabled }
+    }
+}
+
+// Optional subsystems of the tracker, returned by queries on the features key
+// so that dashboards only show what the deployment supports.
+#[derive(Serialize, Debug, Clone)]
+pub struct Features {
+    // speed zones, the site area and the region of interest
+    pub geofencing: Feature,
+    // alerts anticipating conflicts: cut-ins and closing speeds
+    pub prediction: Feature,
+    // state surviving restarts: delayed samples, file sinks and warm starts
+    pub persistence: Feature,
+    // alerts and telemetry forwarded outside of Zenoh: webhooks, desktop
+    // notifications and InfluxDB
+    pub gateway: Feature,
+    // publication through shared memory (--shm)
+    pub shm: Feature,
+    #[serde(skip)]
+    site_area: bool,
+    #[serde(skip)]
+    cutin: bool,
+}
+
+pub type SharedFeatures = Arc<Mutex<Features>>;
+
+impl Features {
+    pub fn new(rules: &Rules, site_area: bool, cutin_range: f64, persistence: bool, gateway: bool, shm: bool) -> Self {
+        let mut f = Features {
+            geofencing: Feature::built(false),
+            prediction: Feature::built(false),
+            persistence: Feature::built(persistence),
+            gateway: Feature::built(gateway),
+            shm: Feature { compiled: cfg!(feature = "shm"), enabled: shm },
+            site_area,
+            cutin: cutin_range > 0.0,
+        };
+        f.update(rules);
+        f
+    }
+
+    // Follows the rules changed at runtime.
+    pub fn update(&mut self, rules: &Rules) {
+        self.geofencing.enabled = self.site_area || !rules.zones.is_empty();
+        self.prediction.enabled = self.cutin || rules.closing_speed > 0.0;
+    }
+}
+
+pub async fn serve(z: Arc<Session>, key: String, features: SharedFeatures, instance: Instance) {
+    let key = KeyExpr::try_from(key).unwrap();
+    let queryable = z.declare_queryable(&key).res().await.unwrap();
+    while let Ok(query) = queryable.recv_async().await {
+        let value = instance.tag(serde_json::to_value(&*features.lock().await).unwrap());
+        let sample = Sample::new(key.clone(), Value::from(value.to_string().into_bytes()).encoding(Encoding::APP_JSON));
+        if let Err(e) = query.reply(Ok(sample)).res().await {
+            warn!("Unable to reply to query on {key}: {e}");
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+use zenoh::prelude::r#async::*;
+use tracing::{info, warn};
+use crate::digest;
+use crate::display::AlertState;
+use crate::rules::Alert;
+
+// Regions whose last summary is older than this many of their periods are
+// left out of the totals.
+const STALE_PERIODS: u64 = 3;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RiskyPair {
+    pub ida: String,
+    pub idb: String,
+    // set by the aggregator
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    pub danger: bool,
+    // m, the shortest distance of its alerts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_distance: Option<f64>,
+    // cycles of the period the pair raised alerts in
+    pub cycles: u64,
+}
+
+// Dangers first, then the closest pairs, then the longest lasting.
+fn riskiest(a: &RiskyPair, b: &RiskyPair) -> Ordering {
+    b.danger.cmp(&a.danger)
+        .then_with(|| a.min_distance.unwrap_or(f64::INFINITY).total_cmp(&b.min_distance.unwrap_or(f64::INFINITY)))
+        .then_with(|| b.cycles.cmp(&a.cycles))
+}
+
+// What a regional tracker publishes on <federation_key>/region/<region> every
+// period instead of its alerts, which stay local.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RegionSummary {
+    pub region: String,
+    // ms since the UNIX epoch
+    pub ts: u64,
+    pub period_ms: u64,
+    pub cycles: u64,
+    pub vehicles: usize,
+    // alerts raised over the cycles of the period, by rule
+    pub alerts: BTreeMap<String, u64>,
+    // pairs which raised alerts over the period, and dangers among them
+    pub alert_pairs: usize,
+    pub danger_pairs: usize,
+    pub top_pairs: Vec<RiskyPair>,
+}
+
+// The cycles of the current period.
+pub struct RegionStats {
+    region: String,
+    top: usize,
+    cycles: u64,
+    vehicles: usize,
+    alerts: BTreeMap<&'static str, u64>,
+    pairs: HashMap<String, RiskyPair>,
+}
+
+pub type SharedRegionStats = Arc<Mutex<RegionStats>>;
+
+impl RegionStats {
+    // Summaries list the `top` riskiest pairs.
+    pub fn new(region: String, top: usize) -> Self {
+        RegionStats { region, top, cycles: 0, vehicles: 0, alerts: BTreeMap::new(), pairs: HashMap::new() }
+    }
+
+    // Starts the period afresh, forgetting its cycles and pairs.
+    pub fn reset(&mut self) {
+        *self = RegionStats::new(std::mem::take(&mut self.region), self.top);
+    }
+
+    pub fn cycle(&mut self, alerts: &[Alert], vehicles: usize) {
+        self.cycles += 1;
+        self.vehicles = vehicles;
+        for a in alerts {
+            *self.alerts.entry(a.name()).or_default() += 1;
+        }
+        let mut seen = Vec::new();
+        for (ida, idb, distance, state) in alerts.iter().filter_map(digest::pair) {
+            let id = crate::geometry::alert_id(ida, idb);
+            let p = self.pairs.entry(id.clone()).or_insert_with(|| {
+                let (a, b) = if ida <= idb { (ida, idb) } else { (idb, ida) };
+                RiskyPair { ida: a.clone(), idb: b.clone(), region: None, danger: false, min_distance: None, cycles: 0 }
+            });
+            p.danger |= state == AlertState::Danger;
+            if let Some(d) = distance {
+                p.min_distance = Some(p.min_distance.map_or(d, |m| m.min(d)));
+            }
+            if !seen.contains(&id) {
+                p.cycles += 1;
+                seen.push(id);
+            }
+        }
+    }
+
+    // The summary of the period, starting the next one.
+    pub fn take(&mut self, ts: u64, period_ms: u64) -> RegionSummary {
+        let mut pairs: Vec<RiskyPair> = std::mem::take(&mut self.pairs).into_values().collect();
+        pairs.sort_by(riskiest);
+        let danger_pairs = pairs.iter().filter(|p| p.danger).count();
+        let alert_pairs = pairs.len();
+        pairs.truncate(self.top);
+        let alerts = std::mem::take(&mut self.alerts).into_iter().map(|(rule, n)| (rule.to_string(), n)).collect();
+        let cycles = std::mem::take(&mut self.cycles);
+        RegionSummary { region: self.region.clone(), ts, period_ms, cycles, vehicles: self.vehicles, alerts, alert_pairs, danger_pairs, top_pairs: pairs }
+    }
+}
+
+// Publishes the summary of the region on `key` every `period`.
+pub async fn publish(z: Arc<Session>, key: String, stats: SharedRegionStats, period: Duration) {
+    let mut tick = tokio::time::interval(period);
+    tick.tick().await;
+    loop {
+        tick.tick().await;
+        let summary = stats.lock().unwrap().take(crate::clock::unix_ms(), period.as_millis() as u64);
+        let value = Value::from(serde_json::to_vec(&summary).unwrap()).encoding(Encoding::APP_JSON);
+        if let Err(e) = z.put(&key, value).res().await {
+            warn!("Unable to publish the region summary on {key}: {e}");
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RegionStatus {
+    pub region: String,
+    // of its last summary
+    pub ts: u64,
+    pub vehicles: usize,
+    pub stale: bool,
+}
+
+// The merged summaries of the regions, published by the aggregator on
+// <federation_key>/summary.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GlobalSummary {
+    pub ts: u64,
+    pub regions: Vec<RegionStatus>,
+    // totals of the last summaries of the regions which are not stale
+    pub vehicles: usize,
+    pub alerts: BTreeMap<String, u64>,
+    pub alert_pairs: usize,
+    pub danger_pairs: usize,
+    pub top_pairs: Vec<RiskyPair>,
+}
+
+// The last summary of every region, with when it was received (unix ms).
+pub struct Aggregator {
+    top: usize,
+    regions: BTreeMap<String, (RegionSummary, u64)>,
+}
+
+impl Aggregator {
+    pub fn new(top: usize) -> Self {
+        Aggregator { top, regions: BTreeMap::new() }
+    }
+
+    pub fn receive(&mut self, summary: RegionSummary, now: u64) {
+        if !self.regions.contains_key(&summary.region) {
+            info!("FEDERATION: region {} joined", summary.region);
+        }
+        self.regions.insert(summary.region.clone(), (summary, now));
+    }
+
+    pub fn merge(&self, now: u64) -> GlobalSummary {
+        let mut global = GlobalSummary { ts: now, regions: vec![], vehicles: 0, alerts: BTreeMap::new(), alert_pairs: 0, danger_pairs: 0, top_pairs: vec![] };
+        for (region, (s, received)) in &self.regions {
+            let stale = now.saturating_sub(*received) > s.period_ms * STALE_PERIODS;
+            global.regions.push(RegionStatus { region: region.clone(), ts: s.ts, vehicles: s.vehicles, stale });
+            if stale {
+                continue;
+            }
+            global.vehicles += s.vehicles;
+            for (rule, n) in &s.alerts {
+                *global.alerts.entry(rule.clone()).or_default() += n;
+            }
+            global.alert_pairs += s.alert_pairs;
+            global.danger_pairs += s.danger_pairs;
+            global.top_pairs.extend(s.top_pairs.iter().map(|p| RiskyPair { region: Some(region.clone()), ..p.clone() }));
+        }
+        global.top_pairs.sort_by(riskiest);
+        global.top_pairs.truncate(self.top);
+        global
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use zenoh::prelude::r#async::*;
+use crate::keys::{self, KeyUse};
+
+// Where the fields of the positions of a third-party feed are, as JSON
+// pointers (RFC 6901) into its payloads: the id, lat and lng are required,
+// the speed defaults to 0 and the other fields are left out when not mapped or
+// missing from a payload.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FieldMap {
+    pub id: String,
+    pub lat: String,
+    pub lng: String,
+    #[serde(default)]
+    pub speed: Option<String>,
+    #[serde(default)]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub ts: Option<String>,
+    #[serde(default)]
+    pub min_distance: Option<String>,
+    #[serde(default)]
+    pub max_distance: Option<String>,
+}
+
+impl FieldMap {
+    fn pointers(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [("id", Some(&self.id)), ("lat", Some(&self.lat)), ("lng", Some(&self.lng)), ("speed", self.speed.as_ref()), ("kind", self.kind.as_ref()),
+            ("color", self.color.as_ref()), ("ts", self.ts.as_ref()), ("min_distance", self.min_distance.as_ref()), ("max_distance", self.max_distance.as_ref())]
+            .into_iter()
+            .filter_map(|(field, p)| p.map(|p| (field, p.as_str())))
+    }
+
+    // The payload in the current position format, {"v": 2, ...}.
+    pub fn normalize(&self, payload: &[u8]) -> Result<Vec<u8>, String> {
+        let doc: Value = serde_json::from_slice(payload).map_err(|e| e.to_string())?;
+        let mut out = Map::new();
+        out.insert("v".into(), json!(crate::ingest::PAYLOAD_VERSION));
+        let mut position = Map::new();
+        for (field, pointer) in self.pointers() {
+            let Some(value) = doc.pointer(pointer).filter(|v| !v.is_null()) else {
+                if matches!(field, "id" | "lat" | "lng") {
+                    return Err(format!("missing {field} at {pointer}"));
+                }
+                continue;
+            };
+            let value = match (field, value) {
+                // numeric ids are common in telemetry
+                ("id", Value::Number(n)) => Value::String(n.to_string()),
+                ("id" | "kind" | "color", Value::String(_)) => value.clone(),
+                ("ts", Value::Number(n)) if n.is_u64() => value.clone(),
+                ("lat" | "lng" | "speed" | "min_distance" | "max_distance", Value::Number(_)) => value.clone(),
+                _ => return Err(format!("invalid {field} at {pointer}: {value}")),
+            };
+            match field {
+                "lat" | "lng" => position.insert(field.into(), value),
+                _ => out.insert(field.into(), value),
+            };
+        }
+        out.insert("position".into(), Value::Object(position));
+        out.entry("speed").or_insert(json!(0.0));
+        Ok(serde_json::to_vec(&out).unwrap())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Feed {
+    pub key: OwnedKeyExpr,
+    pub fields: FieldMap,
+}
+
+// The third-party feeds, whose positions are normalized at reception. Positions
+// received on the keys of none of them are in the tracker's format.
+#[derive(Debug, Clone, Default)]
+pub struct Feeds(Vec<Feed>);
+
+impl Feeds {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // The key expressions of the feeds, subscribed to with the others.
+    pub fn keys(&self) -> impl Iterator<Item = String> + '_ {
+        self.0.iter().map(|f| f.key.to_string())
+    }
+
+    // The field map of the positions published on `key`, if they are those of
+    // a feed.
+    pub fn of_key(&self, key: &keyexpr) -> Option<&FieldMap> {
+        self.0.iter().find(|f| f.key.intersects(key)).map(|f| &f.fields)
+    }
+}
+
+pub fn load(path: &str) -> Result<Feeds, String> {
+    let s = std::fs::read_to_string(path).map_err(|e| format!("Unable to read {path}: {e}"))?;
+    parse(&s).map_err(|e| format!("Invalid field mapping {path}: {e}"))
+}
+
+// Parses a JSON object of {<key expression>: {id, lat, lng, speed, kind, color,
+// ts, min_distance, max_distance}}.
+pub fn parse(s: &str) -> Result<Feeds, String> {
+    let configs: BTreeMap<String, FieldMap> = serde_json::from_str(s).map_err(|e| e.to_string())?;
+    let mut feeds: Vec<Feed> = Vec::new();
+    for (key, fields) in configs {
+        keys::check("feed key", &key, KeyUse::Select)?;
+        let key = OwnedKeyExpr::new(key.as_str()).map_err(|e| format!("{key}: {e}"))?;
+        if let Some(other) = feeds.iter().find(|f| f.key.intersects(&key)) {
+            return Err(format!("keys of feeds {} and {key} overlap", other.key));
+        }
+        if let Some((field, pointer)) = fields.pointers().find(|(_, p)| !p.starts_with('/')) {
+            return Err(format!("{field} of feed {key}: '{pointer}' is not a JSON pointer, expecting /<field>[/<field>..]"));
+        }
+        feeds.push(Feed { key, fields });
+    }
+    Ok(Feeds(feeds))
+}
@@ -0,0 +1,51 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use serde::Serialize;
+use crate::geo::DistanceAlgo;
+use crate::vehicle::VehicleState;
+
+// Vehicles followed at full rate: their positions bypass the downsampling and
+// their pairs are evaluated, and their state published, on every sample.
+#[derive(Clone, Default)]
+pub struct Flagged(Arc<RwLock<HashSet<String>>>);
+
+impl Flagged {
+    pub fn contains(&self, id: &str) -> bool {
+        self.0.read().unwrap().contains(id)
+    }
+
+    // Vehicles are flagged by a put on <prefix>/<vehicle id> and unflagged by
+    // deleting the key.
+    pub fn apply(&self, prefix: &str, key: &str, delete: bool) -> Result<(String, bool), String> {
+        let id = key.strip_prefix(prefix)
+            .and_then(|s| s.strip_prefix('/'))
+            .filter(|id| !id.is_empty() && !id.contains('/'))
+            .ok_or_else(|| format!("{key} is not a flag key, expecting {prefix}/<vehicle id>"))?;
+        let mut ids = self.0.write().unwrap();
+        if delete { ids.remove(id); } else { ids.insert(id.into()); }
+        Ok((id.into(), !delete))
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Neighbour {
+    pub id: String,
+    pub distance: f64,
+}
+
+// Enriched state of a flagged vehicle.
+#[derive(Serialize, Debug)]
+pub struct Tracking<'a> {
+    #[serde(flatten)]
+    pub state: &'a VehicleState,
+    pub nearest: Option<Neighbour>,
+}
+
+pub fn tracking<'a>(map: &'a HashMap<String, VehicleState>, id: &str, algo: DistanceAlgo) -> Option<Tracking<'a>> {
+    let state = map.get(id)?;
+    let nearest = map.iter()
+        .filter(|(oid, _)| oid.as_str() != id)
+        .map(|(oid, ov)| Neighbour { id: oid.clone(), distance: algo.distance(&state.info.position, &ov.info.position) })
+        .min_by(|a, b| a.distance.total_cmp(&b.distance));
+    Some(Tracking { state, nearest })
+}
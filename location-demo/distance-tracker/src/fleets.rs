@@ -0,0 +1,128 @@
+use std::collections::{BTreeMap, HashMap};
+use serde::Deserialize;
+use zenoh::prelude::r#async::*;
+use crate::keys::{self, KeyUse};
+use crate::vehicle::VehicleState;
+
+// An independent fleet tracked by the same process: the vehicles publishing on
+// `key` (demo/tracker/<name>/mobs/** by default), the thresholds of their
+// pairs when they differ from the tracker's, and the prefix of their alert
+// keys, <alert_prefix>/<rule> (demo/tracker/<name>/alert by default).
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct FleetConfig {
+    #[serde(default)]
+    pub key: Option<String>,
+    #[serde(default)]
+    pub alert_prefix: Option<String>,
+    // m
+    #[serde(default)]
+    pub min_distance: Option<f64>,
+    #[serde(default)]
+    pub max_distance: Option<f64>,
+    // pairs with the vehicles of other fleets raise alerts, when those fleets
+    // allow it too
+    #[serde(default)]
+    pub cross_fleet: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Fleet {
+    pub name: String,
+    pub key: OwnedKeyExpr,
+    pub alert_prefix: String,
+    pub min_distance: Option<f64>,
+    pub max_distance: Option<f64>,
+    pub cross_fleet: bool,
+}
+
+// The fleets of the tracker, none by default. Vehicles received on the key of
+// none of them belong to no fleet: they keep the tracker's thresholds and keys
+// and may pair with the vehicles of any fleet allowing cross fleet alerts.
+#[derive(Debug, Clone, Default)]
+pub struct Fleets(Vec<Fleet>);
+
+impl Fleets {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Fleet> {
+        self.0.iter().find(|f| f.name == name)
+    }
+
+    // The key expressions of the positions of the fleets.
+    pub fn keys(&self) -> impl Iterator<Item = String> + '_ {
+        self.0.iter().map(|f| f.key.to_string())
+    }
+
+    // The fleet a position published on `key` belongs to.
+    pub fn of_key(&self, key: &keyexpr) -> Option<&Fleet> {
+        self.0.iter().find(|f| f.key.intersects(key))
+    }
+
+    // Whether vehicles of fleets `a` and `b` may raise pair alerts.
+    pub fn pairs(&self, a: Option<&str>, b: Option<&str>) -> bool {
+        let crosses = |f: Option<&str>| f.and_then(|f| self.get(f)).is_none_or(|f| f.cross_fleet);
+        a == b || (crosses(a) && crosses(b))
+    }
+
+    // The fleet shared by the vehicles of a pair, if any.
+    pub fn shared(&self, a: Option<&str>, b: Option<&str>) -> Option<&Fleet> {
+        (a == b).then_some(a).flatten().and_then(|f| self.get(f))
+    }
+
+    // The largest min distance (m) of the fleets, 0 without fleets.
+    pub fn max_min_distance(&self) -> f64 {
+        self.0.iter().filter_map(|f| f.min_distance).fold(0.0, f64::max)
+    }
+
+    // The key `rule` publishes the alerts of `fleet` on.
+    pub fn alert_key(&self, fleet: &str, rule: &str) -> Option<String> {
+        self.get(fleet).map(|f| format!("{}/{rule}", f.alert_prefix))
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(|f| f.name.as_str())
+    }
+}
+
+// The fleet all the vehicles `ids` belong to, if any.
+pub fn common<'a>(map: &'a HashMap<String, VehicleState>, ids: &[&str]) -> Option<&'a str> {
+    let mut fleets = ids.iter().map(|id| map.get(*id).and_then(|v| v.info.fleet.as_deref()));
+    let first = fleets.next()??;
+    fleets.all(|f| f == Some(first)).then_some(first)
+}
+
+pub fn load(path: &str) -> Result<Fleets, String> {
+    let s = std::fs::read_to_string(path).map_err(|e| format!("Unable to read {path}: {e}"))?;
+    parse(&s).map_err(|e| format!("Invalid fleets file {path}: {e}"))
+}
+
+// Parses a JSON object of {<name>: {key, alert_prefix, min_distance,
+// max_distance, cross_fleet}}, names being single key chunks.
+pub fn parse(s: &str) -> Result<Fleets, String> {
+    let configs: BTreeMap<String, FleetConfig> = serde_json::from_str(s).map_err(|e| e.to_string())?;
+    let mut fleets: Vec<Fleet> = Vec::new();
+    for (name, c) in configs {
+        keys::check(&format!("fleet '{name}'"), &name, KeyUse::Concrete)?;
+        if name.contains('/') {
+            return Err(format!("fleet name '{name}' must be a single key chunk"));
+        }
+        for d in [c.min_distance, c.max_distance].into_iter().flatten() {
+            if !(d.is_finite() && d >= 0.0) {
+                return Err(format!("distances of fleet '{name}' must be non negative"));
+            }
+        }
+        let key = c.key.unwrap_or(format!("demo/tracker/{name}/mobs/**"));
+        keys::check(&format!("key of fleet '{name}'"), &key, KeyUse::Select)?;
+        let key = OwnedKeyExpr::new(key.as_str()).map_err(|e| format!("{key}: {e}"))?;
+        if let Some(other) = fleets.iter().find(|f| f.key.intersects(&key)) {
+            return Err(format!("keys of fleets '{}' and '{name}' overlap", other.name));
+        }
+        let alert_prefix = c.alert_prefix.unwrap_or(format!("demo/tracker/{name}/alert"));
+        keys::check(&format!("alert prefix of fleet '{name}'"), &alert_prefix, KeyUse::Concrete)?;
+        fleets.push(Fleet { name, key, alert_prefix, min_distance: c.min_distance, max_distance: c.max_distance, cross_fleet: c.cross_fleet });
+    }
+    Ok(Fleets(fleets))
+}
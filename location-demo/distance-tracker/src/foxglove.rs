@@ -0,0 +1,230 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_tungstenite::tungstenite::handshake::server::{Callback, ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{info, warn};
+use crate::geojson;
+use crate::mcap;
+use crate::publish::Outgoing;
+use crate::rules::Alert;
+use crate::vehicle::{self, VehicleState};
+
+// The Foxglove WebSocket protocol, https://github.com/foxglove/ws-protocol
+const SUBPROTOCOL: &str = "foxglove.websocket.v1";
+const OP_MESSAGE_DATA: u8 = 0x01;
+// Frames a client can lag behind before missing some.
+const BACKLOG: usize = 16;
+const FLEET_TOPIC: &str = "/fleet";
+const ALERTS_TOPIC: &str = "/alerts";
+const FLEET_CHANNEL: u32 = 0;
+const ALERTS_CHANNEL: u32 = 1;
+const GEOJSON: (&str, &str) = ("foxglove.GeoJSON", r#"{"title": "foxglove.GeoJSON", "type": "object", "properties": {"geojson": {"type": "string"}}}"#);
+
+// What a compute cycle shows in Foxglove: a LocationFix per live vehicle, on
+// /vehicles/<id>, the fleet and its danger pairs as GeoJSON, on /fleet, and the
+// alerts published, on /alerts.
+pub struct Frame {
+    // ns since the UNIX epoch
+    pub ts: u64,
+    pub fixes: Vec<(String, Vec<u8>)>,
+    pub geojson: Vec<u8>,
+    pub alerts: Vec<Vec<u8>>,
+}
+
+pub type Frames = broadcast::Sender<Arc<Frame>>;
+
+pub fn frames() -> Frames {
+    broadcast::channel(BACKLOG).0
+}
+
+// The frame of a cycle, without its alerts until they are published.
+pub fn frame(map: &HashMap<String, VehicleState>, evaluated: &[Alert], ts_ms: u64) -> Frame {
+    let ts = ts_ms * 1_000_000;
+    let mut fixes: Vec<(String, Vec<u8>)> = vehicle::live(map).values()
+        .map(|v| (v.info.id.clone(), mcap::location_fix(&v.info.id, v.info.position.lat, v.info.position.lng, ts)))
+        .collect();
+    fixes.sort_by(|a, b| a.0.cmp(&b.0));
+    let geojson = serde_json::to_vec(&json!({ "geojson": geojson::feature_collection(map, evaluated).to_string() })).unwrap();
+    Frame { ts, fixes, geojson, alerts: vec![] }
+}
+
+impl Frame {
+    pub fn with_alerts(mut self, published: &[Outgoing]) -> Self {
+        self.alerts = published.iter().map(|out| serde_json::to_vec(&out.value).unwrap()).collect();
+        self
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "camelCase")]
+enum ClientOp {
+    Subscribe { subscriptions: Vec<Subscription> },
+    Unsubscribe {
+        #[serde(rename = "subscriptionIds")]
+        subscription_ids: Vec<u32>,
+    },
+    // the capabilities the server does not advertise
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct Subscription {
+    id: u32,
+    #[serde(rename = "channelId")]
+    channel_id: u32,
+}
+
+fn channel(id: u32, topic: &str, (schema_name, schema): (&str, &str)) -> Value {
+    json!({ "id": id, "topic": topic, "encoding": "json", "schemaName": schema_name, "schema": schema, "schemaEncoding": "jsonschema" })
+}
+
+fn message_data(subscription: u32, ts: u64, payload: &[u8]) -> Message {
+    let mut bs = Vec::with_capacity(13 + payload.len());
+    bs.push(OP_MESSAGE_DATA);
+    bs.extend(subscription.to_le_bytes());
+    bs.extend(ts.to_le_bytes());
+    bs.extend(payload);
+    Message::Binary(bs)
+}
+
+// The channels advertised to a client and its subscriptions to them. Vehicle
+// channels are advertised when the vehicles appear and withdrawn when they
+// leave.
+struct Client {
+    vehicles: HashMap<String, u32>,
+    next_channel: u32,
+    // subscription id by channel id
+    subscriptions: HashMap<u32, u32>,
+}
+
+impl Client {
+    // The messages showing `frame`, advertisements first.
+    fn show(&mut self, frame: &Frame) -> Vec<Message> {
+        let mut out = Vec::new();
+        let live: HashSet<&str> = frame.fixes.iter().map(|(id, _)| id.as_str()).collect();
+        let gone: Vec<u32> = self.vehicles.iter().filter(|(id, _)| !live.contains(id.as_str())).map(|(_, c)| *c).collect();
+        if !gone.is_empty() {
+            self.vehicles.retain(|id, _| live.contains(id.as_str()));
+            for c in &gone {
+                self.subscriptions.remove(c);
+            }
+            out.push(Message::Text(json!({ "op": "unadvertise", "channelIds": gone }).to_string()));
+        }
+        let mut advertised = Vec::new();
+        for (id, _) in &frame.fixes {
+            if !self.vehicles.contains_key(id) {
+                let c = self.next_channel;
+                self.next_channel += 1;
+                self.vehicles.insert(id.clone(), c);
+                advertised.push(channel(c, &format!("/vehicles/{id}"), mcap::LOCATION_FIX));
+            }
+        }
+        if !advertised.is_empty() {
+            out.push(Message::Text(json!({ "op": "advertise", "channels": advertised }).to_string()));
+        }
+        let mut send = |c: u32, payload: &[u8]| {
+            if let Some(s) = self.subscriptions.get(&c) {
+                out.push(message_data(*s, frame.ts, payload));
+            }
+        };
+        for (id, fix) in &frame.fixes {
+            send(self.vehicles[id], fix);
+        }
+        send(FLEET_CHANNEL, &frame.geojson);
+        for a in &frame.alerts {
+            send(ALERTS_CHANNEL, a);
+        }
+        out
+    }
+
+    fn apply(&mut self, op: ClientOp) {
+        match op {
+            ClientOp::Subscribe { subscriptions } => {
+                for s in subscriptions {
+                    self.subscriptions.insert(s.channel_id, s.id);
+                }
+            },
+            ClientOp::Unsubscribe { subscription_ids } => self.subscriptions.retain(|_, s| !subscription_ids.contains(s)),
+            ClientOp::Other => {},
+        }
+    }
+}
+
+// Accepts the Foxglove subprotocol when the client offers it.
+struct Negotiate;
+
+impl Callback for Negotiate {
+    fn on_request(self, req: &Request, mut resp: Response) -> Result<Response, ErrorResponse> {
+        let offered = req.headers().get("Sec-WebSocket-Protocol").and_then(|p| p.to_str().ok()).unwrap_or("");
+        if offered.split(',').any(|p| p.trim() == SUBPROTOCOL) {
+            resp.headers_mut().insert("Sec-WebSocket-Protocol", HeaderValue::from_static(SUBPROTOCOL));
+        }
+        Ok(resp)
+    }
+}
+
+// Until the client disconnects.
+async fn connect(stream: TcpStream, mut frames: broadcast::Receiver<Arc<Frame>>) {
+    let mut ws = match tokio_tungstenite::accept_hdr_async(stream, Negotiate).await {
+        Ok(ws) => ws,
+        Err(e) => return warn!("Foxglove handshake failed: {e}"),
+    };
+    let info = json!({ "op": "serverInfo", "name": "distance-tracker", "capabilities": [], "supportedEncodings": [], "metadata": {} });
+    let fixed = [channel(FLEET_CHANNEL, FLEET_TOPIC, GEOJSON), channel(ALERTS_CHANNEL, ALERTS_TOPIC, mcap::ALERT)];
+    for m in [info, json!({ "op": "advertise", "channels": fixed })] {
+        if ws.send(Message::Text(m.to_string())).await.is_err() {
+            return;
+        }
+    }
+    let mut client = Client { vehicles: HashMap::new(), next_channel: ALERTS_CHANNEL + 1, subscriptions: HashMap::new() };
+    loop {
+        tokio::select! {
+            f = frames.recv() => match f {
+                Ok(frame) => {
+                    for m in client.show(&frame) {
+                        if ws.feed(m).await.is_err() {
+                            return;
+                        }
+                    }
+                    if ws.flush().await.is_err() {
+                        return;
+                    }
+                },
+                // the next frame shows the whole fleet again
+                Err(RecvError::Lagged(_)) => {},
+                Err(RecvError::Closed) => break,
+            },
+            m = ws.next() => match m {
+                Some(Ok(Message::Text(t))) => match serde_json::from_str::<ClientOp>(&t) {
+                    Ok(op) => client.apply(op),
+                    Err(e) => warn!("invalid Foxglove client message: {e}"),
+                },
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {},
+            },
+        }
+    }
+    let _ = ws.close(None).await;
+}
+
+// Serves the fleet to Foxglove Studio on ws://0.0.0.0:<port>, each client
+// getting the frames of the compute cycles while connected.
+pub async fn serve(port: u16, frames: Frames) {
+    let listener = TcpListener::bind(("0.0.0.0", port)).await.unwrap_or_else(|e| panic!("--foxglove-port: {e}"));
+    info!("Foxglove bridge listening on ws://0.0.0.0:{port}");
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(connect(stream, frames.subscribe()));
+            },
+            Err(e) => warn!("Foxglove bridge: {e}"),
+        }
+    }
+}
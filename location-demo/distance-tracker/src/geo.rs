@@ -0,0 +1,498 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer, Deserialize};
+
+pub const EARTH_RADIUS: f64 = 6371.0;
+// Decimals kept when serializing coordinates, 1e-7 degrees is about 1 cm.
+pub const COORD_DECIMALS: i32 = 7;
+
+#[derive(clap_derive::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Coordinates {
+    /// WGS-84 latitude and longitude, in degrees
+    #[default]
+    Geographic,
+    /// x (east) and y (north) meters in a local frame, as given by indoor positioning
+    /// systems, with plain Euclidean geometry
+    Local,
+}
+
+// Set once at startup, before any position is received.
+static LOCAL: AtomicBool = AtomicBool::new(false);
+
+pub fn set_coordinates(c: Coordinates) {
+    LOCAL.store(c == Coordinates::Local, Ordering::Relaxed);
+}
+
+// Whether positions are x/y meters in a local frame, y being held in lat and
+// x in lng.
+pub fn is_local() -> bool {
+    LOCAL.load(Ordering::Relaxed)
+}
+
+// Coordinates are computed in f64 but serialized rounded, so that payloads do
+// not carry noise digits, as {x, y} in a local frame. f32 payloads deserialize
+// unchanged, and {x, y} positions are accepted in either mode.
+#[derive (Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct Position {
+    #[serde(alias = "y")]
+    pub lat: f64,
+    #[serde(alias = "x")]
+    pub lng: f64
+}
+
+fn round_coord(v: f64) -> f64 {
+    let scale = 10_f64.powi(COORD_DECIMALS);
+    (v * scale).round() / scale
+}
+
+impl Serialize for Position {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let mut p = s.serialize_struct("Position", 2)?;
+        if is_local() {
+            p.serialize_field("x", &round_coord(self.lng))?;
+            p.serialize_field("y", &round_coord(self.lat))?;
+        } else {
+            p.serialize_field("lat", &round_coord(self.lat))?;
+            p.serialize_field("lng", &round_coord(self.lng))?;
+        }
+        p.end()
+    }
+}
+
+impl Position {
+    // Distance in meters, great circle unless in a local frame.
+    pub fn distance_haverside(&self, other: &Position) -> f64 {
+        if is_local() {
+            return (other.lng - self.lng).hypot(other.lat - self.lat);
+        }
+        let c_lat = self.lat.to_radians();
+        let o_lat = other.lat.to_radians();
+
+        let delta_lat = (other.lat - self.lat).to_radians();
+        let delta_lng = (other.lng - self.lng).to_radians();
+
+        let central_angle_inner = (delta_lat / 2.0).sin().powi(2)
+            + c_lat.cos() * o_lat.cos() * (delta_lng / 2.0).sin().powi(2);
+
+        let central_angle = 2.0 * central_angle_inner.sqrt().asin();
+        EARTH_RADIUS * central_angle * 1000.0 // distance in meters
+    }
+
+    // Initial bearing towards other, in degrees clockwise from north [0, 360).
+    pub fn bearing(&self, other: &Position) -> f64 {
+        if is_local() {
+            return (other.lng - self.lng).atan2(other.lat - self.lat).to_degrees().rem_euclid(360.0);
+        }
+        let c_lat = self.lat.to_radians();
+        let o_lat = other.lat.to_radians();
+        let delta_lng = (other.lng - self.lng).to_radians();
+        let y = delta_lng.sin() * o_lat.cos();
+        let x = c_lat.cos() * o_lat.sin() - c_lat.sin() * o_lat.cos() * delta_lng.cos();
+        y.atan2(x).to_degrees().rem_euclid(360.0)
+    }
+
+    // The point reached travelling `distance` meters along the great circle
+    // leaving with `bearing` (degrees clockwise from north).
+    pub fn destination(&self, bearing: f64, distance: f64) -> Position {
+        if is_local() {
+            let (sin_b, cos_b) = bearing.to_radians().sin_cos();
+            return Position { lat: self.lat + distance * cos_b, lng: self.lng + distance * sin_b };
+        }
+        let lat = self.lat.to_radians();
+        let (sin_b, cos_b) = bearing.to_radians().sin_cos();
+        let (sin_d, cos_d) = (distance / (EARTH_RADIUS * 1000.0)).sin_cos();
+        let dest_lat = (lat.sin() * cos_d + lat.cos() * sin_d * cos_b).asin();
+        let delta_lng = (sin_b * sin_d * lat.cos()).atan2(cos_d - lat.sin() * dest_lat.sin());
+        Position {
+            lat: dest_lat.to_degrees(),
+            // normalized in [-180, 180)
+            lng: (self.lng + delta_lng.to_degrees() + 180.0).rem_euclid(360.0) - 180.0,
+        }
+    }
+
+    // Point halfway along the great circle to other.
+    pub fn midpoint(&self, other: &Position) -> Position {
+        if is_local() {
+            return Position { lat: (self.lat + other.lat) / 2.0, lng: (self.lng + other.lng) / 2.0 };
+        }
+        let (c_lat, o_lat) = (self.lat.to_radians(), other.lat.to_radians());
+        let delta_lng = (other.lng - self.lng).to_radians();
+        let bx = o_lat.cos() * delta_lng.cos();
+        let by = o_lat.cos() * delta_lng.sin();
+        let lat = (c_lat.sin() + o_lat.sin()).atan2(((c_lat.cos() + bx).powi(2) + by * by).sqrt());
+        let delta = by.atan2(c_lat.cos() + bx);
+        Position {
+            lat: lat.to_degrees(),
+            lng: (self.lng + delta.to_degrees() + 180.0).rem_euclid(360.0) - 180.0,
+        }
+    }
+
+    // Signed distance (m) from the great circle through start and end, positive
+    // on its right when looking towards end.
+    pub fn cross_track_distance(&self, start: &Position, end: &Position) -> f64 {
+        if is_local() {
+            let (dx, dy) = (end.lng - start.lng, end.lat - start.lat);
+            let (px, py) = (self.lng - start.lng, self.lat - start.lat);
+            // a line of no length runs north, as on the sphere
+            let len = dx.hypot(dy);
+            return if len > 0.0 { (dy * px - dx * py) / len } else { px };
+        }
+        let r = EARTH_RADIUS * 1000.0;
+        let angular = start.distance_haverside(self) / r;
+        let delta_bearing = (start.bearing(self) - start.bearing(end)).to_radians();
+        (angular.sin() * delta_bearing.sin()).asin() * r
+    }
+}
+
+// Signed difference a - b between two angles in degrees, in (-180, 180].
+pub fn angle_diff(a: f64, b: f64) -> f64 {
+    let d = (a - b).rem_euclid(360.0);
+    if d > 180.0 { d - 360.0 } else { d }
+}
+
+// WGS-84 ellipsoid
+const WGS84_A: f64 = 6378137.0;
+const WGS84_F: f64 = 1.0 / 298.257223563;
+const WGS84_B: f64 = WGS84_A * (1.0 - WGS84_F);
+const VINCENTY_MAX_ITERATIONS: usize = 200;
+
+#[derive(clap_derive::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DistanceAlgo {
+    /// Great circle distance on a spherical earth
+    Haversine,
+    /// Geodesic distance on the WGS-84 ellipsoid
+    Vincenty,
+    /// Planar approximation in a local east-north frame, fast but only
+    /// accurate over a few kilometers
+    EuclideanEnu,
+}
+
+// What "distance" means for the rules: straight line separation for now,
+// travel distance along roads for a road network model. Models are shared by
+// the threads of the tracker, and may give up on a pair (nearly antipodal
+// points, positions off the road network), which then gets the great circle
+// distance.
+pub trait DistanceModel: Send + Sync {
+    fn name(&self) -> &'static str;
+    // Distance in meters.
+    fn distance(&self, a: &Position, b: &Position) -> Option<f64>;
+}
+
+pub struct Haversine;
+pub struct Vincenty;
+pub struct EuclideanEnu;
+
+impl DistanceModel for Haversine {
+    fn name(&self) -> &'static str { "haversine" }
+
+    fn distance(&self, a: &Position, b: &Position) -> Option<f64> {
+        Some(a.distance_haverside(b))
+    }
+}
+
+impl DistanceModel for Vincenty {
+    fn name(&self) -> &'static str { "vincenty" }
+
+    fn distance(&self, a: &Position, b: &Position) -> Option<f64> {
+        vincenty(a.lat, a.lng, b.lat, b.lng)
+    }
+}
+
+impl DistanceModel for EuclideanEnu {
+    fn name(&self) -> &'static str { "euclidean-enu" }
+
+    fn distance(&self, a: &Position, b: &Position) -> Option<f64> {
+        Some(enu_distance(a.lat, a.lng, b.lat, b.lng))
+    }
+}
+
+impl DistanceAlgo {
+    // The model selected with --distance-algo. A model needing data loaded at
+    // startup (a road graph) would be registered once, like the coordinates.
+    pub fn model(self) -> &'static dyn DistanceModel {
+        match self {
+            DistanceAlgo::Haversine => &Haversine,
+            DistanceAlgo::Vincenty => &Vincenty,
+            DistanceAlgo::EuclideanEnu => &EuclideanEnu,
+        }
+    }
+
+    // Distance in meters.
+    pub fn distance(&self, a: &Position, b: &Position) -> f64 {
+        if is_local() {
+            return a.distance_haverside(b);
+        }
+        self.model().distance(a, b).unwrap_or_else(|| a.distance_haverside(b))
+    }
+}
+
+// Vincenty's inverse formula. Returns None when the iteration does not
+// converge, which happens for nearly antipodal points.
+pub fn vincenty(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> Option<f64> {
+    let l = (lng2 - lng1).to_radians();
+    let u1 = ((1.0 - WGS84_F) * lat1.to_radians().tan()).atan();
+    let u2 = ((1.0 - WGS84_F) * lat2.to_radians().tan()).atan();
+    let (sin_u1, cos_u1) = u1.sin_cos();
+    let (sin_u2, cos_u2) = u2.sin_cos();
+
+    let mut lambda = l;
+    for _ in 0..VINCENTY_MAX_ITERATIONS {
+        let (sin_lambda, cos_lambda) = lambda.sin_cos();
+        let sin_sigma = ((cos_u2 * sin_lambda).powi(2)
+            + (cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_lambda).powi(2)).sqrt();
+        if sin_sigma == 0.0 {
+            return Some(0.0); // coincident points
+        }
+        let cos_sigma = sin_u1 * sin_u2 + cos_u1 * cos_u2 * cos_lambda;
+        let sigma = sin_sigma.atan2(cos_sigma);
+        let sin_alpha = cos_u1 * cos_u2 * sin_lambda / sin_sigma;
+        let cos2_alpha = 1.0 - sin_alpha * sin_alpha;
+        // equatorial line: cos2_alpha = 0
+        let cos_2sigma_m = if cos2_alpha != 0.0 { cos_sigma - 2.0 * sin_u1 * sin_u2 / cos2_alpha } else { 0.0 };
+        let c = WGS84_F / 16.0 * cos2_alpha * (4.0 + WGS84_F * (4.0 - 3.0 * cos2_alpha));
+        let prev = lambda;
+        lambda = l + (1.0 - c) * WGS84_F * sin_alpha
+            * (sigma + c * sin_sigma * (cos_2sigma_m + c * cos_sigma * (-1.0 + 2.0 * cos_2sigma_m * cos_2sigma_m)));
+        if (lambda - prev).abs() < 1e-12 {
+            let u_sq = cos2_alpha * (WGS84_A * WGS84_A - WGS84_B * WGS84_B) / (WGS84_B * WGS84_B);
+            let a = 1.0 + u_sq / 16384.0 * (4096.0 + u_sq * (-768.0 + u_sq * (320.0 - 175.0 * u_sq)));
+            let b = u_sq / 1024.0 * (256.0 + u_sq * (-128.0 + u_sq * (74.0 - 47.0 * u_sq)));
+            let delta_sigma = b * sin_sigma * (cos_2sigma_m + b / 4.0
+                * (cos_sigma * (-1.0 + 2.0 * cos_2sigma_m * cos_2sigma_m)
+                    - b / 6.0 * cos_2sigma_m * (-3.0 + 4.0 * sin_sigma * sin_sigma) * (-3.0 + 4.0 * cos_2sigma_m * cos_2sigma_m)));
+            return Some(WGS84_B * a * (sigma - delta_sigma));
+        }
+    }
+    None
+}
+
+// Projects b in the east-north plane tangent at the midpoint of a and b.
+pub fn enu_distance(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> f64 {
+    let r = EARTH_RADIUS * 1000.0;
+    let mid_lat = ((lat1 + lat2) / 2.0).to_radians();
+    let mut d_lng = lng2 - lng1;
+    if d_lng > 180.0 { d_lng -= 360.0 } else if d_lng < -180.0 { d_lng += 360.0 }
+    let east = d_lng.to_radians() * mid_lat.cos() * r;
+    let north = (lat2 - lat1).to_radians() * r;
+    east.hypot(north)
+}
+
+// East-north coordinates (m) of q in the plane tangent at origin.
+pub fn enu(origin: &Position, q: &Position) -> (f64, f64) {
+    if is_local() {
+        return (q.lng - origin.lng, q.lat - origin.lat);
+    }
+    let r = EARTH_RADIUS * 1000.0;
+    let mut d_lng = q.lng - origin.lng;
+    if d_lng > 180.0 { d_lng -= 360.0 } else if d_lng < -180.0 { d_lng += 360.0 }
+    (d_lng.to_radians() * origin.lat.to_radians().cos() * r, (q.lat - origin.lat).to_radians() * r)
+}
+
+// Distance (m) from p to the closest point of the polyline, None if it has no
+// points. Segments are straight in the plane tangent at p.
+pub fn distance_to_polyline(p: &Position, line: &[Position]) -> Option<f64> {
+    let pts: Vec<(f64, f64)> = line.iter().map(|q| enu(p, q)).collect();
+    let to_segment = |(ax, ay): (f64, f64), (bx, by): (f64, f64)| {
+        let (dx, dy) = (bx - ax, by - ay);
+        let len2 = dx * dx + dy * dy;
+        let t = if len2 > 0.0 { (-(ax * dx + ay * dy) / len2).clamp(0.0, 1.0) } else { 0.0 };
+        (ax + t * dx).hypot(ay + t * dy)
+    };
+    match pts.as_slice() {
+        [] => None,
+        [only] => Some(only.0.hypot(only.1)),
+        _ => pts.windows(2).map(|w| to_segment(w[0], w[1])).min_by(|a, b| a.total_cmp(b)),
+    }
+}
+
+// Whether p is inside the polygon of [lat, lng] vertices, by ray casting. Edges
+// are considered straight in the lat/lng plane which is fine for areas of a few
+// kilometers.
+pub fn in_polygon(polygon: &[[f64; 2]], p: &Position) -> bool {
+    let Some(mut j) = polygon.len().checked_sub(1) else { return false };
+    let mut inside = false;
+    for i in 0..polygon.len() {
+        let ([lat_i, lng_i], [lat_j, lng_j]) = (polygon[i], polygon[j]);
+        if (lat_i > p.lat) != (lat_j > p.lat)
+            && p.lng < (lng_j - lng_i) * (p.lat - lat_i) / (lat_j - lat_i) + lng_i {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+// The points of the polyline which Ramer-Douglas-Peucker keeps: those farther
+// than `tolerance` (m) from the simplified line, the ends always being kept.
+pub fn simplify(line: &[Position], tolerance: f64) -> Vec<Position> {
+    if line.len() < 3 {
+        return line.to_vec();
+    }
+    let mut kept = vec![false; line.len()];
+    kept[0] = true;
+    kept[line.len() - 1] = true;
+    let mut spans = vec![(0, line.len() - 1)];
+    while let Some((first, last)) = spans.pop() {
+        let segment = [line[first], line[last]];
+        let farthest = (first + 1..last)
+            .map(|i| (i, distance_to_polyline(&line[i], &segment).unwrap()))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((i, d)) = farthest {
+            if d > tolerance {
+                kept[i] = true;
+                spans.push((first, i));
+                spans.push((i, last));
+            }
+        }
+    }
+    line.iter().zip(kept).filter(|(_, k)| *k).map(|(p, _)| *p).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const TURIN: Position = Position { lat: 45.0703, lng: 7.6869 };
+
+    fn close(a: f64, b: f64, tolerance: f64) -> bool {
+        (a - b).abs() <= tolerance
+    }
+
+    #[test]
+    fn haversine_paris_london() {
+        let paris = Position { lat: 48.8566, lng: 2.3522 };
+        let london = Position { lat: 51.5074, lng: -0.1278 };
+        assert!(close(paris.distance_haverside(&london), 343_556.06, 0.01));
+    }
+
+    #[test]
+    fn haversine_resolves_sub_meter_steps() {
+        // 1e-5 degrees of latitude; with f32 coordinates this came out as 0.85 m
+        let north = Position { lat: 45.07031, lng: 7.6869 };
+        assert!(close(TURIN.distance_haverside(&north), 1.111_949, 1e-6));
+        let east = Position { lat: 45.0703, lng: 7.68691 };
+        assert!(close(TURIN.distance_haverside(&east), 0.785_302, 1e-6));
+    }
+
+    #[test]
+    fn vincenty_flinders_peak_buninyong() {
+        // reference pair from Vincenty's 1975 paper
+        let d = vincenty(-37.95103342, 144.42486789, -37.65282114, 143.92649554).unwrap();
+        assert!(close(d, 54_972.271, 0.001));
+    }
+
+    #[test]
+    fn vincenty_nearly_antipodal_falls_back() {
+        assert!(vincenty(0.0, 0.0, 0.5, 179.7).is_none());
+        let a = Position { lat: 0.0, lng: 0.0 };
+        let b = Position { lat: 0.5, lng: 179.7 };
+        assert_eq!(DistanceAlgo::Vincenty.distance(&a, &b), a.distance_haverside(&b));
+    }
+
+    #[test]
+    fn enu_matches_haversine_at_short_range() {
+        let other = Position { lat: 45.0712, lng: 7.6881 };
+        let h = DistanceAlgo::Haversine.distance(&TURIN, &other);
+        let e = DistanceAlgo::EuclideanEnu.distance(&TURIN, &other);
+        assert!(close(h, e, 0.01));
+    }
+
+    #[test]
+    fn bearing_cardinal_points() {
+        assert!(close(TURIN.bearing(&Position { lat: 45.08, lng: 7.6869 }), 0.0, 1e-9));
+        assert!(close(TURIN.bearing(&Position { lat: 45.0703, lng: 7.69 }), 90.0, 0.01));
+        assert!(close(TURIN.bearing(&Position { lat: 45.06, lng: 7.6869 }), 180.0, 1e-9));
+    }
+
+    #[test]
+    fn destination_inverts_distance_and_bearing() {
+        let d = TURIN.destination(60.0, 12_345.0);
+        assert!(close(TURIN.distance_haverside(&d), 12_345.0, 1e-6));
+        assert!(close(TURIN.bearing(&d), 60.0, 1e-9));
+        // a degree of latitude due north
+        let north = TURIN.destination(0.0, EARTH_RADIUS * 1000.0 * 1_f64.to_radians());
+        assert!(close(north.lat, 46.0703, 1e-9));
+        assert!(close(north.lng, TURIN.lng, 1e-9));
+    }
+
+    #[test]
+    fn destination_wraps_the_antimeridian() {
+        let fiji = Position { lat: -17.0, lng: 179.9 };
+        let d = fiji.destination(90.0, 50_000.0);
+        assert!(d.lng < -179.0);
+        assert!(close(fiji.distance_haverside(&d), 50_000.0, 1e-6));
+    }
+
+    #[test]
+    fn cross_track_distance_is_signed() {
+        let start = TURIN;
+        let end = TURIN.destination(0.0, 10_000.0);
+        let right = TURIN.destination(0.0, 5_000.0).destination(90.0, 100.0);
+        let left = TURIN.destination(0.0, 5_000.0).destination(270.0, 100.0);
+        assert!(close(right.cross_track_distance(&start, &end), 100.0, 0.01));
+        assert!(close(left.cross_track_distance(&start, &end), -100.0, 0.01));
+        assert!(close(end.cross_track_distance(&start, &end), 0.0, 1e-6));
+    }
+
+    #[test]
+    fn angle_diff_wraps() {
+        assert_eq!(angle_diff(10.0, 350.0), 20.0);
+        assert_eq!(angle_diff(350.0, 10.0), -20.0);
+        assert_eq!(angle_diff(180.0, 0.0), 180.0);
+    }
+
+    #[test]
+    fn deserializes_f32_payloads() {
+        // as emitted by publishers still using f32, widened or not
+        let p: Position = serde_json::from_str(r#"{"lat":45.0703,"lng":7.6869}"#).unwrap();
+        assert_eq!(p, TURIN);
+        let p: Position = serde_json::from_str(r#"{"lat":45.0703010559082,"lng":7.6869001388549805}"#).unwrap();
+        assert_eq!(p.lat, 45.0703_f32 as f64);
+        assert_eq!(p.lng, 7.6869_f32 as f64);
+    }
+
+    #[test]
+    fn serializes_rounded_coordinates() {
+        let p = Position { lat: 45.070_301_055_908_2, lng: -7.686_900_138_854_98 };
+        assert_eq!(serde_json::to_string(&p).unwrap(), r#"{"lat":45.0703011,"lng":-7.6869001}"#);
+        let back: Position = serde_json::from_str(&serde_json::to_string(&TURIN).unwrap()).unwrap();
+        assert_eq!(back, TURIN);
+    }
+
+    fn position() -> impl Strategy<Value = Position> {
+        (-90.0..=90.0, -180.0..180.0).prop_map(|(lat, lng)| Position { lat, lng })
+    }
+
+    // Half the circumference, m
+    const ANTIPODAL: f64 = std::f64::consts::PI * EARTH_RADIUS * 1000.0;
+
+    proptest! {
+        #[test]
+        fn haversine_is_zero_to_itself(a in position()) {
+            prop_assert_eq!(a.distance_haverside(&a), 0.0);
+        }
+
+        #[test]
+        fn haversine_is_symmetric(a in position(), b in position()) {
+            let (ab, ba) = (a.distance_haverside(&b), b.distance_haverside(&a));
+            prop_assert!(close(ab, ba, 1e-9 * ab.max(1.0)), "{ab} != {ba}");
+        }
+
+        #[test]
+        fn haversine_satisfies_the_triangle_inequality(a in position(), b in position(), c in position()) {
+            let (ac, ab, bc) = (a.distance_haverside(&c), a.distance_haverside(&b), b.distance_haverside(&c));
+            // the rounding of asin near antipodes is about 0.1 m
+            prop_assert!(ac <= ab + bc + 1.0, "{ac} > {ab} + {bc}");
+        }
+
+        #[test]
+        fn haversine_is_bounded_by_antipodes(a in position(), b in position()) {
+            let d = a.distance_haverside(&b);
+            prop_assert!((0.0..=ANTIPODAL).contains(&d), "{d}");
+            let antipode = Position { lat: -a.lat, lng: a.lng + 180.0 };
+            prop_assert!(close(a.distance_haverside(&antipode), ANTIPODAL, 1.0));
+        }
+    }
+}
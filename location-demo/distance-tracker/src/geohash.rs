@@ -0,0 +1,119 @@
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+const BASE32: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+pub const MAX_CELLS: usize = 1024;
+
+#[derive(Debug, Clone, Copy)]
+pub struct BoundingBox {
+    pub lat_min: f64,
+    pub lng_min: f64,
+    pub lat_max: f64,
+    pub lng_max: f64,
+}
+
+impl BoundingBox {
+    pub fn contains(&self, lat: f64, lng: f64) -> bool {
+        lat >= self.lat_min && lat <= self.lat_max && lng >= self.lng_min && lng <= self.lng_max
+    }
+}
+
+// Accepts "lat_min,lng_min,lat_max,lng_max".
+impl FromStr for BoundingBox {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let v = s.split(',')
+            .map(|x| x.trim().parse::<f64>().map_err(|e| format!("invalid coordinate '{x}': {e}")))
+            .collect::<Result<Vec<f64>, String>>()?;
+        if v.len() != 4 {
+            return Err(format!("expected lat_min,lng_min,lat_max,lng_max but got '{s}'"));
+        }
+        let bb = BoundingBox { lat_min: v[0], lng_min: v[1], lat_max: v[2], lng_max: v[3] };
+        if bb.lat_min > bb.lat_max || bb.lng_min > bb.lng_max {
+            return Err(format!("empty bounding box '{s}'"));
+        }
+        if bb.lat_min < -90.0 || bb.lat_max > 90.0 || bb.lng_min < -180.0 || bb.lng_max > 180.0 {
+            return Err(format!("bounding box '{s}' is out of range"));
+        }
+        Ok(bb)
+    }
+}
+
+pub fn check(cell: &str) -> Result<(), String> {
+    if cell.is_empty() || cell.len() > 12 || !cell.bytes().all(|b| BASE32.contains(&b)) {
+        return Err(format!("invalid geohash cell '{cell}', expecting 1 to 12 of {}", std::str::from_utf8(BASE32).unwrap()));
+    }
+    Ok(())
+}
+
+pub fn encode(lat: f64, lng: f64, precision: usize) -> String {
+    let (mut lat_r, mut lng_r) = ((-90.0, 90.0), (-180.0, 180.0));
+    let mut hash = String::with_capacity(precision);
+    let (mut bits, mut ch, mut even) = (0, 0usize, true);
+    while hash.len() < precision {
+        let (range, v) = if even { (&mut lng_r, lng) } else { (&mut lat_r, lat) };
+        let mid = (range.0 + range.1) / 2.0;
+        ch <<= 1;
+        if v >= mid {
+            ch |= 1;
+            range.0 = mid;
+        } else {
+            range.1 = mid;
+        }
+        even = !even;
+        bits += 1;
+        if bits == 5 {
+            hash.push(BASE32[ch] as char);
+            bits = 0;
+            ch = 0;
+        }
+    }
+    hash
+}
+
+// The key of the position of vehicle `id` under `prefix`, the geohash cell of
+// the position at `precision` in between if any, for the trackers subscribing
+// to the cells of a region.
+pub fn position_key(prefix: &str, precision: Option<usize>, lat: f64, lng: f64, id: &str) -> String {
+    match precision {
+        Some(p) => format!("{prefix}/{}/{id}", encode(lat, lng, p)),
+        None => format!("{prefix}/{id}"),
+    }
+}
+
+pub fn check_precision(precision: usize) -> Result<(), String> {
+    if !(1..=12).contains(&precision) {
+        return Err(format!("invalid geohash precision {precision}, expecting 1 to 12"));
+    }
+    Ok(())
+}
+
+// (height, width) in degrees of a cell at the given precision.
+pub fn cell_size(precision: usize) -> (f64, f64) {
+    let bits = 5 * precision as i32;
+    let lng_bits = (bits + 1) / 2;
+    let lat_bits = bits / 2;
+    (180.0 / 2f64.powi(lat_bits), 360.0 / 2f64.powi(lng_bits))
+}
+
+// Returns the set of geohash cells at `precision` covering the bounding box.
+pub fn cover(bb: &BoundingBox, precision: usize) -> Result<BTreeSet<String>, String> {
+    let (h, w) = cell_size(precision);
+    let rows = ((bb.lat_max - bb.lat_min) / h).ceil() as usize + 1;
+    let cols = ((bb.lng_max - bb.lng_min) / w).ceil() as usize + 1;
+    if rows * cols > MAX_CELLS * 4 {
+        return Err(format!("region needs too many cells at precision {precision}, use a lower precision"));
+    }
+    let mut cells = BTreeSet::new();
+    for r in 0..rows {
+        let lat = (bb.lat_min + r as f64 * h).min(bb.lat_max);
+        for c in 0..cols {
+            let lng = (bb.lng_min + c as f64 * w).min(bb.lng_max);
+            cells.insert(encode(lat, lng, precision));
+        }
+    }
+    if cells.len() > MAX_CELLS {
+        return Err(format!("region covers {} cells at precision {precision} (max {MAX_CELLS}), use a lower precision", cells.len()));
+    }
+    Ok(cells)
+}
@@ -0,0 +1,47 @@
+use std::collections::HashMap;
+use serde_json::{json, Value};
+use crate::rules::Alert;
+use crate::vehicle::VehicleState;
+use crate::Position;
+
+fn coordinates(p: &Position) -> Value {
+    // GeoJSON orders coordinates as [lng, lat]
+    json!([p.lng, p.lat])
+}
+
+// The fleet as a GeoJSON FeatureCollection: a Point per vehicle, with the
+// vehicle state as properties, and a LineString per pair in danger, with the
+// distance alert as properties. Features are sorted so that consecutive
+// collections can be diffed.
+pub fn feature_collection(map: &HashMap<String, VehicleState>, alerts: &[Alert]) -> Value {
+    let mut ids: Vec<&String> = map.keys().collect();
+    ids.sort();
+    let vehicles = ids.into_iter().map(|id| {
+        let state = &map[id];
+        let mut properties = serde_json::to_value(state).unwrap();
+        if let Value::Object(fields) = &mut properties {
+            fields.remove("position");
+        }
+        json!({
+            "type": "Feature",
+            "id": id,
+            "geometry": { "type": "Point", "coordinates": coordinates(&state.info.position) },
+            "properties": properties,
+        })
+    });
+    let mut dangers: Vec<_> = alerts.iter()
+        .filter_map(|a| match a {
+            Alert::Distance(da) if da.kind.is_danger() => Some(da),
+            _ => None,
+        })
+        .filter_map(|da| Some((da, map.get(&da.ida)?, map.get(&da.idb)?)))
+        .collect();
+    dangers.sort_by(|x, y| (&x.0.ida, &x.0.idb).cmp(&(&y.0.ida, &y.0.idb)));
+    let pairs = dangers.into_iter().map(|(da, a, b)| json!({
+        "type": "Feature",
+        "id": format!("{}|{}", da.ida, da.idb),
+        "geometry": { "type": "LineString", "coordinates": [coordinates(&a.info.position), coordinates(&b.info.position)] },
+        "properties": da,
+    }));
+    json!({ "type": "FeatureCollection", "features": vehicles.chain(pairs).collect::<Vec<_>>() })
+}
@@ -0,0 +1,131 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use serde::Serialize;
+use zenoh::prelude::r#async::*;
+use tracing::warn;
+use crate::matching::Matching;
+use crate::rules::Alert;
+use crate::Position;
+
+// What AR/3D clients need to draw the line between the vehicles of a danger
+// pair, published on <geometry_key>/<alert id> so that they do not recompute
+// any geodesy.
+#[derive(Serialize, Debug, Clone)]
+pub struct PairGeometry {
+    pub ida: String,
+    pub idb: String,
+    pub a: Position,
+    pub b: Position,
+    pub midpoint: Position,
+    // m
+    pub distance: f64,
+    // deg from a to b
+    pub bearing: f64,
+}
+
+// Id of the alerts of a pair, <a>~<b> with the ids in order.
+pub fn alert_id(ida: &str, idb: &str) -> String {
+    let (a, b) = if ida <= idb { (ida, idb) } else { (idb, ida) };
+    format!("{a}~{b}")
+}
+
+type Pair = (String, String);
+
+// The danger pairs of the last compute cycle and the latest positions of their
+// vehicles, which the ingest loop follows between cycles.
+#[derive(Default)]
+pub struct DangerPairs {
+    pairs: HashSet<Pair>,
+    positions: HashMap<String, Position>,
+    // pairs with a vehicle which moved since their geometry was published
+    moved: HashSet<Pair>,
+    ended: Vec<Pair>,
+}
+
+pub type SharedDangerPairs = Arc<Mutex<DangerPairs>>;
+
+impl DangerPairs {
+    // Replaces the pairs with the danger pairs among the alerts of a cycle.
+    pub fn set(&mut self, alerts: &[Alert], position: impl Fn(&str) -> Option<Position>) {
+        let pairs: HashSet<Pair> = alerts.iter().filter_map(|a| match a {
+            Alert::Distance(da) if da.kind.is_danger() => {
+                Some(if da.ida <= da.idb { (da.ida.clone(), da.idb.clone()) } else { (da.idb.clone(), da.ida.clone()) })
+            },
+            _ => None,
+        }).collect();
+        self.ended.extend(self.pairs.difference(&pairs).cloned());
+        self.ended.retain(|p| !pairs.contains(p));
+        self.positions.retain(|id, _| pairs.iter().any(|(a, b)| a == id || b == id));
+        for (a, b) in &pairs {
+            for id in [a, b] {
+                if let Some(p) = position(id) {
+                    self.positions.insert(id.clone(), p);
+                }
+            }
+        }
+        self.moved = pairs.clone();
+        self.pairs = pairs;
+    }
+
+    // Follows a new position of a vehicle, if it is in a danger pair.
+    pub fn moved(&mut self, id: &str, position: Position) {
+        let Some(p) = self.positions.get_mut(id) else { return };
+        *p = position;
+        for pair in self.pairs.iter().filter(|(a, b)| a == id || b == id) {
+            if !self.moved.contains(pair) {
+                self.moved.insert(pair.clone());
+            }
+        }
+    }
+
+    // Geometries of the pairs which moved since the last call, and the ids of
+    // the pairs which are no longer in danger.
+    fn take(&mut self) -> (Vec<PairGeometry>, Vec<String>) {
+        let geometries = self.moved.drain().filter_map(|(ida, idb)| {
+            let (a, b) = (*self.positions.get(&ida)?, *self.positions.get(&idb)?);
+            Some(PairGeometry {
+                a,
+                b,
+                midpoint: a.midpoint(&b),
+                distance: a.distance_haverside(&b),
+                bearing: a.bearing(&b),
+                ida,
+                idb,
+            })
+        }).collect();
+        let ended = self.ended.drain(..).map(|(a, b)| alert_id(&a, &b)).collect();
+        (geometries, ended)
+    }
+}
+
+// Publishes the geometry of the danger pairs which moved every `period`, while
+// it has subscribers, and deletes the geometry of the pairs no longer in
+// danger.
+pub async fn publish(z: Arc<Session>, prefix: String, pairs: SharedDangerPairs, period: Duration, matching: Matching) {
+    let mut tick = tokio::time::interval(period);
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    loop {
+        tick.tick().await;
+        let (geometries, ended) = pairs.lock().unwrap().take();
+        if !matching.any() {
+            continue;
+        }
+        for g in geometries {
+            let key = format!("{prefix}/{}", alert_id(&g.ida, &g.idb));
+            if keyexpr::new(&key).is_err() {
+                continue;
+            }
+            let value = Value::from(serde_json::to_vec(&g).unwrap()).encoding(Encoding::APP_JSON);
+            if let Err(e) = z.put(&key, value).congestion_control(CongestionControl::Drop).res().await {
+                warn!("Unable to publish the geometry on {key}: {e}");
+            }
+        }
+        for id in ended {
+            let key = format!("{prefix}/{id}");
+            if keyexpr::new(&key).is_ok() {
+                let _ = z.delete(&key).res().await;
+            }
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::pin::Pin;
+use futures_util::{stream, Stream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::oneshot;
+use tonic::{Request, Response, Status};
+use tracing::{error, info};
+use crate::admin::{SetRequest, Thresholds, Versioned};
+use crate::http::{AlertFilter, RecentAlert, SharedHistory};
+use crate::vehicle::VehicleState;
+use crate::VehicleMap;
+
+pub mod proto {
+    tonic::include_proto!("distance_tracker.v1");
+}
+
+use proto::tracker_server::{Tracker, TrackerServer};
+use proto::{AlertEvent, GetVehiclesRequest, GetVehiclesResponse, SetThresholdsResponse, WatchAlertsRequest};
+
+// The HTTP API of the tracker as a gRPC service, sharing its state.
+struct Service {
+    map: VehicleMap,
+    history: SharedHistory,
+    config: UnboundedSender<SetRequest>,
+}
+
+fn vehicle(v: &VehicleState) -> proto::Vehicle {
+    let info = &v.info;
+    proto::Vehicle {
+        id: info.id.clone(),
+        kind: info.kind.to_string(),
+        color: info.color.to_string(),
+        lat: info.position.lat,
+        lng: info.position.lng,
+        speed: info.speed,
+        implied_speed: v.implied_speed,
+        effective_speed: v.effective_speed,
+        heading: v.heading,
+        quality: format!("{:?}", v.quality),
+        fleet: info.fleet.clone(),
+        ts: info.ts,
+        stale: v.stale_since.is_some(),
+    }
+}
+
+fn event(a: &RecentAlert) -> AlertEvent {
+    AlertEvent { ts: a.ts, key: a.key.clone(), json: a.alert.to_string() }
+}
+
+// The fields set in `t`, as a patch of the thresholds.
+fn patch(t: &proto::Thresholds) -> serde_json::Value {
+    let fields = [
+        ("min_distance", t.min_distance),
+        ("max_distance", t.max_distance),
+        ("red_min_distance", t.red_min_distance),
+        ("closing_speed", t.closing_speed),
+        ("closing_floor", t.closing_floor),
+        ("route_corridor", t.route_corridor),
+        ("checkpoint_radius", t.checkpoint_radius),
+        ("cluster_range", t.cluster_range),
+        ("light_radius", t.light_radius),
+    ];
+    fields.into_iter().filter_map(|(name, v)| v.map(|v| (name.to_string(), v.into()))).collect::<serde_json::Map<_, _>>().into()
+}
+
+fn thresholds(t: &Thresholds) -> proto::Thresholds {
+    proto::Thresholds {
+        min_distance: Some(t.min_distance),
+        max_distance: Some(t.max_distance),
+        red_min_distance: Some(t.red_min_distance),
+        closing_speed: Some(t.closing_speed),
+        closing_floor: Some(t.closing_floor),
+        route_corridor: Some(t.route_corridor),
+        checkpoint_radius: Some(t.checkpoint_radius),
+        cluster_range: Some(t.cluster_range),
+        light_radius: Some(t.light_radius),
+    }
+}
+
+type AlertStream = Pin<Box<dyn Stream<Item = Result<AlertEvent, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Tracker for Service {
+    async fn get_vehicles(&self, req: Request<GetVehiclesRequest>) -> Result<Response<GetVehiclesResponse>, Status> {
+        let ids = req.into_inner().ids;
+        let map = self.map.snapshot();
+        let mut vehicles: Vec<proto::Vehicle> = map.values()
+            .filter(|v| ids.is_empty() || ids.contains(&v.info.id))
+            .map(vehicle)
+            .collect();
+        vehicles.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(Response::new(GetVehiclesResponse { vehicles }))
+    }
+
+    type WatchAlertsStream = AlertStream;
+
+    async fn watch_alerts(&self, req: Request<WatchAlertsRequest>) -> Result<Response<AlertStream>, Status> {
+        let req = req.into_inner();
+        let filter = AlertFilter { id: req.id, severity: req.severity };
+        let alerts = self.history.lock().unwrap().subscribe();
+        let events = stream::unfold((alerts, filter), |(mut alerts, filter)| async move {
+            loop {
+                match alerts.recv().await {
+                    Ok(a) if filter.accepts(&a) => return Some((Ok(event(&a)), (alerts, filter))),
+                    Ok(_) | Err(RecvError::Lagged(_)) => {},
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(events)))
+    }
+
+    async fn set_thresholds(&self, req: Request<proto::Thresholds>) -> Result<Response<SetThresholdsResponse>, Status> {
+        let payload = serde_json::to_vec(&patch(req.get_ref())).unwrap();
+        let (reply, rx) = oneshot::channel();
+        let _ = self.config.send(SetRequest { section: "rules".into(), payload, reply });
+        match rx.await {
+            Ok(Ok(bs)) => {
+                let v: Versioned<Thresholds> = serde_json::from_slice(&bs).map_err(|e| Status::internal(e.to_string()))?;
+                Ok(Response::new(SetThresholdsResponse { thresholds: Some(thresholds(&v.value)), version_time: v.version.time, version_origin: v.version.origin }))
+            },
+            Ok(Err(e)) => Err(Status::invalid_argument(e)),
+            Err(_) => Err(Status::unavailable("the configuration is not followed")),
+        }
+    }
+}
+
+// Serves the tracker service of proto/tracker.proto on 0.0.0.0:<port>, for
+// backends integrating the tracker with their gRPC tooling.
+pub async fn serve(port: u16, map: VehicleMap, history: SharedHistory, config: UnboundedSender<SetRequest>) {
+    let service = TrackerServer::new(Service { map, history, config });
+    let listener = tokio::net::TcpListener::bind(("0.0.0.0", port)).await.unwrap_or_else(|e| panic!("--grpc-port: {e}"));
+    info!("gRPC service listening on 0.0.0.0:{port}");
+    let incoming = stream::unfold(listener, |l| async move { Some((l.accept().await.map(|(s, _)| s), l)) });
+    if let Err(e) = tonic::transport::Server::builder().add_service(service).serve_with_incoming(incoming).await {
+        error!("gRPC service stopped: {e}");
+    }
+}
@@ -0,0 +1,88 @@
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use zenoh::prelude::r#async::*;
+use tracing::{info, warn};
+use crate::diag::{Diagnostics, Stage};
+use crate::geohash::BoundingBox;
+use crate::metadata::{MetaCache, VehicleMeta};
+use crate::track::{TrackPoint, Tracks};
+use crate::{Position, VehicleInfo};
+
+// The area a tracker is responsible for in a multi-site deployment, each site
+// tracking its own namespace.
+#[derive(Debug, Clone)]
+pub struct Site {
+    pub name: String,
+    pub bbox: Option<BoundingBox>,
+}
+
+// What a site knows of a vehicle leaving its area, published on
+// <handover_key>/<site>/<id> so that the site it enters keeps its metadata and
+// its track.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Handover {
+    // site the vehicle left
+    pub from: String,
+    // first position outside of the site
+    pub vehicle: VehicleInfo,
+    pub meta: Option<VehicleMeta>,
+    // oldest point first
+    pub track: Vec<TrackPoint>,
+}
+
+impl Site {
+    // Whether moving from `before` to `after` leaves the site.
+    pub fn left(&self, before: &Position, after: &Position) -> bool {
+        self.bbox.is_some_and(|bb| bb.contains(before.lat, before.lng) && !bb.contains(after.lat, after.lng))
+    }
+
+    // Handovers of other sites are adopted when the vehicle enters this one,
+    // or whatever its position for sites without an area.
+    fn adopts(&self, h: &Handover) -> bool {
+        let p = h.vehicle.position;
+        h.from != self.name && self.bbox.is_none_or(|bb| bb.contains(p.lat, p.lng))
+    }
+
+    pub fn handover(&self, vi: &VehicleInfo, meta: &MetaCache, tracks: &Tracks) -> Handover {
+        Handover { from: self.name.clone(), vehicle: vi.clone(), meta: meta.get(&vi.id), track: tracks.get(&vi.id) }
+    }
+}
+
+// Adopts the vehicles handed over to the site: their metadata and track are
+// registered unless the vehicle already has some here, the kind and color of
+// the handover completing the metadata so that compact positions keep them.
+pub async fn follow(z: Arc<Session>, prefix: String, site: Site, meta: MetaCache, tracks: Tracks, diag: Diagnostics) {
+    let keys = [format!("{prefix}/**")];
+    loop {
+        let (subs, mut rx) = zdemo_common::subscribe(&z, &keys).await;
+        while let Some(sample) = rx.recv().await {
+            if sample.kind == SampleKind::Delete {
+                continue;
+            }
+            let handover = zdemo_common::payload(&sample)
+                .and_then(|payload| serde_json::from_slice::<Handover>(&payload).map_err(|e| format!("invalid handover: {e}")));
+            let h = match handover {
+                Ok(h) => h,
+                Err(e) => {
+                    warn!("Unable to parse handover: {e}");
+                    diag.report(Stage::Handover, sample.key_expr.as_str(), e, Some(&sample.payload.contiguous()));
+                    continue;
+                },
+            };
+            if !site.adopts(&h) {
+                continue;
+            }
+            let id = &h.vehicle.id;
+            let mut m = h.meta.clone().unwrap_or_default();
+            m.kind.get_or_insert_with(|| h.vehicle.kind.clone());
+            m.color.get_or_insert_with(|| h.vehicle.color.clone());
+            let with_meta = meta.adopt(id, m);
+            let with_track = tracks.adopt(id, &h.track);
+            info!("HANDOVER: {id} adopted from {}{}{}", h.from,
+                if with_meta { ", with its metadata" } else { "" },
+                if with_track { format!(", with {} track points", h.track.len()) } else { String::new() });
+        }
+        warn!("handover subscription lost, declaring it again");
+        drop(subs);
+    }
+}
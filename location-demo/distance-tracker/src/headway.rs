@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use serde::Deserialize;
+use crate::vehicle::VehicleState;
+
+// Kind matching the vehicles of any kind not listed.
+const ANY_KIND: &str = "*";
+
+// Safe following distance of a kind of vehicle: the distance covered in `time`
+// at its speed, plus a `margin` kept at standstill, e.g. the 2-second rule.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Headway {
+    // s
+    pub time: f64,
+    // m
+    pub margin: f64,
+}
+
+impl Headway {
+    fn check(&self) -> Result<(), String> {
+        if !(self.time.is_finite() && self.time >= 0.0 && self.margin.is_finite() && self.margin >= 0.0) {
+            return Err(format!("time and margin must be non negative, got {} s and {} m", self.time, self.margin));
+        }
+        Ok(())
+    }
+}
+
+// Headways by kind, {"car": {"time": 2, "margin": 2}, "*": {...}}. Pairs
+// whose faster vehicle has none keep the fixed min distance.
+#[derive(Debug, Clone, Default)]
+pub struct Headways(HashMap<String, Headway>);
+
+impl Headways {
+    // The headway of every kind.
+    pub fn uniform(h: Headway) -> Result<Self, String> {
+        h.check()?;
+        Ok(Headways(HashMap::from([(ANY_KIND.to_string(), h)])))
+    }
+
+    pub fn get(&self, kind: &str) -> Option<Headway> {
+        self.0.get(kind).or_else(|| self.0.get(ANY_KIND)).copied()
+    }
+
+    // The min distance (m) of a pair, from the speed and kind of its faster
+    // vehicle.
+    pub fn min_distance(&self, a: &VehicleState, b: &VehicleState) -> Option<f64> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let faster = if a.effective_speed >= b.effective_speed { a } else { b };
+        self.get(faster.info.kind.as_str()).map(|h| faster.effective_speed * h.time + h.margin)
+    }
+}
+
+// The headways of the file on top of `base`.
+pub fn load(path: &str, base: Headways) -> Result<Headways, String> {
+    let file = File::open(path).map_err(|e| format!("Unable to open {path}: {e}"))?;
+    let by_kind: HashMap<String, Headway> = serde_json::from_reader(BufReader::new(file)).map_err(|e| format!("Invalid headways in {path}: {e}"))?;
+    for (kind, h) in &by_kind {
+        h.check().map_err(|e| format!("headway of '{kind}': {e}"))?;
+    }
+    let mut headways = base;
+    headways.0.extend(by_kind);
+    Ok(headways)
+}
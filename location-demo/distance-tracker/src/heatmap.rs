@@ -0,0 +1,25 @@
+use std::collections::{BTreeMap, HashMap};
+use serde::Serialize;
+use crate::vehicle::{self, VehicleState};
+
+// Vehicle counts on a grid of `cell` x `cell` degrees, for density overlays.
+// Cell [row, col] covers latitudes from row * cell to (row + 1) * cell, and
+// longitudes from col * cell to (col + 1) * cell; only cells with vehicles
+// are listed, as [row, col, count].
+#[derive(Serialize, Debug)]
+pub struct Heatmap {
+    // ms since the UNIX epoch
+    pub ts: u64,
+    // deg
+    pub cell: f64,
+    pub cells: Vec<(i64, i64, usize)>,
+}
+
+pub fn compute(map: &HashMap<String, VehicleState>, cell: f64, ts: u64) -> Heatmap {
+    let mut counts: BTreeMap<(i64, i64), usize> = BTreeMap::new();
+    for v in vehicle::live(map).values() {
+        let p = v.info.position;
+        *counts.entry(((p.lat / cell).floor() as i64, (p.lng / cell).floor() as i64)).or_default() += 1;
+    }
+    Heatmap { ts, cell, cells: counts.into_iter().map(|((row, col), n)| (row, col, n)).collect() }
+}
@@ -0,0 +1,243 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use axum::body::{Body, Bytes};
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Json, Response};
+use axum::routing::{get, put};
+use axum::Router;
+use futures_util::{SinkExt, StreamExt};
+use hyper_util::rt::TokioIo;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::oneshot;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use tracing::{error, info, warn};
+use crate::admin::SetRequest;
+use crate::publish::Outgoing;
+use crate::record::unix_ms;
+use crate::vehicle::VehicleState;
+use crate::VehicleMap;
+
+// Alerts kept for GET /alerts and the recent alert queries, the oldest being
+// dropped first.
+const MAX_ALERTS: usize = 1000;
+// Alerts a WebSocket client can lag behind before missing some.
+const WS_BACKLOG: usize = 256;
+// Fields of the alert payloads naming the vehicles involved.
+const ALERT_ID_FIELDS: [&str; 5] = ["id", "ida", "idb", "cutter", "victim"];
+
+// An alert as published, with the time it was (ms since the UNIX epoch).
+#[derive(Serialize, Debug, Clone)]
+pub struct RecentAlert {
+    pub ts: u64,
+    pub key: String,
+    pub alert: serde_json::Value,
+}
+
+// The last alerts published, for the clients polling them over HTTP or
+// querying them, and the alerts as they are published, for the WebSocket
+// clients.
+pub struct AlertHistory {
+    recent: VecDeque<RecentAlert>,
+    live: broadcast::Sender<Arc<RecentAlert>>,
+}
+
+pub type SharedHistory = Arc<Mutex<AlertHistory>>;
+
+impl Default for AlertHistory {
+    fn default() -> Self {
+        AlertHistory { recent: VecDeque::new(), live: broadcast::channel(WS_BACKLOG).0 }
+    }
+}
+
+impl AlertHistory {
+    pub fn push(&mut self, out: &Outgoing) {
+        if self.recent.len() == MAX_ALERTS {
+            self.recent.pop_front();
+        }
+        let alert = RecentAlert { ts: unix_ms(), key: out.key.clone(), alert: out.value.clone() };
+        if self.live.receiver_count() > 0 {
+            let _ = self.live.send(Arc::new(alert.clone()));
+        }
+        self.recent.push_back(alert);
+    }
+
+    // Forgets the last alerts, the WebSocket clients staying subscribed.
+    pub fn clear(&mut self) {
+        self.recent.clear();
+    }
+
+    // The alerts as they are published.
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<RecentAlert>> {
+        self.live.subscribe()
+    }
+
+    // The alerts published after `since`, oldest first.
+    fn since(&self, since: u64) -> Vec<RecentAlert> {
+        let start = self.recent.partition_point(|a| a.ts <= since);
+        self.recent.range(start..).cloned().collect()
+    }
+
+    // The last `n` alerts published after `since` that `filter` accepts,
+    // oldest first.
+    pub fn recent(&self, n: usize, since: u64, filter: &AlertFilter) -> Vec<RecentAlert> {
+        let start = self.recent.partition_point(|a| a.ts <= since);
+        let mut last: Vec<RecentAlert> = self.recent.range(start..).rev().filter(|a| filter.accepts(a)).take(n).cloned().collect();
+        last.reverse();
+        last
+    }
+}
+
+// Alerts a WebSocket or gRPC client is interested in: those involving vehicle `id`
+// and those of `severity`, the severity band of the alert or else the level
+// of its kind (alert or danger).
+#[derive(Deserialize, Debug, Default)]
+pub struct AlertFilter {
+    pub id: Option<String>,
+    pub severity: Option<String>,
+}
+
+impl AlertFilter {
+    pub fn accepts(&self, a: &RecentAlert) -> bool {
+        let involves = |id: &str| ALERT_ID_FIELDS.iter().any(|f| a.alert.get(f).and_then(Value::as_str) == Some(id));
+        self.id.as_deref().is_none_or(involves) && self.severity.as_deref().is_none_or(|s| severity(&a.alert).is_some_and(|x| x.eq_ignore_ascii_case(s)))
+    }
+}
+
+fn severity(alert: &Value) -> Option<&str> {
+    alert.get("severity").and_then(Value::as_str).or_else(|| {
+        let kind = alert.get("kind")?.as_str()?;
+        ["Danger", "Alert"].into_iter().find(|level| kind.starts_with(level))
+    })
+}
+
+#[derive(Clone)]
+struct Api {
+    map: VehicleMap,
+    history: SharedHistory,
+    config: UnboundedSender<SetRequest>,
+}
+
+#[derive(Deserialize)]
+struct AlertsQuery {
+    // ms since the UNIX epoch
+    #[serde(default)]
+    since: u64,
+}
+
+async fn vehicles(State(api): State<Api>) -> Json<Vec<VehicleState>> {
+    let map = api.map.snapshot();
+    let mut vehicles: Vec<VehicleState> = map.values().cloned().collect();
+    vehicles.sort_by(|a, b| a.info.id.cmp(&b.info.id));
+    Json(vehicles)
+}
+
+async fn vehicle(Path(id): Path<String>, State(api): State<Api>) -> Response {
+    match api.map.read(|map| map.get(&id).cloned()) {
+        Some(v) => Json(v).into_response(),
+        None => (StatusCode::NOT_FOUND, format!("no vehicle {id}")).into_response(),
+    }
+}
+
+async fn alerts(Query(q): Query<AlertsQuery>, State(api): State<Api>) -> Json<Vec<RecentAlert>> {
+    Json(api.history.lock().unwrap().since(q.since))
+}
+
+// Changes the thresholds given in the JSON object of the body, as a put on
+// <admin_key>/<instance id>/set/rules would, answering the versioned
+// thresholds shared with the other instances.
+async fn set_thresholds(State(api): State<Api>, body: Bytes) -> Response {
+    let (reply, rx) = oneshot::channel();
+    let _ = api.config.send(SetRequest { section: "rules".into(), payload: body.to_vec(), reply });
+    match rx.await {
+        Ok(Ok(bs)) => ([(header::CONTENT_TYPE, "application/json")], bs).into_response(),
+        Ok(Err(e)) => (StatusCode::BAD_REQUEST, e).into_response(),
+        Err(_) => (StatusCode::SERVICE_UNAVAILABLE, "the configuration is not followed").into_response(),
+    }
+}
+
+// Streams the alerts accepted by the filter of the query, as GET /alerts
+// returns them, one per text message.
+async fn ws_alerts(Query(filter): Query<AlertFilter>, State(api): State<Api>, mut req: Request) -> Response {
+    let upgrade = req.headers().get(header::UPGRADE).is_some_and(|u| u.as_bytes().eq_ignore_ascii_case(b"websocket"));
+    let Some(key) = req.headers().get(header::SEC_WEBSOCKET_KEY).filter(|_| upgrade) else {
+        return (StatusCode::UPGRADE_REQUIRED, "expecting a WebSocket upgrade").into_response();
+    };
+    let accept = derive_accept_key(key.as_bytes());
+    let alerts = api.history.lock().unwrap().subscribe();
+    let on_upgrade = hyper::upgrade::on(&mut req);
+    tokio::spawn(async move {
+        match on_upgrade.await {
+            Ok(upgraded) => {
+                let ws = WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Server, None).await;
+                stream_alerts(ws, alerts, filter).await;
+            },
+            Err(e) => warn!("WebSocket upgrade failed: {e}"),
+        }
+    });
+    Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(header::CONNECTION, "upgrade")
+        .header(header::UPGRADE, "websocket")
+        .header(header::SEC_WEBSOCKET_ACCEPT, accept)
+        .body(Body::empty())
+        .unwrap()
+}
+
+// Until the client closes the connection. Alerts missed by a client lagging
+// behind are skipped.
+async fn stream_alerts<S>(mut ws: WebSocketStream<S>, mut alerts: broadcast::Receiver<Arc<RecentAlert>>, filter: AlertFilter)
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    loop {
+        tokio::select! {
+            a = alerts.recv() => match a {
+                Ok(a) if filter.accepts(&a) => {
+                    if ws.send(Message::Text(serde_json::to_string(&*a).unwrap())).await.is_err() {
+                        break;
+                    }
+                },
+                Ok(_) | Err(RecvError::Lagged(_)) => {},
+                Err(RecvError::Closed) => break,
+            },
+            // pings are answered while reading
+            m = ws.next() => match m {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {},
+            },
+        }
+    }
+    let _ = ws.close(None).await;
+}
+
+// Serves the fleet state, the recent alerts and the thresholds on
+// http://0.0.0.0:<port>, for clients without a Zenoh library.
+pub async fn serve(port: u16, map: VehicleMap, history: SharedHistory, config: UnboundedSender<SetRequest>) {
+    let app = Router::new()
+        .route("/vehicles", get(vehicles))
+        .route("/vehicles/:id", get(vehicle))
+        .route("/alerts", get(alerts))
+        .route("/config/thresholds", put(set_thresholds))
+        .route("/ws/alerts", get(ws_alerts))
+        .with_state(Api { map, history, config });
+    let listener = tokio::net::TcpListener::bind(("0.0.0.0", port)).await.unwrap_or_else(|e| panic!("--http-port: {e}"));
+    info!("HTTP API listening on http://0.0.0.0:{port}");
+    if let Err(e) = axum::serve(listener, app).await {
+        error!("HTTP API stopped: {e}");
+    }
+}
+
+// Keeps the alerts of a cycle in the history.
+pub fn record(history: &SharedHistory, alerts: &[Outgoing]) {
+    let mut h = history.lock().unwrap();
+    for out in alerts {
+        h.push(out);
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use zenoh::prelude::r#async::*;
+use crate::Position;
+
+// Sources of an id not heard from for this long are forgotten, so that a
+// publisher replacing another one is not a conflict.
+const SOURCE_TTL: Duration = Duration::from_secs(10);
+// Conflicts of an id are raised at most this often.
+const CONFLICT_INTERVAL: Duration = Duration::from_secs(10);
+// Jumps are measured over at least this interval, closer fixes being
+// dominated by GPS noise.
+const MIN_JUMP_INTERVAL_S: f64 = 1.0;
+
+// The publisher of a sample, borrowing its identity from the sample, or owning
+// it once recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source<S> {
+    // Zenoh id of the publishing session, when the sample carries it
+    Zenoh(ZenohId),
+    // instance id tagged in the payload
+    Instance(S),
+    // key expression the sample was published on
+    Key(S),
+}
+
+impl<S: AsRef<str>> Source<S> {
+    fn is(&self, other: &Source<&str>) -> bool {
+        match (self, other) {
+            (Source::Zenoh(a), Source::Zenoh(b)) => a == b,
+            (Source::Instance(a), Source::Instance(b)) | (Source::Key(a), Source::Key(b)) => a.as_ref() == *b,
+            _ => false,
+        }
+    }
+}
+
+impl Source<&str> {
+    fn owned(self) -> Source<String> {
+        match self {
+            Source::Zenoh(zid) => Source::Zenoh(zid),
+            Source::Instance(i) => Source::Instance(i.into()),
+            Source::Key(k) => Source::Key(k.into()),
+        }
+    }
+}
+
+impl<S: AsRef<str>> fmt::Display for Source<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Zenoh(zid) => write!(f, "zenoh:{zid}"),
+            Source::Instance(i) => write!(f, "instance:{}", i.as_ref()),
+            Source::Key(k) => write!(f, "key:{}", k.as_ref()),
+        }
+    }
+}
+
+// Identity of the publisher of a sample, the most reliable one it carries.
+pub fn source<'a>(sample: &'a Sample, instance: Option<&'a str>) -> Source<&'a str> {
+    if let Some(zid) = sample.source_info.source_id {
+        return Source::Zenoh(zid);
+    }
+    match instance {
+        Some(i) => Source::Instance(i),
+        None => Source::Key(sample.key_expr.as_str()),
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictReason {
+    // positions of the id come from several publishers
+    MultipleSources,
+    // the position moved faster than any vehicle can
+    ImpossibleJump,
+}
+
+// Raised when a vehicle id looks shared by several publishers, whose positions
+// would make the vehicle teleport and its alerts go haywire.
+#[derive(Serialize, Debug)]
+pub struct IdentityConflict {
+    pub id: String,
+    pub reason: ConflictReason,
+    // key of the sample revealing the conflict
+    pub key: String,
+    // publishers of the id heard from recently, as <kind>:<identity>
+    pub sources: Vec<String>,
+    // for impossible jumps, the distance (m) and the speed (m/s) it implies
+    pub jump: Option<f64>,
+    pub implied_speed: Option<f64>,
+}
+
+#[derive(Default)]
+struct Seen {
+    sources: Vec<(Source<String>, Instant)>,
+    last: Option<(Position, Instant)>,
+    raised: Option<Instant>,
+}
+
+// Follows the publishers and the fixes of every id.
+#[derive(Default)]
+pub struct IdentityGuard {
+    ids: HashMap<String, Seen>,
+}
+
+impl IdentityGuard {
+    // Forgets the publishers of every id.
+    pub fn reset(&mut self) {
+        self.ids.clear();
+    }
+
+    // Records the fix of `id` from `source`, returning the conflict it reveals
+    // if one was not raised for the id recently, jumps being measured against
+    // `max_speed` (m/s). Only allocates for new ids and sources.
+    //
+    // The keys of a vehicle are not several sources: it publishes on another
+    // one when it crosses into another geohash cell, or moves to the prefix of
+    // another fleet, so that the jumps alone reveal ids shared over keys.
+    pub fn check(&mut self, id: &str, source: Source<&str>, key: &str, position: Position, now: Instant, max_speed: f64) -> Option<IdentityConflict> {
+        if !self.ids.contains_key(id) {
+            self.ids.insert(id.into(), Seen::default());
+        }
+        let seen = self.ids.get_mut(id).unwrap();
+        seen.sources.retain(|(_, at)| now.duration_since(*at) < SOURCE_TTL);
+        match seen.sources.iter_mut().find(|(s, _)| s.is(&source)) {
+            Some(s) => s.1 = now,
+            None => seen.sources.push((source.owned(), now)),
+        }
+        let publishers = seen.sources.iter().filter(|(s, _)| !matches!(s, Source::Key(_))).count();
+        let jump = seen.last.and_then(|(p, at)| {
+            let distance = p.distance_haverside(&position);
+            let implied = distance / now.duration_since(at).as_secs_f64().max(MIN_JUMP_INTERVAL_S);
+            (implied > max_speed).then_some((distance, implied))
+        });
+        seen.last = Some((position, now));
+        let reason = match (publishers > 1, jump) {
+            (true, _) => ConflictReason::MultipleSources,
+            (false, Some(_)) => ConflictReason::ImpossibleJump,
+            (false, None) => return None,
+        };
+        if seen.raised.is_some_and(|at| now.duration_since(at) < CONFLICT_INTERVAL) {
+            return None;
+        }
+        seen.raised = Some(now);
+        let sources = seen.sources.iter().map(|(s, _)| s.to_string()).collect();
+        Some(IdentityConflict { id: id.into(), reason, key: key.into(), sources, jump: jump.map(|j| j.0), implied_speed: jump.map(|j| j.1) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_vehicle_crossing_geohash_cells_is_no_conflict() {
+        let mut guard = IdentityGuard::default();
+        let now = Instant::now();
+        let (a, b) = (Position { lat: 45.0703, lng: 7.6869 }, Position { lat: 45.0703, lng: 7.6872 });
+        assert!(guard.check("v", Source::Key("demo/tracker/mobs/u0j2m/v"), "demo/tracker/mobs/u0j2m/v", a, now, 120.0).is_none());
+        let later = now + Duration::from_secs(2);
+        assert!(guard.check("v", Source::Key("demo/tracker/mobs/u0j2q/v"), "demo/tracker/mobs/u0j2q/v", b, later, 120.0).is_none());
+        // two instances publishing the id still are a conflict
+        assert!(guard.check("v", Source::Instance("a"), "demo/tracker/mobs/u0j2q/v", b, later, 120.0).is_none());
+        let conflict = guard.check("v", Source::Instance("b"), "demo/tracker/mobs/u0j2q/v", b, later, 120.0).unwrap();
+        assert_eq!(conflict.reason, ConflictReason::MultipleSources);
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use zenoh::prelude::r#async::*;
+use tracing::{info, warn};
+use crate::diag::{Diagnostics, Stage};
+use crate::geometry::alert_id;
+use crate::rules::Alert;
+
+// Acknowledgement of the danger of a pair by an operator, put on the ack key.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Ack {
+    pub ida: String,
+    pub idb: String,
+    #[serde(default)]
+    pub operator: Option<String>,
+}
+
+// A pair in danger since `opened`, until it is no longer.
+#[derive(Serialize, Debug, Clone)]
+pub struct Incident {
+    pub ida: String,
+    pub idb: String,
+    // m, as of the last cycle
+    pub distance: f64,
+    // ms since the UNIX epoch
+    pub opened: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub acked: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub operator: Option<String>,
+    pub escalated: bool,
+    #[serde(skip)]
+    since: Instant,
+}
+
+// Published on the escalation key for the incidents nobody acknowledged in
+// time.
+#[derive(Serialize, Debug)]
+pub struct Escalation<'a> {
+    pub ida: &'a str,
+    pub idb: &'a str,
+    pub distance: f64,
+    pub opened: u64,
+    // ms the incident waited for an acknowledgement
+    pub unacked_ms: u64,
+}
+
+// The open incidents by alert id, <a>~<b> with the ids in order.
+pub struct Incidents {
+    timeout: Duration,
+    open: BTreeMap<String, Incident>,
+}
+
+pub type SharedIncidents = Arc<Mutex<Incidents>>;
+
+impl Incidents {
+    pub fn new(timeout: Duration) -> Self {
+        Incidents { timeout, open: BTreeMap::new() }
+    }
+
+    pub fn clear(&mut self) {
+        self.open.clear();
+    }
+
+    // Opens the incidents of the danger pairs among the alerts of a cycle and
+    // closes those of the pairs no longer in danger, then calls `escalate` with
+    // the incidents left unacknowledged for longer than the timeout, once each.
+    pub fn update(&mut self, alerts: &[Alert], now: Instant, unix_ms: u64, mut escalate: impl FnMut(Escalation)) {
+        let mut dangers: BTreeMap<String, (&str, &str, f64)> = BTreeMap::new();
+        for a in alerts {
+            if let Alert::Distance(da) = a {
+                if da.kind.is_danger() {
+                    dangers.insert(alert_id(&da.ida, &da.idb), (&da.ida, &da.idb, da.distance));
+                }
+            }
+        }
+        self.open.retain(|id, i| {
+            let kept = dangers.contains_key(id);
+            if !kept {
+                info!(target: "alert", rule = "incident", ida = %i.ida, idb = %i.idb, acked = i.acked.is_some(), "INCIDENT: {} -> {} closed{}", i.ida, i.idb, if i.acked.is_some() { "" } else { " unacknowledged" });
+            }
+            kept
+        });
+        for (id, (ida, idb, distance)) in dangers {
+            let i = self.open.entry(id).or_insert_with(|| {
+                info!(target: "alert", rule = "incident", ida, idb, distance, "INCIDENT: {ida} -> {idb} opened");
+                Incident { ida: ida.into(), idb: idb.into(), distance, opened: unix_ms, acked: None, operator: None, escalated: false, since: now }
+            });
+            i.distance = distance;
+            let waited = now.duration_since(i.since);
+            if i.acked.is_none() && !i.escalated && waited >= self.timeout {
+                warn!(target: "alert", rule = "escalation", ida = %i.ida, idb = %i.idb, distance, unacked_ms = waited.as_millis() as u64, "ESCALATION: {} -> {} unacknowledged for {} ms", i.ida, i.idb, waited.as_millis());
+                i.escalated = true;
+                escalate(Escalation { ida: &i.ida, idb: &i.idb, distance, opened: i.opened, unacked_ms: waited.as_millis() as u64 });
+            }
+        }
+    }
+
+    pub fn ack(&mut self, ack: Ack, unix_ms: u64) -> Result<(), String> {
+        let Some(i) = self.open.get_mut(&alert_id(&ack.ida, &ack.idb)) else {
+            return Err(format!("no open incident for {} and {}", ack.ida, ack.idb));
+        };
+        if i.acked.is_none() {
+            i.acked = Some(unix_ms);
+            i.operator = ack.operator;
+        }
+        Ok(())
+    }
+
+    pub fn list(&self) -> Vec<Incident> {
+        self.open.values().cloned().collect()
+    }
+}
+
+// Applies the acknowledgements put on `key` and answers the queries on it with
+// the open incidents.
+pub async fn serve(z: Arc<Session>, key: String, incidents: SharedIncidents, diag: Diagnostics) {
+    let queryable = z.declare_queryable(&key).res().await.unwrap();
+    let keys = [key.clone()];
+    let (mut subs, mut rx) = zdemo_common::subscribe(&z, &keys).await;
+    loop {
+        tokio::select! {
+            Ok(query) = queryable.recv_async() => {
+                let open = incidents.lock().unwrap().list();
+                let sample = Sample::new(KeyExpr::try_from(key.clone()).unwrap(), Value::from(serde_json::to_vec(&open).unwrap()).encoding(Encoding::APP_JSON));
+                if let Err(e) = query.reply(Ok(sample)).res().await {
+                    warn!("Unable to reply to query on {key}: {e}");
+                }
+            },
+            sample = rx.recv() => {
+                let Some(sample) = sample else {
+                    warn!("ack subscription lost, declaring it again");
+                    drop(subs);
+                    (subs, rx) = zdemo_common::subscribe(&z, &keys).await;
+                    continue;
+                };
+                let acked = zdemo_common::payload(&sample)
+                    .and_then(|payload| serde_json::from_slice::<Ack>(&payload).map_err(|e| e.to_string()))
+                    .and_then(|ack| {
+                        let ids = format!("{} -> {}", ack.ida, ack.idb);
+                        incidents.lock().unwrap().ack(ack, crate::record::unix_ms()).map(|_| ids)
+                    });
+                match acked {
+                    Ok(ids) => info!("INCIDENT: {ids} acknowledged"),
+                    Err(e) => {
+                        warn!("Unable to acknowledge: {e}");
+                        diag.report(Stage::Ack, sample.key_expr.as_str(), e, Some(&sample.payload.contiguous()));
+                    },
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use hyper::Uri;
+use serde::Deserialize;
+use tokio::sync::mpsc::{channel, error::TrySendError, Sender};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+use crate::geo::DistanceAlgo;
+use crate::sinks;
+use crate::vehicle::VehicleState;
+
+const DEFAULT_CAPACITY: usize = 16;
+
+// Vehicle field used as the value of a tag.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Field { Id, Kind, Color }
+
+impl Field {
+    fn of(self, v: &VehicleState) -> &str {
+        match self {
+            Field::Id => &v.info.id,
+            Field::Kind => v.info.kind.as_str(),
+            Field::Color => v.info.color.as_str(),
+        }
+    }
+}
+
+// Field of either vehicle of a pair, "a.<field>" or "b.<field>".
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(try_from = "String")]
+pub struct PairField {
+    second: bool,
+    field: Field,
+}
+
+impl TryFrom<String> for PairField {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, String> {
+        let (side, field) = s.split_once('.').ok_or_else(|| format!("invalid pair tag {s}, expecting a.<field> or b.<field>"))?;
+        let second = match side {
+            "a" => false,
+            "b" => true,
+            _ => return Err(format!("invalid pair tag {s}, expecting a.<field> or b.<field>")),
+        };
+        let field = serde_json::from_value(serde_json::Value::String(field.into())).map_err(|_| format!("unknown vehicle field in {s}"))?;
+        Ok(PairField { second, field })
+    }
+}
+
+// One point per vehicle, with its position and speeds as fields.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields, default)]
+pub struct VehicleSeries {
+    pub measurement: String,
+    // tag name -> vehicle field
+    pub tags: BTreeMap<String, Field>,
+}
+
+impl Default for VehicleSeries {
+    fn default() -> Self {
+        VehicleSeries {
+            measurement: "vehicle".into(),
+            tags: [("id", Field::Id), ("kind", Field::Kind), ("color", Field::Color)].into_iter().map(|(k, f)| (k.to_string(), f)).collect(),
+        }
+    }
+}
+
+// One point per pair of vehicles closer than `max_distance`, with their distance.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields, default)]
+pub struct PairSeries {
+    pub measurement: String,
+    pub tags: BTreeMap<String, PairField>,
+    // the tracker's --max-distance when null
+    pub max_distance: Option<f64>,
+}
+
+impl Default for PairSeries {
+    fn default() -> Self {
+        PairSeries {
+            measurement: "distance".into(),
+            tags: [("a", PairField { second: false, field: Field::Id }), ("b", PairField { second: true, field: Field::Id })]
+                .into_iter().map(|(k, f)| (k.to_string(), f)).collect(),
+            max_distance: None,
+        }
+    }
+}
+
+// Telemetry file, e.g. {"url": "http://localhost:8086/api/v2/write?org=demo&bucket=fleet", "token": "..."}
+// or {"path": "telemetry.lp"}.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct InfluxConfig {
+    // write endpoint, only plain http is supported
+    pub url: Option<String>,
+    // sent as "Authorization: Token <token>"
+    pub token: Option<String>,
+    // file the lines are appended to, instead of the url
+    pub path: Option<String>,
+    // the compute period when null
+    pub period_ms: Option<u64>,
+    #[serde(default)]
+    pub vehicles: VehicleSeries,
+    #[serde(default)]
+    pub pairs: PairSeries,
+    // batches queued for writing, further ones are dropped while it is full
+    pub capacity: Option<usize>,
+}
+
+pub fn load(path: &str) -> Result<InfluxConfig, String> {
+    let s = std::fs::read_to_string(path).map_err(|e| format!("Unable to read {path}: {e}"))?;
+    let config: InfluxConfig = serde_json::from_str(&s).map_err(|e| format!("Invalid telemetry file {path}: {e}"))?;
+    match (&config.url, &config.path) {
+        (Some(url), None) => {
+            let uri: Uri = url.parse().map_err(|e| format!("invalid url {url}: {e}"))?;
+            if uri.scheme_str() != Some("http") || uri.host().is_none() {
+                return Err(format!("invalid url {url}, expecting http://<host>[:port]/<path>"));
+            }
+        },
+        (None, Some(_)) => {},
+        _ => return Err("expecting either a url or a path".into()),
+    }
+    if config.pairs.max_distance.is_some_and(|d| !d.is_finite() || d < 0.0) {
+        return Err("pairs.max_distance must be a finite distance, not negative".into());
+    }
+    Ok(config)
+}
+
+// Measurements escape commas and spaces, tag keys and values equal signs too.
+fn escape(out: &mut String, s: &str, equals: bool) {
+    for c in s.chars() {
+        if c == ',' || c == ' ' || (equals && c == '=') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+}
+
+fn point<'a>(out: &mut String, measurement: &str, tags: impl Iterator<Item = (&'a String, &'a str)>, fields: &[(&str, f64)], ts: u128) {
+    escape(out, measurement, false);
+    // empty tag values are not accepted, the tag is left out instead
+    for (k, v) in tags.filter(|(_, v)| !v.is_empty()) {
+        out.push(',');
+        escape(out, k, true);
+        out.push('=');
+        escape(out, v, true);
+    }
+    for (i, (k, v)) in fields.iter().enumerate() {
+        let _ = write!(out, "{}{k}={v}", if i == 0 { ' ' } else { ',' });
+    }
+    let _ = writeln!(out, " {ts}");
+}
+
+// The fleet as InfluxDB line protocol, timestamped in nanoseconds. Pairs are
+// written once, the vehicle with the lowest id as `a`.
+pub fn lines(map: &HashMap<String, VehicleState>, config: &InfluxConfig, algo: DistanceAlgo, max_distance: f64) -> String {
+    let ts = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    let mut vehicles: Vec<&VehicleState> = map.values().collect();
+    vehicles.sort_by(|a, b| a.info.id.cmp(&b.info.id));
+    let mut out = String::new();
+    let series = &config.vehicles;
+    for v in &vehicles {
+        let mut fields = vec![("lat", v.info.position.lat), ("lng", v.info.position.lng), ("speed", v.info.speed), ("effective_speed", v.effective_speed)];
+        if let Some(h) = v.heading {
+            fields.push(("heading", h));
+        }
+        point(&mut out, &series.measurement, series.tags.iter().map(|(k, f)| (k, f.of(v))), &fields, ts);
+    }
+    let series = &config.pairs;
+    let range = series.max_distance.unwrap_or(max_distance);
+    for (i, a) in vehicles.iter().enumerate() {
+        for b in &vehicles[i + 1..] {
+            let distance = algo.distance(&a.info.position, &b.info.position);
+            if distance <= range {
+                let tags = series.tags.iter().map(|(k, f)| (k, f.field.of(if f.second { b } else { a })));
+                point(&mut out, &series.measurement, tags, &[("distance", distance)], ts);
+            }
+        }
+    }
+    out
+}
+
+// Sender to the writing task. Like alert sinks, sending never waits.
+pub struct Telemetry {
+    name: String,
+    pub period: Option<Duration>,
+    tx: Sender<String>,
+}
+
+impl Telemetry {
+    pub fn send(&self, lines: String) {
+        match self.tx.try_send(lines) {
+            Ok(()) => {},
+            Err(TrySendError::Full(_)) => warn!("{} is lagging, dropping telemetry", self.name),
+            Err(TrySendError::Closed(_)) => warn!("{} is gone, dropping telemetry", self.name),
+        }
+    }
+}
+
+// Starts the task writing the batches, which ends once the returned Telemetry
+// is dropped and the queued batches are written.
+pub fn spawn(config: &InfluxConfig) -> Result<(Telemetry, JoinHandle<()>), String> {
+    let (tx, mut rx) = channel::<String>(config.capacity.unwrap_or(DEFAULT_CAPACITY).max(1));
+    let (name, handle) = match (&config.url, &config.path) {
+        (Some(url), _) => {
+            let uri: Uri = url.parse().map_err(|e| format!("{url}: {e}"))?;
+            let authorization = config.token.as_ref().map(|t| format!("Token {t}"));
+            let name = format!("influx {uri}");
+            let handle = tokio::spawn(async move {
+                while let Some(lines) = rx.recv().await {
+                    let post = sinks::post(&uri, "text/plain; charset=utf-8", authorization.as_deref(), lines.into_bytes());
+                    match tokio::time::timeout(sinks::WEBHOOK_TIMEOUT, post).await {
+                        Ok(Ok(status)) if status.is_success() => {},
+                        Ok(Ok(status)) => error!("influx {uri} replied {status}"),
+                        Ok(Err(e)) => error!("unable to write to influx {uri}: {e}"),
+                        Err(_) => error!("influx {uri} gave no response within {:?}", sinks::WEBHOOK_TIMEOUT),
+                    }
+                }
+            });
+            (name, handle)
+        },
+        (None, Some(path)) => {
+            let mut out = std::fs::OpenOptions::new().create(true).append(true).open(path)
+                .map_err(|e| format!("Unable to open {path}: {e}"))?;
+            let path = path.clone();
+            let name = format!("telemetry file {path}");
+            let handle = tokio::spawn(async move {
+                while let Some(lines) = rx.recv().await {
+                    if let Err(e) = out.write_all(lines.as_bytes()) {
+                        error!("unable to write telemetry to {path}: {e}");
+                    }
+                }
+            });
+            (name, handle)
+        },
+        (None, None) => return Err("expecting either a url or a path".into()),
+    };
+    info!("Writing telemetry to {name}");
+    Ok((Telemetry { name, period: config.period_ms.map(Duration::from_millis), tx }, handle))
+}
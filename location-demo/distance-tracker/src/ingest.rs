@@ -0,0 +1,385 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde::de::{self, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::de::value::MapAccessDeserializer;
+use zenoh::prelude::r#async::*;
+use crate::advisory::{self, Deprecation};
+use crate::geo;
+use crate::metadata::VehicleMeta;
+use crate::telemetry::{self, Telemetry};
+use crate::{Color, Position, VehicleInfo, VehicleKind};
+
+// Attachment entries carrying the slow changing fields of compact payloads.
+pub const KIND_ATTACHMENT: &str = "kind";
+pub const COLOR_ATTACHMENT: &str = "color";
+
+// Version of the current position payload, {"v": 2, ...}. Payloads without a
+// version (or with "v": 1) are the previous format, still accepted.
+pub const PAYLOAD_VERSION: u64 = 2;
+// Longer ids are rejected, they end up in keys and in every alert.
+pub const MAX_ID_LEN: usize = 64;
+
+// Position payload decoded without allocating: strings are borrowed from the
+// payload (unless they hold escapes) and the position is read in the current
+// format or in the deprecated ones. Compact payloads, just the id, position
+// and speed, leave out the kind and color, which are then completed by the
+// vehicle's `Profiles`; full payloads are still accepted.
+#[derive(Deserialize, Debug)]
+pub struct RawVehicle<'a> {
+    #[serde(default)]
+    pub v: Option<u64>,
+    #[serde(borrow)]
+    pub id: Cow<'a, str>,
+    #[serde(borrow, default, deserialize_with = "borrowed")]
+    pub kind: Option<Cow<'a, str>>,
+    #[serde(borrow, default, deserialize_with = "borrowed")]
+    pub color: Option<Cow<'a, str>>,
+    pub speed: f64,
+    // instance id of the publisher, for those tagging their payloads
+    #[serde(borrow, default, deserialize_with = "borrowed")]
+    pub instance: Option<Cow<'a, str>>,
+    // safety bubble (m) of the vehicle, kept in its profile
+    #[serde(default)]
+    pub min_distance: Option<f64>,
+    #[serde(default)]
+    pub max_distance: Option<f64>,
+    // source timestamp, ms since the UNIX epoch
+    #[serde(default)]
+    pub ts: Option<u64>,
+    // {field: number}, validated field by field
+    #[serde(default)]
+    pub telemetry: Option<BTreeMap<String, serde_json::Value>>,
+    position: Option<RawPosition>,
+    lat: Option<f64>,
+    lng: Option<f64>,
+}
+
+// Optional strings are only borrowed through a wrapper, serde allocating
+// options of Cow otherwise.
+fn borrowed<'de: 'a, 'a, D: Deserializer<'de>>(d: D) -> Result<Option<Cow<'a, str>>, D::Error> {
+    #[derive(Deserialize)]
+    struct Borrowed<'a>(#[serde(borrow)] Cow<'a, str>);
+    Ok(Option::<Borrowed>::deserialize(d)?.map(|b| b.0))
+}
+
+#[derive(Debug, Clone, Copy)]
+enum RawPosition {
+    Object(Position),
+    // deprecated [lat, lng]
+    Array(Position),
+}
+
+impl<'de> Deserialize<'de> for RawPosition {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        d.deserialize_any(RawPositionVisitor)
+    }
+}
+
+struct RawPositionVisitor;
+
+impl<'de> Visitor<'de> for RawPositionVisitor {
+    type Value = RawPosition;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a {lat, lng} object or a [lat, lng] array")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<RawPosition, A::Error> {
+        Position::deserialize(MapAccessDeserializer::new(map)).map(RawPosition::Object)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<RawPosition, A::Error> {
+        let lat = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let lng = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        if seq.next_element::<IgnoredAny>()?.is_some() {
+            return Err(de::Error::invalid_length(3, &self));
+        }
+        Ok(RawPosition::Array(Position { lat, lng }))
+    }
+}
+
+impl<'a> RawVehicle<'a> {
+    pub fn parse(payload: &'a [u8]) -> serde_json::Result<Self> {
+        let raw: RawVehicle = serde_json::from_slice(payload)?;
+        match (raw.version(), raw.position) {
+            // v1 shim: the deprecated position formats are still accepted
+            (1, None) if raw.lat.is_none() || raw.lng.is_none() => Err(de::Error::missing_field("position")),
+            (1, _) => Ok(raw),
+            (PAYLOAD_VERSION, Some(RawPosition::Object(_))) if raw.lat.is_none() && raw.lng.is_none() => Ok(raw),
+            (PAYLOAD_VERSION, _) => Err(de::Error::custom("v2 payloads must have a {lat, lng} position object and no top level lat or lng")),
+            (v, _) => Err(de::Error::custom(format!("unsupported payload version {v}, expecting 1 or {PAYLOAD_VERSION}"))),
+        }
+    }
+
+    pub fn version(&self) -> u64 {
+        self.v.unwrap_or(1)
+    }
+
+    pub fn position(&self) -> Position {
+        match self.position {
+            Some(RawPosition::Object(p) | RawPosition::Array(p)) => p,
+            None => Position { lat: self.lat.unwrap(), lng: self.lng.unwrap() },
+        }
+    }
+
+    // Positions outside of the WGS-84 ranges (or not finite in a local frame),
+    // or with a negative speed, would only produce meaningless distances.
+    // Applies to every version.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.id.is_empty() || self.id.len() > MAX_ID_LEN {
+            return Err(format!("id must be 1 to {MAX_ID_LEN} bytes long, got {}", self.id.len()));
+        }
+        let p = self.position();
+        if geo::is_local() {
+            if !(p.lat.is_finite() && p.lng.is_finite()) {
+                return Err(format!("position out of range: x {}, y {}", p.lng, p.lat));
+            }
+        } else if !(-90.0..=90.0).contains(&p.lat) || !(-180.0..=180.0).contains(&p.lng) {
+            return Err(format!("position out of range: lat {}, lng {}", p.lat, p.lng));
+        }
+        if self.speed < 0.0 {
+            return Err(format!("negative speed {}", self.speed));
+        }
+        if let Some(d) = self.min_distance.into_iter().chain(self.max_distance).find(|d| !(d.is_finite() && *d >= 0.0)) {
+            return Err(format!("invalid safety bubble {d}"));
+        }
+        Ok(())
+    }
+
+    // The deprecated format the payload uses, if any.
+    pub fn deprecation(&self) -> Option<Deprecation> {
+        match self.position {
+            Some(RawPosition::Array(_)) => Some(advisory::POSITION_ARRAY),
+            Some(RawPosition::Object(_)) => None,
+            None => Some(advisory::TOP_LEVEL_COORDS),
+        }
+    }
+
+    // Writes the sample into `info`, reusing its strings, with the kind,
+    // color and safety bubble of the vehicle's profile.
+    pub fn write_to(&self, info: &mut VehicleInfo, profile: &Profile) {
+        info.position = self.position();
+        info.speed = self.speed;
+        if info.color != profile.color {
+            info.color.clone_from(&profile.color);
+        }
+        info.id.clear();
+        info.id.push_str(&self.id);
+        if info.kind != profile.kind {
+            info.kind.clone_from(&profile.kind);
+        }
+        info.min_distance = profile.min_distance;
+        info.max_distance = profile.max_distance;
+        info.ts = self.ts;
+    }
+
+    // Writes the valid telemetry fields of the sample, none without any,
+    // describing the invalid ones.
+    pub fn write_telemetry(&self, telemetry: &mut Telemetry) -> Option<String> {
+        match &self.telemetry {
+            Some(raw) => telemetry::validate(raw, telemetry),
+            None => {
+                telemetry.clear();
+                None
+            },
+        }
+    }
+}
+
+// The kind and color attached to a position sample, if any.
+pub fn attached(sample: &Sample) -> (Option<String>, Option<String>) {
+    let Some(attachment) = sample.attachment() else { return (None, None) };
+    let get = |key: &str| attachment.get(&key).and_then(|v| String::from_utf8(v.as_slice().to_vec()).ok());
+    (get(KIND_ATTACHMENT), get(COLOR_ATTACHMENT))
+}
+
+// Slow changing fields of a vehicle.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+    pub kind: VehicleKind,
+    pub color: Color,
+    pub min_distance: Option<f64>,
+    pub max_distance: Option<f64>,
+}
+
+// Last known profile of the vehicles, completing compact payloads.
+#[derive(Default)]
+pub struct Profiles(HashMap<String, Profile>);
+
+impl Profiles {
+    // The profile of the sample's vehicle, each field taken from the first of:
+    // the payload (full payloads), the attachments (kind and color), the
+    // vehicle's metadata, and the last known profile. Invalid kinds and colors
+    // are ignored, and described in the returned error. Only allocates when
+    // the profile changes.
+    pub fn resolve(&mut self, raw: &RawVehicle, attached: (Option<&str>, Option<&str>), meta: Option<&VehicleMeta>) -> (&Profile, Option<String>) {
+        if !self.0.contains_key(raw.id.as_ref()) {
+            self.0.insert(raw.id.to_string(), Profile::default());
+        }
+        let profile = self.0.get_mut(raw.id.as_ref()).unwrap();
+        let mut invalid = Vec::new();
+        pick(&mut profile.kind, raw.kind.as_deref().or(attached.0), VehicleKind::as_str, VehicleKind::parse, meta.and_then(|m| m.kind.as_ref()), &mut invalid);
+        pick(&mut profile.color, raw.color.as_deref().or(attached.1), Color::as_str, Color::parse, meta.and_then(|m| m.color.as_ref()), &mut invalid);
+        if let Some(d) = raw.min_distance.or(meta.and_then(|m| m.min_distance)) {
+            profile.min_distance = Some(d);
+        }
+        if let Some(d) = raw.max_distance.or(meta.and_then(|m| m.max_distance)) {
+            profile.max_distance = Some(d);
+        }
+        (profile, (!invalid.is_empty()).then(|| invalid.join(", ")))
+    }
+}
+
+// Sets `current` to the value `given` by the sample, parsed unless unchanged,
+// or else to the one of the metadata.
+fn pick<T: Clone + PartialEq>(current: &mut T, given: Option<&str>, as_str: fn(&T) -> &str, parse: fn(&str) -> Result<T, String>, meta: Option<&T>, invalid: &mut Vec<String>) {
+    if let Some(s) = given {
+        if s == as_str(current) {
+            return;
+        }
+        match parse(s) {
+            Ok(v) => {
+                if v != *current {
+                    *current = v;
+                }
+                return;
+            },
+            Err(e) => invalid.push(e),
+        }
+    }
+    if let Some(v) = meta.filter(|v| *v != current) {
+        current.clone_from(v);
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct IngestMetrics {
+    pub received: u64,
+    pub accepted: u64,
+    pub dropped: u64,
+    pub dropped_by_vehicle: BTreeMap<String, u64>,
+    // samples that are not valid positions, by the stage rejecting them
+    pub rejected: u64,
+    pub rejected_by_stage: BTreeMap<String, u64>,
+    // positions implying an implausible speed, whether dropped or flagged
+    pub outliers: u64,
+    // positions outside the region of interest
+    pub outside_roi: u64,
+    // positions outside the cells of the shard, and border vehicles received
+    // from the other instances
+    pub outside_shard: u64,
+    pub from_border: u64,
+    // positions whose kind, color or telemetry fields were ignored
+    pub invalid_attributes: u64,
+}
+
+impl IngestMetrics {
+    pub fn count_rejected(&mut self, stage: &str) {
+        self.rejected += 1;
+        match self.rejected_by_stage.get_mut(stage) {
+            Some(n) => *n += 1,
+            None => { self.rejected_by_stage.insert(stage.into(), 1); },
+        }
+    }
+}
+
+struct Slot {
+    accepted: Instant,
+    // latest sample received since then, applied once the interval elapses;
+    // kept when not held so that its strings are reused
+    pending: VehicleInfo,
+    held: bool,
+}
+
+fn count_dropped(metrics: &mut IngestMetrics, id: &str) {
+    metrics.dropped += 1;
+    match metrics.dropped_by_vehicle.get_mut(id) {
+        Some(n) => *n += 1,
+        None => { metrics.dropped_by_vehicle.insert(id.into(), 1); },
+    }
+}
+
+// Lets through at most `max_rate` samples per second per vehicle. Samples
+// arriving too early are held back, each one replacing (dropping) the previous,
+// so the latest position is never lost. Samples are copied in place, so that
+// only the first sample of a vehicle allocates.
+pub struct Downsampler {
+    interval: Option<Duration>,
+    slots: HashMap<String, Slot>,
+}
+
+impl Downsampler {
+    // A `max_rate` of 0 disables the limit.
+    pub fn new(max_rate: f64) -> Self {
+        let interval = (max_rate > 0.0).then(|| Duration::from_secs_f64(1.0 / max_rate));
+        Downsampler { interval, slots: HashMap::new() }
+    }
+
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval
+    }
+
+    // Whether the sample can be applied right away, otherwise it is held back.
+    pub fn offer(&mut self, vi: &VehicleInfo, now: Instant, metrics: &mut IngestMetrics) -> bool {
+        metrics.received += 1;
+        let Some(interval) = self.interval else {
+            metrics.accepted += 1;
+            return true;
+        };
+        let Some(slot) = self.slots.get_mut(&vi.id) else {
+            self.slots.insert(vi.id.clone(), Slot { accepted: now, pending: vi.clone(), held: false });
+            metrics.accepted += 1;
+            return true;
+        };
+        // the held back sample is superseded either way
+        if slot.held {
+            count_dropped(metrics, &vi.id);
+        }
+        let early = now.duration_since(slot.accepted) < interval;
+        if early {
+            slot.pending.copy_from(vi);
+        } else {
+            slot.accepted = now;
+            metrics.accepted += 1;
+        }
+        slot.held = early;
+        !early
+    }
+
+    // Lets the sample of `id` through whatever the rate, dropping any held back one.
+    pub fn pass(&mut self, id: &str, now: Instant, metrics: &mut IngestMetrics) {
+        metrics.received += 1;
+        metrics.accepted += 1;
+        if let Some(slot) = self.slots.get_mut(id) {
+            slot.accepted = now;
+            if slot.held {
+                slot.held = false;
+                count_dropped(metrics, id);
+            }
+        }
+    }
+
+    // Forgets the vehicle, dropping its held back sample if any.
+    pub fn forget(&mut self, id: &str) {
+        self.slots.remove(id);
+    }
+
+    // Forgets every vehicle, dropping the held back samples.
+    pub fn reset(&mut self) {
+        self.slots.clear();
+    }
+
+    // Applies the held back samples whose interval elapsed.
+    pub fn flush(&mut self, now: Instant, metrics: &mut IngestMetrics, mut apply: impl FnMut(&VehicleInfo)) {
+        let Some(interval) = self.interval else { return };
+        for s in self.slots.values_mut().filter(|s| s.held && now.duration_since(s.accepted) >= interval) {
+            s.accepted = now;
+            s.held = false;
+            metrics.accepted += 1;
+            apply(&s.pending);
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use serde::Serialize;
+use serde_json::json;
+use zenoh::prelude::r#async::*;
+use zdemo_common::Instance;
+use tracing::warn;
+use crate::VehicleMap;
+use crate::admin::Thresholds;
+use crate::clock;
+use crate::digest::SharedDigest;
+use crate::matching::Matching;
+use crate::metrics::SharedMetrics;
+use crate::sinks::SinkQueues;
+use crate::vehicle::DataQuality;
+
+// What is answered on <introspection_key>/<instance id>/<item>.
+pub const ITEMS: [&str; 5] = ["vehicles", "pairs", "sinks", "config", "subscribers"];
+
+// The thresholds in use, kept up to date by the compute loop.
+pub type SharedThresholds = Arc<Mutex<Thresholds>>;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct LastSeen {
+    // ms since the UNIX epoch of the last fix, and since then
+    pub ts: u64,
+    pub age_ms: u64,
+    pub stale: bool,
+    pub quality: DataQuality,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Publication {
+    pub key: String,
+    pub subscribers: bool,
+}
+
+// The internals of the tracker it reads, without changing any.
+pub struct Internals {
+    pub vehicles: VehicleMap,
+    pub digest: SharedDigest,
+    pub sinks: SinkQueues,
+    pub metrics: SharedMetrics,
+    // the publications computation follows
+    pub publications: Vec<Matching>,
+    pub thresholds: SharedThresholds,
+    // the command line
+    pub args: Vec<String>,
+}
+
+impl Internals {
+    pub async fn item(&self, name: &str) -> Option<serde_json::Value> {
+        let value = match name {
+            "vehicles" => {
+                let (now, unix_ms) = (clock::now(), clock::unix_ms());
+                let map = self.vehicles.snapshot();
+                let seen: BTreeMap<&String, LastSeen> = map.iter().map(|(id, v)| {
+                    let age_ms = v.age(now, unix_ms).as_millis() as u64;
+                    (id, LastSeen { ts: unix_ms.saturating_sub(age_ms), age_ms, stale: v.stale_since.is_some(), quality: v.quality })
+                }).collect();
+                json!({ "vehicles": seen })
+            },
+            "pairs" => json!({ "pairs": self.digest.lock().unwrap().current() }),
+            "sinks" => json!({ "sinks": self.sinks.depths() }),
+            "config" => json!({ "args": self.args, "thresholds": *self.thresholds.lock().unwrap() }),
+            "subscribers" => {
+                let publications: Vec<Publication> = self.publications.iter().flat_map(Matching::keys)
+                    .map(|(key, subscribers)| Publication { key, subscribers })
+                    .collect();
+                json!({ "subscriptions": self.metrics.lock().await.subscriptions, "publications": publications })
+            },
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+// Answers the queries on <key>/<instance id>/<item>, as the admin space of
+// Zenoh does, with the items they select, each a JSON object.
+pub async fn serve(z: Arc<Session>, key: String, instance: Instance, internals: Internals) {
+    let prefix = format!("{key}/{}", instance.id);
+    let keys: Vec<(&str, KeyExpr)> = ITEMS.iter()
+        .map(|item| (*item, KeyExpr::try_from(format!("{prefix}/{item}")).unwrap()))
+        .collect();
+    let queryable = z.declare_queryable(format!("{prefix}/*")).res().await.unwrap();
+    while let Ok(query) = queryable.recv_async().await {
+        for (item, k) in keys.iter().filter(|(_, k)| query.key_expr().intersects(k)) {
+            let value = instance.tag(internals.item(item).await.unwrap());
+            let sample = Sample::new(k.clone(), Value::from(value.to_string().into_bytes()).encoding(Encoding::APP_JSON));
+            if let Err(e) = query.reply(Ok(sample)).res().await {
+                warn!("Unable to reply to query on {k}: {e}");
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use zenoh::prelude::{keyexpr, OwnedKeyExpr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyUse {
+    // subscriptions and queries, wildcards allowed
+    Select,
+    // publications and queryables replying on their own key, wildcards refused
+    Concrete,
+}
+
+// Checks `key` against Zenoh's key expression rules, reporting the flag (or
+// config entry) it comes from and a hint on how to fix it.
+pub fn check(origin: &str, key: &str, usage: KeyUse) -> Result<(), String> {
+    match keyexpr::new(key) {
+        Ok(ke) => {
+            if usage == KeyUse::Concrete && ke.is_wild() {
+                Err(format!("{origin} '{key}' must not contain wildcards ('*', '**' or '$*')"))
+            } else {
+                Ok(())
+            }
+        },
+        Err(e) => {
+            // zenoh errors end with the source location they were raised at
+            let e = e.to_string();
+            let reason = e.split(" at /").next().unwrap_or(&e).trim_end_matches('.');
+            Err(format!("{origin} '{key}' is not a valid key expression: {reason}{}", hint(key)))
+        },
+    }
+}
+
+fn hint(key: &str) -> String {
+    let mut fixed = key.trim_matches('/').to_string();
+    while fixed.contains("//") {
+        fixed = fixed.replace("//", "/");
+    }
+    if key.contains('#') || key.contains('?') {
+        return "\n  hint: '#' and '?' are reserved, selector parameters are only accepted by queries".into();
+    }
+    if key.is_empty() {
+        return "\n  hint: key expressions cannot be empty".into();
+    }
+    match OwnedKeyExpr::autocanonize(fixed.clone()) {
+        Ok(k) if k.as_str() == key => String::new(),
+        Ok(k) => format!("\n  hint: did you mean '{k}'?"),
+        Err(_) if fixed != key => format!("\n  hint: remove leading, trailing or repeated '/' ('{fixed}')"),
+        Err(_) => String::new(),
+    }
+}
+
+// Runs all checks, returning every error found rather than the first one.
+pub fn check_all<'a>(checks: impl IntoIterator<Item = (&'a str, &'a str, KeyUse)>) -> Result<(), Vec<String>> {
+    let errors: Vec<String> = checks.into_iter()
+        .filter_map(|(origin, key, usage)| check(origin, key, usage).err())
+        .collect();
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
@@ -0,0 +1,83 @@
+use std::fmt;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+// Longer kinds are rejected, they are free text published in every state.
+pub const MAX_KIND_LEN: usize = 32;
+
+// The kind of a vehicle, the ones frontends know how to draw, or any other
+// made of lower case letters, digits, '-' and '_'. Other("") is the kind of
+// vehicles which never published a valid one.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum VehicleKind {
+    Car,
+    Bus,
+    Truck,
+    Tow,
+    Bicycle,
+    Pedestrian,
+    Drone,
+    Beacon,
+    Other(String),
+}
+
+const KNOWN: [VehicleKind; 8] = [
+    VehicleKind::Car, VehicleKind::Bus, VehicleKind::Truck, VehicleKind::Tow,
+    VehicleKind::Bicycle, VehicleKind::Pedestrian, VehicleKind::Drone, VehicleKind::Beacon,
+];
+
+impl Default for VehicleKind {
+    fn default() -> Self {
+        VehicleKind::Other(String::new())
+    }
+}
+
+impl VehicleKind {
+    // Whatever the case and surrounding spaces.
+    pub fn parse(kind: &str) -> Result<VehicleKind, String> {
+        let k = kind.trim();
+        if let Some(known) = KNOWN.iter().find(|known| known.as_str().eq_ignore_ascii_case(k)) {
+            return Ok(known.clone());
+        }
+        if k.is_empty() || k.len() > MAX_KIND_LEN || !k.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(format!("invalid kind '{kind}', expecting 1 to {MAX_KIND_LEN} letters, digits, '-' or '_'"));
+        }
+        Ok(VehicleKind::Other(k.to_ascii_lowercase()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            VehicleKind::Car => "car",
+            VehicleKind::Bus => "bus",
+            VehicleKind::Truck => "truck",
+            VehicleKind::Tow => "tow",
+            VehicleKind::Bicycle => "bicycle",
+            VehicleKind::Pedestrian => "pedestrian",
+            VehicleKind::Drone => "drone",
+            VehicleKind::Beacon => "beacon",
+            VehicleKind::Other(k) => k,
+        }
+    }
+}
+
+impl fmt::Display for VehicleKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for VehicleKind {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(self.as_str())
+    }
+}
+
+// "" being the unknown kind.
+impl<'de> Deserialize<'de> for VehicleKind {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<str>>::deserialize(d)?;
+        if s.is_empty() {
+            return Ok(VehicleKind::default());
+        }
+        VehicleKind::parse(&s).map_err(serde::de::Error::custom)
+    }
+}
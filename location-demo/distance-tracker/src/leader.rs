@@ -0,0 +1,105 @@
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use zenoh::prelude::r#async::*;
+use tracing::{info, warn};
+
+// Whether this instance publishes, shared by the tasks that do. Instances
+// without leader election always lead.
+#[derive(Clone, Debug)]
+pub struct Leadership(Arc<AtomicBool>);
+
+impl Default for Leadership {
+    fn default() -> Self {
+        Leadership(Arc::new(AtomicBool::new(true)))
+    }
+}
+
+impl Leadership {
+    // Until the first election, so that a starting instance never publishes
+    // alongside the leader.
+    pub fn standby() -> Self {
+        Leadership(Arc::new(AtomicBool::new(false)))
+    }
+
+    pub fn leads(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn set(&self, leads: bool) {
+        self.0.store(leads, Ordering::Relaxed);
+    }
+}
+
+// The live instances of a group of redundant trackers, the smallest id
+// leading: every instance elects the same one without exchanging more than
+// their presence.
+#[derive(Debug)]
+pub struct Election {
+    me: String,
+    alive: BTreeSet<String>,
+}
+
+impl Election {
+    pub fn new(me: &str) -> Self {
+        Election { me: me.into(), alive: BTreeSet::from([me.to_string()]) }
+    }
+
+    pub fn join(&mut self, id: &str) {
+        self.alive.insert(id.into());
+    }
+
+    // Itself never leaves.
+    pub fn leave(&mut self, id: &str) {
+        if id != self.me {
+            self.alive.remove(id);
+        }
+    }
+
+    pub fn leader(&self) -> &str {
+        self.alive.first().unwrap()
+    }
+
+    pub fn leads(&self) -> bool {
+        self.leader() == self.me
+    }
+}
+
+// Declares the presence of the instance as a liveliness token on <key>/<id>
+// and follows those of the others, the elected leader taking over as soon as
+// the previous one loses its token (on exit or when its session is lost).
+pub async fn elect(z: Arc<Session>, key: String, id: String, leadership: Leadership) {
+    let _token = z.liveliness().declare_token(format!("{key}/{id}")).res().await.unwrap_or_else(|e| panic!("--leader-key: {e}"));
+    let members = format!("{key}/*");
+    let subscriber = z.liveliness().declare_subscriber(&members).res().await.unwrap();
+    let mut election = Election::new(&id);
+    let prefix = format!("{key}/");
+    let member = |k: &KeyExpr| k.as_str().strip_prefix(&prefix).unwrap_or("").to_string();
+    match z.liveliness().get(&members).res().await {
+        Ok(replies) => {
+            while let Ok(reply) = replies.recv_async().await {
+                if let Ok(s) = reply.sample {
+                    election.join(&member(&s.key_expr));
+                }
+            }
+        },
+        Err(e) => warn!("Unable to query the tracker instances on {members}: {e}"),
+    }
+    let mut elected = None;
+    loop {
+        if elected.as_deref() != Some(election.leader()) {
+            if election.leads() {
+                info!("LEADER: {id} leads, publishing");
+            } else {
+                info!("LEADER: {} leads, {id} on standby", election.leader());
+            }
+            leadership.set(election.leads());
+            elected = Some(election.leader().to_string());
+        }
+        match subscriber.recv_async().await {
+            Ok(s) if s.kind == SampleKind::Put => election.join(&member(&s.key_expr)),
+            Ok(s) => election.leave(&member(&s.key_expr)),
+            Err(_) => break,
+        }
+    }
+}
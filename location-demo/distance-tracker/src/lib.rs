@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+use snapshot::Snapshots;
+use telemetry::Telemetry;
+use vehicle::VehicleState;
+
+pub mod admin;
+pub mod advisory;
+pub mod analyze;
+pub mod backfill;
+pub mod cbor;
+pub mod client;
+pub mod clock;
+pub mod closing;
+pub mod clusters;
+pub mod coldstart;
+pub mod color;
+pub mod cutin;
+pub mod dedup;
+pub mod diag;
+pub mod digest;
+pub mod delay;
+pub mod display;
+pub mod estop;
+pub mod features;
+pub mod federation;
+pub mod feeds;
+pub mod flagged;
+pub mod fleets;
+pub mod foxglove;
+pub mod geo;
+pub mod geojson;
+pub mod geometry;
+pub mod grpc;
+pub mod handover;
+pub mod heatmap;
+pub mod headway;
+pub mod http;
+pub mod identity;
+pub mod incidents;
+pub mod geohash;
+pub mod influx;
+pub mod ingest;
+pub mod introspection;
+pub mod keys;
+pub mod kind;
+pub mod leader;
+pub mod lights;
+pub mod logging;
+pub mod matching;
+pub mod matrix;
+pub mod mcap;
+pub mod metadata;
+pub mod metrics;
+pub mod outputs;
+pub mod pacing;
+pub mod pairing;
+pub mod plausibility;
+pub mod poi;
+pub mod publish;
+pub mod pull;
+pub mod qos;
+pub mod query;
+pub mod record;
+pub mod retention;
+pub mod risk;
+pub mod roi;
+pub mod routes;
+pub mod runtime_state;
+pub mod rules;
+pub mod scenario;
+pub mod sectors;
+pub mod session;
+pub mod severity;
+pub mod shard;
+pub mod shm;
+pub mod signing;
+pub mod smooth;
+pub mod snapshot;
+pub mod sinks;
+pub mod status;
+pub mod stdin;
+pub mod storm;
+pub mod summary;
+pub mod subscriptions;
+pub mod telemetry;
+pub mod tether;
+pub mod tools;
+pub mod track;
+pub mod tracker;
+pub mod trail;
+pub mod transport;
+pub mod vehicle;
+pub mod warmstart;
+pub mod zones;
+
+pub use color::Color;
+pub use geo::{angle_diff, Position};
+pub use kind::VehicleKind;
+pub use tracker::{run, Settings};
+
+#[derive (Serialize, Deserialize, Debug, Clone, Default)]
+pub struct VehicleInfo {
+    pub position: Position,
+    pub speed: f64,
+    pub color: Color,
+    pub id: String,
+    pub kind: VehicleKind,
+    // safety bubble (m) the vehicle declares, replacing the min and max distances
+    // of its pairs when larger than the other vehicle's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_distance: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_distance: Option<f64>,
+    // wall clock time the position was published at, ms since the UNIX epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ts: Option<u64>,
+    // fleet whose key the position was received on, set by the tracker
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fleet: Option<String>,
+    // battery charge, temperature, payload weight... of the last position
+    #[serde(default, skip_serializing_if = "Telemetry::is_empty")]
+    pub telemetry: Telemetry,
+}
+
+impl VehicleInfo {
+    // Copies `other`, reusing the strings already allocated.
+    pub fn copy_from(&mut self, other: &VehicleInfo) {
+        self.position = other.position;
+        self.speed = other.speed;
+        if self.color != other.color {
+            self.color.clone_from(&other.color);
+        }
+        self.id.clone_from(&other.id);
+        if self.kind != other.kind {
+            self.kind.clone_from(&other.kind);
+        }
+        self.min_distance = other.min_distance;
+        self.max_distance = other.max_distance;
+        self.ts = other.ts;
+        self.fleet.clone_from(&other.fleet);
+        if self.telemetry != other.telemetry {
+            self.telemetry.clone_from(&other.telemetry);
+        }
+    }
+}
+
+// The vehicles, updated by the ingestion and read by the compute loop, queries
+// and the HTTP API through snapshots.
+pub type VehicleMap = Arc<Snapshots<HashMap<String, VehicleState>>>;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertLevel { Alert, Danger }
+
+// The threshold a distance alert crossed: proximity alerts are raised by
+// vehicles too close to each other, separation alerts by vehicles too far
+// apart. Published as the AlertMin, DangerMin, AlertMax and DangerMax tags
+// consumers and recordings already use.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(into = "&'static str", try_from = "String")]
+pub enum AlertKind {
+    Proximity { level: AlertLevel },
+    Separation { level: AlertLevel },
+}
+
+impl AlertKind {
+    pub fn proximity(level: AlertLevel) -> Self {
+        AlertKind::Proximity { level }
+    }
+
+    pub fn separation(level: AlertLevel) -> Self {
+        AlertKind::Separation { level }
+    }
+
+    pub fn level(&self) -> AlertLevel {
+        match self {
+            AlertKind::Proximity { level } | AlertKind::Separation { level } => *level,
+        }
+    }
+
+    pub fn is_danger(&self) -> bool {
+        self.level() == AlertLevel::Danger
+    }
+
+    pub fn tag(&self) -> &'static str {
+        match self {
+            AlertKind::Proximity { level: AlertLevel::Alert } => "AlertMin",
+            AlertKind::Proximity { level: AlertLevel::Danger } => "DangerMin",
+            AlertKind::Separation { level: AlertLevel::Alert } => "AlertMax",
+            AlertKind::Separation { level: AlertLevel::Danger } => "DangerMax",
+        }
+    }
+}
+
+impl From<AlertKind> for &'static str {
+    fn from(kind: AlertKind) -> Self {
+        kind.tag()
+    }
+}
+
+impl TryFrom<String> for AlertKind {
+    type Error = String;
+
+    fn try_from(tag: String) -> Result<Self, String> {
+        match tag.as_str() {
+            "AlertMin" => Ok(AlertKind::proximity(AlertLevel::Alert)),
+            "DangerMin" => Ok(AlertKind::proximity(AlertLevel::Danger)),
+            "AlertMax" => Ok(AlertKind::separation(AlertLevel::Alert)),
+            "DangerMax" => Ok(AlertKind::separation(AlertLevel::Danger)),
+            _ => Err(format!("unknown alert kind '{tag}', expecting AlertMin, DangerMin, AlertMax or DangerMax")),
+        }
+    }
+}
+
+#[derive (Serialize, Deserialize, Debug, Clone)]
+pub struct DistanceAlert {
+    pub ida: String,
+    pub idb: String,
+    pub distance: f64,
+    // bearing (deg) from ida to idb
+    #[serde(default)]
+    pub bearing: f64,
+    // rate (m/s) at which the distance changes, negative when the pair converges,
+    // unknown until the pair was computed twice
+    #[serde(default)]
+    pub range_rate: Option<f64>,
+    // severity band and its color, with --severities
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub severity: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    pub kind: AlertKind
+}
@@ -0,0 +1,61 @@
+use serde::{Serialize, Deserialize};
+use crate::Position;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LightState { Green, Yellow, Red }
+
+// State of a traffic light, published on <lights_key>/<name>.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LightStatus {
+    pub name: String,
+    pub position: Position,
+    pub state: LightState,
+}
+
+// A simulated traffic light cycling green, yellow, red. Durations in seconds,
+// `offset` shifts the cycle to coordinate neighbouring lights.
+#[derive(Deserialize, Debug, Clone)]
+pub struct TrafficLight {
+    pub name: String,
+    // [lat, lng]
+    pub position: [f64; 2],
+    pub green: f64,
+    pub yellow: f64,
+    pub red: f64,
+    #[serde(default)]
+    pub offset: f64,
+}
+
+impl TrafficLight {
+    // State `t` seconds after the start of the simulation.
+    pub fn state_at(&self, t: f64) -> LightState {
+        let t = (t + self.offset).rem_euclid(self.green + self.yellow + self.red);
+        if t < self.green {
+            LightState::Green
+        } else if t < self.green + self.yellow {
+            LightState::Yellow
+        } else {
+            LightState::Red
+        }
+    }
+
+    pub fn status(&self, t: f64) -> LightStatus {
+        LightStatus {
+            name: self.name.clone(),
+            position: Position { lat: self.position[0], lng: self.position[1] },
+            state: self.state_at(t),
+        }
+    }
+}
+
+pub fn load(path: &str) -> Result<Vec<TrafficLight>, String> {
+    let s = std::fs::read_to_string(path).map_err(|e| format!("Unable to read {path}: {e}"))?;
+    let lights: Vec<TrafficLight> = serde_json::from_str(&s).map_err(|e| format!("Invalid traffic lights file {path}: {e}"))?;
+    for l in &lights {
+        if [l.green, l.yellow, l.red].iter().any(|d| *d < 0.0) || l.green + l.yellow + l.red <= 0.0 {
+            return Err(format!("traffic light '{}' needs a positive cycle", l.name));
+        }
+    }
+    Ok(lights)
+}
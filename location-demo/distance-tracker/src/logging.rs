@@ -0,0 +1,25 @@
+use std::io::IsTerminal;
+use tracing_subscriber::EnvFilter;
+
+// Zenoh logs its own operations at info level.
+const DEFAULT_FILTER: &str = "info,zenoh=warn";
+
+#[derive(clap_derive::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// One human readable line per event
+    #[default]
+    Text,
+    /// One JSON object per event, with its fields and spans, for log aggregators
+    Json,
+}
+
+// Logs to stdout what RUST_LOG selects (e.g. `distance_tracker=debug,alert=warn`),
+// info and above by default, in color on terminals.
+pub fn init(format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let logs = tracing_subscriber::fmt().with_env_filter(filter).with_writer(std::io::stdout).with_ansi(std::io::stdout().is_terminal());
+    match format {
+        LogFormat::Text => logs.init(),
+        LogFormat::Json => logs.json().flatten_event(true).with_current_span(true).with_span_list(false).init(),
+    }
+}
@@ -0,0 +1,57 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::task::JoinHandle;
+use zenoh::prelude::r#async::*;
+use tracing::{info, warn};
+
+// Whether anybody subscribes to what is published on a key expression, kept up
+// to date by a matching listener. Until the status is known, or if it cannot be
+// watched, subscribers are assumed to be there. Several key expressions are
+// watched together with `or`.
+#[derive(Clone)]
+pub struct Matching(Vec<(String, Arc<AtomicBool>)>);
+
+impl Matching {
+    pub fn watch(z: Arc<Session>, key: String) -> (Self, JoinHandle<()>) {
+        let flag = Arc::new(AtomicBool::new(true));
+        let f = flag.clone();
+        let key_name = key.clone();
+        let handle = tokio::spawn(async move {
+            let publisher = match z.declare_publisher(key.clone()).res().await {
+                Ok(p) => p,
+                Err(e) => return warn!("unable to watch subscribers of {key}: {e}"),
+            };
+            let listener = match publisher.matching_listener().res().await {
+                Ok(l) => l,
+                Err(e) => return warn!("unable to watch subscribers of {key}: {e}"),
+            };
+            let set = |matching: bool| {
+                if f.swap(matching, Ordering::Relaxed) != matching {
+                    info!("MATCHING: {key} has {}subscribers", if matching { "" } else { "no " });
+                }
+            };
+            if let Ok(s) = publisher.matching_status().res().await {
+                set(s.matching_subscribers());
+            }
+            while let Ok(s) = listener.recv_async().await {
+                set(s.matching_subscribers());
+            }
+        });
+        (Matching(vec![(key_name, flag)]), handle)
+    }
+
+    pub fn any(&self) -> bool {
+        self.0.iter().any(|(_, f)| f.load(Ordering::Relaxed))
+    }
+
+    // The key expressions watched, with whether they have subscribers.
+    pub fn keys(&self) -> Vec<(String, bool)> {
+        self.0.iter().map(|(k, f)| (k.clone(), f.load(Ordering::Relaxed))).collect()
+    }
+
+    // Whether anybody subscribes to the keys of either.
+    pub fn or(mut self, other: Matching) -> Matching {
+        self.0.extend(other.0);
+        self
+    }
+}
@@ -0,0 +1,43 @@
+use std::collections::HashMap;
+use serde::Serialize;
+use crate::geo::DistanceAlgo;
+use crate::vehicle::{self, VehicleState};
+
+// The distances between the vehicles of a compute cycle, for analytics jobs
+// computing their own statistics. `ids` are sorted, and the distances (m)
+// refer to them by index: either the full matrix, row i holding the distances
+// from ids[i], or with a cap the pairs [i, j, distance] with i < j closer than
+// the cap.
+#[derive(Serialize, Debug)]
+pub struct DistanceMatrix {
+    // ms since the UNIX epoch
+    pub ts: u64,
+    pub ids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distances: Option<Vec<Vec<f64>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pairs: Option<Vec<(usize, usize, f64)>>,
+}
+
+pub fn compute(map: &HashMap<String, VehicleState>, algo: DistanceAlgo, cap: Option<f64>, ts: u64) -> DistanceMatrix {
+    let live = vehicle::live(map);
+    let mut vehicles: Vec<&VehicleState> = live.values().collect();
+    vehicles.sort_by(|a, b| a.info.id.cmp(&b.info.id));
+    let distance = |i: usize, j: usize| algo.distance(&vehicles[i].info.position, &vehicles[j].info.position);
+    let n = vehicles.len();
+    let (distances, pairs) = match cap {
+        None => {
+            let rows = (0..n).map(|i| (0..n).map(|j| if i == j { 0.0 } else { distance(i, j) }).collect()).collect();
+            (Some(rows), None)
+        },
+        Some(cap) => {
+            let pairs = (0..n)
+                .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+                .map(|(i, j)| (i, j, distance(i, j)))
+                .filter(|(_, _, d)| *d <= cap)
+                .collect();
+            (None, Some(pairs))
+        },
+    };
+    DistanceMatrix { ts, ids: vehicles.iter().map(|v| v.info.id.clone()).collect(), distances, pairs }
+}
@@ -0,0 +1,174 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+// MCAP (https://mcap.dev) files, as read by Foxglove Studio: no chunks, no
+// compression and no summary section, which readers handle by scanning the
+// data section, and no CRCs, which the format leaves optional.
+const MAGIC: &[u8] = b"\x89MCAP0\r\n";
+const OP_HEADER: u8 = 0x01;
+const OP_FOOTER: u8 = 0x02;
+const OP_SCHEMA: u8 = 0x03;
+const OP_CHANNEL: u8 = 0x04;
+const OP_MESSAGE: u8 = 0x05;
+const OP_DATA_END: u8 = 0x0f;
+
+// The schema of positions, Foxglove's LocationFix, shown by its Map panel.
+pub const LOCATION_FIX: (&str, &str) = ("foxglove.LocationFix", r#"{
+  "title": "foxglove.LocationFix",
+  "type": "object",
+  "properties": {
+    "timestamp": {"type": "object", "properties": {"sec": {"type": "integer"}, "nsec": {"type": "integer"}}},
+    "frame_id": {"type": "string"},
+    "latitude": {"type": "number"},
+    "longitude": {"type": "number"},
+    "altitude": {"type": "number"},
+    "position_covariance": {"type": "array", "items": {"type": "number"}},
+    "position_covariance_type": {"type": "integer"}
+  }
+}"#);
+// The schema of alerts, which share no more than being JSON objects.
+pub const ALERT: (&str, &str) = ("distance_tracker.Alert", r#"{
+  "title": "distance_tracker.Alert",
+  "type": "object",
+  "properties": {
+    "kind": {"type": "string"},
+    "ida": {"type": "string"},
+    "idb": {"type": "string"},
+    "id": {"type": "string"},
+    "distance": {"type": "number"}
+  }
+}"#);
+
+// Writes JSON messages on one channel per topic, registering the schemas and
+// channels as they are first used.
+pub struct McapWriter<W: Write> {
+    out: W,
+    schemas: HashMap<&'static str, u16>,
+    channels: HashMap<String, u16>,
+    sequence: u32,
+    // bytes written so far
+    pub written: u64,
+}
+
+impl<W: Write> McapWriter<W> {
+    pub fn new(out: W) -> io::Result<Self> {
+        let mut w = McapWriter { out, schemas: HashMap::new(), channels: HashMap::new(), sequence: 0, written: 0 };
+        w.raw(MAGIC)?;
+        let mut header = Vec::new();
+        string(&mut header, "");
+        string(&mut header, concat!("distance-tracker ", env!("CARGO_PKG_VERSION")));
+        w.record(OP_HEADER, &header)?;
+        Ok(w)
+    }
+
+    fn raw(&mut self, bs: &[u8]) -> io::Result<()> {
+        self.out.write_all(bs)?;
+        self.written += bs.len() as u64;
+        Ok(())
+    }
+
+    fn record(&mut self, op: u8, content: &[u8]) -> io::Result<()> {
+        self.raw(&[op])?;
+        self.raw(&(content.len() as u64).to_le_bytes())?;
+        self.raw(content)
+    }
+
+    fn schema(&mut self, (name, data): (&'static str, &str)) -> io::Result<u16> {
+        if let Some(id) = self.schemas.get(name) {
+            return Ok(*id);
+        }
+        // schema 0 stands for no schema
+        let id = self.schemas.len() as u16 + 1;
+        let mut content = id.to_le_bytes().to_vec();
+        string(&mut content, name);
+        string(&mut content, "jsonschema");
+        bytes(&mut content, data.as_bytes());
+        self.record(OP_SCHEMA, &content)?;
+        self.schemas.insert(name, id);
+        Ok(id)
+    }
+
+    fn channel(&mut self, topic: &str, schema: Option<(&'static str, &str)>) -> io::Result<u16> {
+        if let Some(id) = self.channels.get(topic) {
+            return Ok(*id);
+        }
+        let schema_id = match schema {
+            Some(s) => self.schema(s)?,
+            None => 0,
+        };
+        let id = self.channels.len() as u16;
+        let mut content = id.to_le_bytes().to_vec();
+        content.extend(schema_id.to_le_bytes());
+        string(&mut content, topic);
+        string(&mut content, "json");
+        map(&mut content, &BTreeMap::new());
+        self.record(OP_CHANNEL, &content)?;
+        self.channels.insert(topic.to_string(), id);
+        Ok(id)
+    }
+
+    // Writes `json` on `topic`, received at `log_time` and published at
+    // `publish_time` (ns since the UNIX epoch).
+    pub fn message(&mut self, topic: &str, schema: Option<(&'static str, &str)>, log_time: u64, publish_time: u64, json: &[u8]) -> io::Result<()> {
+        let channel = self.channel(topic, schema)?;
+        let mut content = channel.to_le_bytes().to_vec();
+        content.extend(self.sequence.to_le_bytes());
+        content.extend(log_time.to_le_bytes());
+        content.extend(publish_time.to_le_bytes());
+        content.extend(json);
+        self.record(OP_MESSAGE, &content)?;
+        self.sequence = self.sequence.wrapping_add(1);
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    // Ends the file, which readers reject until then.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.record(OP_DATA_END, &0u32.to_le_bytes())?;
+        let mut footer = Vec::new();
+        footer.extend(0u64.to_le_bytes());
+        footer.extend(0u64.to_le_bytes());
+        footer.extend(0u32.to_le_bytes());
+        self.record(OP_FOOTER, &footer)?;
+        self.raw(MAGIC)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+fn string(out: &mut Vec<u8>, s: &str) {
+    bytes(out, s.as_bytes());
+}
+
+fn bytes(out: &mut Vec<u8>, bs: &[u8]) {
+    out.extend((bs.len() as u32).to_le_bytes());
+    out.extend(bs);
+}
+
+fn map(out: &mut Vec<u8>, entries: &BTreeMap<String, String>) {
+    let mut content = Vec::new();
+    for (k, v) in entries {
+        string(&mut content, k);
+        string(&mut content, v);
+    }
+    bytes(out, &content);
+}
+
+// A LocationFix of a vehicle at `lat`, `lng`, published at `ts` (ns since the
+// UNIX epoch).
+pub fn location_fix(id: &str, lat: f64, lng: f64, ts: u64) -> Vec<u8> {
+    let covariance = [0.0; 9];
+    serde_json::to_vec(&serde_json::json!({
+        "timestamp": { "sec": ts / 1_000_000_000, "nsec": ts % 1_000_000_000 },
+        "frame_id": id,
+        "latitude": lat,
+        "longitude": lng,
+        "altitude": 0.0,
+        "position_covariance": covariance,
+        // unknown
+        "position_covariance_type": 0,
+    })).unwrap()
+}
@@ -0,0 +1,148 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+use serde::{Serialize, Deserialize};
+use zenoh::prelude::r#async::*;
+use tracing::{info, warn};
+use crate::diag::{Diagnostics, Stage};
+use crate::{Color, VehicleKind};
+
+// Slow changing description of a vehicle, published once on <meta_key>/<id>.
+// Fields besides the known ones are kept as is.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct VehicleMeta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub capabilities: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    // m/s
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_speed: Option<f64>,
+    // for vehicles publishing compact positions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<VehicleKind>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<Color>,
+    // safety bubble (m), for vehicles not declaring it in their positions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_distance: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_distance: Option<f64>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+// Metadata of the registered vehicles, by id.
+#[derive(Clone, Default)]
+pub struct MetaCache(Arc<RwLock<HashMap<String, VehicleMeta>>>);
+
+impl MetaCache {
+    // Registers (or, on delete, forgets) the metadata published on <prefix>/<id>.
+    pub fn apply(&self, prefix: &str, key: &str, delete: bool, payload: &[u8]) -> Result<String, String> {
+        let id = key.strip_prefix(prefix)
+            .and_then(|s| s.strip_prefix('/'))
+            .filter(|id| !id.is_empty() && !id.contains('/'))
+            .ok_or_else(|| format!("{key} is not a metadata key, expecting {prefix}/<vehicle id>"))?;
+        if delete {
+            self.0.write().unwrap().remove(id);
+        } else {
+            let meta: VehicleMeta = serde_json::from_slice(payload).map_err(|e| format!("invalid metadata for {id}: {e}"))?;
+            self.0.write().unwrap().insert(id.into(), meta);
+        }
+        Ok(id.into())
+    }
+
+    pub fn get(&self, id: &str) -> Option<VehicleMeta> {
+        self.0.read().unwrap().get(id).cloned()
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, VehicleMeta> {
+        self.0.read().unwrap().iter().map(|(id, m)| (id.clone(), m.clone())).collect()
+    }
+
+    // Registers metadata received otherwise than from the vehicle (another
+    // tracker), unless the vehicle registered its own.
+    pub fn adopt(&self, id: &str, meta: VehicleMeta) -> bool {
+        let mut cache = self.0.write().unwrap();
+        if cache.contains_key(id) {
+            return false;
+        }
+        cache.insert(id.into(), meta);
+        true
+    }
+
+    // Runs `f` with the kind and color registered by the vehicle, if any.
+    pub fn with_profile<R>(&self, id: &str, f: impl FnOnce(Option<&VehicleMeta>) -> R) -> R {
+        f(self.0.read().unwrap().get(id))
+    }
+
+    // Adds a `meta` object, by vehicle id, to the alert for the given vehicles
+    // that registered metadata.
+    pub fn enrich(&self, alert: &mut serde_json::Value, ids: &[&str]) {
+        let cache = self.0.read().unwrap();
+        let meta: serde_json::Map<String, serde_json::Value> = ids.iter()
+            .filter_map(|id| cache.get(*id).map(|m| (id.to_string(), serde_json::to_value(m).unwrap())))
+            .collect();
+        if let (serde_json::Value::Object(fields), false) = (alert, meta.is_empty()) {
+            fields.insert("meta".into(), meta.into());
+        }
+    }
+}
+
+// Replies to queries on <prefix>/** with the metadata of the matching vehicles.
+pub async fn serve(z: Arc<Session>, prefix: String, cache: MetaCache) {
+    let queryable = z.declare_queryable(format!("{prefix}/**")).res().await.unwrap();
+    while let Ok(query) = queryable.recv_async().await {
+        let replies: Vec<(String, Vec<u8>)> = cache.0.read().unwrap().iter()
+            .map(|(id, meta)| (format!("{prefix}/{id}"), meta))
+            .filter(|(key, _)| keyexpr::new(key).is_ok_and(|k| k.intersects(query.key_expr())))
+            .map(|(key, meta)| (key, serde_json::to_vec(meta).unwrap()))
+            .collect();
+        for (key, bs) in replies {
+            let sample = Sample::new(KeyExpr::try_from(key).unwrap(), Value::from(bs).encoding(Encoding::APP_JSON));
+            if let Err(e) = query.reply(Ok(sample)).res().await {
+                warn!("Unable to reply to query on {prefix}: {e}");
+            }
+        }
+    }
+}
+
+// Keeps `cache` up to date with the metadata registered on <prefix>/<id>,
+// reporting invalid metadata to `diag` if given.
+pub async fn follow(z: Arc<Session>, prefix: String, cache: MetaCache, diag: Option<Diagnostics>) {
+    let keys = [format!("{prefix}/*")];
+    let register = |sample: &Sample| {
+        let registered = zdemo_common::payload(sample)
+            .and_then(|payload| cache.apply(&prefix, sample.key_expr.as_str(), sample.kind == SampleKind::Delete, &payload));
+        match registered {
+            Ok(id) => info!("META: {id} {}", if sample.kind == SampleKind::Delete { "unregistered" } else { "registered" }),
+            Err(e) => {
+                warn!("Unable to register vehicle: {e}");
+                if let Some(d) = &diag {
+                    d.report(Stage::Metadata, sample.key_expr.as_str(), e, Some(&sample.payload.contiguous()));
+                }
+            },
+        }
+    };
+    let (mut subs, mut rx) = zdemo_common::subscribe(&z, &keys).await;
+    // metadata published before the tracker started, kept by storages or other trackers
+    match z.get(&keys[0]).res().await {
+        Ok(replies) => {
+            while let Ok(reply) = replies.recv_async().await {
+                if let Ok(sample) = reply.sample {
+                    register(&sample);
+                }
+            }
+        },
+        Err(e) => warn!("unable to query the registered vehicles: {e}"),
+    }
+    loop {
+        while let Some(sample) = rx.recv().await {
+            register(&sample);
+        }
+        warn!("metadata subscription lost, declaring it again");
+        drop(subs);
+        (subs, rx) = zdemo_common::subscribe(&z, &keys).await;
+    }
+}
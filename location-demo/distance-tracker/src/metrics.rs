@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use serde::Serialize;
+use tokio::sync::Mutex;
+use zenoh::prelude::r#async::*;
+use zdemo_common::Instance;
+use tracing::warn;
+use crate::ingest::IngestMetrics;
+use crate::record::unix_ms;
+
+// Upper bounds (us) of the latency buckets, the last bucket holding the rest.
+const BOUNDS_US: [u64; 14] = [50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000, 1_000_000];
+
+// Latency histogram with fixed buckets, so that recording does not allocate.
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(into = "HistogramSummary")]
+pub struct Histogram {
+    counts: [u64; BOUNDS_US.len() + 1],
+    count: u64,
+    sum_us: u64,
+    max_us: u64,
+}
+
+impl Histogram {
+    pub fn record(&mut self, d: Duration) {
+        let us = d.as_micros().min(u64::MAX as u128) as u64;
+        self.counts[BOUNDS_US.partition_point(|b| *b < us)] += 1;
+        self.count += 1;
+        self.sum_us = self.sum_us.saturating_add(us);
+        self.max_us = self.max_us.max(us);
+    }
+
+    pub fn mean_us(&self) -> Option<u64> {
+        self.sum_us.checked_div(self.count)
+    }
+
+    pub fn max_us(&self) -> u64 {
+        self.max_us
+    }
+
+    // Upper bound of the bucket holding the `q` quantile, the maximum for the last one.
+    pub fn quantile(&self, q: f64) -> Option<u64> {
+        let rank = (q * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (i, c) in self.counts.iter().enumerate() {
+            seen += c;
+            if seen >= rank {
+                return Some(BOUNDS_US.get(i).map_or(self.max_us, |b| (*b).min(self.max_us)));
+            }
+        }
+        None
+    }
+}
+
+#[derive(Serialize)]
+struct Bucket {
+    // null for the last bucket
+    le_us: Option<u64>,
+    count: u64,
+}
+
+#[derive(Serialize)]
+struct HistogramSummary {
+    count: u64,
+    mean_us: Option<u64>,
+    max_us: u64,
+    p50_us: Option<u64>,
+    p90_us: Option<u64>,
+    p99_us: Option<u64>,
+    buckets: Vec<Bucket>,
+}
+
+impl From<Histogram> for HistogramSummary {
+    fn from(h: Histogram) -> Self {
+        let buckets = h.counts.iter().enumerate()
+            .map(|(i, count)| Bucket { le_us: BOUNDS_US.get(i).copied(), count: *count })
+            .collect();
+        HistogramSummary {
+            count: h.count,
+            mean_us: h.mean_us(),
+            max_us: h.max_us,
+            p50_us: h.quantile(0.5),
+            p90_us: h.quantile(0.9),
+            p99_us: h.quantile(0.99),
+            buckets,
+        }
+    }
+}
+
+// Latency of each stage of the pipeline.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct PipelineLatency {
+    // from the reception of a sample to its decoded and verified position
+    pub ingest: Histogram,
+    // downsampling and applying the position to the vehicle map, waiting for its lock included
+    pub map_update: Histogram,
+    // evaluation of the rules on every compute cycle
+    pub compute: Histogram,
+    // from queuing a publication to the end of its put
+    pub publish: Histogram,
+    // from the source timestamp of a sample (its ts field, or else its Zenoh
+    // timestamp) to its reception, the clocks being assumed in sync
+    pub end_to_end: Histogram,
+    // samples timestamped after their reception, by a clock ahead of the tracker's
+    pub clock_ahead: u64,
+}
+
+impl PipelineLatency {
+    pub fn record_end_to_end(&mut self, source: SystemTime, received: SystemTime) {
+        match received.duration_since(source) {
+            Ok(d) => self.end_to_end.record(d),
+            Err(_) => self.clock_ahead += 1,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct LastError {
+    pub message: String,
+    // wall clock time, milliseconds since the UNIX epoch
+    pub at: u64,
+    #[serde(skip)]
+    pub seen: Instant,
+}
+
+// Counters of the tracker, returned by queries on the metrics key.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Metrics {
+    pub ingest: IngestMetrics,
+    pub latency: PipelineLatency,
+    // vehicles in the map at the last compute cycle
+    pub vehicles: usize,
+    // position key expressions subscribed to
+    pub subscriptions: Vec<String>,
+    // alerts raised, by type
+    pub alerts: BTreeMap<String, u64>,
+    pub errors: u64,
+    pub last_error: Option<LastError>,
+    // period (ms) of the compute loop, raised under load with --auto-period
+    pub compute_period_ms: u64,
+    // compute cycles which took longer than the period
+    pub compute_overruns: u64,
+    // id of the current demo session, with --session-duration
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    #[serde(skip)]
+    pub last_cycle: Option<Instant>,
+}
+
+impl Metrics {
+    pub fn error(&mut self, message: impl Into<String>) {
+        self.errors += 1;
+        self.last_error = Some(LastError { message: message.into(), at: unix_ms(), seen: Instant::now() });
+    }
+}
+
+pub type SharedMetrics = Arc<Mutex<Metrics>>;
+
+async fn value(metrics: &SharedMetrics, instance: &Instance) -> Value {
+    let value = instance.tag(serde_json::to_value(&*metrics.lock().await).unwrap());
+    Value::from(value.to_string().into_bytes()).encoding(Encoding::APP_JSON)
+}
+
+pub async fn serve(z: Arc<Session>, key: String, metrics: SharedMetrics, instance: Instance) {
+    let key = KeyExpr::try_from(key).unwrap();
+    let queryable = z.declare_queryable(&key).res().await.unwrap();
+    while let Ok(query) = queryable.recv_async().await {
+        let sample = Sample::new(key.clone(), value(&metrics, &instance).await);
+        if let Err(e) = query.reply(Ok(sample)).res().await {
+            warn!("Unable to reply to query on {key}: {e}");
+        }
+    }
+}
+
+// Publishes the metrics on `key` every `period`.
+pub async fn publish(z: Arc<Session>, key: String, metrics: SharedMetrics, instance: Instance, period: Duration) {
+    let mut tick = tokio::time::interval(period);
+    tick.tick().await;
+    loop {
+        tick.tick().await;
+        if let Err(e) = z.put(&key, value(&metrics, &instance).await).res().await {
+            warn!("Unable to publish the stats on {key}: {e}");
+        }
+    }
+}
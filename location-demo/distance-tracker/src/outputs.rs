@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::warn;
+use crate::keys::{self, KeyUse};
+use crate::publish::Outgoing;
+
+// Names of the rules, as in Alert::name.
+const RULES: [&str; 9] = ["distance", "cutin", "closing", "speed", "route", "tether", "poi", "sector", "battery"];
+
+// Where and how a rule publishes its alerts, for consumers with fixed topic
+// contracts. `key` is a template where {field} is replaced by that field of
+// the alert, e.g. "fleet/proximity/{ida}/{idb}", and `fields` the fields of the
+// alert published, all of them when empty.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RuleOutput {
+    #[serde(default)]
+    pub key: Option<String>,
+    #[serde(default)]
+    pub fields: Vec<String>,
+}
+
+// Outputs by rule name, the rules not listed keeping their keys and payloads.
+#[derive(Debug, Clone, Default)]
+pub struct Outputs(HashMap<String, RuleOutput>);
+
+// Splits a key template into its literal parts and placeholders.
+fn parse(template: &str) -> Result<Vec<(&str, Option<&str>)>, String> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..].find('}').map(|i| start + i).ok_or_else(|| format!("unclosed placeholder in '{template}'"))?;
+        let field = &rest[start + 1..end];
+        if field.is_empty() || field.contains('{') {
+            return Err(format!("invalid placeholder in '{template}'"));
+        }
+        parts.push((&rest[..start], Some(field)));
+        rest = &rest[end + 1..];
+    }
+    if rest.contains('}') {
+        return Err(format!("unopened placeholder in '{template}'"));
+    }
+    parts.push((rest, None));
+    Ok(parts)
+}
+
+// The key expression matching every key of a template, its chunks with a
+// placeholder becoming wildcards.
+fn selector(template: &str) -> String {
+    template.split('/').map(|chunk| if chunk.contains('{') { "*" } else { chunk }).collect::<Vec<_>>().join("/")
+}
+
+// Reads a JSON object of {<rule>: {key, fields}}.
+pub fn load(path: &str) -> Result<Outputs, String> {
+    let s = std::fs::read_to_string(path).map_err(|e| format!("Unable to read {path}: {e}"))?;
+    let outputs: HashMap<String, RuleOutput> = serde_json::from_str(&s).map_err(|e| format!("Invalid outputs file {path}: {e}"))?;
+    for (rule, output) in &outputs {
+        if !RULES.contains(&rule.as_str()) {
+            return Err(format!("unknown rule '{rule}', expecting one of {}", RULES.join(", ")));
+        }
+        if let Some(template) = &output.key {
+            let usage = if parse(template)?.len() > 1 { KeyUse::Select } else { KeyUse::Concrete };
+            keys::check(&format!("key of rule '{rule}'"), &selector(template), usage)?;
+        }
+    }
+    Ok(Outputs(outputs))
+}
+
+impl Outputs {
+    // The key expression the alerts of `rule` are published on, when changed.
+    pub fn selector(&self, rule: &str) -> Option<String> {
+        self.0.get(rule).and_then(|o| o.key.as_deref()).map(selector)
+    }
+
+    // Publishes `out`, an alert of `rule`, on the key and with the fields of
+    // the rule's output. Alerts which do not fit the template (a missing field,
+    // an id which is not a key chunk) keep their key.
+    pub fn apply(&self, rule: &str, mut out: Outgoing) -> Outgoing {
+        let Some(output) = self.0.get(rule) else { return out };
+        if let Some(template) = &output.key {
+            match render(template, &out.value) {
+                Ok(key) => out.key = key,
+                Err(e) => warn!("{e}, publishing on {}", out.key),
+            }
+        }
+        if let (false, Value::Object(fields)) = (output.fields.is_empty(), &mut out.value) {
+            fields.retain(|name, _| output.fields.contains(name));
+        }
+        out
+    }
+}
+
+fn render(template: &str, value: &Value) -> Result<String, String> {
+    let mut key = String::new();
+    for (literal, field) in parse(template)? {
+        key.push_str(literal);
+        let Some(field) = field else { continue };
+        match value.get(field) {
+            Some(Value::String(s)) => key.push_str(s),
+            Some(v @ (Value::Number(_) | Value::Bool(_))) => key.push_str(&v.to_string()),
+            _ => return Err(format!("no '{field}' field for '{template}'")),
+        }
+    }
+    keys::check("alert key", &key, KeyUse::Concrete)?;
+    Ok(key)
+}
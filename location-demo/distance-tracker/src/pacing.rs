@@ -0,0 +1,70 @@
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+// The period is raised to this much the work of the cycle overrunning it.
+const HEADROOM: f64 = 1.5;
+// ... and no further than this many times the configured period.
+const MAX_SCALE: u32 = 8;
+// Cycles working less than half the period before it is lowered again.
+const RELAX_AFTER: u32 = 10;
+// Overruns are logged at most this often.
+const WARN_EVERY: Duration = Duration::from_secs(10);
+
+// The period of the compute loop: the configured one, or with --auto-period,
+// one long enough for the cycles to fit in, back to the configured one when the
+// load goes down.
+pub struct Pacer {
+    base: Duration,
+    period: Duration,
+    auto: bool,
+    fast: u32,
+    // overruns since the last warning
+    unreported: u64,
+    warned: Option<Instant>,
+    pub overruns: u64,
+}
+
+impl Pacer {
+    pub fn new(base: Duration, auto: bool) -> Self {
+        Pacer { base, period: base, auto, fast: 0, unreported: 0, warned: None, overruns: 0 }
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    // The longest period, for the cycles to be deemed stalled.
+    pub fn max_period(&self) -> Duration {
+        if self.auto { self.base * MAX_SCALE } else { self.base }
+    }
+
+    // Accounts for a cycle which took `work`, returning whether it overran the
+    // period.
+    pub fn cycle(&mut self, work: Duration, now: Instant) -> bool {
+        let overran = work > self.period;
+        if overran {
+            self.overruns += 1;
+            self.unreported += 1;
+            self.fast = 0;
+            if self.warned.is_none_or(|w| now.duration_since(w) >= WARN_EVERY) {
+                warn!("OVERRUN: compute cycle took {} ms, above the {} ms period ({} overruns since the last warning)", work.as_millis(), self.period.as_millis(), self.unreported);
+                self.warned = Some(now);
+                self.unreported = 0;
+            }
+            if self.auto && self.period < self.max_period() {
+                self.period = work.mul_f64(HEADROOM).min(self.max_period());
+                info!("PACING: compute period raised to {} ms", self.period.as_millis());
+            }
+        } else if self.auto && self.period > self.base && work * 2 < self.period {
+            self.fast += 1;
+            if self.fast >= RELAX_AFTER {
+                self.fast = 0;
+                self.period = (self.period * 3 / 4).max(self.base);
+                info!("PACING: compute period lowered to {} ms", self.period.as_millis());
+            }
+        } else {
+            self.fast = 0;
+        }
+        overran
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+use crate::VehicleInfo;
+
+// Vehicles matching every given field. `id` is a pattern where `*` matches any
+// sequence of characters and `?` any single one.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Selector {
+    pub id: Option<String>,
+    pub kind: Option<String>,
+    pub group: Option<String>,
+}
+
+// Pair of vehicles matching a and b, in either order.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PairRule {
+    pub a: Selector,
+    pub b: Selector,
+}
+
+// Which pairs of vehicles may raise pair alerts (distance, cut-in, closing).
+// Excluded pairs never do; when `only` is not empty, the other pairs must
+// match one of its rules. A group matches any of its selectors.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct PairingRules {
+    #[serde(default)]
+    pub groups: HashMap<String, Vec<Selector>>,
+    #[serde(default)]
+    pub exclude: Vec<PairRule>,
+    #[serde(default)]
+    pub only: Vec<PairRule>,
+}
+
+impl PairingRules {
+    pub fn allows(&self, a: &VehicleInfo, b: &VehicleInfo) -> bool {
+        let pair = |r: &PairRule| (self.matches(&r.a, a) && self.matches(&r.b, b)) || (self.matches(&r.a, b) && self.matches(&r.b, a));
+        !self.exclude.iter().any(pair) && (self.only.is_empty() || self.only.iter().any(pair))
+    }
+
+    fn matches(&self, s: &Selector, v: &VehicleInfo) -> bool {
+        s.id.as_ref().is_none_or(|p| glob(p, &v.id))
+            && s.kind.as_ref().is_none_or(|k| k == v.kind.as_str())
+            && s.group.as_ref().is_none_or(|g| self.groups.get(g).is_some_and(|sel| sel.iter().any(|s| self.matches(s, v))))
+    }
+
+    fn check(&self) -> Result<(), String> {
+        let selectors = self.groups.values().flatten()
+            .chain(self.exclude.iter().chain(&self.only).flat_map(|r| [&r.a, &r.b]));
+        for s in selectors {
+            if s.id.is_none() && s.kind.is_none() && s.group.is_none() {
+                return Err("empty selector, expecting id, kind or group".into());
+            }
+            if let Some(g) = &s.group {
+                if !self.groups.contains_key(g) {
+                    return Err(format!("unknown group '{g}'"));
+                }
+            }
+        }
+        // groups referring to each other would never end matching
+        for (name, sel) in &self.groups {
+            if sel.iter().any(|s| s.group.is_some()) {
+                return Err(format!("group '{name}' cannot refer to other groups"));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn load(path: &str) -> Result<PairingRules, String> {
+    let s = std::fs::read_to_string(path).map_err(|e| format!("Unable to read {path}: {e}"))?;
+    let rules: PairingRules = serde_json::from_str(&s).map_err(|e| format!("Invalid pairing rules file {path}: {e}"))?;
+    rules.check().map_err(|e| format!("Invalid pairing rules file {path}: {e}"))?;
+    Ok(rules)
+}
+
+fn glob(pattern: &str, s: &str) -> bool {
+    let (p, s): (Vec<char>, Vec<char>) = (pattern.chars().collect(), s.chars().collect());
+    // position after the last `*` in the pattern and the text it was matched at
+    let (mut i, mut j, mut star) = (0, 0, None);
+    while j < s.len() {
+        if i < p.len() && (p[i] == '?' || p[i] == s[j]) {
+            i += 1;
+            j += 1;
+        } else if i < p.len() && p[i] == '*' {
+            star = Some((i + 1, j));
+            i += 1;
+        } else if let Some((si, sj)) = star {
+            i = si;
+            j = sj + 1;
+            star = Some((si, sj + 1));
+        } else {
+            return false;
+        }
+    }
+    p[i..].iter().all(|c| *c == '*')
+}
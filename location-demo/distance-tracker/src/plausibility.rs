@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::time::Instant;
+use crate::Position;
+
+// Implied speeds are measured over at least this interval, closer fixes being
+// dominated by GPS noise.
+const MIN_INTERVAL_S: f64 = 1.0;
+// After this many outliers in a row the vehicle is assumed to have really
+// moved (a device restarted elsewhere), and its next fix is trusted.
+const MAX_CONSECUTIVE: u32 = 3;
+// Entry of the speeds file applying to the kinds it does not list.
+const ANY_KIND: &str = "*";
+// Maximum plausible speeds (m/s) of the common kinds, which the speeds file
+// changes or completes.
+const DEFAULT_MAX_SPEEDS: [(&str, f64); 3] = [("pedestrian", 4.0), ("car", 70.0), ("drone", 40.0)];
+
+// What to do with the fixes implying a speed above the maximum of their kind.
+#[derive(clap_derive::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutlierPolicy {
+    // rejected, the vehicle staying where it was
+    Drop,
+    // applied anyway, only reported
+    Flag,
+    // not checked
+    Off,
+}
+
+// A fix too far from the previous one of its vehicle.
+#[derive(Debug, Clone, Copy)]
+pub struct Outlier {
+    // m
+    pub jump: f64,
+    // m/s
+    pub implied_speed: f64,
+    pub max_speed: f64,
+}
+
+impl std::fmt::Display for Outlier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "jumped {:.0} m, implying {:.1} m/s above the {} m/s maximum", self.jump, self.implied_speed, self.max_speed)
+    }
+}
+
+struct Fix {
+    position: Position,
+    at: Instant,
+    outliers: u32,
+}
+
+// Maximum plausible speed (m/s) of each kind of vehicle, {"car": 70, "truck": 40,
+// "*": 90}, bounding the jumps of its fixes and the speeds derived from them.
+#[derive(Debug, Clone)]
+pub struct MaxSpeeds(HashMap<String, f64>);
+
+impl Default for MaxSpeeds {
+    fn default() -> Self {
+        MaxSpeeds(DEFAULT_MAX_SPEEDS.iter().map(|(k, s)| (k.to_string(), *s)).collect())
+    }
+}
+
+impl MaxSpeeds {
+    // The maximum speed of `kind`, if it has one.
+    pub fn get(&self, kind: &str) -> Option<f64> {
+        self.0.get(kind).or_else(|| self.0.get(ANY_KIND)).copied()
+    }
+}
+
+// The default speeds, with those of the file.
+pub fn load(path: &str) -> Result<MaxSpeeds, String> {
+    let file = File::open(path).map_err(|e| format!("Unable to open {path}: {e}"))?;
+    let speeds: HashMap<String, f64> = serde_json::from_reader(BufReader::new(file)).map_err(|e| format!("Invalid maximum speeds in {path}: {e}"))?;
+    if let Some((kind, _)) = speeds.iter().find(|(_, s)| !(s.is_finite() && **s > 0.0)) {
+        return Err(format!("maximum speed of '{kind}' must be positive"));
+    }
+    let mut max_speeds = MaxSpeeds::default();
+    max_speeds.0.extend(speeds);
+    Ok(max_speeds)
+}
+
+// Checks the fixes of every vehicle against its previous plausible one.
+pub struct Plausibility {
+    max_speeds: MaxSpeeds,
+    last: HashMap<String, Fix>,
+}
+
+impl Plausibility {
+    pub fn new(max_speeds: MaxSpeeds) -> Self {
+        Plausibility { max_speeds, last: HashMap::new() }
+    }
+
+    // Forgets the last plausible fixes.
+    pub fn reset(&mut self) {
+        self.last.clear();
+    }
+
+    // Checks the fix of `id` against its previous plausible one. Outliers are
+    // not remembered, unless `keep` or once too many in a row, so that a single
+    // bad fix does not shift the reference. Only allocates for new ids.
+    pub fn check(&mut self, id: &str, kind: &str, position: Position, now: Instant, keep: bool) -> Result<(), Outlier> {
+        let Some(max_speed) = self.max_speeds.get(kind) else {
+            return Ok(());
+        };
+        let Some(last) = self.last.get_mut(id) else {
+            self.last.insert(id.into(), Fix { position, at: now, outliers: 0 });
+            return Ok(());
+        };
+        let jump = last.position.distance_haverside(&position);
+        let implied_speed = jump / now.duration_since(last.at).as_secs_f64().max(MIN_INTERVAL_S);
+        if implied_speed <= max_speed || last.outliers >= MAX_CONSECUTIVE {
+            *last = Fix { position, at: now, outliers: 0 };
+            return Ok(());
+        }
+        if keep {
+            *last = Fix { position, at: now, outliers: 0 };
+        } else {
+            last.outliers += 1;
+        }
+        Err(Outlier { jump, implied_speed, max_speed })
+    }
+}
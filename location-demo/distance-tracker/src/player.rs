@@ -0,0 +1,28 @@
+use clap::Parser;
+use zenoh::prelude::r#async::*;
+use distance_tracker::logging::{self, LogFormat};
+use distance_tracker::tools::{self, ReplayArgs};
+use zdemo_common::Compression;
+
+#[derive(clap_derive::Parser)]
+struct AppArgs {
+    #[command(flatten)]
+    replay: ReplayArgs,
+    /// Compression of the replayed payloads (none, lz4 or zstd).
+    #[arg(long)]
+    compression: Option<Compression>,
+    #[arg(long)]
+    config: Option<String>
+}
+
+// Same as `DistanceAlert replay`.
+#[tokio::main]
+async fn main() {
+    let args = AppArgs::parse();
+    logging::init(LogFormat::default());
+    let config = match args.config {
+        Some(f) => Config::from_file(f).unwrap(),
+        None => Config::default()
+    };
+    tools::replay(args.replay, config, args.compression.unwrap_or_default()).await;
+}
@@ -0,0 +1,72 @@
+use std::collections::HashSet;
+use serde::{Serialize, Deserialize};
+use crate::geo::DistanceAlgo;
+use crate::keys::{self, KeyUse};
+use crate::Position;
+
+// A static point of interest (a charging station, a loading dock, a hazard)
+// raising an alert for every vehicle within `radius` meters of it. Hazards
+// raise dangers.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Poi {
+    pub id: String,
+    #[serde(default)]
+    pub kind: Option<String>,
+    pub position: [f64; 2],
+    pub radius: f64,
+    #[serde(default)]
+    pub hazard: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PoiAlert {
+    pub poi: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    pub id: String,
+    // m
+    pub distance: f64,
+    pub radius: f64,
+    #[serde(default)]
+    pub hazard: bool,
+}
+
+impl Poi {
+    pub fn position(&self) -> Position {
+        Position { lat: self.position[0], lng: self.position[1] }
+    }
+
+    // The alert of a vehicle at `p`, when within the radius.
+    pub fn check(&self, id: &str, p: &Position, algo: DistanceAlgo) -> Option<PoiAlert> {
+        let distance = algo.distance(&self.position(), p);
+        (distance <= self.radius).then(|| PoiAlert { poi: self.id.clone(), kind: self.kind.clone(), id: id.into(), distance, radius: self.radius, hazard: self.hazard })
+    }
+}
+
+// Reads a JSON array of {id, kind, position: [lat, lng], radius (m), hazard},
+// kind and hazard being optional. Ids are single key chunks, alerts being
+// published on <poi_key>/<id>.
+pub fn load(path: &str) -> Result<Vec<Poi>, String> {
+    let s = std::fs::read_to_string(path).map_err(|e| format!("Unable to read {path}: {e}"))?;
+    let pois: Vec<Poi> = serde_json::from_str(&s).map_err(|e| format!("Invalid POIs file {path}: {e}"))?;
+    check(&pois)?;
+    Ok(pois)
+}
+
+pub fn check(pois: &[Poi]) -> Result<(), String> {
+    let mut ids = HashSet::new();
+    for p in pois {
+        keys::check(&format!("POI '{}'", p.id), &p.id, KeyUse::Concrete)?;
+        if p.id.contains('/') {
+            return Err(format!("POI id '{}' must be a single key chunk", p.id));
+        }
+        if !ids.insert(&p.id) {
+            return Err(format!("duplicated POI '{}'", p.id));
+        }
+        if !(p.radius.is_finite() && p.radius > 0.0) {
+            return Err(format!("POI '{}' must have a positive radius", p.id));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use std::sync::Arc;
+use std::time::Instant;
+use futures_util::stream::{self, StreamExt};
+use serde::Serialize;
+use tokio::sync::mpsc::{channel, Sender};
+use tokio::task::JoinHandle;
+use zenoh::prelude::r#async::*;
+use zdemo_common::{with_backoff, Compression, Instance};
+use tracing::{debug_span, error, info_span, Instrument};
+use crate::leader::Leadership;
+use crate::metrics::SharedMetrics;
+use crate::qos::{Level, QosTable};
+use crate::shm::ShmSegment;
+
+const QUEUE_SIZE: usize = 4096;
+const PUT_ATTEMPTS: u32 = 5;
+// Puts in flight at once when publishing concurrently.
+const MAX_IN_FLIGHT: usize = 64;
+
+#[derive(Clone)]
+pub struct Outgoing {
+    pub key: String,
+    pub value: serde_json::Value,
+    // dangers are published first, then alerts, each level with its QoS
+    pub level: Level,
+    pub queued: Instant,
+}
+
+impl Outgoing {
+    pub fn json<T: Serialize>(key: impl Into<String>, value: &T) -> Self {
+        Outgoing { key: key.into(), value: serde_json::to_value(value).unwrap(), level: Level::Info, queued: Instant::now() }
+    }
+
+    pub fn level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+
+    pub fn is_urgent(&self) -> bool {
+        self.level == Level::Danger
+    }
+
+    // The severity band of an alert, for the QoS table.
+    fn band(&self) -> Option<&str> {
+        self.value.get("severity").and_then(serde_json::Value::as_str)
+    }
+}
+
+#[derive(clap_derive::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Batching {
+    /// Publish every alert on its own key
+    Off,
+    /// Also publish the alerts of a compute cycle as a single batch
+    Both,
+    /// Only publish the alerts of a compute cycle as a single batch
+    Only,
+    /// Publish every alert on its own key, the puts running concurrently
+    Concurrent,
+}
+
+impl Batching {
+    // Whether the alerts of a cycle are published as a batch.
+    pub fn batches(self) -> bool {
+        matches!(self, Batching::Both | Batching::Only)
+    }
+}
+
+#[derive(Serialize)]
+struct BatchItem<'a> {
+    key: &'a str,
+    alert: &'a serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct Batch<'a> {
+    alerts: Vec<BatchItem<'a>>,
+}
+
+// Coalesces alerts into a single publication on `key`, {"alerts": [{key,
+// alert}, ...]}, of the highest level of them, so that bursts cost one put.
+pub fn batch(alerts: Vec<Outgoing>, key: &str, batching: Batching) -> Vec<Outgoing> {
+    if !batching.batches() || alerts.is_empty() {
+        return alerts;
+    }
+    let level = alerts.iter().map(|a| a.level).max().unwrap_or_default();
+    let items = alerts.iter().map(|a| BatchItem { key: &a.key, alert: &a.value }).collect();
+    let batched = Outgoing::json(key, &Batch { alerts: items }).level(level);
+    match batching {
+        Batching::Only => vec![batched],
+        _ => std::iter::once(batched).chain(alerts).collect(),
+    }
+}
+
+// Publishes everything sent on the returned channel, tagged with the instance
+// id and retrying failed puts with backoff. Urgent publications overtake the
+// others waiting in the queue. The task ends once all senders are dropped and
+// the queue is drained, so awaiting it flushes pending publications. The time
+// from queuing to the end of the put is recorded in the publish latency. When
+// `concurrent`, the puts waiting in the queue are started together (urgent
+// ones first) instead of one after the other. Instances on standby drop them.
+pub fn spawn(z: Arc<Session>, instance: Instance, qos: QosTable, compression: Compression, metrics: SharedMetrics, concurrent: bool, leadership: Leadership) -> (Sender<Outgoing>, JoinHandle<()>) {
+    let (tx, mut rx) = channel::<Outgoing>(QUEUE_SIZE);
+    let shm = ShmSegment::of(&z, "distance-tracker");
+    let handle = tokio::spawn(async move {
+        while let Some(first) = rx.recv().await {
+            let mut batch = vec![first];
+            while let Ok(out) = rx.try_recv() {
+                batch.push(out);
+            }
+            if !leadership.leads() {
+                continue;
+            }
+            // stable, so the order is kept within each class
+            batch.sort_by_key(|out| std::cmp::Reverse(out.level));
+            let puts = stream::iter(batch).map(|out| {
+                let (z, instance, qos, shm) = (&z, &instance, &qos, &shm);
+                let span = debug_span!("put", key = %out.key, level = ?out.level);
+                async move {
+                    let q = qos.of(out.level, out.band());
+                    let value = shm.value(zdemo_common::json_value_with(&instance.tag(out.value), compression));
+                    let r = with_backoff(&format!("put on {}", out.key), PUT_ATTEMPTS, || {
+                        z.put(&out.key, value.clone())
+                            .priority(q.priority)
+                            .congestion_control(q.congestion)
+                            .res()
+                    }).await;
+                    (out.key, out.queued, r)
+                }.instrument(span)
+            });
+            let mut done = puts.buffered(if concurrent { MAX_IN_FLIGHT } else { 1 });
+            while let Some((key, queued, r)) = done.next().await {
+                let mut m = metrics.lock().await;
+                if let Err(e) = r {
+                    error!("dropping publication on {key}: {e}");
+                    m.error(format!("dropped publication on {key}: {e}"));
+                }
+                m.latency.publish.record(queued.elapsed());
+            }
+        }
+    }.instrument(info_span!("publish")));
+    (tx, handle)
+}
@@ -0,0 +1,30 @@
+use clap::Parser;
+use zenoh::prelude::r#async::*;
+use distance_tracker::logging::{self, LogFormat};
+use distance_tracker::tools::{self, PublishArgs};
+use zdemo_common::Compression;
+
+#[derive(clap_derive::Parser)]
+struct AppArgs {
+    #[command(flatten)]
+    publish: PublishArgs,
+    /// Compression of the publications (none, lz4 or zstd).
+    #[arg(long)]
+    compression: Option<Compression>,
+    #[arg(long)]
+    instance_id: Option<String>,
+    #[arg(long)]
+    config: Option<String>
+}
+
+// Same as `DistanceAlert publish`.
+#[tokio::main]
+async fn main() {
+    let args = AppArgs::parse();
+    logging::init(LogFormat::default());
+    let config = match args.config {
+        Some(f) => Config::from_file(f).unwrap(),
+        None => Config::default()
+    };
+    tools::publish_positions(args.publish, config, args.compression.unwrap_or_default(), args.instance_id).await;
+}
@@ -0,0 +1,47 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use zenoh::prelude::r#async::*;
+use tracing::warn;
+
+// Feeds the replies of a periodic get on `keys` into the returned channel, like
+// subscriptions do, for trackers pulling the latest positions from a storage
+// (or any queryable) instead of ingesting every publication. Storages keep
+// replying with the last position of idle vehicles, so samples already seen,
+// with the same timestamp (or payload when they have none), are skipped.
+pub fn spawn(z: Arc<Session>, keys: Vec<String>, period: Duration) -> UnboundedReceiver<Sample> {
+    let (tx, rx) = unbounded_channel();
+    tokio::spawn(async move {
+        let mut seen: HashMap<String, Vec<u8>> = HashMap::new();
+        let mut tick = tokio::time::interval(period);
+        tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tick.tick().await;
+            for key in &keys {
+                let replies = match z.get(key).timeout(period.max(Duration::from_secs(1))).res().await {
+                    Ok(replies) => replies,
+                    Err(e) => {
+                        warn!("unable to pull positions on {key}: {e}");
+                        continue;
+                    }
+                };
+                while let Ok(reply) = replies.recv_async().await {
+                    let Ok(sample) = reply.sample else { continue };
+                    let version = match &sample.timestamp {
+                        Some(t) => t.to_string().into_bytes(),
+                        None => sample.payload.contiguous().into_owned(),
+                    };
+                    if seen.get(sample.key_expr.as_str()) == Some(&version) {
+                        continue;
+                    }
+                    seen.insert(sample.key_expr.to_string(), version);
+                    if tx.send(sample).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+    rx
+}
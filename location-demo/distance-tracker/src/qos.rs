@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+use zenoh::prelude::r#async::*;
+
+// Command line counterparts of the zenoh QoS settings. The control priority is
+// reserved to zenoh itself.
+#[derive(clap_derive::ValueEnum, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum PriorityArg { RealTime, InteractiveHigh, InteractiveLow, DataHigh, Data, DataLow, Background }
+
+impl From<PriorityArg> for Priority {
+    fn from(p: PriorityArg) -> Self {
+        match p {
+            PriorityArg::RealTime => Priority::RealTime,
+            PriorityArg::InteractiveHigh => Priority::InteractiveHigh,
+            PriorityArg::InteractiveLow => Priority::InteractiveLow,
+            PriorityArg::DataHigh => Priority::DataHigh,
+            PriorityArg::Data => Priority::Data,
+            PriorityArg::DataLow => Priority::DataLow,
+            PriorityArg::Background => Priority::Background,
+        }
+    }
+}
+
+#[derive(clap_derive::ValueEnum, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum CongestionArg {
+    /// Wait for the network to accept the publication
+    Block,
+    /// Drop the publication when the network is congested
+    Drop,
+}
+
+impl From<CongestionArg> for CongestionControl {
+    fn from(c: CongestionArg) -> Self {
+        match c {
+            CongestionArg::Block => CongestionControl::Block,
+            CongestionArg::Drop => CongestionControl::Drop,
+        }
+    }
+}
+
+#[derive(clap_derive::ValueEnum, Clone, Copy, Debug)]
+pub enum ReliabilityArg { BestEffort, Reliable }
+
+impl From<ReliabilityArg> for Reliability {
+    fn from(r: ReliabilityArg) -> Self {
+        match r {
+            ReliabilityArg::BestEffort => Reliability::BestEffort,
+            ReliabilityArg::Reliable => Reliability::Reliable,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Qos {
+    pub priority: Priority,
+    pub congestion: CongestionControl,
+}
+
+impl Qos {
+    pub fn new(priority: PriorityArg, congestion: CongestionArg) -> Self {
+        Qos { priority: priority.into(), congestion: congestion.into() }
+    }
+}
+
+// What a publication is, for its QoS: a danger (danger level alerts, cut-ins,
+// closing speeds, emergency stops), another alert, or anything else (displays,
+// risks, notices).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    #[default]
+    Info,
+    Alert,
+    Danger,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct QosEntry {
+    priority: PriorityArg,
+    congestion: CongestionArg,
+}
+
+// QoS of the publications by level, and of the alerts of the severity bands
+// given, which takes precedence over their level.
+#[derive(Clone, Debug)]
+pub struct QosTable {
+    pub danger: Qos,
+    pub alert: Qos,
+    pub info: Qos,
+    pub bands: HashMap<String, Qos>,
+}
+
+impl Default for QosTable {
+    fn default() -> Self {
+        QosTable {
+            danger: Qos::new(PriorityArg::RealTime, CongestionArg::Block),
+            alert: Qos::new(PriorityArg::InteractiveHigh, CongestionArg::Block),
+            info: Qos::new(PriorityArg::Background, CongestionArg::Drop),
+            bands: HashMap::new(),
+        }
+    }
+}
+
+impl QosTable {
+    // The QoS of a publication of `level`, or of its severity band.
+    pub fn of(&self, level: Level, band: Option<&str>) -> Qos {
+        band.and_then(|b| self.bands.get(b)).copied().unwrap_or(match level {
+            Level::Danger => self.danger,
+            Level::Alert => self.alert,
+            Level::Info => self.info,
+        })
+    }
+}
+
+// Reads a JSON object of {priority, congestion} by level ("danger", "alert" or
+// "info") or severity band name, the levels not given keeping their default.
+pub fn load(path: &str) -> Result<QosTable, String> {
+    let s = std::fs::read_to_string(path).map_err(|e| format!("Unable to read {path}: {e}"))?;
+    let entries: HashMap<String, QosEntry> = serde_json::from_str(&s).map_err(|e| format!("Invalid QoS table {path}: {e}"))?;
+    let mut table = QosTable::default();
+    for (name, e) in entries {
+        let q = Qos::new(e.priority, e.congestion);
+        match name.as_str() {
+            "danger" => table.danger = q,
+            "alert" => table.alert = q,
+            "info" => table.info = q,
+            _ => { table.bands.insert(name, q); },
+        }
+    }
+    Ok(table)
+}
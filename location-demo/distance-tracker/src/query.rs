@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use zenoh::prelude::r#async::*;
+use tracing::warn;
+use crate::{Position, VehicleMap};
+use crate::vehicle::VehicleState;
+
+pub const DEFAULT_PAGE_LIMIT: usize = 500;
+pub const MAX_PAGE_LIMIT: usize = 5000;
+
+// Pagination parameters accepted on the state queryable:
+//   ?offset=<n>&limit=<n>&token=<continuation token from a previous page>
+// When a token is given the page starts right after the item it designates,
+// and `offset` is applied relative to that position.
+#[derive(Debug, Clone)]
+pub struct PageRequest {
+    pub offset: usize,
+    pub limit: usize,
+    pub after: Option<String>,
+}
+
+impl PageRequest {
+    pub fn from_parameters(params: &HashMap<String, String>) -> Result<Self, String> {
+        let offset = match params.get("offset") {
+            Some(s) => s.parse::<usize>().map_err(|e| format!("invalid offset '{s}': {e}"))?,
+            None => 0,
+        };
+        let limit = match params.get("limit") {
+            Some(s) => s.parse::<usize>().map_err(|e| format!("invalid limit '{s}': {e}"))?,
+            None => DEFAULT_PAGE_LIMIT,
+        };
+        if limit == 0 {
+            return Err("limit must be greater than zero".into());
+        }
+        let after = match params.get("token") {
+            Some(t) => Some(decode_token(t).ok_or_else(|| format!("invalid token '{t}'"))?),
+            None => None,
+        };
+        Ok(PageRequest { offset, limit: limit.min(MAX_PAGE_LIMIT), after })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: usize,
+    pub offset: usize,
+    pub next: Option<String>,
+}
+
+// `items` must be sorted by key. Keys are used for keyset continuation so that
+// a client walking through pages does not skip or repeat entries when the
+// underlying collection changes between two requests.
+pub fn paginate<T: Clone>(items: &[(String, T)], req: &PageRequest) -> Page<T> {
+    let start = match &req.after {
+        Some(after) => items.partition_point(|(k, _)| k <= after),
+        None => 0,
+    };
+    let start = (start + req.offset).min(items.len());
+    let end = (start + req.limit).min(items.len());
+    let next = if end < items.len() && end > start {
+        Some(encode_token(&items[end - 1].0))
+    } else {
+        None
+    };
+    Page {
+        items: items[start..end].iter().map(|(_, v)| v.clone()).collect(),
+        total: items.len(),
+        offset: start,
+        next,
+    }
+}
+
+// Tokens are hex encoded keys, so they never clash with the selector syntax.
+fn encode_token(key: &str) -> String {
+    key.bytes().map(|b| format!("{b:02x}")).collect()
+}
+
+fn decode_token(token: &str) -> Option<String> {
+    if !token.len().is_multiple_of(2) {
+        return None;
+    }
+    let bytes = (0..token.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(token.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    String::from_utf8(bytes).ok()
+}
+
+// Serves `key` by running `handler` on the query parameters and the current
+// vehicle map; the handler returns the JSON replies, in order, or an error
+// message.
+pub async fn serve<F>(z: Arc<Session>, key: String, map: VehicleMap, handler: F)
+where
+    F: Fn(&HashMap<String, String>, &HashMap<String, VehicleState>) -> Result<Vec<Vec<u8>>, String>,
+{
+    let key = KeyExpr::try_from(key).unwrap();
+    let queryable = z.declare_queryable(&key).res().await.unwrap();
+    while let Ok(query) = queryable.recv_async().await {
+        let reply = match query.selector().parameters_stringmap() {
+            Ok(params) => {
+                handler(&params, &map.snapshot())
+            },
+            Err(e) => Err(e.to_string()),
+        };
+        let replies = match reply {
+            Ok(replies) => replies.into_iter().map(|bs| Ok(Sample::new(key.clone(), Value::from(bs).encoding(Encoding::APP_JSON)))).collect(),
+            Err(e) => {
+                warn!("Invalid query {}: {e}", query.selector());
+                vec![Err(Value::from(e))]
+            }
+        };
+        for reply in replies {
+            if let Err(e) = query.reply(reply).res().await {
+                warn!("Unable to reply to query on {key}: {e}");
+                break;
+            }
+        }
+    }
+}
+
+// Filters accepted on the state queryable, applied before the pagination:
+//   ?kind=<kind>[,<kind>..]&within=<lat>,<lng>,<radius m>&max_age_ms=<n>
+#[derive(Debug, Clone, Default)]
+pub struct StateFilter {
+    pub kinds: Option<Vec<String>>,
+    pub within: Option<(Position, f64)>,
+    pub max_age: Option<Duration>,
+}
+
+impl StateFilter {
+    pub fn from_parameters(params: &HashMap<String, String>) -> Result<Self, String> {
+        let kinds = params.get("kind").map(|s| s.split(',').map(|k| k.trim().to_string()).collect());
+        let within = match params.get("within") {
+            Some(s) => {
+                let fields: Vec<f64> = s.split(',').map(|f| f.trim().parse::<f64>()).collect::<Result<_, _>>().map_err(|e| format!("invalid within '{s}': {e}"))?;
+                let [lat, lng, radius] = fields[..] else {
+                    return Err(format!("invalid within '{s}', expecting lat,lng,radius"));
+                };
+                if !(radius.is_finite() && radius >= 0.0) {
+                    return Err(format!("invalid within '{s}', the radius must be non negative"));
+                }
+                Some((Position { lat, lng }, radius))
+            },
+            None => None,
+        };
+        let max_age = match params.get("max_age_ms") {
+            Some(s) => Some(Duration::from_millis(s.parse::<u64>().map_err(|e| format!("invalid max_age_ms '{s}': {e}"))?)),
+            None => None,
+        };
+        Ok(StateFilter { kinds, within, max_age })
+    }
+
+    pub fn accepts(&self, v: &VehicleState, now: Instant, unix_ms: u64) -> bool {
+        self.kinds.as_ref().is_none_or(|ks| ks.iter().any(|k| k == v.info.kind.as_str()))
+            && self.within.is_none_or(|(center, radius)| center.distance_haverside(&v.info.position) <= radius)
+            && self.max_age.is_none_or(|max| v.age(now, unix_ms) <= max)
+    }
+}
+
+// With ?chunked=true the reply is split into one page of `limit` vehicles per
+// reply, from the requested one to the end of the fleet, each with the token
+// of the next. All are on the state key, so that clients must not consolidate
+// the replies.
+pub fn state_page(params: &HashMap<String, String>, map: &HashMap<String, VehicleState>) -> Result<Vec<Vec<u8>>, String> {
+    let mut req = PageRequest::from_parameters(params)?;
+    let chunked = match params.get("chunked").map(String::as_str) {
+        Some("" | "true") => true,
+        Some("false") | None => false,
+        Some(s) => return Err(format!("invalid chunked '{s}', expecting true or false")),
+    };
+    let filter = StateFilter::from_parameters(params)?;
+    let (now, unix_ms) = (crate::clock::now(), crate::clock::unix_ms());
+    let mut items: Vec<(String, VehicleState)> = map.iter()
+        .filter(|(_, vi)| filter.accepts(vi, now, unix_ms))
+        .map(|(id, vi)| (id.clone(), vi.clone()))
+        .collect();
+    items.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut replies = Vec::new();
+    loop {
+        let page = paginate(&items, &req);
+        replies.push(serde_json::to_vec(&page).unwrap());
+        match page.next {
+            Some(token) if chunked => req = PageRequest { offset: 0, after: decode_token(&token), ..req },
+            _ => return Ok(replies),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Neighbor {
+    pub id: String,
+    pub distance: f64,
+    pub vehicle: VehicleState,
+}
+
+// ?lat=<deg>&lng=<deg>&k=<n>, k defaults to 5.
+pub fn nearest(params: &HashMap<String, String>, map: &HashMap<String, VehicleState>) -> Result<Vec<Vec<u8>>, String> {
+    let coord = |name: &str| -> Result<f64, String> {
+        let s = params.get(name).ok_or_else(|| format!("missing parameter '{name}'"))?;
+        s.parse::<f64>().map_err(|e| format!("invalid {name} '{s}': {e}"))
+    };
+    let target = Position { lat: coord("lat")?, lng: coord("lng")? };
+    let k = match params.get("k") {
+        Some(s) => s.parse::<usize>().map_err(|e| format!("invalid k '{s}': {e}"))?,
+        None => 5,
+    };
+    let mut neighbors: Vec<Neighbor> = map.values()
+        .map(|vs| Neighbor { id: vs.info.id.clone(), distance: target.distance_haverside(&vs.info.position), vehicle: vs.clone() })
+        .collect();
+    neighbors.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+    neighbors.truncate(k);
+    Ok(vec![serde_json::to_vec(&neighbors).unwrap()])
+}
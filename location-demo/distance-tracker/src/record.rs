@@ -0,0 +1,295 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use zdemo_common::Compression;
+use tracing::warn;
+use crate::geohash::{self, BoundingBox};
+use crate::ingest::{self, RawVehicle, COLOR_ATTACHMENT, KIND_ATTACHMENT};
+use crate::signing::Signers;
+use zenoh::prelude::r#async::*;
+
+// One line of a JSON lines recording.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Record {
+    // milliseconds since the beginning of the recording
+    pub t: u64,
+    // wall clock time of reception, milliseconds since the UNIX epoch
+    pub ts: u64,
+    pub key: String,
+    #[serde(default, skip_serializing_if = "is_put")]
+    pub delete: bool,
+    pub encoding: String,
+    pub payload: Payload,
+    // kind and color attached to compact positions
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attachment: BTreeMap<String, String>,
+}
+
+fn is_put(delete: &bool) -> bool { !delete }
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum Payload {
+    // JSON payloads are embedded as is so that recordings stay greppable
+    Json(serde_json::Value),
+    // anything else is hex encoded
+    Bytes(String),
+}
+
+impl Payload {
+    pub fn from_bytes(bs: &[u8]) -> Self {
+        match serde_json::from_slice::<serde_json::Value>(bs) {
+            Ok(v) => Payload::Json(v),
+            Err(_) => Payload::Bytes(bs.iter().map(|b| format!("{b:02x}")).collect()),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Payload::Json(v) => serde_json::to_vec(v).unwrap(),
+            Payload::Bytes(h) => (0..h.len())
+                .step_by(2)
+                .filter_map(|i| h.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+                .collect(),
+        }
+    }
+}
+
+pub fn unix_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+// Selects the samples worth recording. Samples on a key intersecting an
+// excluded key expression are dropped, as well as those not intersecting any of
+// the included ones when given. The vehicle filters only apply to positions:
+// those of excluded vehicles, of vehicles not listed or of other kinds, and
+// outside the bounding box are dropped, other samples are kept. Compact
+// positions without an attached kind are kept whatever the kinds.
+#[derive(Debug, Clone, Default)]
+pub struct RecordFilter {
+    pub include_keys: Vec<OwnedKeyExpr>,
+    pub exclude_keys: Vec<OwnedKeyExpr>,
+    pub ids: Vec<String>,
+    pub exclude_ids: Vec<String>,
+    pub kinds: Vec<String>,
+    pub bbox: Option<BoundingBox>,
+}
+
+impl RecordFilter {
+    fn filters_positions(&self) -> bool {
+        !self.ids.is_empty() || !self.exclude_ids.is_empty() || !self.kinds.is_empty() || self.bbox.is_some()
+    }
+
+    pub fn accepts(&self, key: &keyexpr, payload: &[u8], attached_kind: Option<&str>) -> bool {
+        if self.exclude_keys.iter().any(|k| k.intersects(key)) {
+            return false;
+        }
+        if !self.include_keys.is_empty() && !self.include_keys.iter().any(|k| k.intersects(key)) {
+            return false;
+        }
+        if !self.filters_positions() {
+            return true;
+        }
+        let Ok(raw) = RawVehicle::parse(payload) else { return true };
+        let id = raw.id.as_ref();
+        let kind = raw.kind.as_deref().or(attached_kind);
+        let position = raw.position();
+        (self.ids.is_empty() || self.ids.iter().any(|i| i == id))
+            && !self.exclude_ids.iter().any(|i| i == id)
+            && (self.kinds.is_empty() || kind.is_none_or(|k| self.kinds.iter().any(|i| i == k)))
+            && self.bbox.is_none_or(|bb| bb.contains(position.lat, position.lng))
+    }
+}
+
+pub struct Recorder<W: Write> {
+    out: W,
+    start: Instant,
+    pub filter: RecordFilter,
+    pub count: u64,
+    // samples dropped by the filter
+    pub skipped: u64,
+    // bytes written since the last rotation
+    pub written: u64,
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(out: W) -> Self {
+        Recorder { out, start: Instant::now(), filter: RecordFilter::default(), count: 0, skipped: 0, written: 0 }
+    }
+
+    pub fn filter(mut self, filter: RecordFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    // Continues the recording into `out`, returning the previous output once
+    // flushed. Times stay relative to the beginning of the recording, so that
+    // the parts can be concatenated.
+    pub fn rotate(&mut self, out: W) -> std::io::Result<W> {
+        self.out.flush()?;
+        self.written = 0;
+        Ok(std::mem::replace(&mut self.out, out))
+    }
+
+    pub fn record(&mut self, sample: &Sample) -> std::io::Result<()> {
+        // compressed payloads are recorded decompressed, so that they stay greppable
+        let (payload, encoding) = match zdemo_common::payload(sample) {
+            Ok(p) => (p, zdemo_common::split_encoding(&sample.encoding).1),
+            Err(_) => (sample.payload.contiguous(), sample.encoding.clone()),
+        };
+        let (kind, color) = ingest::attached(sample);
+        if !self.filter.accepts(&sample.key_expr, payload.as_ref(), kind.as_deref()) {
+            self.skipped += 1;
+            return Ok(());
+        }
+        let r = Record {
+            t: self.start.elapsed().as_millis() as u64,
+            ts: unix_ms(),
+            key: sample.key_expr.to_string(),
+            delete: sample.kind == SampleKind::Delete,
+            encoding: encoding.to_string(),
+            payload: Payload::from_bytes(payload.as_ref()),
+            attachment: [(KIND_ATTACHMENT, kind), (COLOR_ATTACHMENT, color)].into_iter()
+                .filter_map(|(k, v)| Some((k.to_string(), v?)))
+                .collect(),
+        };
+        let mut line = serde_json::to_vec(&r)?;
+        line.push(b'\n');
+        self.out.write_all(&line)?;
+        self.written += line.len() as u64;
+        self.count += 1;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
+// Moves the keys under `from` to `to` when replaying, "from=to".
+#[derive(Debug, Clone)]
+pub struct KeyMapping {
+    pub from: String,
+    pub to: String,
+}
+
+impl FromStr for KeyMapping {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (from, to) = s.split_once('=').ok_or_else(|| format!("expected from=to but got '{s}'"))?;
+        Ok(KeyMapping { from: from.trim_end_matches('/').into(), to: to.trim_end_matches('/').into() })
+    }
+}
+
+// Payload fields holding vehicle ids, prefixed along with the keys of the positions.
+const ID_FIELDS: [&str; 5] = ["id", "ida", "idb", "cutter", "victim"];
+
+// Rewrites recorded samples so that they can be replayed next to live
+// publishers: keys are moved to other namespaces (the first mapping matching is
+// applied), vehicle ids are prefixed, both in the payloads and in the key
+// chunk naming the vehicle, and the geohash cells of the positions are inserted
+// before that chunk.
+#[derive(Debug, Clone, Default)]
+pub struct Rewrite {
+    pub keys: Vec<KeyMapping>,
+    pub id_prefix: Option<String>,
+    pub geohash_precision: Option<usize>,
+}
+
+impl Rewrite {
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty() && self.id_prefix.is_none() && self.geohash_precision.is_none()
+    }
+
+    fn key(&self, key: &str) -> String {
+        let mapped = self.keys.iter().find_map(|m| match key.strip_prefix(m.from.as_str()) {
+            Some(rest) if rest.is_empty() || rest.starts_with('/') => Some(format!("{}{rest}", m.to)),
+            _ => None,
+        });
+        mapped.unwrap_or_else(|| key.to_string())
+    }
+
+    pub fn apply(&self, mut r: Record) -> Record {
+        r.key = self.key(&r.key);
+        let Payload::Json(serde_json::Value::Object(fields)) = &mut r.payload else {
+            return r;
+        };
+        if let Some(prefix) = &self.id_prefix {
+            if let Some(id) = fields.get("id").and_then(|v| v.as_str()) {
+                r.key = r.key.split('/')
+                    .map(|c| if c == id { format!("{prefix}{c}") } else { c.to_string() })
+                    .collect::<Vec<_>>()
+                    .join("/");
+            }
+            for f in ID_FIELDS {
+                if let Some(serde_json::Value::String(id)) = fields.get_mut(f) {
+                    id.insert_str(0, prefix);
+                }
+            }
+        }
+        // the positions of the vehicles, on <prefix>/<id>
+        let id = fields.get("id").and_then(|v| v.as_str()).filter(|_| fields.contains_key("kind"));
+        let at = |c: &str| fields.get("position").and_then(|p| p.get(c)).and_then(|v| v.as_f64());
+        if let (Some(p), Some(id), Some(lat), Some(lng)) = (self.geohash_precision, id, at("lat"), at("lng")) {
+            if let Some(prefix) = r.key.strip_suffix(id).and_then(|k| k.strip_suffix('/')) {
+                r.key = geohash::position_key(prefix, Some(p), lat, lng, id);
+            }
+        }
+        r
+    }
+}
+
+pub fn read_records<R: BufRead>(input: R) -> impl Iterator<Item = Result<Record, String>> {
+    input.lines().enumerate().filter_map(|(n, line)| match line {
+        Ok(l) if l.trim().is_empty() => None,
+        Ok(l) => Some(serde_json::from_str::<Record>(&l).map_err(|e| format!("line {}: {e}", n + 1))),
+        Err(e) => Some(Err(format!("line {}: {e}", n + 1))),
+    })
+}
+
+// Republishes `records` with their original pacing, `speed` > 1 replaying faster.
+// Source timestamps (`ts` fields) are stamped again with the replay time, so
+// that they still measure the latency, or with the reception time shifted by
+// `source_time` ms, positions without one included, for trackers on the data
+// clock. Positions of the vehicles `signers` has
+// a key for are signed, before being compressed with `compression`.
+pub async fn replay(z: &Session, records: &[Record], speed: f64, signers: &Signers, compression: Compression, source_time: Option<u64>) -> usize {
+    let start = Instant::now();
+    let t0 = records.first().map(|r| r.t).unwrap_or(0);
+    let mut n = 0;
+    for r in records {
+        let due = Duration::from_secs_f64(r.t.saturating_sub(t0) as f64 / 1000.0 / speed);
+        if let Some(wait) = due.checked_sub(start.elapsed()) {
+            tokio::time::sleep(wait).await;
+        }
+        let res = if r.delete {
+            z.delete(&r.key).res().await
+        } else {
+            let bs = match &r.payload {
+                Payload::Json(serde_json::Value::Object(fields)) if fields.contains_key("ts") || (source_time.is_some() && fields.contains_key("position")) => {
+                    let mut fields = fields.clone();
+                    fields.insert("ts".into(), source_time.map_or_else(unix_ms, |shift| r.ts + shift).into());
+                    serde_json::to_vec(&fields).unwrap()
+                },
+                payload => payload.to_bytes(),
+            };
+            let mut attachment = signers.attachment(&bs).unwrap_or_default();
+            for (k, v) in &r.attachment {
+                attachment.insert(k, v);
+            }
+            let value = compression.encode(bs, Encoding::from(r.encoding.clone()));
+            if attachment.is_empty() {
+                z.put(&r.key, value).res().await
+            } else {
+                z.put(&r.key, value).with_attachment(attachment).res().await
+            }
+        };
+        match res {
+            Ok(_) => n += 1,
+            Err(e) => warn!("Unable to replay sample on {}: {e}", r.key),
+        }
+    }
+    n
+}
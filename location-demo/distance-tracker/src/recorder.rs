@@ -0,0 +1,24 @@
+use clap::Parser;
+use zenoh::prelude::r#async::*;
+use distance_tracker::logging::{self, LogFormat};
+use distance_tracker::tools::{self, RecordArgs};
+
+#[derive(clap_derive::Parser)]
+struct AppArgs {
+    #[command(flatten)]
+    record: RecordArgs,
+    #[arg(long)]
+    config: Option<String>
+}
+
+// Same as `DistanceAlert record`.
+#[tokio::main]
+async fn main() {
+    let args = AppArgs::parse();
+    logging::init(LogFormat::default());
+    let config = match args.config {
+        Some(f) => Config::from_file(f).unwrap(),
+        None => Config::default()
+    };
+    tools::record(args.record, config).await;
+}
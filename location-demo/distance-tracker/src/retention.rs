@@ -0,0 +1,104 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use serde::Deserialize;
+use zenoh::prelude::r#async::*;
+use crate::record::{Payload, Record};
+
+// A class of recorded samples (raw positions, warnings, dangers...) and how
+// long its samples are kept.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RetentionClass {
+    pub name: String,
+    // key expressions of the samples of the class
+    pub keys: Vec<String>,
+    // only the samples whose payload has one of these kinds (DangerMin...), any
+    // sample of the keys when empty
+    #[serde(default)]
+    pub kinds: Vec<String>,
+    pub hours: f64,
+}
+
+// Classes are tried in order, the first one matching a sample deciding its
+// retention, so classes are listed from the most specific. Samples of no
+// class are kept.
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    classes: Vec<(RetentionClass, Vec<OwnedKeyExpr>)>,
+}
+
+pub fn load(path: &str) -> Result<RetentionPolicy, String> {
+    let file = File::open(path).map_err(|e| format!("Unable to open {path}: {e}"))?;
+    let classes: Vec<RetentionClass> = serde_json::from_reader(BufReader::new(file)).map_err(|e| format!("Invalid retention classes in {path}: {e}"))?;
+    let classes = classes.into_iter().map(|c| {
+        if !(c.hours.is_finite() && c.hours > 0.0) {
+            return Err(format!("retention of class '{}' must be a positive number of hours", c.name));
+        }
+        let keys = c.keys.iter()
+            .map(|k| OwnedKeyExpr::new(k.as_str()).map_err(|e| format!("invalid key expression '{k}' in class '{}': {e}", c.name)))
+            .collect::<Result<Vec<_>, _>>()?;
+        if keys.is_empty() {
+            return Err(format!("class '{}' has no keys", c.name));
+        }
+        Ok((c, keys))
+    }).collect::<Result<_, _>>()?;
+    Ok(RetentionPolicy { classes })
+}
+
+#[derive(Debug, Default)]
+pub struct PruneStats {
+    pub kept: u64,
+    pub pruned: u64,
+}
+
+impl RetentionPolicy {
+    // The class the record belongs to, if any.
+    pub fn class(&self, r: &Record) -> Option<&RetentionClass> {
+        let key = keyexpr::new(&r.key).ok()?;
+        let kind = match &r.payload {
+            Payload::Json(v) => v.get("kind").and_then(|k| k.as_str()),
+            Payload::Bytes(_) => None,
+        };
+        self.classes.iter()
+            .find(|(c, keys)| keys.iter().any(|k| k.intersects(key)) && (c.kinds.is_empty() || kind.is_some_and(|k| c.kinds.iter().any(|ck| ck == k))))
+            .map(|(c, _)| c)
+    }
+
+    // Whether the record is past the retention of its class at `now` (ms
+    // since the UNIX epoch).
+    pub fn expired(&self, r: &Record, now: u64) -> bool {
+        self.class(r).is_some_and(|c| now.saturating_sub(r.ts) as f64 > c.hours * 3_600_000.0)
+    }
+
+    // Rewrites the recording at `path` without its expired records, lines
+    // being copied as is. Invalid lines are kept, and the file is removed
+    // once nothing is left of it.
+    pub fn prune(&self, path: &str, now: u64) -> std::io::Result<PruneStats> {
+        let tmp = format!("{path}.pruning");
+        let mut stats = PruneStats::default();
+        {
+            let mut out = BufWriter::new(File::create(&tmp)?);
+            for line in BufReader::new(File::open(path)?).lines() {
+                let line = line?;
+                let expired = serde_json::from_str::<Record>(&line).is_ok_and(|r| self.expired(&r, now));
+                if expired {
+                    stats.pruned += 1;
+                } else if !line.trim().is_empty() {
+                    out.write_all(line.as_bytes())?;
+                    out.write_all(b"\n")?;
+                    stats.kept += 1;
+                }
+            }
+            out.flush()?;
+        }
+        if stats.pruned == 0 {
+            std::fs::remove_file(&tmp)?;
+        } else if stats.kept == 0 {
+            std::fs::remove_file(&tmp)?;
+            std::fs::remove_file(path)?;
+        } else {
+            std::fs::rename(&tmp, path)?;
+        }
+        Ok(stats)
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+use serde::Serialize;
+use crate::closing::closing_speed;
+use crate::geo::DistanceAlgo;
+use crate::vehicle::{self, VehicleState};
+
+// Weight of the contribution of a neighbor by its kind, larger vehicles
+// weighing more. Other kinds weigh 1.
+const KIND_WEIGHTS: [(&str, f64); 6] = [("truck", 2.0), ("bus", 2.0), ("tow", 1.5), ("car", 1.0), ("bicycle", 0.5), ("pedestrian", 0.5)];
+// Closing speed (m/s) doubling the contribution of a neighbor.
+const CLOSING_SCALE: f64 = 10.0;
+// Closer neighbors (m) contribute as much as at this distance.
+const NEAREST: f64 = 1.0;
+
+// The risk a vehicle runs from its neighbors, published on <risk_key>/<id>: each
+// neighbor within range contributes min_distance / distance, scaled up by the
+// speed at which it closes in and weighted by its kind, so that a car at the
+// min distance and keeping its distance contributes 1.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Risk {
+    pub id: String,
+    // rounded to hundredths
+    pub score: f64,
+    // the neighbor contributing the most
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top: Option<String>,
+}
+
+fn weight(kind: &str) -> f64 {
+    KIND_WEIGHTS.iter().find(|(k, _)| *k == kind).map_or(1.0, |(_, w)| *w)
+}
+
+pub fn compute(map: &HashMap<String, VehicleState>, algo: DistanceAlgo, min_distance: f64, range: f64) -> Vec<Risk> {
+    let live = vehicle::live(map);
+    let mut vehicles: Vec<&VehicleState> = live.values().collect();
+    vehicles.sort_by(|a, b| a.info.id.cmp(&b.info.id));
+    vehicles.iter().map(|v| {
+        let (mut score, mut top) = (0.0, None::<(f64, &str)>);
+        for n in vehicles.iter().filter(|n| n.info.id != v.info.id) {
+            let d = algo.distance(&v.info.position, &n.info.position);
+            if d > range {
+                continue;
+            }
+            let closing = closing_speed(v, n).unwrap_or(0.0).max(0.0);
+            let c = weight(n.info.kind.as_str()) * (1.0 + closing / CLOSING_SCALE) * min_distance / d.max(NEAREST);
+            score += c;
+            if top.is_none_or(|(t, _)| c > t) {
+                top = Some((c, &n.info.id));
+            }
+        }
+        Risk { id: v.info.id.clone(), score: (score * 100.0).round() / 100.0, top: top.map(|(_, id)| id.to_string()) }
+    }).collect()
+}
+
+// The scores last published, so that only those which changed are published
+// again.
+#[derive(Default)]
+pub struct RiskBoard(HashMap<String, Risk>);
+
+impl RiskBoard {
+    // The risks which changed since the last cycle, and the ids of the vehicles
+    // gone since, whose risks are deleted.
+    pub fn update(&mut self, risks: Vec<Risk>) -> (Vec<Risk>, Vec<String>) {
+        let live: HashMap<&str, &Risk> = risks.iter().map(|r| (r.id.as_str(), r)).collect();
+        let gone: Vec<String> = self.0.keys().filter(|id| !live.contains_key(id.as_str())).cloned().collect();
+        for id in &gone {
+            self.0.remove(id);
+        }
+        let changed: Vec<Risk> = risks.iter().filter(|r| self.0.get(&r.id) != Some(*r)).cloned().collect();
+        for r in &changed {
+            self.0.insert(r.id.clone(), r.clone());
+        }
+        (changed, gone)
+    }
+}
@@ -0,0 +1,85 @@
+use serde_json::Value;
+use crate::geo::in_polygon;
+use crate::geohash::BoundingBox;
+use crate::Position;
+
+// A polygon of [lat, lng] vertices with its holes.
+#[derive(Debug, Clone)]
+pub struct Polygon {
+    pub outer: Vec<[f64; 2]>,
+    pub holes: Vec<Vec<[f64; 2]>>,
+}
+
+// The region the tracker cares about, vehicles outside of it being ignored.
+#[derive(Debug, Clone)]
+pub enum Roi {
+    Bbox(BoundingBox),
+    Polygons(Vec<Polygon>),
+}
+
+impl Roi {
+    pub fn contains(&self, p: &Position) -> bool {
+        match self {
+            Roi::Bbox(bb) => bb.contains(p.lat, p.lng),
+            Roi::Polygons(polygons) => polygons.iter().any(|poly| {
+                in_polygon(&poly.outer, p) && !poly.holes.iter().any(|h| in_polygon(h, p))
+            }),
+        }
+    }
+}
+
+// GeoJSON rings are arrays of [lng, lat] positions.
+fn ring(v: &Value) -> Result<Vec<[f64; 2]>, String> {
+    let points = v.as_array().ok_or("a ring is not an array")?;
+    let ring = points.iter().map(|p| match p.as_array().map(Vec::as_slice) {
+        Some([lng, lat, ..]) => match (lat.as_f64(), lng.as_f64()) {
+            (Some(lat), Some(lng)) if (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lng) => Ok([lat, lng]),
+            _ => Err(format!("invalid position {p}")),
+        },
+        _ => Err(format!("invalid position {p}")),
+    }).collect::<Result<Vec<_>, _>>()?;
+    if ring.len() < 3 {
+        return Err("a ring needs at least 3 positions".into());
+    }
+    Ok(ring)
+}
+
+fn polygon(v: &Value) -> Result<Polygon, String> {
+    let rings = v.as_array().ok_or("polygon coordinates are not an array")?;
+    let (outer, holes) = rings.split_first().ok_or("polygon without rings")?;
+    Ok(Polygon { outer: ring(outer)?, holes: holes.iter().map(ring).collect::<Result<_, _>>()? })
+}
+
+// The polygons of a GeoJSON object, other geometries being rejected.
+fn polygons(v: &Value, out: &mut Vec<Polygon>) -> Result<(), String> {
+    match v["type"].as_str() {
+        Some("Polygon") => out.push(polygon(&v["coordinates"])?),
+        Some("MultiPolygon") => {
+            for p in v["coordinates"].as_array().ok_or("multipolygon coordinates are not an array")? {
+                out.push(polygon(p)?);
+            }
+        },
+        Some("Feature") => polygons(&v["geometry"], out)?,
+        Some("FeatureCollection") => {
+            for f in v["features"].as_array().ok_or("features are not an array")? {
+                polygons(f, out)?;
+            }
+        },
+        Some(t) => return Err(format!("unsupported geometry {t}, expecting polygons")),
+        None => return Err("not a GeoJSON object".into()),
+    }
+    Ok(())
+}
+
+// Reads a GeoJSON Polygon or MultiPolygon, possibly in a Feature or a
+// FeatureCollection.
+pub fn load(path: &str) -> Result<Roi, String> {
+    let s = std::fs::read_to_string(path).map_err(|e| format!("Unable to read {path}: {e}"))?;
+    let v: Value = serde_json::from_str(&s).map_err(|e| format!("Invalid GeoJSON file {path}: {e}"))?;
+    let mut out = vec![];
+    polygons(&v, &mut out).map_err(|e| format!("Invalid region of interest in {path}: {e}"))?;
+    if out.is_empty() {
+        return Err(format!("No polygon in {path}"));
+    }
+    Ok(Roi::Polygons(out))
+}
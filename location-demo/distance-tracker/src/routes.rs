@@ -0,0 +1,141 @@
+use std::time::Instant;
+use serde::{Serialize, Deserialize};
+use tracing::info;
+use crate::geo::DistanceAlgo;
+use crate::Position;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RouteDeviationAlert {
+    pub id: String,
+    // distance (m) from the closest point of the assigned route
+    pub distance: f64,
+    pub corridor: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Checkpoint {
+    pub name: String,
+    // [lat, lng]
+    pub position: [f64; 2],
+    // seconds after the route is assigned, if scheduled
+    #[serde(default)]
+    pub due: Option<f64>,
+}
+
+impl Checkpoint {
+    fn position(&self) -> Position {
+        Position { lat: self.position[0], lng: self.position[1] }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Route {
+    pub points: Vec<Position>,
+    // in the order they are expected to be reached
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+// Either a bare array of [lat, lng] points, or {points, checkpoints}. Without
+// points the route goes through the checkpoints.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RoutePayload {
+    Points(Vec<[f64; 2]>),
+    Full {
+        #[serde(default)]
+        points: Vec<[f64; 2]>,
+        #[serde(default)]
+        checkpoints: Vec<Checkpoint>,
+    },
+}
+
+// Assignment (or removal, when route is None) of the planned route of a vehicle.
+#[derive(Debug)]
+pub struct RouteUpdate {
+    pub id: String,
+    pub route: Option<Route>,
+}
+
+// Routes are published on <prefix>/<vehicle id>, deleting the key unassigns the route.
+pub fn parse_update(prefix: &str, key: &str, delete: bool, payload: &[u8]) -> Result<RouteUpdate, String> {
+    let id = key.strip_prefix(prefix)
+        .and_then(|s| s.strip_prefix('/'))
+        .filter(|id| !id.is_empty() && !id.contains('/'))
+        .ok_or_else(|| format!("{key} is not a route key, expecting {prefix}/<vehicle id>"))?;
+    if delete {
+        return Ok(RouteUpdate { id: id.into(), route: None });
+    }
+    let (points, checkpoints) = match serde_json::from_slice(payload).map_err(|e| format!("invalid route for {id}: {e}"))? {
+        RoutePayload::Points(points) => (points, vec![]),
+        RoutePayload::Full { points, checkpoints } if points.is_empty() => (checkpoints.iter().map(|c| c.position).collect(), checkpoints),
+        RoutePayload::Full { points, checkpoints } => (points, checkpoints),
+    };
+    if points.is_empty() {
+        return Err(format!("empty route for {id}"));
+    }
+    let points = points.into_iter().map(|[lat, lng]| Position { lat, lng }).collect();
+    Ok(RouteUpdate { id: id.into(), route: Some(Route { points, checkpoints }) })
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Progress {
+    pub id: String,
+    pub last: Option<String>,
+    pub next: Option<String>,
+    pub reached: usize,
+    pub total: usize,
+    // seconds behind schedule (negative when early): how late the next
+    // checkpoint is if its due time has passed, otherwise the deviation at the
+    // last one
+    pub deviation: Option<f64>,
+}
+
+// A route assigned to a vehicle and how far along it the vehicle got.
+#[derive(Debug, Clone)]
+pub struct Itinerary {
+    pub route: Route,
+    // set on the first evaluation after the assignment
+    assigned: Option<Instant>,
+    reached: usize,
+    last_deviation: Option<f64>,
+}
+
+impl Itinerary {
+    pub fn new(route: Route) -> Self {
+        Itinerary { route, assigned: None, reached: 0, last_deviation: None }
+    }
+
+    // Starts the route over, from its first checkpoint.
+    pub fn restart(&mut self) {
+        self.assigned = None;
+        self.reached = 0;
+        self.last_deviation = None;
+    }
+
+    // Checkpoints are reached in order, once within `radius` meters of them.
+    pub fn advance(&mut self, id: &str, p: &Position, now: Instant, radius: f64, algo: DistanceAlgo) -> Option<Progress> {
+        if self.route.checkpoints.is_empty() {
+            return None;
+        }
+        let assigned = *self.assigned.get_or_insert(now);
+        let elapsed = now.duration_since(assigned).as_secs_f64();
+        while let Some(cp) = self.route.checkpoints.get(self.reached) {
+            if algo.distance(p, &cp.position()) > radius {
+                break;
+            }
+            self.last_deviation = cp.due.map(|due| elapsed - due);
+            self.reached += 1;
+            info!("CHECKPOINT: {id} reached {} ({}/{})", cp.name, self.reached, self.route.checkpoints.len());
+        }
+        let next = self.route.checkpoints.get(self.reached);
+        let overdue = next.and_then(|cp| cp.due).map(|due| elapsed - due).filter(|late| *late > 0.0);
+        Some(Progress {
+            id: id.into(),
+            last: self.reached.checked_sub(1).map(|i| self.route.checkpoints[i].name.clone()),
+            next: next.map(|cp| cp.name.clone()),
+            reached: self.reached,
+            total: self.route.checkpoints.len(),
+            deviation: overdue.or(self.last_deviation),
+        })
+    }
+}
@@ -0,0 +1,667 @@
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::{self, Write as _};
+use std::hash::Hasher;
+use std::time::Instant;
+use serde::Serialize;
+use tracing::{info, warn};
+use crate::closing::{self, ClosingAlert};
+use crate::clusters::{Cluster, ClusterBuilder};
+use crate::cutin::{CutInAlert, CutInDetector};
+use crate::fleets::Fleets;
+use crate::geo::{self, DistanceAlgo};
+use crate::headway::Headways;
+use crate::lights::{LightState, LightStatus};
+use crate::pairing::PairingRules;
+use crate::plausibility::MaxSpeeds;
+use crate::severity::Severities;
+use crate::tether::{TetherAlert, TetherGroup};
+use crate::poi::{Poi, PoiAlert};
+use crate::routes::{Itinerary, Progress, RouteDeviationAlert, RouteUpdate};
+use crate::sectors::{Sector, SectorAlert};
+use crate::telemetry::{BatteryAlert, BatteryRule};
+use crate::vehicle::{self, VehicleState};
+use crate::zones::{self, SpeedAlert, Zone};
+use crate::{AlertKind, AlertLevel, Color, DistanceAlert, Position, VehicleInfo, VehicleKind};
+
+const MIN_DISTANCE_SCALE: f64 = 1.5_f64;
+const MAX_DISTANCE_SCALE: f64 = 0.75_f64;
+// pairs closer than this many min distances are kept, so that their range rate
+// is known when they get close enough to raise alerts
+const APPROACH_SCALE: f64 = 3.0_f64;
+
+// Distance and alerts of a pair as of the last time one of its vehicles moved.
+struct CachedPair {
+    distance: f64,
+    at: Instant,
+    // cut-ins are momentary and never replayed
+    alerts: Vec<Alert>,
+}
+
+// Serialized as the payload of its kind.
+#[derive(Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Alert {
+    Distance(DistanceAlert),
+    CutIn(CutInAlert),
+    Closing(ClosingAlert),
+    Speed(SpeedAlert),
+    Route(RouteDeviationAlert),
+    Tether(TetherAlert),
+    Poi(PoiAlert),
+    Sector(SectorAlert),
+    Battery(BatteryAlert),
+}
+
+impl Alert {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Alert::Distance(_) => "distance",
+            Alert::CutIn(_) => "cutin",
+            Alert::Closing(_) => "closing",
+            Alert::Speed(_) => "speed",
+            Alert::Route(_) => "route",
+            Alert::Tether(_) => "tether",
+            Alert::Poi(_) => "poi",
+            Alert::Sector(_) => "sector",
+            Alert::Battery(_) => "battery",
+        }
+    }
+
+    // The vehicle the alert is about, and the other one of its pair if any.
+    pub fn vehicles(&self) -> (&str, Option<&str>) {
+        match self {
+            Alert::Distance(da) => (&da.ida, Some(&da.idb)),
+            Alert::CutIn(ca) => (&ca.cutter, Some(&ca.victim)),
+            Alert::Closing(ca) => (&ca.ida, Some(&ca.idb)),
+            Alert::Speed(sa) => (&sa.id, None),
+            Alert::Route(ra) => (&ra.id, None),
+            Alert::Tether(ta) => (&ta.id, None),
+            Alert::Poi(pa) => (&pa.id, None),
+            Alert::Sector(sa) => (&sa.id, Some(&sa.other)),
+            Alert::Battery(ba) => (&ba.id, None),
+        }
+    }
+}
+
+// What the rules print on stdout, per-pair output slowing the compute loop
+// down with many vehicles.
+#[derive(clap_derive::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrintOnly {
+    // every evaluated pair, alerting or not
+    All,
+    Alerts,
+    // dangers, cut-ins and closing speeds
+    Danger,
+    None,
+}
+
+impl PrintOnly {
+    fn alert(self, danger: bool) -> bool {
+        match self {
+            PrintOnly::All | PrintOnly::Alerts => true,
+            PrintOnly::Danger => danger,
+            PrintOnly::None => false,
+        }
+    }
+}
+
+// Logs a distance alert of a pair, dangers as warnings, crossing `threshold`
+// (m) when it is not from a severity band.
+fn log_pair(kind: AlertKind, ida: &str, idb: &str, distance: f64, threshold: Option<f64>, message: std::fmt::Arguments) {
+    if kind.is_danger() {
+        warn!(target: "alert", rule = "distance", kind = kind.tag(), ida, idb, distance, threshold, "{message}");
+    } else {
+        info!(target: "alert", rule = "distance", kind = kind.tag(), ida, idb, distance, threshold, "{message}");
+    }
+}
+
+// The alerting rules applied on every compute cycle, shared by the live
+// tracker and the offline analysis.
+pub struct Rules {
+    pub min_distance: f64,
+    pub max_distance: f64,
+    pub distance_algo: DistanceAlgo,
+    cutin: CutInDetector,
+    // closing speed (m/s) above which pairs raise an alert at any distance, 0 disables the rule
+    pub closing_speed: f64,
+    // below this distance (m) approaches are left to the distance rules
+    pub closing_floor: f64,
+    pub zones: Vec<Zone>,
+    // groups whose members must stay near their leader or centroid
+    pub tethers: Vec<TetherGroup>,
+    // only the pairs of the tether groups may be too far apart
+    pub tethered_separation: bool,
+    // static points of interest vehicles within their radius raise alerts for
+    pub pois: Vec<Poi>,
+    // sectors around the heading of the vehicles other vehicles raise alerts in
+    pub sectors: Vec<Sector>,
+    pub battery: BatteryRule,
+    // maximum distance (m) from the assigned route
+    pub route_corridor: f64,
+    routes: HashMap<String, Itinerary>,
+    // distance (m) at which a checkpoint is considered reached
+    pub checkpoint_radius: f64,
+    progress: Vec<Progress>,
+    // vehicles closer than this (m) and heading the same way are grouped, 0 disables clustering
+    pub cluster_range: f64,
+    clusters: Vec<Cluster>,
+    // last known state of the traffic lights, by name
+    pub lights: HashMap<String, LightStatus>,
+    // vehicles within this distance (m) of a red light are queuing at it
+    pub light_radius: f64,
+    // minimum distance (m) between two vehicles queuing at red lights
+    pub red_min_distance: f64,
+    // pairs of vehicles allowed to raise pair alerts
+    pub pairing: PairingRules,
+    pub print: PrintOnly,
+    // bounds the speeds derived from the fixes of each kind of vehicle
+    pub max_speeds: MaxSpeeds,
+    // pairs within the min distance but moving apart raise an alert instead of a danger
+    pub danger_only_closing: bool,
+    // distance bands replacing the ones derived from min and max distances when not empty
+    pub severities: Severities,
+    // min distances following the speed of the pairs instead of min_distance
+    pub headways: Headways,
+    // fleets of the vehicles, isolated from each other unless they allow cross
+    // fleet alerts, with their own thresholds
+    pub fleets: Fleets,
+    // vehicles which moved less than this (m) since their pairs were last computed
+    // keep the cached distances and alerts of these pairs
+    pub move_epsilon: f64,
+    // state of each vehicle its pairs were last computed from
+    anchors: HashMap<String, Anchor>,
+    // pairs, by first and second id in order, near enough to cluster, approaching or raising alerts
+    pairs: HashMap<String, HashMap<String, CachedPair>>,
+    // fingerprint of the configuration the cached pairs were computed with
+    computed_with: Option<u64>,
+}
+
+// What the pairs of a vehicle were last computed from: besides moving, a
+// vehicle changing speed or heading, starting or stopping to queue at a red
+// light, or changing its bubble or profile has its pairs computed again.
+struct Anchor {
+    position: Position,
+    red: bool,
+    speed: f64,
+    heading: Option<f64>,
+    min_distance: Option<f64>,
+    max_distance: Option<f64>,
+    kind: VehicleKind,
+    color: Color,
+    fleet: Option<String>,
+}
+
+impl Anchor {
+    fn of(v: &VehicleState, red: bool) -> Self {
+        Anchor {
+            position: v.info.position,
+            red,
+            speed: v.effective_speed,
+            heading: v.heading,
+            min_distance: v.info.min_distance,
+            max_distance: v.info.max_distance,
+            kind: v.info.kind.clone(),
+            color: v.info.color.clone(),
+            fleet: v.info.fleet.clone(),
+        }
+    }
+
+    // Whether the cached pairs of `v` still hold, it having moved `epsilon`
+    // meters at most.
+    fn holds(&self, v: &VehicleState, red: bool, algo: DistanceAlgo, epsilon: f64) -> bool {
+        self.red == red && self.speed == v.effective_speed && self.heading == v.heading
+            && self.min_distance == v.info.min_distance && self.max_distance == v.info.max_distance
+            && self.kind == v.info.kind && self.color == v.info.color && self.fleet == v.info.fleet
+            && algo.distance(&self.position, &v.info.position) <= epsilon
+    }
+}
+
+// Hashes what is written into it.
+struct Fingerprint(DefaultHasher);
+
+impl fmt::Write for Fingerprint {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl Rules {
+    pub fn new(min_distance: f64, max_distance: f64, distance_algo: DistanceAlgo, cutin_range: f64, cutin_bearing_rate: f64) -> Self {
+        Rules {
+            min_distance,
+            max_distance,
+            distance_algo,
+            cutin: CutInDetector::new(cutin_range, cutin_bearing_rate),
+            closing_speed: 0.0,
+            closing_floor: 0.0,
+            zones: vec![],
+            tethers: vec![],
+            tethered_separation: false,
+            pois: vec![],
+            sectors: vec![],
+            battery: BatteryRule::default(),
+            route_corridor: 0.0,
+            routes: HashMap::new(),
+            checkpoint_radius: 0.0,
+            progress: vec![],
+            cluster_range: 0.0,
+            clusters: vec![],
+            lights: HashMap::new(),
+            light_radius: 0.0,
+            red_min_distance: min_distance,
+            pairing: PairingRules::default(),
+            print: PrintOnly::All,
+            max_speeds: MaxSpeeds::default(),
+            danger_only_closing: false,
+            severities: Severities::default(),
+            headways: Headways::default(),
+            fleets: Fleets::default(),
+            move_epsilon: 0.0,
+            anchors: HashMap::new(),
+            pairs: HashMap::new(),
+            computed_with: None,
+        }
+    }
+
+    pub fn set_route(&mut self, update: RouteUpdate) {
+        match update.route {
+            Some(route) => self.routes.insert(update.id, Itinerary::new(route)),
+            None => self.routes.remove(&update.id),
+        };
+    }
+
+    // Forgets what was learnt about the vehicles, for a new demo session: the
+    // cached pairs, the cut-in bearings, the clusters and the progress along
+    // the routes, which stay assigned.
+    pub fn reset(&mut self) {
+        self.cutin.retain(|_| false);
+        for itinerary in self.routes.values_mut() {
+            itinerary.restart();
+        }
+        self.progress.clear();
+        self.clusters.clear();
+        self.anchors.clear();
+        self.pairs.clear();
+        self.computed_with = None;
+    }
+
+    pub fn set_light(&mut self, status: LightStatus) {
+        self.lights.insert(status.name.clone(), status);
+    }
+
+    pub fn evaluate(&mut self, map: &HashMap<String, VehicleState>, now: Instant) -> Vec<Alert> {
+        let live = vehicle::live(map);
+        let map = live.as_ref();
+        let at_red: HashSet<&String> = map.iter()
+            .filter(|(_, v)| self.at_red(v))
+            .map(|(id, _)| id)
+            .collect();
+        let dirty = self.moved(map, &at_red);
+        let mut alerts = Vec::new();
+        let mut clusters = ClusterBuilder::default();
+        let mut progress = Vec::new();
+        for (cid, cv) in map.iter() {
+            if let Some(zone) = zones::limiting(&self.zones, &cv.info.position) {
+                if cv.info.speed > zone.speed_limit {
+                    if self.print.alert(false) { info!(target: "alert", rule = "speed", id = %cid, speed = cv.info.speed, limit = zone.speed_limit, zone = %zone.name, "SPEED: {cid} = {} >? {} in {}", cv.info.speed, zone.speed_limit, zone.name); }
+                    alerts.push(Alert::Speed(SpeedAlert { id: cid.clone(), zone: zone.name.clone(), speed: cv.info.speed, limit: zone.speed_limit }));
+                }
+            }
+            if let Some(ba) = self.battery.check(cv) {
+                if self.print.alert(false) { info!(target: "alert", rule = "battery", id = %cid, battery = ba.battery, low = ba.low, "BATTERY: {cid} = {} <? {}", ba.battery, ba.low); }
+                alerts.push(Alert::Battery(ba));
+            }
+            if let Some(it) = self.routes.get_mut(cid) {
+                if let Some(p) = it.advance(cid, &cv.info.position, now, self.checkpoint_radius, self.distance_algo) {
+                    progress.push(p);
+                }
+            }
+            if let Some(distance) = self.routes.get(cid).and_then(|it| geo::distance_to_polyline(&cv.info.position, &it.route.points)) {
+                if distance > self.route_corridor {
+                    if self.print.alert(false) { info!(target: "alert", rule = "route", id = %cid, distance, corridor = self.route_corridor, "ROUTE: {cid} = {distance} >? {} from its route", self.route_corridor); }
+                    alerts.push(Alert::Route(RouteDeviationAlert { id: cid.clone(), distance, corridor: self.route_corridor }));
+                }
+            }
+            for pa in self.pois.iter().filter_map(|p| p.check(cid, &cv.info.position, self.distance_algo)) {
+                if pa.hazard && self.print.alert(true) {
+                    warn!(target: "alert", rule = "poi", id = %cid, poi = %pa.poi, distance = pa.distance, radius = pa.radius, hazard = true, "POI: {cid} = {} <? {} from hazard {}", pa.distance, pa.radius, pa.poi);
+                } else if !pa.hazard && self.print.alert(false) {
+                    info!(target: "alert", rule = "poi", id = %cid, poi = %pa.poi, distance = pa.distance, radius = pa.radius, hazard = false, "POI: {cid} = {} <? {} from {}", pa.distance, pa.radius, pa.poi);
+                }
+                alerts.push(Alert::Poi(pa));
+            }
+        }
+        for g in &self.tethers {
+            for ta in g.stragglers(map, self.distance_algo) {
+                if self.print.alert(false) { info!(target: "alert", rule = "tether", id = %ta.id, distance = ta.distance, radius = ta.radius, group = %ta.group, "TETHER: {} = {} >? {} in {}", ta.id, ta.distance, ta.radius, ta.group); }
+                alerts.push(Alert::Tether(ta));
+            }
+        }
+        // pairs of vehicles which did not move, from the cache
+        for (aid, pairs) in self.pairs.iter().filter(|(id, _)| !dirty.contains(id)) {
+            for (bid, p) in pairs.iter().filter(|(id, _)| !dirty.contains(id)) {
+                if self.cluster_range > 0.0 {
+                    clusters.link(&map[aid], &map[bid], p.distance, self.cluster_range);
+                }
+                alerts.extend(p.alerts.iter().cloned());
+            }
+        }
+        for cid in &dirty {
+            let cv = &map[*cid];
+            for (oid, ov) in map.iter() {
+                // pairs of two vehicles which moved are computed once
+                if oid == *cid || (oid < *cid && dirty.contains(oid)) {
+                    continue;
+                }
+                let (a, b) = if *cid < oid { (cv, ov) } else { (ov, cv) };
+                let at_red = at_red.contains(cid) && at_red.contains(oid);
+                self.compute_pair(a, b, at_red, now, &mut clusters, &mut alerts);
+            }
+        }
+        self.cutin.retain(|id| map.contains_key(id));
+        self.clusters = clusters.build(map);
+        self.progress = progress;
+        alerts
+    }
+
+    // Fingerprint of everything the pairs are computed with, through Debug so
+    // that the thresholds, bands, headways, sectors, tethers, fleets and
+    // pairing rules changed at runtime all invalidate the cached pairs.
+    fn fingerprint(&self) -> u64 {
+        let mut f = Fingerprint(DefaultHasher::new());
+        let _ = write!(f, "{:?}", (
+            [self.min_distance, self.max_distance, self.red_min_distance, self.closing_speed, self.closing_floor, self.cluster_range],
+            (self.distance_algo, self.danger_only_closing, self.tethered_separation),
+            (&self.severities, &self.headways, &self.sectors, &self.tethers, &self.fleets, &self.pairing),
+        ));
+        f.0.finish()
+    }
+
+    // Vehicles which moved more than `move_epsilon`, or otherwise changed what
+    // their pairs are computed from (see Anchor), since their pairs were last
+    // computed, dropping the cached pairs of vehicles which left and all of
+    // them when the configuration changed.
+    fn moved<'a>(&mut self, map: &'a HashMap<String, VehicleState>, at_red: &HashSet<&String>) -> HashSet<&'a String> {
+        let fingerprint = self.fingerprint();
+        if self.computed_with != Some(fingerprint) {
+            self.anchors.clear();
+            self.pairs.clear();
+            self.computed_with = Some(fingerprint);
+        }
+        self.anchors.retain(|id, _| map.contains_key(id));
+        self.pairs.retain(|id, pairs| {
+            pairs.retain(|oid, _| map.contains_key(oid));
+            map.contains_key(id) && !pairs.is_empty()
+        });
+        let mut dirty = HashSet::new();
+        for (id, v) in map.iter() {
+            let red = at_red.contains(id);
+            let moved = match self.anchors.get(id) {
+                Some(a) => !a.holds(v, red, self.distance_algo, self.move_epsilon),
+                None => true,
+            };
+            if moved {
+                self.anchors.insert(id.clone(), Anchor::of(v, red));
+                dirty.insert(id);
+            }
+        }
+        dirty
+    }
+
+    // Computes the pair of a and b, ordered by id, caching it when its
+    // vehicles are near enough to cluster or approach, or it raised alerts.
+    fn compute_pair(&mut self, a: &VehicleState, b: &VehicleState, at_red: bool, now: Instant, clusters: &mut ClusterBuilder, alerts: &mut Vec<Alert>) {
+        let distance = self.distance_algo.distance(&a.info.position, &b.info.position);
+        if self.cluster_range > 0.0 {
+            clusters.link(a, b, distance, self.cluster_range);
+        }
+        let mut raised = Vec::new();
+        self.check_pair(a, b, distance, at_red, now, &mut raised);
+        let replayed: Vec<Alert> = raised.iter().filter(|a| !matches!(a, Alert::CutIn(_))).cloned().collect();
+        let reach = self.min_distance.max(self.severities.reach())
+            .max(self.headways.min_distance(a, b).unwrap_or(0.0))
+            .max(self.fleets.max_min_distance())
+            .max(a.info.min_distance.unwrap_or(0.0))
+            .max(b.info.min_distance.unwrap_or(0.0));
+        if replayed.is_empty() && distance > self.cluster_range && distance > reach * APPROACH_SCALE {
+            if let Some(pairs) = self.pairs.get_mut(&a.info.id) {
+                pairs.remove(&b.info.id);
+            }
+        } else {
+            let pair = CachedPair { distance, at: now, alerts: replayed };
+            match self.pairs.get_mut(&a.info.id) {
+                Some(pairs) => match pairs.get_mut(&b.info.id) {
+                    Some(p) => *p = pair,
+                    None => { pairs.insert(b.info.id.clone(), pair); },
+                },
+                None => { self.pairs.insert(a.info.id.clone(), HashMap::from([(b.info.id.clone(), pair)])); },
+            }
+        }
+        alerts.append(&mut raised);
+    }
+
+    // Rate (m/s) at which the distance of the pair changed since it was last
+    // computed, negative when it converges, None if it was not cached.
+    fn range_rate(&self, a: &str, b: &str, distance: f64, now: Instant) -> Option<f64> {
+        let (a, b) = if a < b { (a, b) } else { (b, a) };
+        let p = self.pairs.get(a)?.get(b)?;
+        let dt = now.checked_duration_since(p.at)?.as_secs_f64();
+        (dt > 0.0).then(|| (distance - p.distance) / dt)
+    }
+
+    // Pair alerts between cv and ov, which are `distance` meters apart, with
+    // their range rate since the pair was cached.
+    fn check_pair(&mut self, cv: &VehicleState, ov: &VehicleState, distance: f64, at_red: bool, now: Instant, alerts: &mut Vec<Alert>) {
+        let (cid, oid) = (&cv.info.id, &ov.info.id);
+        if !self.pairing.allows(&cv.info, &ov.info) || !self.fleets.pairs(cv.info.fleet.as_deref(), ov.info.fleet.as_deref()) {
+            return;
+        }
+        for ca in self.cutin.check(cv, ov, distance, now) {
+            if self.print.alert(true) {
+                warn!(target: "alert", rule = "cutin", ida = %ca.cutter, idb = %ca.victim, distance = ca.distance, bearing = ca.relative_bearing, bearing_rate = ca.bearing_rate,
+                    "CUT-IN: {} -> {} = {} (bearing {} closing at {} deg/s)", ca.cutter, ca.victim, ca.distance, ca.relative_bearing, ca.bearing_rate);
+            }
+            alerts.push(Alert::CutIn(ca));
+        }
+        if self.closing_speed > 0.0 && distance > self.closing_floor {
+            if let Some(cs) = closing::closing_speed(cv, ov).filter(|cs| *cs > self.closing_speed) {
+                if self.print.alert(true) { warn!(target: "alert", rule = "closing", ida = %cid, idb = %oid, distance, closing_speed = cs, threshold = self.closing_speed, "CLOSING: {cid} -> {oid} = {cs} >? {} m/s at {distance}", self.closing_speed); }
+                alerts.push(Alert::Closing(ClosingAlert { ida: cid.clone(), idb: oid.clone(), distance, closing_speed: cs, threshold: self.closing_speed }));
+            }
+        }
+        for (v, o) in [(cv, ov), (ov, cv)] {
+            for sa in self.sectors.iter().filter_map(|s| s.check(v, o, distance)) {
+                if sa.danger && self.print.alert(true) {
+                    warn!(target: "alert", rule = "sector", ida = %sa.id, idb = %sa.other, distance, range = sa.range, bearing = sa.relative_bearing, sector = %sa.sector, "SECTOR: {} -> {} = {distance} <? {} at {} deg in {}", sa.id, sa.other, sa.range, sa.relative_bearing, sa.sector);
+                } else if !sa.danger && self.print.alert(false) {
+                    info!(target: "alert", rule = "sector", ida = %sa.id, idb = %sa.other, distance, range = sa.range, bearing = sa.relative_bearing, sector = %sa.sector, "SECTOR: {} -> {} = {distance} <? {} at {} deg in {}", sa.id, sa.other, sa.range, sa.relative_bearing, sa.sector);
+                }
+                alerts.push(Alert::Sector(sa));
+            }
+        }
+        // the larger safety bubble of the two vehicles, those declaring none
+        // having the default one
+        let bubble = |default: f64, declared: fn(&VehicleInfo) -> Option<f64>| {
+            declared(&cv.info).unwrap_or(default).max(declared(&ov.info).unwrap_or(default))
+        };
+        // pairs of a fleet have its thresholds, the others the tracker's
+        let fleet = self.fleets.shared(cv.info.fleet.as_deref(), ov.info.fleet.as_deref());
+        let fleet_min = fleet.and_then(|f| f.min_distance).unwrap_or(self.min_distance);
+        let fleet_max = fleet.and_then(|f| f.max_distance).unwrap_or(self.max_distance);
+        let base = if at_red { self.red_min_distance } else { self.headways.min_distance(cv, ov).unwrap_or(fleet_min) };
+        let min_distance = bubble(base, |v| v.min_distance);
+        let max_distance = bubble(fleet_max, |v| v.max_distance);
+        let bearing = cv.info.position.bearing(&ov.info.position);
+        let range_rate = self.range_rate(cid, oid, distance, now);
+        let da = |kind| Alert::Distance(DistanceAlert { ida: cid.clone(), idb: oid.clone(), distance, bearing, range_rate, severity: None, color: None, kind });
+        // pairs never computed before are assumed to be closing
+        let closing = range_rate.is_none_or(|r| r < 0.0);
+        let separation = !self.tethered_separation || self.tethers.iter().any(|g| g.leashes(cid, oid));
+        if !self.severities.is_empty() {
+            let near_scale = if self.min_distance > 0.0 { min_distance / self.min_distance } else { 1.0 };
+            let classified = self.severities.classify(distance, near_scale, self.danger_only_closing && !closing)
+                .filter(|(band, _)| separation || band.near());
+            match classified {
+                Some((band, kind)) => {
+                    if self.print.alert(kind.is_danger()) { log_pair(kind, cid, oid, distance, None, format_args!("{}: {cid} -> {oid} = {distance}", band.name.to_uppercase())); }
+                    alerts.push(Alert::Distance(DistanceAlert {
+                        ida: cid.clone(), idb: oid.clone(), distance, bearing, range_rate,
+                        severity: Some(band.name.clone()), color: band.color.clone(), kind,
+                    }));
+                },
+                None => if self.print == PrintOnly::All { info!(target: "alert", ida = %cid, idb = %oid, distance, "{cid} -> {oid} = {distance}"); },
+            }
+            return;
+        }
+        if distance <= min_distance && self.danger_only_closing && !closing {
+            let kind = AlertKind::proximity(AlertLevel::Alert);
+            if self.print.alert(false) { log_pair(kind, cid, oid, distance, Some(min_distance), format_args!("ALERT: {cid} -> {oid} = {distance} <? {min_distance} moving apart")); }
+            alerts.push(da(kind));
+        } else if distance <= min_distance {
+            let kind = AlertKind::proximity(AlertLevel::Danger);
+            if self.print.alert(true) { log_pair(kind, cid, oid, distance, Some(min_distance), format_args!("DANGER: {cid} -> {oid} = {distance} <? {min_distance}")); }
+            alerts.push(da(kind));
+        } else if  distance <= (min_distance * MIN_DISTANCE_SCALE)  {
+            let kind = AlertKind::proximity(AlertLevel::Alert);
+            if self.print.alert(false) { log_pair(kind, cid, oid, distance, Some(min_distance), format_args!("ALERT: {cid} -> {oid} = {distance} <? {min_distance}")); }
+            alerts.push(da(kind));
+        }
+        if !separation {
+            if self.print == PrintOnly::All && distance > min_distance * MIN_DISTANCE_SCALE {
+                info!(target: "alert", ida = %cid, idb = %oid, distance, "{cid} -> {oid} = {distance}");
+            }
+        } else if distance > max_distance {
+            let kind = AlertKind::separation(AlertLevel::Danger);
+            if self.print.alert(true) { log_pair(kind, cid, oid, distance, Some(max_distance), format_args!("DANGER: {cid} -> {oid} = {distance} >? {max_distance}")); }
+            alerts.push(da(kind));
+        } else if  distance > (max_distance * MAX_DISTANCE_SCALE)  {
+            let kind = AlertKind::separation(AlertLevel::Alert);
+            if self.print.alert(false) { log_pair(kind, cid, oid, distance, Some(max_distance), format_args!("ALERT: {cid} -> {oid} = {distance} >? {max_distance}")); }
+            alerts.push(da(kind));
+        } else if self.print == PrintOnly::All {
+            info!(target: "alert", ida = %cid, idb = %oid, distance, "{cid} -> {oid} = {distance}");
+        }
+    }
+
+    fn at_red(&self, v: &VehicleState) -> bool {
+        self.lights.values().any(|l| l.state == LightState::Red
+            && self.distance_algo.distance(&v.info.position, &l.position) <= self.light_radius)
+    }
+
+    // Pair alerts of vehicle `id` only, for vehicles needing to be followed
+    // more closely than the compute period allows.
+    pub fn evaluate_pairs_of(&mut self, map: &HashMap<String, VehicleState>, id: &str, now: Instant) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        let live = vehicle::live(map);
+        let map = live.as_ref();
+        let Some(cv) = map.get(id) else {
+            return alerts;
+        };
+        let cv_at_red = self.at_red(cv);
+        for (oid, ov) in map.iter() {
+            if oid != id {
+                let distance = self.distance_algo.distance(&cv.info.position, &ov.info.position);
+                let at_red = cv_at_red && self.at_red(ov);
+                self.check_pair(cv, ov, distance, at_red, now, &mut alerts);
+            }
+        }
+        alerts
+    }
+
+    // Progress along their checkpoints of the vehicles with a route, as of the
+    // last evaluation.
+    pub fn progress(&self) -> &[Progress] {
+        &self.progress
+    }
+
+    // Groups of vehicles travelling together found by the last evaluation.
+    pub fn clusters(&self) -> &[Cluster] {
+        &self.clusters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+
+    const TURIN: Position = Position { lat: 45.0703, lng: 7.6869 };
+
+    // The distance alert kinds raised by two vehicles `distance` meters apart,
+    // with min and max distances of 10 and 1000 m.
+    fn kinds_at(distance: f64) -> Vec<AlertKind> {
+        kinds_with(Headways::default(), distance, 0.0)
+    }
+
+    // Likewise for vehicles moving at `speed` (m/s), with `headways`.
+    fn kinds_with(headways: Headways, distance: f64, speed: f64) -> Vec<AlertKind> {
+        let mut rules = Rules::new(10.0, 1000.0, DistanceAlgo::Haversine, 0.0, 0.0);
+        rules.print = PrintOnly::None;
+        rules.headways = headways;
+        let now = Instant::now();
+        let vehicle = |id: &str, position| (id.to_string(), VehicleState::new(VehicleInfo { id: id.into(), position, speed, ..Default::default() }, now));
+        // 1e-5 degrees of latitude are 1.111949 m
+        let north = Position { lat: TURIN.lat + distance / 1.111_949 * 1e-5, lng: TURIN.lng };
+        let map = HashMap::from([vehicle("a", TURIN), vehicle("b", north)]);
+        rules.evaluate(&map, now).into_iter().filter_map(|a| match a {
+            Alert::Distance(da) => Some(da.kind),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn proximity_danger_within_the_min_distance() {
+        assert_eq!(kinds_at(5.0), [AlertKind::proximity(AlertLevel::Danger)]);
+    }
+
+    #[test]
+    fn proximity_alert_within_one_and_a_half_min_distance() {
+        assert_eq!(kinds_at(12.0), [AlertKind::proximity(AlertLevel::Alert)]);
+    }
+
+    #[test]
+    fn separation_alert_beyond_three_quarters_of_the_max_distance() {
+        assert_eq!(kinds_at(800.0), [AlertKind::separation(AlertLevel::Alert)]);
+    }
+
+    #[test]
+    fn separation_danger_beyond_the_max_distance() {
+        assert_eq!(kinds_at(1200.0), [AlertKind::separation(AlertLevel::Danger)]);
+    }
+
+    #[test]
+    fn no_distance_alert_in_between() {
+        assert!(kinds_at(500.0).is_empty());
+    }
+
+    #[test]
+    fn cached_pairs_follow_the_speed_of_their_vehicles() {
+        let mut rules = Rules::new(10.0, 1000.0, DistanceAlgo::Haversine, 0.0, 0.0);
+        rules.print = PrintOnly::None;
+        rules.closing_speed = 5.0;
+        rules.move_epsilon = 100.0;
+        let now = Instant::now();
+        let north = Position { lat: TURIN.lat + 200.0 / 1.111_949 * 1e-5, lng: TURIN.lng };
+        // a heading north to b, b heading south to a, neither moving between the cycles
+        let fleet = |speed: f64| HashMap::from([("a", TURIN, 0.0), ("b", north, 180.0)].map(|(id, position, heading)| {
+            let mut v = VehicleState::new(VehicleInfo { id: id.into(), position, speed, ..Default::default() }, now);
+            v.heading = Some(heading);
+            (id.to_string(), v)
+        }));
+        let closing = |alerts: Vec<Alert>| alerts.iter().any(|a| matches!(a, Alert::Closing(_)));
+        assert!(!closing(rules.evaluate(&fleet(1.0), now)));
+        assert!(closing(rules.evaluate(&fleet(5.0), now + Duration::from_secs(1))));
+    }
+
+    #[test]
+    fn alert_kinds_keep_their_tags() {
+        for tag in ["AlertMin", "DangerMin", "AlertMax", "DangerMax"] {
+            let kind: AlertKind = serde_json::from_str(&format!("\"{tag}\"")).unwrap();
+            assert_eq!(serde_json::to_string(&kind).unwrap(), format!("\"{tag}\""));
+        }
+        assert!(matches!(serde_json::from_str(r#""DangerMax""#).unwrap(), AlertKind::Separation { level: AlertLevel::Danger }));
+        assert!(serde_json::from_str::<AlertKind>(r#""Danger""#).is_err());
+    }
+}
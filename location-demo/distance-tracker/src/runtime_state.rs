@@ -0,0 +1,78 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+use tracing::warn;
+use crate::admin::{Thresholds, Versioned};
+use crate::metadata::{MetaCache, VehicleMeta};
+use crate::zones::Zone;
+
+// What operators changed at runtime, restored at startup so that a restart
+// does not lose it: the shared configuration, in the versions last applied,
+// and the metadata the vehicles registered.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RuntimeState {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rules: Option<Versioned<Thresholds>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zones: Option<Versioned<Vec<Zone>>>,
+    #[serde(default)]
+    pub meta: BTreeMap<String, VehicleMeta>,
+}
+
+// The configuration sections, kept up to date by the admin task.
+pub type SharedRuntimeState = Arc<Mutex<RuntimeState>>;
+
+// The state saved in `path`, empty when the file does not exist yet.
+pub fn load(path: &Path) -> Result<RuntimeState, String> {
+    match std::fs::read_to_string(path) {
+        Ok(s) => serde_json::from_str(&s).map_err(|e| format!("Invalid runtime state {}: {e}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(RuntimeState::default()),
+        Err(e) => Err(format!("Unable to read {}: {e}", path.display())),
+    }
+}
+
+pub struct RuntimeStateFile {
+    path: PathBuf,
+    state: SharedRuntimeState,
+    meta: MetaCache,
+    // what the file holds
+    written: Option<Vec<u8>>,
+}
+
+pub type SharedRuntimeStateFile = Arc<Mutex<RuntimeStateFile>>;
+
+impl RuntimeStateFile {
+    pub fn new(path: PathBuf, state: SharedRuntimeState, meta: MetaCache) -> Self {
+        RuntimeStateFile { path, state, meta, written: None }
+    }
+
+    // Writes the state, if it changed, replacing the previous file atomically.
+    pub fn save(&mut self) {
+        let mut state = self.state.lock().unwrap().clone();
+        state.meta = self.meta.snapshot();
+        let bs = serde_json::to_vec_pretty(&state).unwrap();
+        if self.written.as_ref() == Some(&bs) {
+            return;
+        }
+        let tmp = self.path.with_extension("tmp");
+        let r = self.path.parent().map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&tmp, &bs))
+            .and_then(|_| std::fs::rename(&tmp, &self.path));
+        match r {
+            Ok(_) => self.written = Some(bs),
+            Err(e) => warn!("unable to save the runtime state in {}: {e}", self.path.display()),
+        }
+    }
+}
+
+// Saves the state every second.
+pub async fn run(file: SharedRuntimeStateFile) {
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        tick.tick().await;
+        file.lock().unwrap().save();
+    }
+}
@@ -0,0 +1,339 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Deserialize;
+use tracing::{info, warn};
+use crate::{Color, Position, VehicleInfo, VehicleKind};
+
+const DEFAULT_PERIOD_MS: u64 = 200;
+// How long before the target a vehicle cutting across reaches the crossing
+// point, by default.
+const DEFAULT_LEAD_S: f64 = 1.0;
+
+// A scripted run of simulated vehicles, read from YAML.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    pub name: String,
+    // simulated time (s) after which the scenario ends
+    pub duration: f64,
+    // simulated time between two position updates, also their publication period
+    #[serde(default)]
+    pub period_ms: Option<u64>,
+    pub vehicles: Vec<ScenarioVehicle>,
+    #[serde(default)]
+    pub events: Vec<Event>,
+    #[serde(default)]
+    pub noise: Noise,
+}
+
+// Imperfections of the published positions, drawn from the seed of the
+// simulation so that a seed always gives the same run.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Noise {
+    // m, published positions are anywhere within this distance of the true ones
+    #[serde(default)]
+    pub position: f64,
+    // m/s, published speeds are off by this much at most
+    #[serde(default)]
+    pub speed: f64,
+    // chance (0 to 1) of a position not being published
+    #[serde(default)]
+    pub dropout: f64,
+}
+
+impl Noise {
+    pub fn is_none(&self) -> bool {
+        self.position == 0.0 && self.speed == 0.0 && self.dropout == 0.0
+    }
+
+    // What is published of `info`, if anything.
+    fn apply(&self, info: &VehicleInfo, rng: &mut ChaCha8Rng) -> Option<VehicleInfo> {
+        if self.is_none() {
+            return Some(info.clone());
+        }
+        if self.dropout > 0.0 && rng.gen_bool(self.dropout) {
+            return None;
+        }
+        let mut published = info.clone();
+        if self.position > 0.0 {
+            // uniformly spread over the disc
+            let bearing = rng.gen_range(0.0..360.0);
+            published.position = info.position.destination(bearing, self.position * rng.gen::<f64>().sqrt());
+        }
+        if self.speed > 0.0 {
+            published.speed = (info.speed + rng.gen_range(-self.speed..=self.speed)).max(0.0);
+        }
+        Some(published)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ScenarioVehicle {
+    pub id: String,
+    #[serde(default)]
+    pub kind: VehicleKind,
+    #[serde(default)]
+    pub color: Color,
+    // [lat, lng] points driven through in a straight line, the first one being
+    // the starting position
+    pub waypoints: Vec<[f64; 2]>,
+    // m/s
+    pub speed: f64,
+    // time (s) at which the vehicle appears, and starts publishing
+    #[serde(default)]
+    pub start: f64,
+    // drive the waypoints again once the last one is reached
+    #[serde(default, rename = "loop")]
+    pub looped: bool,
+    // assign the waypoints as the vehicle's planned route when it appears
+    #[serde(default)]
+    pub route: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Event {
+    // s
+    pub at: f64,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Action {
+    // changes the speed (m/s) of a vehicle, 0 stops it
+    Speed { vehicle: String, speed: f64 },
+    // sends a vehicle through new waypoints, from where it is
+    Reroute { vehicle: String, waypoints: Vec<[f64; 2]> },
+    // sends a vehicle across the path of the target, `ahead` meters in front
+    // of it, reaching the crossing point `lead` seconds before the target
+    CutAcross { vehicle: String, target: String, ahead: f64, #[serde(default)] lead: Option<f64> },
+}
+
+impl Action {
+    fn vehicles(&self) -> Vec<&str> {
+        match self {
+            Action::Speed { vehicle, .. } | Action::Reroute { vehicle, .. } => vec![vehicle],
+            Action::CutAcross { vehicle, target, .. } => vec![vehicle, target],
+        }
+    }
+}
+
+fn position(p: &[f64; 2]) -> Position {
+    Position { lat: p[0], lng: p[1] }
+}
+
+pub fn load(path: &str) -> Result<Scenario, String> {
+    let s = std::fs::read_to_string(path).map_err(|e| format!("Unable to read {path}: {e}"))?;
+    let scenario: Scenario = serde_yaml::from_str(&s).map_err(|e| format!("Invalid scenario {path}: {e}"))?;
+    validate(&scenario)?;
+    Ok(scenario)
+}
+
+fn validate(s: &Scenario) -> Result<(), String> {
+    if !(s.duration.is_finite() && s.duration > 0.0) {
+        return Err("duration must be positive".into());
+    }
+    if s.period_ms == Some(0) {
+        return Err("period_ms must be positive".into());
+    }
+    let n = &s.noise;
+    if ![n.position, n.speed].iter().all(|v| v.is_finite() && *v >= 0.0) || !(0.0..1.0).contains(&n.dropout) {
+        return Err("noise must have a non negative position and speed, and a dropout in [0, 1)".into());
+    }
+    let mut ids = HashSet::new();
+    for v in &s.vehicles {
+        if !ids.insert(v.id.as_str()) {
+            return Err(format!("duplicated vehicle '{}'", v.id));
+        }
+        if v.waypoints.is_empty() {
+            return Err(format!("vehicle '{}' has no waypoints", v.id));
+        }
+        if !(v.speed.is_finite() && v.speed >= 0.0) {
+            return Err(format!("vehicle '{}' must have a non negative speed", v.id));
+        }
+        if v.looped && v.waypoints.windows(2).all(|w| w[0] == w[1]) {
+            return Err(format!("vehicle '{}' loops over a single point", v.id));
+        }
+    }
+    for e in &s.events {
+        if let Some(unknown) = e.action.vehicles().into_iter().find(|id| !ids.contains(id)) {
+            return Err(format!("event at {}s refers to unknown vehicle '{unknown}'", e.at));
+        }
+        match &e.action {
+            Action::Speed { speed, .. } if !(speed.is_finite() && *speed >= 0.0) => {
+                return Err(format!("event at {}s sets a negative speed", e.at));
+            },
+            Action::Reroute { waypoints, .. } if waypoints.is_empty() => {
+                return Err(format!("event at {}s reroutes through no waypoints", e.at));
+            },
+            Action::CutAcross { vehicle, target, ahead, .. } if vehicle == target || *ahead <= 0.0 => {
+                return Err(format!("event at {}s must cut across another vehicle, a positive distance ahead", e.at));
+            },
+            _ => {},
+        }
+    }
+    Ok(())
+}
+
+struct SimVehicle {
+    info: VehicleInfo,
+    // waypoints left
+    path: VecDeque<Position>,
+    waypoints: Vec<Position>,
+    speed: f64,
+    start: f64,
+    looped: bool,
+    route: bool,
+    started: bool,
+}
+
+impl SimVehicle {
+    fn advance(&mut self, dt: f64) {
+        let mut step = self.speed * dt;
+        while step > 0.0 {
+            let Some(next) = self.path.front().copied() else { break };
+            let d = self.info.position.distance_haverside(&next);
+            if d <= step {
+                self.info.position = next;
+                step -= d;
+                self.path.pop_front();
+                if self.path.is_empty() && self.looped {
+                    self.path.extend(self.waypoints.iter().copied());
+                }
+            } else {
+                let bearing = self.info.position.bearing(&next);
+                self.info.position = self.info.position.destination(bearing, step);
+                step = 0.0;
+            }
+        }
+        // vehicles at the end of their path are stopped
+        self.info.speed = if self.path.is_empty() { 0.0 } else { self.speed };
+    }
+
+    fn heading(&self) -> Option<f64> {
+        self.path.front().map(|next| self.info.position.bearing(next))
+    }
+}
+
+// What the simulation publishes at one step.
+pub enum Output {
+    Position(VehicleInfo),
+    // waypoints of a vehicle assigned as its planned route
+    Route { id: String, points: Vec<[f64; 2]> },
+}
+
+// Runs a scenario in simulated time, advancing by the same period whatever
+// the wall clock does, so that a scenario always produces the same positions
+// for the same seed.
+pub struct Simulation {
+    vehicles: Vec<SimVehicle>,
+    index: HashMap<String, usize>,
+    events: VecDeque<Event>,
+    noise: Noise,
+    rng: ChaCha8Rng,
+    pub period_ms: u64,
+    pub duration: f64,
+    // steps run so far
+    steps: u64,
+}
+
+impl Simulation {
+    // The noise of the positions is drawn from `seed`.
+    pub fn new(s: &Scenario, seed: u64) -> Self {
+        let vehicles: Vec<SimVehicle> = s.vehicles.iter().map(|v| {
+            let waypoints: Vec<Position> = v.waypoints.iter().map(position).collect();
+            SimVehicle {
+                info: VehicleInfo { position: waypoints[0], speed: if waypoints.len() > 1 { v.speed } else { 0.0 }, color: v.color.clone(), id: v.id.clone(), kind: v.kind.clone(), ..Default::default() },
+                path: waypoints.iter().skip(1).copied().collect(),
+                waypoints,
+                speed: v.speed,
+                start: v.start,
+                looped: v.looped,
+                route: v.route,
+                started: false,
+            }
+        }).collect();
+        let index = vehicles.iter().enumerate().map(|(i, v)| (v.info.id.clone(), i)).collect();
+        let mut events = s.events.clone();
+        events.sort_by(|a, b| a.at.total_cmp(&b.at));
+        Simulation { vehicles, index, events: events.into(), noise: s.noise.clone(), rng: ChaCha8Rng::seed_from_u64(seed), period_ms: s.period_ms.unwrap_or(DEFAULT_PERIOD_MS), duration: s.duration, steps: 0 }
+    }
+
+    // Simulated time (s) of the next step, counted in steps so that events
+    // fall on the step they are scheduled at.
+    pub fn t(&self) -> f64 {
+        (self.steps * self.period_ms) as f64 / 1000.0
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.t() > self.duration
+    }
+
+    fn apply(&mut self, action: Action) {
+        let t = self.t();
+        match action {
+            Action::Speed { vehicle, speed } => {
+                info!("SCENARIO: {vehicle} speed set to {speed} m/s at {:.1}s", t);
+                self.vehicles[self.index[&vehicle]].speed = speed;
+            },
+            Action::Reroute { vehicle, waypoints } => {
+                info!("SCENARIO: {vehicle} rerouted at {:.1}s", t);
+                let v = &mut self.vehicles[self.index[&vehicle]];
+                v.path = waypoints.iter().map(position).collect();
+                v.waypoints = v.path.iter().copied().collect();
+            },
+            Action::CutAcross { vehicle, target, ahead, lead } => {
+                let lead = lead.unwrap_or(DEFAULT_LEAD_S);
+                let b = &self.vehicles[self.index[&target]];
+                let Some(heading) = b.heading().filter(|_| b.speed > 0.0) else {
+                    warn!("{target} is not moving at {:.1}s, {vehicle} cannot cut across it", t);
+                    return;
+                };
+                let crossing = b.info.position.destination(heading, ahead);
+                let time_left = ahead / b.speed - lead;
+                if time_left <= 0.0 {
+                    warn!("{target} is too close to the crossing point for {vehicle} to lead it by {lead}s");
+                    return;
+                }
+                let a = &mut self.vehicles[self.index[&vehicle]];
+                let distance = a.info.position.distance_haverside(&crossing);
+                let bearing = a.info.position.bearing(&crossing);
+                info!("SCENARIO: {vehicle} cuts across {target} at {:.1}s, {ahead} m ahead of it", t);
+                a.speed = distance / time_left;
+                // keeps going as far past the crossing point
+                a.path = [crossing, crossing.destination(bearing, distance.max(ahead))].into();
+                a.waypoints = a.path.iter().copied().collect();
+                a.looped = false;
+            },
+        }
+    }
+
+    // Applies the events due, moves the vehicles by a period and returns what
+    // to publish.
+    pub fn step(&mut self) -> Vec<Output> {
+        let t = self.t();
+        while self.events.front().is_some_and(|e| e.at <= t) {
+            let e = self.events.pop_front().unwrap();
+            self.apply(e.action);
+        }
+        let dt = self.period_ms as f64 / 1000.0;
+        let mut out = vec![];
+        for v in self.vehicles.iter_mut().filter(|v| v.start <= t) {
+            if !v.started {
+                v.started = true;
+                if v.route {
+                    out.push(Output::Route { id: v.info.id.clone(), points: v.waypoints.iter().map(|p| [p.lat, p.lng]).collect() });
+                }
+            } else {
+                v.advance(dt);
+            }
+            out.extend(self.noise.apply(&v.info, &mut self.rng).map(Output::Position));
+        }
+        self.steps += 1;
+        out
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::HashSet;
+use serde::{Serialize, Deserialize};
+use crate::geo::angle_diff;
+use crate::keys::{self, KeyUse};
+use crate::vehicle::VehicleState;
+
+// An angular sector around the vehicles of `kinds` (all when empty), relative
+// to their heading: another vehicle within `range` meters in it raises an
+// alert, a danger with `danger`. The sector spans clockwise from `from` to
+// `to`, in degrees from the heading: 0 ahead, 90 right, 180 behind, -90 left.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Sector {
+    pub name: String,
+    #[serde(default)]
+    pub kinds: Vec<String>,
+    pub from: f64,
+    pub to: f64,
+    pub range: f64,
+    #[serde(default)]
+    pub danger: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SectorAlert {
+    pub sector: String,
+    // the vehicle whose sector `other` is in
+    pub id: String,
+    pub other: String,
+    // m
+    pub distance: f64,
+    // degrees from the heading of `id`, negative on its left
+    pub relative_bearing: f64,
+    pub range: f64,
+    #[serde(default)]
+    pub danger: bool,
+}
+
+impl Sector {
+    fn width(&self) -> f64 {
+        if self.to > self.from { (self.to - self.from).min(360.0) } else { self.to - self.from + 360.0 }
+    }
+
+    pub fn contains(&self, relative_bearing: f64) -> bool {
+        (relative_bearing - self.from).rem_euclid(360.0) <= self.width()
+    }
+
+    // The alert of `other`, `distance` meters away, when in the sector of `v`.
+    // Vehicles whose heading is unknown have no sectors.
+    pub fn check(&self, v: &VehicleState, other: &VehicleState, distance: f64) -> Option<SectorAlert> {
+        if distance > self.range || !(self.kinds.is_empty() || self.kinds.iter().any(|k| k == v.info.kind.as_str())) {
+            return None;
+        }
+        let relative_bearing = angle_diff(v.info.position.bearing(&other.info.position), v.heading?);
+        self.contains(relative_bearing).then(|| SectorAlert {
+            sector: self.name.clone(), id: v.info.id.clone(), other: other.info.id.clone(), distance, relative_bearing, range: self.range, danger: self.danger,
+        })
+    }
+}
+
+// Reads a JSON array of {name, kinds, from (deg), to (deg), range (m), danger},
+// kinds and danger being optional. Names are single key chunks, alerts being
+// published on <sector_key>/<name>.
+pub fn load(path: &str) -> Result<Vec<Sector>, String> {
+    let s = std::fs::read_to_string(path).map_err(|e| format!("Unable to read {path}: {e}"))?;
+    let sectors: Vec<Sector> = serde_json::from_str(&s).map_err(|e| format!("Invalid sectors file {path}: {e}"))?;
+    check(&sectors)?;
+    Ok(sectors)
+}
+
+pub fn check(sectors: &[Sector]) -> Result<(), String> {
+    let mut names = HashSet::new();
+    for s in sectors {
+        keys::check(&format!("sector '{}'", s.name), &s.name, KeyUse::Concrete)?;
+        if s.name.contains('/') {
+            return Err(format!("sector name '{}' must be a single key chunk", s.name));
+        }
+        if !names.insert(&s.name) {
+            return Err(format!("duplicated sector '{}'", s.name));
+        }
+        if ![s.from, s.to].iter().all(|a| a.is_finite() && a.abs() <= 360.0) || s.from == s.to {
+            return Err(format!("sector '{}' must span from one angle to another, within [-360, 360] degrees", s.name));
+        }
+        if !(s.range.is_finite() && s.range > 0.0) {
+            return Err(format!("sector '{}' must have a positive range", s.name));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use crate::clock;
+
+// A time-boxed demo session: unattended installations start afresh every
+// `duration`, with a new id. Sessions follow the clock of the tracker, that of
+// the data with --clock data.
+#[derive(Debug)]
+pub struct DemoSession {
+    pub id: String,
+    duration: Duration,
+    started: Instant,
+}
+
+// Published on the session key when a session ends, the state of the tracker
+// having been cleared for the next one.
+#[derive(Serialize, Debug, Clone)]
+pub struct SessionReset {
+    pub previous: String,
+    pub session: String,
+    // ms since the UNIX epoch
+    pub ts: u64,
+    pub duration_s: u64,
+    // vehicles cleared from the map
+    pub vehicles: usize,
+}
+
+fn new_id() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
+
+impl DemoSession {
+    pub fn new(duration: Duration) -> Self {
+        DemoSession { id: new_id(), duration, started: clock::now() }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    pub fn is_over(&self) -> bool {
+        clock::now().duration_since(self.started) >= self.duration
+    }
+
+    // Starts the next session, once the state of this one is cleared.
+    pub fn next(&mut self, ts: u64, vehicles: usize) -> SessionReset {
+        let previous = std::mem::replace(&mut self.id, new_id());
+        self.started = clock::now();
+        SessionReset { previous, session: self.id.clone(), ts, duration_s: self.duration.as_secs(), vehicles }
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::HashSet;
+use serde::Deserialize;
+use crate::keys::{self, KeyUse};
+use crate::{AlertKind, AlertLevel};
+
+// A distance band raising alerts: pairs closer than `below` meters, or
+// farther than `above`, published on the distance alert key followed by
+// `key` when given.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SeverityBand {
+    pub name: String,
+    pub below: Option<f64>,
+    pub above: Option<f64>,
+    pub color: Option<String>,
+    pub key: Option<String>,
+}
+
+impl SeverityBand {
+    pub fn near(&self) -> bool {
+        self.below.is_some()
+    }
+
+    fn contains(&self, distance: f64, near_scale: f64) -> bool {
+        match (self.below, self.above) {
+            (Some(b), _) => distance <= b * near_scale,
+            (_, Some(a)) => distance > a,
+            _ => false,
+        }
+    }
+}
+
+// Ordered list of severity bands replacing the alert and danger bands derived
+// from the min and max distances. The first band containing the distance of a
+// pair is the one raised, so bands are listed from the most severe.
+#[derive(Debug, Clone, Default)]
+pub struct Severities {
+    pub bands: Vec<SeverityBand>,
+}
+
+impl Severities {
+    pub fn is_empty(&self) -> bool {
+        self.bands.is_empty()
+    }
+
+    // Largest bound (m) of the near bands, 0 without any.
+    pub fn reach(&self) -> f64 {
+        self.bands.iter().filter_map(|b| b.below).fold(0.0, f64::max)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SeverityBand> {
+        self.bands.iter().find(|b| b.name == name)
+    }
+
+    // The band containing `distance`, with the legacy kind matching it: the
+    // most severe near and far bands are dangers, the others alerts. Near
+    // bounds are scaled by `near_scale` (for vehicles queuing at red lights),
+    // and with `calm` the most severe near band is skipped (for pairs moving
+    // apart).
+    pub fn classify(&self, distance: f64, near_scale: f64, calm: bool) -> Option<(&SeverityBand, AlertKind)> {
+        let first_near = self.bands.iter().position(|b| b.near());
+        let first_far = self.bands.iter().position(|b| !b.near());
+        self.bands.iter().enumerate()
+            .filter(|(i, _)| !(calm && Some(*i) == first_near))
+            .find(|(_, b)| b.contains(distance, near_scale))
+            .map(|(i, b)| (b, match (b.near(), Some(i) == first_near || Some(i) == first_far) {
+                (true, true) => AlertKind::proximity(AlertLevel::Danger),
+                (true, false) => AlertKind::proximity(AlertLevel::Alert),
+                (false, true) => AlertKind::separation(AlertLevel::Danger),
+                (false, false) => AlertKind::separation(AlertLevel::Alert),
+            }))
+    }
+}
+
+pub fn load(path: &str) -> Result<Severities, String> {
+    let s = std::fs::read_to_string(path).map_err(|e| format!("Unable to read {path}: {e}"))?;
+    let bands: Vec<SeverityBand> = serde_json::from_str(&s).map_err(|e| format!("Invalid severities file {path}: {e}"))?;
+    let mut names = HashSet::new();
+    for b in &bands {
+        if !names.insert(&b.name) {
+            return Err(format!("duplicated severity '{}'", b.name));
+        }
+        match (b.below, b.above) {
+            (Some(d), None) | (None, Some(d)) if d.is_finite() && d >= 0.0 => (),
+            (Some(_), None) | (None, Some(_)) => return Err(format!("severity '{}' must have a non negative bound", b.name)),
+            _ => return Err(format!("severity '{}' must have exactly one of below and above", b.name)),
+        }
+        if let Some(k) = &b.key {
+            if k.contains('/') {
+                return Err(format!("key of severity '{}' must be a single chunk", b.name));
+            }
+            keys::check(&format!("key of severity '{}'", b.name), k, KeyUse::Concrete)?;
+        }
+    }
+    Ok(Severities { bands })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn band(name: &str, below: Option<f64>, above: Option<f64>) -> SeverityBand {
+        SeverityBand { name: name.into(), below, above, color: None, key: None }
+    }
+
+    // Near bands from the most severe, so with increasing bounds, followed by
+    // far bands with decreasing bounds.
+    fn severities() -> impl Strategy<Value = Severities> {
+        (prop::collection::vec(0.0..100.0, 0..4), prop::collection::vec(100.0..2000.0, 0..4)).prop_map(|(mut near, mut far)| {
+            near.sort_by(f64::total_cmp);
+            far.sort_by(|a: &f64, b| b.total_cmp(a));
+            let near = near.into_iter().enumerate().map(|(i, b)| band(&format!("near{i}"), Some(b), None));
+            let far = far.into_iter().enumerate().map(|(i, a)| band(&format!("far{i}"), None, Some(a)));
+            Severities { bands: near.chain(far).collect() }
+        })
+    }
+
+    fn rank(s: &Severities, name: &str) -> usize {
+        s.bands.iter().position(|b| b.name == name).unwrap()
+    }
+
+    proptest! {
+        #[test]
+        fn a_band_is_raised_exactly_when_one_contains_the_distance(s in severities(), distance in 0.0..3000.0) {
+            let contained = s.bands.iter().any(|b| b.below.is_some_and(|below| distance <= below) || b.above.is_some_and(|above| distance > above));
+            prop_assert_eq!(s.classify(distance, 1.0, false).is_some(), contained);
+        }
+
+        #[test]
+        fn calm_pairs_never_raise_the_most_severe_near_band(s in severities(), distance in 0.0..3000.0) {
+            let first_near = s.bands.iter().find(|b| b.near()).map(|b| b.name.as_str());
+            let calm = s.classify(distance, 1.0, true).map(|(b, _)| b.name.as_str());
+            prop_assert!(calm.is_none() || calm != first_near);
+            // the other bands are raised as without calm
+            let raised = s.classify(distance, 1.0, false).map(|(b, _)| b.name.as_str());
+            if raised != first_near {
+                prop_assert_eq!(calm, raised);
+            }
+        }
+
+        #[test]
+        fn kinds_follow_the_bands(s in severities(), distance in 0.0..3000.0) {
+            if let Some((b, kind)) = s.classify(distance, 1.0, false) {
+                let most_severe = s.bands.iter().find(|o| o.near() == b.near()).unwrap().name == b.name;
+                prop_assert_eq!(kind.is_danger(), most_severe);
+                prop_assert_eq!(matches!(kind, AlertKind::Proximity { .. }), b.near());
+                prop_assert!(!b.near() || distance <= s.reach());
+            } else {
+                prop_assert!(distance > s.reach() || s.bands.iter().all(|b| !b.near()));
+            }
+        }
+
+        #[test]
+        fn closer_pairs_are_not_less_severe(s in severities(), a in 0.0..100.0, b in 0.0..100.0) {
+            let (closer, farther) = if a <= b { (a, b) } else { (b, a) };
+            if let Some((fb, _)) = s.classify(farther, 1.0, false).filter(|(fb, _)| fb.near()) {
+                let (cb, _) = s.classify(closer, 1.0, false).unwrap();
+                prop_assert!(rank(&s, &cb.name) <= rank(&s, &fb.name));
+            }
+        }
+
+        #[test]
+        fn near_bounds_scale(s in severities(), distance in 0.0..100.0, scale in 0.5..3.0) {
+            // distances on a bound may round to either side once scaled
+            prop_assume!(s.bands.iter().filter_map(|b| b.below).all(|b| f64::abs(b - distance) > 1e-6));
+            let near = |d: f64, scale: f64| s.classify(d, scale, false).filter(|(b, _)| b.near()).map(|(b, _)| b.name.clone());
+            prop_assert_eq!(near(distance * scale, scale), near(distance, 1.0));
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::UnboundedSender;
+use zenoh::prelude::r#async::*;
+use tracing::{info, warn};
+use crate::geohash;
+use crate::rules::Alert;
+use crate::vehicle::VehicleState;
+use crate::{Position, VehicleInfo};
+
+// Length of the geohashes the positions are checked against, the longest
+// static cell being at most this long.
+pub const MAX_PRECISION: usize = 12;
+
+// Which geohash cells an instance owns.
+#[derive(Debug, Clone)]
+pub enum Cells {
+    // the cells given on the command line, of any precision
+    Static(Vec<String>),
+    // the cells of `precision` whose rendezvous hash is the highest for this
+    // instance among the live ones, so that every instance assigns them the
+    // same way without exchanging more than their presence
+    Elected { precision: usize, members: Arc<Mutex<BTreeSet<String>>> },
+}
+
+// The part of the fleet a tracker instance computes the alerts of, among the
+// instances sharing a large fleet: the vehicles in its cells, and the border
+// vehicles of its neighbors, within `border` meters of its cells.
+#[derive(Debug, Clone)]
+pub struct Shard {
+    pub me: String,
+    pub cells: Cells,
+    // m
+    pub border: f64,
+}
+
+// A vehicle within the border distance of the cells of other instances,
+// published on <shard_key>/border/<from>/<id>.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Border {
+    // instance owning the vehicle
+    pub from: String,
+    pub vehicle: VehicleInfo,
+}
+
+// FNV-1a, stable across builds and platforms unlike the std hashers.
+fn fnv(parts: &[&str]) -> u64 {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in parts.iter().flat_map(|p| p.bytes().chain([0])) {
+        h ^= b as u64;
+        h = h.wrapping_mul(0x0100_0000_01b3);
+    }
+    h
+}
+
+// The live instance owning `cell`.
+pub fn owner<'a>(members: &'a BTreeSet<String>, cell: &str) -> Option<&'a String> {
+    members.iter().max_by_key(|m| fnv(&[m, cell]))
+}
+
+impl Shard {
+    pub fn elected(me: &str, precision: usize, border: f64) -> Self {
+        let members = Arc::new(Mutex::new(BTreeSet::from([me.to_string()])));
+        Shard { me: me.into(), cells: Cells::Elected { precision, members }, border }
+    }
+
+    pub fn owns(&self, p: &Position) -> bool {
+        match &self.cells {
+            Cells::Static(cells) => {
+                let hash = geohash::encode(p.lat, p.lng, MAX_PRECISION);
+                cells.iter().any(|c| hash.starts_with(c.as_str()))
+            },
+            Cells::Elected { precision, members } => {
+                let cell = geohash::encode(p.lat, p.lng, *precision);
+                owner(&members.lock().unwrap(), &cell).is_some_and(|o| *o == self.me)
+            },
+        }
+    }
+
+    // Points around `p` at the border distance, whose owners are its neighbors.
+    fn around(&self, p: &Position) -> impl Iterator<Item = Position> + '_ {
+        let p = *p;
+        (0..8).map(move |i| p.destination(i as f64 * 45.0, self.border))
+    }
+
+    // Whether the neighbors need `p`, a position in the cells of the instance.
+    pub fn is_border(&self, p: &Position) -> bool {
+        self.border > 0.0 && self.around(p).any(|q| !self.owns(&q))
+    }
+
+    // Whether the instance needs `p`, a position in the cells of another one.
+    pub fn is_near(&self, p: &Position) -> bool {
+        self.border > 0.0 && self.around(p).any(|q| self.owns(&q))
+    }
+
+    // Alerts are published by the instance owning their first vehicle in id
+    // order, so that the pairs across instances raise them once.
+    pub fn owns_alert(&self, alert: &Alert, map: &HashMap<String, VehicleState>) -> bool {
+        let (a, b) = alert.vehicles();
+        let first = b.map_or(a, |b| a.min(b));
+        map.get(first).is_some_and(|v| self.owns(&v.info.position))
+    }
+}
+
+// Declares the instance as a liveliness token on <key>/<id> and follows the
+// others, the cells being assigned again as they come and go.
+pub async fn elect(z: Arc<Session>, key: String, shard: Shard) {
+    let Cells::Elected { members: shared, .. } = &shard.cells else { return };
+    let id = &shard.me;
+    let _token = z.liveliness().declare_token(format!("{key}/{id}")).res().await.unwrap_or_else(|e| panic!("--shard-key: {e}"));
+    let pattern = format!("{key}/*");
+    let subscriber = z.liveliness().declare_subscriber(&pattern).res().await.unwrap();
+    let prefix = format!("{key}/");
+    let member = |k: &KeyExpr| k.as_str().strip_prefix(&prefix).unwrap_or("").to_string();
+    match z.liveliness().get(&pattern).res().await {
+        Ok(replies) => {
+            while let Ok(reply) = replies.recv_async().await {
+                if let Ok(s) = reply.sample {
+                    shared.lock().unwrap().insert(member(&s.key_expr));
+                }
+            }
+        },
+        Err(e) => warn!("Unable to query the tracker instances on {pattern}: {e}"),
+    }
+    loop {
+        let members = shared.lock().unwrap().iter().cloned().collect::<Vec<_>>().join(", ");
+        info!("SHARD: cells shared by {members}");
+        match subscriber.recv_async().await {
+            Ok(s) if s.kind == SampleKind::Put => { shared.lock().unwrap().insert(member(&s.key_expr)); },
+            // itself never leaves
+            Ok(s) if member(&s.key_expr) != *id => { shared.lock().unwrap().remove(&member(&s.key_expr)); },
+            Ok(_) => (),
+            Err(_) => break,
+        }
+    }
+}
+
+// Forwards the border vehicles of the other instances published under
+// `prefix` which are near the cells of this one.
+pub async fn follow(z: Arc<Session>, prefix: String, shard: Shard, tx: UnboundedSender<VehicleInfo>) {
+    let keys = [format!("{prefix}/**")];
+    loop {
+        let (subs, mut rx) = zdemo_common::subscribe(&z, &keys).await;
+        while let Some(sample) = rx.recv().await {
+            if sample.kind == SampleKind::Delete {
+                continue;
+            }
+            match zdemo_common::decode_json::<Border>(&sample) {
+                Ok(b) if b.from != shard.me && shard.is_near(&b.vehicle.position) => {
+                    if tx.send(b.vehicle).is_err() {
+                        return;
+                    }
+                },
+                Ok(_) => (),
+                Err(e) => warn!("Ignoring the border vehicle on {}: {e}", sample.key_expr),
+            }
+        }
+        warn!("border subscription lost, declaring it again");
+        drop(subs);
+    }
+}
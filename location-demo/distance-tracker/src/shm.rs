@@ -0,0 +1,72 @@
+use zenoh::prelude::r#async::*;
+#[cfg(feature = "shm")]
+use std::sync::Mutex;
+#[cfg(feature = "shm")]
+use zenoh::shm::SharedMemoryManager;
+#[cfg(feature = "shm")]
+use tracing::{info, warn};
+
+// Size of the segment the publications of a process are written into.
+#[cfg(feature = "shm")]
+const SEGMENT_SIZE: usize = 32 * 1024 * 1024;
+
+// The shared memory segment publications are written into, with the `shm`
+// feature and shared memory enabled on the session (--shm), so that the
+// peers on the same host map them instead of receiving copies. Payloads are
+// published as they are otherwise, and when the segment is full.
+pub struct ShmSegment {
+    #[cfg(feature = "shm")]
+    manager: Option<Mutex<SharedMemoryManager>>,
+}
+
+impl ShmSegment {
+    #[cfg(feature = "shm")]
+    pub fn of(z: &Session, name: &str) -> Self {
+        if !*z.config().lock().transport().shared_memory().enabled() {
+            return ShmSegment { manager: None };
+        }
+        let id = format!("{name}-{}", std::process::id());
+        match SharedMemoryManager::make(id, SEGMENT_SIZE) {
+            Ok(m) => {
+                info!("Publishing through a shared memory segment of {} MiB", SEGMENT_SIZE >> 20);
+                ShmSegment { manager: Some(Mutex::new(m)) }
+            },
+            Err(e) => {
+                warn!("Unable to create a shared memory segment, publishing without: {e}");
+                ShmSegment { manager: None }
+            },
+        }
+    }
+
+    #[cfg(not(feature = "shm"))]
+    pub fn of(_z: &Session, _name: &str) -> Self {
+        ShmSegment {}
+    }
+
+    // `value` with its payload in the segment.
+    #[cfg(feature = "shm")]
+    pub fn value(&self, value: Value) -> Value {
+        let Some(manager) = &self.manager else { return value };
+        let payload = value.payload.contiguous();
+        let buf = manager.lock().unwrap().alloc(payload.len()).ok().map(|mut buf| {
+            // allocations are rounded up to the alignment of the segment, the
+            // receivers reading the length of the buffer from its info
+            let padding = buf.len - payload.len();
+            buf.len -= padding;
+            buf.info.length -= padding;
+            // the buffer is not shared before being published
+            unsafe { buf.as_mut_slice() }.copy_from_slice(&payload);
+            buf
+        });
+        drop(payload);
+        match buf {
+            Some(buf) => Value::from(buf).encoding(value.encoding),
+            None => value,
+        }
+    }
+
+    #[cfg(not(feature = "shm"))]
+    pub fn value(&self, value: Value) -> Value {
+        value
+    }
+}
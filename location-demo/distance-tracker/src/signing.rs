@@ -0,0 +1,122 @@
+use std::collections::{BTreeMap, HashMap};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use serde::Serialize;
+use zenoh::prelude::r#async::*;
+use zenoh::sample::Attachment;
+
+// Attachment entry carrying the ed25519 signature of the payload.
+pub const SIGNATURE_ATTACHMENT: &str = "sig";
+
+#[derive(clap_derive::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignaturePolicy {
+    /// Drop unsigned and invalid samples
+    Reject,
+    /// Apply unsigned and invalid samples, raising a tamper alert
+    Flag,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Tamper { Unsigned, UnknownPublisher, InvalidSignature }
+
+#[derive(Serialize, Debug)]
+pub struct TamperAlert<'a> {
+    pub id: &'a str,
+    // key the sample was received on
+    pub key: &'a str,
+    pub reason: Tamper,
+    pub rejected: bool,
+}
+
+fn read_map(path: &str) -> Result<BTreeMap<String, Vec<u8>>, String> {
+    let s = std::fs::read_to_string(path).map_err(|e| format!("Unable to read {path}: {e}"))?;
+    let keys: BTreeMap<String, String> = serde_json::from_str(&s).map_err(|e| format!("Invalid keys in {path}: {e}"))?;
+    keys.into_iter()
+        .map(|(id, k)| match BASE64.decode(&k) {
+            Ok(bs) if bs.len() == 32 => Ok((id, bs)),
+            Ok(_) => Err(format!("Invalid key for {id} in {path}: expecting 32 bytes")),
+            Err(e) => Err(format!("Invalid key for {id} in {path}: {e}")),
+        })
+        .collect()
+}
+
+fn write_map(path: &str, keys: &BTreeMap<String, Vec<u8>>) -> Result<(), String> {
+    let keys: BTreeMap<&String, String> = keys.iter().map(|(id, k)| (id, BASE64.encode(k))).collect();
+    std::fs::write(path, serde_json::to_string_pretty(&keys).unwrap()).map_err(|e| format!("Unable to write {path}: {e}"))
+}
+
+// Public keys of the publishers allowed to publish positions, by vehicle id,
+// read from a JSON object of base64 keys.
+pub struct Keyring(HashMap<String, Vec<u8>>);
+
+impl Keyring {
+    pub fn load(path: &str) -> Result<Self, String> {
+        Ok(Keyring(read_map(path)?.into_iter().collect()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn verify(&self, id: &str, payload: &[u8], signature: Option<&[u8]>) -> Result<(), Tamper> {
+        let signature = signature.ok_or(Tamper::Unsigned)?;
+        let key = self.0.get(id).ok_or(Tamper::UnknownPublisher)?;
+        UnparsedPublicKey::new(&ED25519, key).verify(payload, signature).map_err(|_| Tamper::InvalidSignature)
+    }
+}
+
+pub fn signature(sample: &Sample) -> Option<Vec<u8>> {
+    sample.attachment()?.get(&SIGNATURE_ATTACHMENT).map(|s| s.as_slice().to_vec())
+}
+
+// Private keys of the vehicles a publisher signs for, by vehicle id, read from
+// a JSON object of base64 seeds.
+#[derive(Default)]
+pub struct Signers(HashMap<String, Ed25519KeyPair>);
+
+impl Signers {
+    pub fn load(path: &str) -> Result<Self, String> {
+        read_map(path)?.into_iter()
+            .map(|(id, seed)| Ed25519KeyPair::from_seed_unchecked(&seed)
+                .map(|k| (id.clone(), k))
+                .map_err(|e| format!("Invalid seed for {id} in {path}: {e}")))
+            .collect::<Result<_, _>>()
+            .map(Signers)
+    }
+
+    // The attachment signing a position payload, if its vehicle has a key.
+    pub fn attachment(&self, payload: &[u8]) -> Option<Attachment> {
+        let v: serde_json::Value = serde_json::from_slice(payload).ok()?;
+        let key = self.0.get(v.get("id")?.as_str()?)?;
+        let mut attachment = Attachment::new();
+        attachment.insert(SIGNATURE_ATTACHMENT, key.sign(payload).as_ref());
+        Some(attachment)
+    }
+}
+
+// Generates keys for the given vehicles, adding their seeds to `seeds` and
+// their public keys to `keyring`. Vehicles already in `seeds` keep their key.
+pub fn keygen(ids: &[String], seeds: &str, keyring: &str) -> Result<(), String> {
+    let existing = |path: &str| if std::path::Path::new(path).exists() { read_map(path) } else { Ok(BTreeMap::new()) };
+    let mut private = existing(seeds)?;
+    let mut public = existing(keyring)?;
+    let rng = SystemRandom::new();
+    for id in ids {
+        let seed = private.entry(id.clone()).or_insert_with(|| {
+            let mut seed = vec![0; 32];
+            rng.fill(&mut seed).unwrap();
+            seed
+        });
+        let key = Ed25519KeyPair::from_seed_unchecked(seed).map_err(|e| format!("Invalid seed for {id} in {seeds}: {e}"))?;
+        public.insert(id.clone(), key.public_key().as_ref().to_vec());
+    }
+    write_map(seeds, &private)?;
+    write_map(keyring, &public)
+}
@@ -0,0 +1,305 @@
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::io::Write;
+use std::time::{Duration, Instant};
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::{header, Request, Uri};
+use hyper_util::rt::TokioIo;
+use serde::{Serialize, Deserialize};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::{channel, error::TrySendError, Receiver, Sender, WeakSender};
+use tokio::task::JoinHandle;
+use zdemo_common::Instance;
+use tracing::{error, info, warn};
+use crate::publish::Outgoing;
+use crate::record::unix_ms;
+
+const DEFAULT_CAPACITY: usize = 256;
+pub const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Sinks file entry, e.g. {"type": "webhook", "url": "http://localhost:9000/alerts"}.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SinkConfig {
+    #[serde(flatten)]
+    pub kind: SinkKind,
+    // alerts queued for the sink, further ones are dropped while it is full
+    #[serde(default)]
+    pub capacity: Option<usize>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SinkKind {
+    // POSTs every alert as JSON, only plain http is supported
+    Webhook { url: String },
+    // appends every alert to a JSON lines file
+    File { path: String },
+    // notify-send notification for the urgent (danger level) alerts
+    Desktop,
+    // sounds the urgent alerts, playing the clip of their severity or kind with
+    // `player` (paplay by default) or else speaking them with `speak`
+    // (espeak-ng by default), once per pair every `cooldown_s` (10 by default)
+    Audio {
+        #[serde(default)]
+        speak: Option<String>,
+        #[serde(default)]
+        player: Option<String>,
+        // sound file by severity band name or alert kind
+        #[serde(default)]
+        clips: BTreeMap<String, String>,
+        #[serde(default)]
+        cooldown_s: Option<f64>,
+    },
+}
+
+pub fn load(path: &str) -> Result<Vec<SinkConfig>, String> {
+    let s = std::fs::read_to_string(path).map_err(|e| format!("Unable to read {path}: {e}"))?;
+    let sinks: Vec<SinkConfig> = serde_json::from_str(&s).map_err(|e| format!("Invalid sinks file {path}: {e}"))?;
+    for sink in &sinks {
+        if let SinkKind::Webhook { url } = &sink.kind {
+            let uri: Uri = url.parse().map_err(|e| format!("invalid webhook url {url}: {e}"))?;
+            if uri.scheme_str() != Some("http") || uri.host().is_none() {
+                return Err(format!("invalid webhook url {url}, expecting http://<host>[:port]/<path>"));
+            }
+        }
+        if let SinkKind::Audio { cooldown_s: Some(c), .. } = &sink.kind {
+            if !(c.is_finite() && *c >= 0.0) {
+                return Err(format!("invalid audio cooldown_s {c}, expecting a non negative number of seconds"));
+            }
+        }
+    }
+    Ok(sinks)
+}
+
+// Destination of the alerts besides Zenoh. Each sink runs in its own task, so
+// it may take its time delivering an alert.
+pub trait AlertSink: Send + 'static {
+    fn name(&self) -> String;
+    fn deliver(&mut self, alert: &Outgoing, body: &serde_json::Value) -> impl Future<Output = Result<(), String>> + Send;
+}
+
+pub struct Webhook {
+    url: Uri,
+}
+
+impl AlertSink for Webhook {
+    fn name(&self) -> String {
+        format!("webhook {}", self.url)
+    }
+
+    async fn deliver(&mut self, _: &Outgoing, body: &serde_json::Value) -> Result<(), String> {
+        let body = serde_json::to_vec(body).unwrap();
+        let status = tokio::time::timeout(WEBHOOK_TIMEOUT, post(&self.url, "application/json", None, body)).await
+            .map_err(|_| format!("no response within {WEBHOOK_TIMEOUT:?}"))??;
+        if !status.is_success() {
+            return Err(format!("server replied {status}"));
+        }
+        Ok(())
+    }
+}
+
+// Plain http POST, returning the status of the response.
+pub async fn post(url: &Uri, content_type: &str, authorization: Option<&str>, body: Vec<u8>) -> Result<hyper::StatusCode, String> {
+    let host = url.host().unwrap_or_default();
+    let stream = TcpStream::connect((host, url.port_u16().unwrap_or(80))).await.map_err(|e| e.to_string())?;
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await.map_err(|e| e.to_string())?;
+    tokio::spawn(conn);
+    let path = url.path_and_query().map_or("/", |p| p.as_str());
+    let mut req = Request::post(path)
+        .header(header::HOST, url.authority().map_or(host, |a| a.as_str()))
+        .header(header::CONTENT_TYPE, content_type);
+    if let Some(a) = authorization {
+        req = req.header(header::AUTHORIZATION, a);
+    }
+    let req = req
+        .body(Full::new(Bytes::from(body)))
+        .map_err(|e| e.to_string())?;
+    let res = sender.send_request(req).await.map_err(|e| e.to_string())?;
+    Ok(res.status())
+}
+
+pub struct JsonLinesFile {
+    path: String,
+    out: std::fs::File,
+}
+
+impl AlertSink for JsonLinesFile {
+    fn name(&self) -> String {
+        format!("file {}", self.path)
+    }
+
+    async fn deliver(&mut self, _: &Outgoing, body: &serde_json::Value) -> Result<(), String> {
+        writeln!(self.out, "{body}").map_err(|e| e.to_string())
+    }
+}
+
+pub struct Desktop;
+
+impl AlertSink for Desktop {
+    fn name(&self) -> String {
+        "desktop".into()
+    }
+
+    async fn deliver(&mut self, alert: &Outgoing, _: &serde_json::Value) -> Result<(), String> {
+        if !alert.is_urgent() {
+            return Ok(());
+        }
+        let status = tokio::process::Command::new("notify-send")
+            .args(["-u", "critical", "-a", "distance-tracker", &alert.key, &alert.value.to_string()])
+            .status().await
+            .map_err(|e| format!("unable to run notify-send: {e}"))?;
+        if !status.success() {
+            return Err(format!("notify-send exited with {status}"));
+        }
+        Ok(())
+    }
+}
+
+pub struct Audio {
+    speak: String,
+    player: String,
+    clips: BTreeMap<String, String>,
+    cooldown: Duration,
+    // last sounded, by pair (or vehicle)
+    sounded: HashMap<String, Instant>,
+}
+
+// What the audio sink says of an alert, e.g. "danger: vehicle a and vehicle b,
+// 4 meters".
+pub fn phrase(alert: &serde_json::Value) -> String {
+    let text = |k: &str| alert[k].as_str();
+    let distance = alert["distance"].as_f64().map_or(String::new(), |d| format!(", {d:.0} meters"));
+    match (text("ida"), text("idb"), text("cutter"), text("victim"), text("id")) {
+        (Some(a), Some(b), ..) => format!("danger: vehicle {a} and vehicle {b}{distance}"),
+        (_, _, Some(c), Some(v), _) => format!("danger: vehicle {c} cutting in on vehicle {v}{distance}"),
+        (.., Some(id)) => format!("danger: vehicle {id}"),
+        _ => "danger".into(),
+    }
+}
+
+impl AlertSink for Audio {
+    fn name(&self) -> String {
+        "audio".into()
+    }
+
+    async fn deliver(&mut self, alert: &Outgoing, _: &serde_json::Value) -> Result<(), String> {
+        if !alert.is_urgent() {
+            return Ok(());
+        }
+        let v = &alert.value;
+        let ids: Vec<&str> = ["ida", "idb", "cutter", "victim", "id"].iter().filter_map(|k| v[k].as_str()).collect();
+        let now = Instant::now();
+        if self.sounded.get(&ids.join("~")).is_some_and(|t| now.duration_since(*t) < self.cooldown) {
+            return Ok(());
+        }
+        self.sounded.retain(|_, t| now.duration_since(*t) < self.cooldown);
+        self.sounded.insert(ids.join("~"), now);
+        let clip = [v["severity"].as_str(), v["kind"].as_str()].into_iter().flatten().find_map(|k| self.clips.get(k));
+        let (program, arg) = match clip {
+            Some(path) => (&self.player, path.clone()),
+            None => (&self.speak, phrase(v)),
+        };
+        let status = tokio::process::Command::new(program).arg(&arg).status().await
+            .map_err(|e| format!("unable to run {program}: {e}"))?;
+        if !status.success() {
+            return Err(format!("{program} exited with {status}"));
+        }
+        Ok(())
+    }
+}
+
+// Senders to the configured sinks. Sending never waits: alerts a sink has no
+// room for are dropped, so a slow sink cannot hold back the compute loop.
+pub struct Sinks {
+    sinks: Vec<(String, Sender<Outgoing>)>,
+}
+
+impl Sinks {
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    pub fn queues(&self) -> SinkQueues {
+        SinkQueues(self.sinks.iter().map(|(name, tx)| (name.clone(), tx.downgrade())).collect())
+    }
+
+    pub fn send(&self, alert: &Outgoing) {
+        for (name, tx) in &self.sinks {
+            match tx.try_send(alert.clone()) {
+                Ok(()) => {},
+                Err(TrySendError::Full(_)) => warn!("{name} is lagging, dropping alert on {}", alert.key),
+                Err(TrySendError::Closed(_)) => warn!("{name} is gone, dropping alert on {}", alert.key),
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct QueueDepth {
+    pub sink: String,
+    // alerts waiting to be delivered, of at most `capacity`
+    pub queued: usize,
+    pub capacity: usize,
+}
+
+// The queues of the sinks, which do not keep the sinks running.
+#[derive(Clone, Default)]
+pub struct SinkQueues(Vec<(String, WeakSender<Outgoing>)>);
+
+impl SinkQueues {
+    // The depths of the queues of the sinks still running.
+    pub fn depths(&self) -> Vec<QueueDepth> {
+        self.0.iter().filter_map(|(name, tx)| {
+            let tx = tx.upgrade()?;
+            Some(QueueDepth { sink: name.clone(), queued: tx.max_capacity() - tx.capacity(), capacity: tx.max_capacity() })
+        }).collect()
+    }
+}
+
+// Starts a task per sink. The tasks end once the returned Sinks is dropped and
+// the queued alerts are delivered.
+pub fn spawn(configs: &[SinkConfig], instance: &Instance) -> Result<(Sinks, Vec<JoinHandle<()>>), String> {
+    let mut sinks = Vec::new();
+    let mut handles = Vec::new();
+    for config in configs {
+        let (tx, rx) = channel::<Outgoing>(config.capacity.unwrap_or(DEFAULT_CAPACITY).max(1));
+        let instance = instance.clone();
+        let (name, handle) = match &config.kind {
+            SinkKind::Webhook { url } => start(Webhook { url: url.parse().map_err(|e| format!("{url}: {e}"))? }, rx, instance),
+            SinkKind::File { path } => {
+                let out = std::fs::OpenOptions::new().create(true).append(true).open(path)
+                    .map_err(|e| format!("Unable to open {path}: {e}"))?;
+                start(JsonLinesFile { path: path.clone(), out }, rx, instance)
+            },
+            SinkKind::Desktop => start(Desktop, rx, instance),
+            SinkKind::Audio { speak, player, clips, cooldown_s } => {
+                let audio = Audio {
+                    speak: speak.clone().unwrap_or("espeak-ng".into()),
+                    player: player.clone().unwrap_or("paplay".into()),
+                    clips: clips.clone(),
+                    cooldown: Duration::from_secs_f64(cooldown_s.unwrap_or(10.0)),
+                    sounded: HashMap::new(),
+                };
+                start(audio, rx, instance)
+            },
+        };
+        info!("Delivering alerts to {name}");
+        sinks.push((name, tx));
+        handles.push(handle);
+    }
+    Ok((Sinks { sinks }, handles))
+}
+
+fn start<S: AlertSink>(mut sink: S, mut rx: Receiver<Outgoing>, instance: Instance) -> (String, JoinHandle<()>) {
+    let name = sink.name();
+    let handle = tokio::spawn(async move {
+        while let Some(alert) = rx.recv().await {
+            let body = serde_json::json!({ "t": unix_ms(), "key": alert.key, "alert": instance.tag(alert.value.clone()) });
+            if let Err(e) = sink.deliver(&alert, &body).await {
+                error!("{} failed to deliver alert on {}: {e}", sink.name(), alert.key);
+            }
+        }
+    });
+    (name, handle)
+}
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use zenoh::prelude::r#async::*;
+use tracing::warn;
+use crate::clock;
+use crate::matching::Matching;
+use crate::vehicle::{self, VehicleState};
+use crate::{Position, VehicleInfo, VehicleMap};
+
+// Vehicles are extrapolated this long after their last fix at most, and then
+// held still until the next one.
+const MAX_EXTRAPOLATION: Duration = Duration::from_secs(3);
+// Bounds of the time taken to blend into the motion of a new fix, that of the
+// interval between the last two fixes.
+const MIN_BLEND: Duration = Duration::from_millis(100);
+const MAX_BLEND: Duration = Duration::from_secs(2);
+
+// An estimated position of a vehicle, published on <smooth_key>/<id> for map
+// animations, never used by the rules.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SmoothPosition {
+    pub id: String,
+    pub position: Position,
+    pub heading: Option<f64>,
+    // m/s
+    pub speed: f64,
+    // ms since the UNIX epoch
+    pub ts: u64,
+    // the position is not one the vehicle published
+    pub estimated: bool,
+    // ms since the last fix of the vehicle
+    pub fix_age_ms: u64,
+}
+
+struct Motion {
+    seen: Instant,
+    // where the vehicle was shown when its last fix arrived
+    from: Position,
+    shown: Position,
+    blend: Duration,
+}
+
+fn lerp(a: Position, b: Position, t: f64) -> Position {
+    Position { lat: a.lat + (b.lat - a.lat) * t, lng: a.lng + (b.lng - a.lng) * t }
+}
+
+// Dead reckoning from the last fix along its heading at its speed, blended from
+// where the vehicle was shown when the fix arrived, so that fixes do not make
+// the vehicles jump.
+#[derive(Default)]
+pub struct Smoother(HashMap<String, Motion>);
+
+impl Smoother {
+    pub fn estimate(&mut self, map: &HashMap<String, VehicleState>, now: Instant, unix_ms: u64) -> Vec<SmoothPosition> {
+        let live = vehicle::live(map);
+        self.0.retain(|id, _| live.contains_key(id));
+        let mut out = Vec::with_capacity(live.len());
+        for (id, v) in live.iter() {
+            let VehicleInfo { position: fix, speed, .. } = v.info;
+            let seen = v.seen();
+            let m = self.0.entry(id.clone()).or_insert(Motion { seen, from: fix, shown: fix, blend: MAX_BLEND });
+            if seen != m.seen {
+                m.blend = seen.saturating_duration_since(m.seen).clamp(MIN_BLEND, MAX_BLEND);
+                m.from = m.shown;
+                m.seen = seen;
+            }
+            let age = now.saturating_duration_since(seen);
+            let predicted = match v.heading {
+                Some(h) if speed > 0.0 => fix.destination(h, speed * age.min(MAX_EXTRAPOLATION).as_secs_f64()),
+                _ => fix,
+            };
+            m.shown = lerp(m.from, predicted, (age.as_secs_f64() / m.blend.as_secs_f64()).min(1.0));
+            out.push(SmoothPosition { id: id.clone(), position: m.shown, heading: v.heading, speed, ts: unix_ms, estimated: m.shown != fix, fix_age_ms: age.as_millis() as u64 });
+        }
+        out
+    }
+}
+
+// Publishes the estimated positions of the live vehicles every `period` while
+// they have subscribers.
+pub async fn publish(z: Arc<Session>, prefix: String, map: VehicleMap, period: Duration, matching: Matching) {
+    let mut tick = tokio::time::interval(period);
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let mut smoother = Smoother::default();
+    loop {
+        tick.tick().await;
+        if !matching.any() {
+            smoother = Smoother::default();
+            continue;
+        }
+        let estimates = map.read(|map| smoother.estimate(map, clock::now(), clock::unix_ms()));
+        for s in estimates {
+            let key = format!("{prefix}/{}", s.id);
+            if keyexpr::new(&key).is_err() {
+                continue;
+            }
+            let value = Value::from(serde_json::to_vec(&s).unwrap()).encoding(Encoding::APP_JSON);
+            if let Err(e) = z.put(&key, value).congestion_control(CongestionControl::Drop).res().await {
+                warn!("Unable to publish the smooth position on {key}: {e}");
+            }
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use std::sync::{Arc, Mutex};
+
+// A value updated in place by its writer while readers see it whole, never
+// half updated. Short reads run under the lock (`read`), and the readers
+// working on the value for longer copy it out under the lock (`copy`), both
+// leaving the writer to update the value in place once the lock is released.
+// Occasional readers can instead share a snapshot, read-copy-update style
+// (`snapshot`): the first update while a snapshot is alive copies the value,
+// leaving the snapshot as it was, so that the longer snapshots live the more
+// updates copy it.
+pub struct Snapshots<T>(Mutex<Arc<T>>);
+
+impl<T: Clone> Snapshots<T> {
+    pub fn new(value: T) -> Self {
+        Snapshots(Mutex::new(Arc::new(value)))
+    }
+
+    // The value as of now, unchanged by the updates which follow.
+    pub fn snapshot(&self) -> Arc<T> {
+        self.0.lock().unwrap().clone()
+    }
+
+    // A copy of the value as of now, owned by the caller.
+    pub fn copy(&self) -> T {
+        T::clone(&self.0.lock().unwrap())
+    }
+
+    pub fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(&self.0.lock().unwrap())
+    }
+
+    pub fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        f(Arc::make_mut(&mut self.0.lock().unwrap()))
+    }
+}
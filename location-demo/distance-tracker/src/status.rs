@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use zenoh::prelude::r#async::*;
+use zdemo_common::Instance;
+use tracing::warn;
+use crate::metrics::{LastError, SharedMetrics};
+
+// The tracker is degraded for this long after an error.
+const DEGRADED_FOR: Duration = Duration::from_secs(60);
+// The compute loop is stalled when it misses this many cycles.
+const STALLED_CYCLES: u32 = 10;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Health { Ok, Degraded, Stalled }
+
+// Summary of the compute loop latency.
+#[derive(Serialize, Debug)]
+pub struct LoopLatency {
+    pub mean_us: Option<u64>,
+    pub p99_us: Option<u64>,
+    pub max_us: u64,
+}
+
+// Status document for ops dashboards, published on the status key and
+// returned by queries on it.
+#[derive(Serialize, Debug)]
+pub struct Status {
+    pub health: Health,
+    pub uptime_s: u64,
+    pub vehicles: usize,
+    // position key expressions subscribed to
+    pub subscriptions: Vec<String>,
+    pub loop_latency: LoopLatency,
+    // alerts raised since the start, by type
+    pub alerts: BTreeMap<String, u64>,
+    pub errors: u64,
+    pub last_error: Option<LastError>,
+}
+
+async fn status(metrics: &SharedMetrics, started: Instant, cycle: Duration, instance: &Instance) -> Value {
+    let m = metrics.lock().await;
+    let now = Instant::now();
+    let health = match (m.last_cycle, &m.last_error) {
+        (Some(c), _) if now.duration_since(c) > cycle.max(Duration::from_millis(m.compute_period_ms)) * STALLED_CYCLES => Health::Stalled,
+        (_, Some(e)) if now.duration_since(e.seen) < DEGRADED_FOR => Health::Degraded,
+        _ => Health::Ok,
+    };
+    let compute = &m.latency.compute;
+    let s = Status {
+        health,
+        uptime_s: started.elapsed().as_secs(),
+        vehicles: m.vehicles,
+        subscriptions: m.subscriptions.clone(),
+        loop_latency: LoopLatency { mean_us: compute.mean_us(), p99_us: compute.quantile(0.99), max_us: compute.max_us() },
+        alerts: m.alerts.clone(),
+        errors: m.errors,
+        last_error: m.last_error.clone(),
+    };
+    let value = instance.tag(serde_json::to_value(&s).unwrap());
+    Value::from(value.to_string().into_bytes()).encoding(Encoding::APP_JSON)
+}
+
+// Publishes the status on `key` every `period` (0 only serves it), and replies
+// with it to queries on `key`. `cycle` is the compute period.
+pub async fn run(z: Arc<Session>, key: String, metrics: SharedMetrics, instance: Instance, period: Duration, cycle: Duration) {
+    let started = Instant::now();
+    let key = KeyExpr::try_from(key).unwrap();
+    let queryable = z.declare_queryable(&key).res().await.unwrap();
+    let mut tick = tokio::time::interval(period.max(Duration::from_millis(1)));
+    loop {
+        tokio::select! {
+            _ = tick.tick(), if !period.is_zero() => {
+                if let Err(e) = z.put(&key, status(&metrics, started, cycle, &instance).await).res().await {
+                    warn!("Unable to publish the status on {key}: {e}");
+                }
+            },
+            q = queryable.recv_async() => {
+                let Ok(query) = q else { break };
+                let sample = Sample::new(key.clone(), status(&metrics, started, cycle, &instance).await);
+                if let Err(e) = query.reply(Ok(sample)).res().await {
+                    warn!("Unable to reply to query on {key}: {e}");
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use std::io::BufRead;
+use serde_json::json;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use zenoh::prelude::r#async::*;
+use tracing::{error, info, warn};
+use crate::Position;
+
+// Parses an "id,lat,lng,speed" line.
+pub fn parse_line(line: &str) -> Result<(String, Position, f64), String> {
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    let [id, lat, lng, speed] = fields[..] else {
+        return Err(format!("expecting id,lat,lng,speed, got {} fields", fields.len()));
+    };
+    if id.is_empty() {
+        return Err("empty id".into());
+    }
+    let number = |name: &str, s: &str| s.parse::<f64>().map_err(|e| format!("invalid {name} '{s}': {e}"));
+    Ok((id.to_string(), Position { lat: number("lat", lat)?, lng: number("lng", lng)? }, number("speed", speed)?))
+}
+
+// Empty lines, comments and the header line (the `n`th line being the first,
+// 0) carry no position.
+pub fn is_skipped(n: usize, line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with('#') || (n == 0 && line.starts_with("id,"))
+}
+
+// Parses an "id,lat,lng,speed" line into a compact position payload.
+fn parse(line: &str) -> Result<(String, Vec<u8>), String> {
+    let (id, position, speed) = parse_line(line)?;
+    let payload = json!({ "id": id, "position": { "lat": position.lat, "lng": position.lng }, "speed": speed });
+    Ok((id, payload.to_string().into_bytes()))
+}
+
+// Feeds the positions read from stdin, one "id,lat,lng,speed" per line, into
+// the returned channel as samples on stdin/<id>, so that scripts can drive the
+// tracker without any Zenoh publisher. Empty lines, lines starting with '#'
+// and a header line are skipped. The channel stays open at the end of the
+// input, the tracker then keeps the last positions.
+pub fn spawn() -> UnboundedReceiver<Sample> {
+    let (tx, rx) = unbounded_channel();
+    std::thread::spawn(move || {
+        for (n, line) in std::io::stdin().lock().lines().enumerate() {
+            let line = match line {
+                Ok(l) => l,
+                Err(e) => {
+                    error!("unable to read stdin: {e}");
+                    break;
+                }
+            };
+            if is_skipped(n, &line) {
+                continue;
+            }
+            match parse(line.trim()) {
+                Ok((id, payload)) => {
+                    let key = OwnedKeyExpr::try_from(format!("stdin/{id}")).unwrap_or_else(|_| OwnedKeyExpr::try_from("stdin").unwrap());
+                    let sample = Sample::new(key, Value::from(payload).encoding(Encoding::APP_JSON));
+                    if tx.send(sample).is_err() {
+                        return;
+                    }
+                },
+                Err(e) => warn!("Skipping line {} of stdin: {e}", n + 1),
+            }
+        }
+        info!("end of the positions on stdin");
+        // dropping the sender would look like a lost subscription
+        std::mem::forget(tx);
+    });
+    rx
+}
@@ -0,0 +1,89 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use tracing::{info, warn};
+use crate::publish::Outgoing;
+use crate::qos::Level;
+
+// Window over which the alert rate is measured.
+const STORM_WINDOW: Duration = Duration::from_secs(2);
+// A storm ends once the rate drops below this fraction of the threshold, so
+// that a rate hovering around it does not toggle the storm on every cycle.
+const STORM_EXIT_RATIO: f64 = 0.5;
+
+// Published when a storm starts and when it ends.
+#[derive(Serialize, Debug, Clone)]
+pub struct AlertStorm {
+    pub active: bool,
+    // alerts per second over the last window
+    pub rate: f64,
+    pub threshold: f64,
+}
+
+// Replaces the alerts of one compute cycle while a storm is active.
+#[derive(Serialize, Debug, Clone)]
+pub struct AlertDigest {
+    pub alerts: usize,
+    pub by_key: BTreeMap<String, usize>,
+}
+
+pub struct StormGuard {
+    key: String,
+    threshold: f64,
+    window: VecDeque<(Instant, usize)>,
+    active: bool,
+}
+
+impl StormGuard {
+    // A `threshold` of 0 disables storm detection.
+    pub fn new(key: String, threshold: f64) -> Self {
+        StormGuard { key, threshold, window: VecDeque::new(), active: false }
+    }
+
+    // Forgets the alerts of the last window, ending an active storm.
+    pub fn reset(&mut self) -> Option<Outgoing> {
+        self.window.clear();
+        let active = std::mem::replace(&mut self.active, false);
+        active.then(|| Outgoing::json(&self.key, &AlertStorm { active: false, rate: 0.0, threshold: self.threshold }).level(Level::Alert))
+    }
+
+    fn rate(&mut self, now: Instant, count: usize) -> f64 {
+        self.window.push_back((now, count));
+        while self.window.front().is_some_and(|(t, _)| now.duration_since(*t) > STORM_WINDOW) {
+            self.window.pop_front();
+        }
+        let total: usize = self.window.iter().map(|(_, c)| c).sum();
+        total as f64 / STORM_WINDOW.as_secs_f64()
+    }
+
+    // Takes the alerts produced by a compute cycle and returns what should be
+    // published: the alerts themselves, or a digest of them during a storm.
+    pub fn filter(&mut self, alerts: Vec<Outgoing>, now: Instant) -> Vec<Outgoing> {
+        if self.threshold <= 0.0 {
+            return alerts;
+        }
+        let rate = self.rate(now, alerts.len());
+        let mut out = Vec::new();
+        if !self.active && rate > self.threshold {
+            warn!(target: "alert", rule = "storm", rate, threshold = self.threshold, "STORM: {rate:.0} alerts/s > {}, publishing digests only", self.threshold);
+            self.active = true;
+            out.push(Outgoing::json(&self.key, &AlertStorm { active: true, rate, threshold: self.threshold }).level(Level::Alert));
+        } else if self.active && rate < self.threshold * STORM_EXIT_RATIO {
+            info!(target: "alert", rule = "storm", rate, "STORM: over, {rate:.0} alerts/s");
+            self.active = false;
+            out.push(Outgoing::json(&self.key, &AlertStorm { active: false, rate, threshold: self.threshold }).level(Level::Alert));
+        }
+        if !self.active {
+            out.extend(alerts);
+        } else if !alerts.is_empty() {
+            let mut by_key = BTreeMap::new();
+            for a in &alerts {
+                *by_key.entry(a.key.clone()).or_insert(0) += 1;
+            }
+            // of the highest level of the alerts it stands for
+            let level = alerts.iter().map(|a| a.level).max().unwrap_or_default();
+            out.push(Outgoing::json(&self.key, &AlertDigest { alerts: alerts.len(), by_key }).level(level));
+        }
+        out
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use serde::Deserialize;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use zenoh::prelude::r#async::*;
+use zenoh::subscriber::Subscriber;
+use zdemo_common::with_backoff;
+use tracing::info;
+use crate::keys::{self, KeyUse};
+
+// Attempts to subscribe to a key expression added by a control command.
+const ADD_ATTEMPTS: u32 = 3;
+
+// Control command changing the position key expressions the tracker
+// subscribes to, {"add": "<key expr>"} or {"remove": "<key expr>"}.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+pub enum SubCommand {
+    Add(String),
+    Remove(String),
+}
+
+pub fn parse(payload: &[u8]) -> Result<SubCommand, String> {
+    let cmd: SubCommand = serde_json::from_slice(payload)
+        .map_err(|e| format!("expecting {{\"add\": <key expr>}} or {{\"remove\": <key expr>}}: {e}"))?;
+    let (SubCommand::Add(k) | SubCommand::Remove(k)) = &cmd;
+    keys::check("subscription", k, KeyUse::Select)?;
+    Ok(cmd)
+}
+
+// The position key expressions subscribed to, which control commands change at
+// runtime. The samples of every subscription go to the same channel, which
+// stays open when they are all removed.
+pub struct Subscriptions {
+    z: Arc<Session>,
+    reliability: Reliability,
+    tx: UnboundedSender<Sample>,
+    subs: BTreeMap<String, Subscriber<'static, ()>>,
+}
+
+impl Subscriptions {
+    // Subscribes to `keys`, retrying until it succeeds.
+    pub async fn declare(z: &Arc<Session>, keys: &[String], reliability: Reliability) -> (Self, UnboundedReceiver<Sample>) {
+        let (tx, rx) = unbounded_channel();
+        let mut subs = Subscriptions { z: z.clone(), reliability, tx, subs: BTreeMap::new() };
+        for key in keys {
+            subs.subscribe(key, 0).await.unwrap();
+        }
+        info!("Subscribed to {} key expression(s)", subs.subs.len());
+        (subs, rx)
+    }
+
+    async fn subscribe(&mut self, key: &str, attempts: u32) -> Result<bool, String> {
+        if self.subs.contains_key(key) {
+            return Ok(false);
+        }
+        let sub = with_backoff(&format!("subscribing to {key}"), attempts, || {
+            let tx = self.tx.clone();
+            self.z.declare_subscriber(key.to_string())
+                .reliability(self.reliability)
+                .callback(move |sample| { let _ = tx.send(sample); })
+                .res()
+        }).await.map_err(|e| format!("unable to subscribe to {key}: {e}"))?;
+        self.subs.insert(key.into(), sub);
+        Ok(true)
+    }
+
+    // Subscribes to `key`, false when it already was.
+    pub async fn add(&mut self, key: &str) -> Result<bool, String> {
+        self.subscribe(key, ADD_ATTEMPTS).await
+    }
+
+    // Undeclares the subscription to `key`, false when there was none.
+    pub fn remove(&mut self, key: &str) -> bool {
+        self.subs.remove(key).is_some()
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        self.subs.keys().cloned().collect()
+    }
+}
@@ -0,0 +1,51 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use tracing::info;
+use crate::rules::Alert;
+
+// One line summary of the compute cycles, printed periodically when the per-pair
+// output is throttled.
+pub struct Summary {
+    period: Duration,
+    since: Instant,
+    cycles: u64,
+    compute: Duration,
+    alerts: BTreeMap<&'static str, u64>,
+}
+
+impl Summary {
+    // A zero period disables the summary.
+    pub fn new(period: Duration) -> Self {
+        Summary { period, since: Instant::now(), cycles: 0, compute: Duration::ZERO, alerts: BTreeMap::new() }
+    }
+
+    // Starts the period afresh, forgetting its cycles.
+    pub fn reset(&mut self) {
+        *self = Summary::new(self.period);
+    }
+
+    pub fn cycle(&mut self, alerts: &[Alert], compute: Duration) {
+        self.cycles += 1;
+        self.compute += compute;
+        for a in alerts {
+            *self.alerts.entry(a.name()).or_default() += 1;
+        }
+    }
+
+    // Prints the summary of the cycles since the last one when it is due.
+    pub fn print_due(&mut self, vehicles: usize, now: Instant) {
+        let elapsed = now.duration_since(self.since);
+        if self.period.is_zero() || elapsed < self.period {
+            return;
+        }
+        let cycles = match self.compute.checked_div(self.cycles as u32) {
+            Some(mean) => format!("{} cycles (compute {:.1} ms on average)", self.cycles, mean.as_secs_f64() * 1000.0),
+            None => "no cycle".into(),
+        };
+        let total: u64 = self.alerts.values().sum();
+        let by_type: Vec<String> = self.alerts.iter().map(|(name, n)| format!("{name} {n}")).collect();
+        info!("SUMMARY: {vehicles} vehicles, {cycles}, {total} alerts in {:.0}s{}{}", elapsed.as_secs_f64(),
+            if by_type.is_empty() { "" } else { ": " }, by_type.join(", "));
+        *self = Summary { since: now, ..Summary::new(self.period) };
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+use zenoh::prelude::r#async::*;
+use zdemo_common::Instance;
+use tracing::warn;
+use crate::clock;
+use crate::kind::VehicleKind;
+use crate::matching::Matching;
+use crate::vehicle::{self, VehicleState};
+use crate::VehicleMap;
+
+// Fields beyond these are ignored, they are published in every state.
+pub const MAX_FIELDS: usize = 16;
+const MAX_FIELD_LEN: usize = 32;
+
+pub const BATTERY: &str = "battery";
+// The valid values of the fields the tracker knows: the battery charge (%),
+// the temperature (°C) and the payload weight (kg).
+const RANGES: [(&str, f64, f64); 3] = [(BATTERY, 0.0, 100.0), ("temperature", -100.0, 200.0), ("payload", 0.0, f64::MAX)];
+
+// Readings a vehicle reports with its position, by field, passed through to
+// its state and aggregated per fleet.
+pub type Telemetry = BTreeMap<String, f64>;
+
+// Replaces `telemetry` with the valid fields of `raw`, a JSON object of
+// numbers, describing what is wrong with the others, which are ignored.
+pub fn validate(raw: &BTreeMap<String, serde_json::Value>, telemetry: &mut Telemetry) -> Option<String> {
+    telemetry.retain(|field, _| raw.contains_key(field));
+    let mut invalid = Vec::new();
+    for (field, value) in raw {
+        let checked = if field.is_empty() || field.len() > MAX_FIELD_LEN || !field.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            Err(format!("invalid telemetry field '{field}', expecting 1 to {MAX_FIELD_LEN} letters, digits, '-' or '_'"))
+        } else {
+            match (value.as_f64().filter(|v| v.is_finite()), RANGES.iter().find(|(f, _, _)| f == field)) {
+                (None, _) => Err(format!("invalid telemetry {field} {value}, expecting a number")),
+                (Some(v), Some((_, min, max))) if !(*min..=*max).contains(&v) => Err(format!("telemetry {field} {v} out of range [{min}, {max}]")),
+                (Some(_), _) if telemetry.len() >= MAX_FIELDS && !telemetry.contains_key(field) => Err(format!("more than {MAX_FIELDS} telemetry fields, ignoring {field}")),
+                (Some(v), _) => Ok(v),
+            }
+        };
+        match checked {
+            Ok(v) => match telemetry.get_mut(field) {
+                Some(current) => *current = v,
+                None => { telemetry.insert(field.clone(), v); },
+            },
+            Err(e) => {
+                telemetry.remove(field);
+                invalid.push(e);
+            },
+        }
+    }
+    (!invalid.is_empty()).then(|| invalid.join(", "))
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FieldStats {
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+    // reporting the field
+    pub vehicles: usize,
+}
+
+// Published on the telemetry key, over the live vehicles reporting each field.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TelemetryReport {
+    // ms since the UNIX epoch
+    pub ts: u64,
+    pub all: BTreeMap<String, FieldStats>,
+    pub fleets: BTreeMap<String, BTreeMap<String, FieldStats>>,
+}
+
+fn add(stats: &mut BTreeMap<String, FieldStats>, field: &str, v: f64) {
+    match stats.get_mut(field) {
+        Some(s) => {
+            s.min = s.min.min(v);
+            s.max = s.max.max(v);
+            // the sum until averaged
+            s.avg += v;
+            s.vehicles += 1;
+        },
+        None => { stats.insert(field.into(), FieldStats { min: v, avg: v, max: v, vehicles: 1 }); },
+    }
+}
+
+pub fn aggregate(map: &HashMap<String, VehicleState>, ts: u64) -> TelemetryReport {
+    let mut report = TelemetryReport { ts, all: BTreeMap::new(), fleets: BTreeMap::new() };
+    for v in vehicle::live(map).values() {
+        for (field, value) in &v.info.telemetry {
+            add(&mut report.all, field, *value);
+            if let Some(fleet) = &v.info.fleet {
+                add(report.fleets.entry(fleet.clone()).or_default(), field, *value);
+            }
+        }
+    }
+    for s in report.all.values_mut().chain(report.fleets.values_mut().flat_map(|f| f.values_mut())) {
+        s.avg /= s.vehicles as f64;
+    }
+    report
+}
+
+fn value(map: &VehicleMap, instance: &Instance) -> Value {
+    let value = instance.tag(serde_json::to_value(map.read(|map| aggregate(map, clock::unix_ms()))).unwrap());
+    Value::from(value.to_string().into_bytes()).encoding(Encoding::APP_JSON)
+}
+
+// Answers the queries on `key` with the aggregated telemetry, and publishes it
+// there every `period` while it has subscribers, unless zero.
+pub async fn serve(z: Arc<Session>, key: String, map: VehicleMap, instance: Instance, period: Duration, matching: Matching) {
+    let key = KeyExpr::try_from(key).unwrap();
+    let queryable = z.declare_queryable(&key).res().await.unwrap();
+    let mut tick = tokio::time::interval(period.max(Duration::from_millis(1)));
+    tick.tick().await;
+    loop {
+        tokio::select! {
+            Ok(query) = queryable.recv_async() => {
+                if let Err(e) = query.reply(Ok(Sample::new(key.clone(), value(&map, &instance)))).res().await {
+                    warn!("Unable to reply to query on {key}: {e}");
+                }
+            },
+            _ = tick.tick(), if !period.is_zero() => {
+                if !matching.any() {
+                    continue;
+                }
+                if let Err(e) = z.put(&key, value(&map, &instance)).res().await {
+                    warn!("Unable to publish the telemetry on {key}: {e}");
+                }
+            },
+        }
+    }
+}
+
+// Vehicles of `kinds`, which carry a battery, raise alerts while reporting a
+// charge below `low` (%).
+#[derive(Debug, Clone, Default)]
+pub struct BatteryRule {
+    pub kinds: Vec<VehicleKind>,
+    pub low: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BatteryAlert {
+    pub id: String,
+    // %
+    pub battery: f64,
+    pub low: f64,
+}
+
+impl BatteryRule {
+    pub fn check(&self, v: &VehicleState) -> Option<BatteryAlert> {
+        let battery = *v.info.telemetry.get(BATTERY)?;
+        (battery < self.low && self.kinds.contains(&v.info.kind)).then(|| BatteryAlert { id: v.info.id.clone(), battery, low: self.low })
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::{HashMap, HashSet};
+use serde::{Serialize, Deserialize};
+use crate::geo::DistanceAlgo;
+use crate::vehicle::VehicleState;
+use crate::Position;
+
+// A named group of vehicles (a tour group of robots...) whose members must stay
+// within `radius` meters of the leader, or of the centroid of the members
+// present when there is none. Groups without a radius only leash their pairs
+// for the max distance rule.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TetherGroup {
+    pub name: String,
+    pub members: Vec<String>,
+    pub leader: Option<String>,
+    #[serde(default)]
+    pub radius: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TetherAlert {
+    pub group: String,
+    pub id: String,
+    // distance (m) to the leader, or to the centroid when null
+    pub distance: f64,
+    pub radius: f64,
+    pub leader: Option<String>,
+}
+
+impl TetherGroup {
+    // The point the members are tethered to, None when the leader (or every
+    // member) is missing.
+    fn anchor(&self, map: &HashMap<String, VehicleState>) -> Option<Position> {
+        if let Some(leader) = &self.leader {
+            return map.get(leader).map(|v| v.info.position);
+        }
+        let present: Vec<Position> = self.members.iter().filter_map(|id| map.get(id)).map(|v| v.info.position).collect();
+        let n = present.len() as f64;
+        (!present.is_empty()).then(|| Position {
+            lat: present.iter().map(|p| p.lat).sum::<f64>() / n,
+            lng: present.iter().map(|p| p.lng).sum::<f64>() / n,
+        })
+    }
+
+    // Whether a and b are tethered to each other: the leader and a member, or
+    // two members of a group without leader.
+    pub fn leashes(&self, a: &str, b: &str) -> bool {
+        let member = |id: &str| self.members.iter().any(|m| m == id);
+        match &self.leader {
+            Some(l) => (l == a && member(b)) || (l == b && member(a)),
+            None => member(a) && member(b),
+        }
+    }
+
+    // The members present farther than the radius from the anchor.
+    pub fn stragglers(&self, map: &HashMap<String, VehicleState>, algo: DistanceAlgo) -> Vec<TetherAlert> {
+        let (Some(radius), Some(anchor)) = (self.radius, self.anchor(map)) else { return vec![] };
+        self.members.iter()
+            .filter(|id| self.leader.as_ref() != Some(*id))
+            .filter_map(|id| map.get(id).map(|v| (id, algo.distance(&anchor, &v.info.position))))
+            .filter(|(_, distance)| *distance > radius)
+            .map(|(id, distance)| TetherAlert { group: self.name.clone(), id: id.clone(), distance, radius, leader: self.leader.clone() })
+            .collect()
+    }
+}
+
+// Reads a JSON array of {name, members, leader, radius}, leader and radius
+// being optional.
+pub fn load(path: &str) -> Result<Vec<TetherGroup>, String> {
+    let s = std::fs::read_to_string(path).map_err(|e| format!("Unable to read {path}: {e}"))?;
+    let groups: Vec<TetherGroup> = serde_json::from_str(&s).map_err(|e| format!("Invalid tethers file {path}: {e}"))?;
+    let mut names = HashSet::new();
+    for g in &groups {
+        if !names.insert(&g.name) {
+            return Err(format!("duplicated tether group '{}'", g.name));
+        }
+        if g.members.is_empty() {
+            return Err(format!("tether group '{}' has no members", g.name));
+        }
+        if g.radius.is_some_and(|r| !(r.is_finite() && r > 0.0)) {
+            return Err(format!("tether group '{}' must have a positive radius", g.name));
+        }
+    }
+    Ok(groups)
+}
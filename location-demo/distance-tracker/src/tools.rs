@@ -0,0 +1,767 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use zenoh::prelude::r#async::*;
+use zdemo_common::{Compression, Instance, JsonPublisher};
+use tracing::{error, info, warn};
+use crate::geohash::{self, BoundingBox};
+use crate::ingest::RawVehicle;
+use crate::keys::{self, KeyUse};
+use crate::lights::{self, LightState, LightStatus, TrafficLight};
+use crate::mcap::{self, McapWriter};
+use crate::record::{self, read_records, unix_ms, KeyMapping, Payload, Record, RecordFilter, Recorder, Rewrite};
+use crate::retention::{self, RetentionPolicy};
+use crate::scenario::{self, Scenario, Simulation};
+use crate::shm::ShmSegment;
+use crate::signing::Signers;
+use crate::stdin;
+use crate::{Color, VehicleInfo, VehicleKind};
+
+// The demo tools other than the tracker, run by its subcommands and by their
+// own binaries. Their arguments leave out the Zenoh config, the compression and
+// the instance id, which the tracker shares with its subcommands.
+
+#[derive(clap_derive::Args, Debug, Clone)]
+pub struct RecordArgs {
+    /// Key expression to record.
+    #[arg(long)]
+    pub key: Option<String>,
+    /// Output JSON lines file, defaults to tracker-<unix time>.jsonl
+    #[arg(long)]
+    pub out: Option<String>,
+    /// Only record samples on keys intersecting this key expression, can be repeated.
+    #[arg(long)]
+    pub include_key: Vec<String>,
+    /// Do not record samples on keys intersecting this key expression, can be repeated.
+    #[arg(long)]
+    pub exclude_key: Vec<String>,
+    /// Only record the positions of this vehicle, can be repeated.
+    #[arg(long)]
+    pub id: Vec<String>,
+    /// Do not record the positions of this vehicle, can be repeated.
+    #[arg(long)]
+    pub exclude_id: Vec<String>,
+    /// Only record the positions of vehicles of this kind, can be repeated.
+    #[arg(long)]
+    pub kind: Vec<String>,
+    /// Only record the positions inside "lat_min,lng_min,lat_max,lng_max".
+    #[arg(long)]
+    pub bbox: Option<BoundingBox>,
+    /// Continue the recording into a new file (<out stem>.<n>.jsonl) once the current
+    /// one reaches this size in MB, 0 never rotates.
+    #[arg(long)]
+    pub max_file_mb: Option<u64>,
+    /// JSON file with the retention classes, each {name, keys: [key expr], kinds: [alert kind], hours}:
+    /// the samples past the retention of their class are pruned from the rotated parts.
+    #[arg(long)]
+    pub retention: Option<String>,
+    /// Period of the pruning of the rotated parts, in seconds.
+    #[arg(long)]
+    pub prune_period_s: Option<u64>,
+}
+
+// Path of the `n`th part of a recording to `out`, the first part being `out` itself.
+fn part_path(out: &str, n: u32) -> String {
+    part_path_with(out, n, ".jsonl")
+}
+
+fn part_path_with(out: &str, n: u32, extension: &str) -> String {
+    if n == 0 {
+        return out.to_string();
+    }
+    match out.strip_suffix(extension) {
+        Some(stem) => format!("{stem}.{n}{extension}"),
+        None => format!("{out}.{n}"),
+    }
+}
+
+// Prunes the expired samples from the closed parts of a recording, returning
+// the parts removed.
+fn prune_parts(policy: &RetentionPolicy, parts: &[String]) -> Vec<String> {
+    let now = unix_ms();
+    let mut removed = vec![];
+    for p in parts {
+        match policy.prune(p, now) {
+            Ok(s) if s.pruned == 0 => {},
+            Ok(s) if s.kept == 0 => {
+                info!("RETENTION: removed {p}, its {} samples expired", s.pruned);
+                removed.push(p.clone());
+            },
+            Ok(s) => info!("RETENTION: pruned {} expired samples from {p}, {} kept", s.pruned, s.kept),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => removed.push(p.clone()),
+            Err(e) => error!("unable to prune {p}: {e}"),
+        }
+    }
+    removed
+}
+
+// Records everything published on the key into a JSON lines file, until stopped.
+pub async fn record(args: RecordArgs, config: Config) {
+    let key = args.key.unwrap_or("demo/tracker/**".into());
+    let selectors = std::iter::once(("--key", &key))
+        .chain(args.include_key.iter().map(|k| ("--include-key", k)))
+        .chain(args.exclude_key.iter().map(|k| ("--exclude-key", k)));
+    if let Err(errors) = keys::check_all(selectors.map(|(o, k)| (o, k.as_str(), KeyUse::Select))) {
+        for e in errors {
+            error!("{e}");
+        }
+        std::process::exit(2);
+    }
+    let out = args.out.unwrap_or_else(|| format!("tracker-{}.jsonl", unix_ms()));
+    let max_bytes = args.max_file_mb.unwrap_or(0) * 1024 * 1024;
+    let retention = args.retention.as_ref().map(|f| retention::load(f).unwrap_or_else(|e| panic!("--retention: {e}")));
+    if retention.is_some() && max_bytes == 0 {
+        // the part being written is never pruned
+        error!("--retention only prunes rotated parts, it needs --max-file-mb");
+        std::process::exit(2);
+    }
+    let mut prune = tokio::time::interval(Duration::from_secs(args.prune_period_s.unwrap_or(600).max(1)));
+    // parts no longer written to, which can be pruned
+    let mut closed: Vec<String> = vec![];
+    let mut pruning: Option<tokio::task::JoinHandle<Vec<String>>> = None;
+    let filter = RecordFilter {
+        include_keys: args.include_key.iter().map(|k| OwnedKeyExpr::new(k.as_str()).unwrap()).collect(),
+        exclude_keys: args.exclude_key.iter().map(|k| OwnedKeyExpr::new(k.as_str()).unwrap()).collect(),
+        ids: args.id,
+        exclude_ids: args.exclude_id,
+        kinds: args.kind,
+        bbox: args.bbox,
+    };
+
+    let create = |path: &str| BufWriter::new(File::create(path).unwrap_or_else(|e| panic!("Unable to create {path}: {e}")));
+    let mut part = 0;
+    let mut path = out.clone();
+    let mut recorder = Recorder::new(create(&path)).filter(filter);
+    let z = zdemo_common::open(config).await;
+    let (mut subs, mut rx) = zdemo_common::subscribe(&z, std::slice::from_ref(&key)).await;
+    info!("Recording {key} to {out}");
+    let shutdown = zdemo_common::shutdown_signal();
+    tokio::pin!(shutdown);
+    let mut flush = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+            _ = flush.tick() => {
+                if let Err(e) = recorder.flush() {
+                    error!("unable to write {path}: {e}");
+                }
+            },
+            _ = prune.tick(), if retention.is_some() => {
+                if pruning.as_ref().is_some_and(|h| !h.is_finished()) {
+                    continue;
+                }
+                if let Some(h) = pruning.take() {
+                    let removed = h.await.unwrap_or_default();
+                    closed.retain(|p| !removed.contains(p));
+                }
+                let (policy, parts) = (retention.clone().unwrap(), closed.clone());
+                pruning = Some(tokio::task::spawn_blocking(move || prune_parts(&policy, &parts)));
+            },
+            s = rx.recv() => match s {
+                Some(sample) => {
+                    if let Err(e) = recorder.record(&sample) {
+                        error!("unable to write {path}: {e}");
+                    }
+                    if max_bytes > 0 && recorder.written >= max_bytes {
+                        part += 1;
+                        closed.push(std::mem::replace(&mut path, part_path(&out, part)));
+                        match recorder.rotate(create(&path)) {
+                            Ok(_) => info!("Recording continues in {path}"),
+                            Err(e) => error!("unable to write {}: {e}", part_path(&out, part - 1)),
+                        }
+                    }
+                },
+                None => {
+                    warn!("subscription lost, declaring it again");
+                    drop(subs);
+                    (subs, rx) = zdemo_common::subscribe(&z, std::slice::from_ref(&key)).await;
+                }
+            }
+        }
+    }
+    recorder.flush().unwrap();
+    if let Some(h) = pruning {
+        let _ = h.await;
+    }
+    let files = if part == 0 { out } else { format!("{} files from {out}", part + 1) };
+    info!("Recorded {} samples to {files}, {} filtered out", recorder.count, recorder.skipped);
+}
+
+#[derive(clap_derive::Args, Debug, Clone)]
+pub struct McapArgs {
+    /// Key expression to capture, can be repeated (demo/tracker/mobs/** and
+    /// demo/tracker/alert/** by default).
+    #[arg(long)]
+    pub topic: Vec<String>,
+    /// Samples on keys intersecting this key expression are positions, written as
+    /// foxglove.LocationFix messages for the Map panel.
+    #[arg(long)]
+    pub positions_key: Option<String>,
+    /// Samples on keys intersecting this key expression are alerts.
+    #[arg(long)]
+    pub alerts_key: Option<String>,
+    /// Output MCAP file, defaults to tracker-<unix time>.mcap
+    #[arg(long)]
+    pub out: Option<String>,
+    /// Continue into a new file (<out stem>.<n>.mcap) once the current one reaches this size
+    /// in MB, 0 never rotates.
+    #[arg(long)]
+    pub max_file_mb: Option<u64>,
+    /// Convert this JSON lines recording instead of capturing live samples.
+    #[arg(long)]
+    pub from: Option<String>,
+}
+
+// The parts of an MCAP export, one channel per key: positions as LocationFix,
+// alerts and the other JSON samples as is.
+struct McapExport {
+    out: String,
+    part: u32,
+    path: String,
+    max_bytes: u64,
+    writer: Option<McapWriter<BufWriter<File>>>,
+    positions: OwnedKeyExpr,
+    alerts: OwnedKeyExpr,
+    messages: u64,
+    skipped: u64,
+}
+
+impl McapExport {
+    fn create(path: &str) -> McapWriter<BufWriter<File>> {
+        let file = File::create(path).unwrap_or_else(|e| panic!("Unable to create {path}: {e}"));
+        McapWriter::new(BufWriter::new(file)).unwrap_or_else(|e| panic!("Unable to write {path}: {e}"))
+    }
+
+    // Writes a sample received at `ts` (ms since the UNIX epoch), skipping
+    // those which are neither positions nor JSON.
+    fn write(&mut self, key: &keyexpr, payload: &[u8], ts: u64) {
+        let ns = ts * 1_000_000;
+        let message = if key.intersects(&self.positions) {
+            RawVehicle::parse(payload).ok().filter(|raw| raw.validate().is_ok()).map(|raw| {
+                let p = raw.position();
+                // positions carry the time they were published at
+                let published = raw.ts.map_or(ns, |t| t * 1_000_000);
+                (Some(mcap::LOCATION_FIX), published, mcap::location_fix(&raw.id, p.lat, p.lng, published))
+            })
+        } else {
+            let schema = key.intersects(&self.alerts).then_some(mcap::ALERT);
+            serde_json::from_slice::<serde_json::Value>(payload).is_ok().then(|| (schema, ns, payload.to_vec()))
+        };
+        let Some((schema, published, json)) = message else {
+            self.skipped += 1;
+            return;
+        };
+        let w = self.writer.as_mut().unwrap();
+        if let Err(e) = w.message(key.as_str(), schema, ns, published, &json) {
+            error!("unable to write {}: {e}", self.path);
+        }
+        self.messages += 1;
+        if self.max_bytes > 0 && w.written >= self.max_bytes {
+            self.part += 1;
+            let next = part_path_with(&self.out, self.part, ".mcap");
+            self.close();
+            self.writer = Some(Self::create(&next));
+            self.path = next;
+            info!("Export continues in {}", self.path);
+        }
+    }
+
+    fn flush(&mut self) {
+        if let Some(Err(e)) = self.writer.as_mut().map(|w| w.flush()) {
+            error!("unable to write {}: {e}", self.path);
+        }
+    }
+
+    fn close(&mut self) {
+        if let Some(Err(e)) = self.writer.take().map(|w| w.finish()) {
+            error!("unable to write {}: {e}", self.path);
+        }
+    }
+}
+
+// Exports the positions and alerts published on the topics, or those of a
+// recording, to MCAP files which Foxglove Studio opens.
+pub async fn export_mcap(args: McapArgs, config: Config) {
+    let topics = if args.topic.is_empty() { vec!["demo/tracker/mobs/**".to_string(), "demo/tracker/alert/**".to_string()] } else { args.topic };
+    let positions = args.positions_key.unwrap_or("demo/tracker/mobs/**".into());
+    let alerts = args.alerts_key.unwrap_or("demo/tracker/alert/**".into());
+    let selectors = topics.iter().map(|k| ("--topic", k.as_str()))
+        .chain([("--positions-key", positions.as_str()), ("--alerts-key", alerts.as_str())]);
+    if let Err(errors) = keys::check_all(selectors.map(|(o, k)| (o, k, KeyUse::Select))) {
+        for e in errors {
+            error!("{e}");
+        }
+        std::process::exit(2);
+    }
+    let out = args.out.unwrap_or_else(|| format!("tracker-{}.mcap", unix_ms()));
+    let mut export = McapExport {
+        writer: Some(McapExport::create(&out)),
+        path: out.clone(),
+        out,
+        part: 0,
+        max_bytes: args.max_file_mb.unwrap_or(0) * 1024 * 1024,
+        positions: OwnedKeyExpr::new(positions.as_str()).unwrap(),
+        alerts: OwnedKeyExpr::new(alerts.as_str()).unwrap(),
+        messages: 0,
+        skipped: 0,
+    };
+    let topics: Vec<OwnedKeyExpr> = topics.iter().map(|k| OwnedKeyExpr::new(k.as_str()).unwrap()).collect();
+    match &args.from {
+        Some(recording) => {
+            let file = File::open(recording).unwrap_or_else(|e| panic!("Unable to open {recording}: {e}"));
+            for r in read_records(BufReader::new(file)).filter_map(|r| r.map_err(|e| warn!("Skipping invalid record at {e}")).ok()) {
+                match keyexpr::new(&r.key) {
+                    Ok(key) if !r.delete && topics.iter().any(|t| t.intersects(key)) => export.write(key, &r.payload.to_bytes(), r.ts),
+                    _ => export.skipped += 1,
+                }
+            }
+        },
+        None => {
+            let z = zdemo_common::open(config).await;
+            let (mut subs, mut rx) = zdemo_common::subscribe(&z, &topics.iter().map(|t| t.to_string()).collect::<Vec<_>>()).await;
+            info!("Exporting {} to {}", topics.iter().map(|t| t.as_str()).collect::<Vec<_>>().join(", "), export.out);
+            let shutdown = zdemo_common::shutdown_signal();
+            tokio::pin!(shutdown);
+            let mut flush = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                tokio::select! {
+                    _ = &mut shutdown => break,
+                    _ = flush.tick() => export.flush(),
+                    s = rx.recv() => match s {
+                        Some(sample) if sample.kind == SampleKind::Delete => export.skipped += 1,
+                        Some(sample) => match zdemo_common::payload(&sample) {
+                            Ok(payload) => export.write(&sample.key_expr, &payload, unix_ms()),
+                            Err(_) => export.skipped += 1,
+                        },
+                        None => {
+                            warn!("subscription lost, declaring it again");
+                            drop(subs);
+                            (subs, rx) = zdemo_common::subscribe(&z, &topics.iter().map(|t| t.to_string()).collect::<Vec<_>>()).await;
+                        },
+                    }
+                }
+            }
+        },
+    }
+    export.close();
+    let files = if export.part == 0 { export.out.clone() } else { format!("{} files from {}", export.part + 1, export.out) };
+    info!("Exported {} messages to {files}, {} skipped", export.messages, export.skipped);
+}
+
+#[derive(clap_derive::Args, Debug, Clone)]
+pub struct ReplayArgs {
+    /// JSON lines file produced by tracker-recorder.
+    pub input: String,
+    /// Replay speed factor, 2.0 replays twice as fast as recorded.
+    #[arg(long)]
+    pub speed: Option<f64>,
+    /// Replay the recording forever.
+    #[arg(long = "loop")]
+    pub repeat: bool,
+    /// JSON file with the ed25519 seeds of vehicles, {vehicle id: base64 seed}, whose
+    /// positions are signed when replayed. With --id-prefix the seeds are those of the
+    /// prefixed ids.
+    #[arg(long)]
+    pub sign_keys: Option<String>,
+    /// Replay the samples recorded under <from> under <to> instead, as "from=to", can
+    /// be repeated.
+    #[arg(long)]
+    pub map_key: Vec<KeyMapping>,
+    /// Prefix of the vehicle ids, in the payloads and in the keys of the positions.
+    #[arg(long)]
+    pub id_prefix: Option<String>,
+    /// Replay the positions recorded on <key>/<vehicle id> on <key>/<geohash>/<vehicle id>, the
+    /// geohash of the position at this precision, for trackers subscribing to a
+    /// --geohash-region.
+    #[arg(long)]
+    pub geohash_precision: Option<usize>,
+    /// Stamp the positions with the time they were recorded at rather than the time they are
+    /// replayed at, for trackers on the data clock (--clock data). Each loop stamps them a
+    /// recording later than the previous one.
+    #[arg(long)]
+    pub source_time: bool,
+}
+
+// Republishes a recording with its original pacing, until it ends or is stopped.
+pub async fn replay(args: ReplayArgs, config: Config, compression: Compression) {
+    let speed = args.speed.unwrap_or(1.0);
+    if speed <= 0.0 {
+        panic!("--speed must be positive");
+    }
+
+    let file = File::open(&args.input).unwrap_or_else(|e| panic!("Unable to open {}: {e}", args.input));
+    let records: Vec<Record> = read_records(BufReader::new(file))
+        .filter_map(|r| r.map_err(|e| warn!("Skipping invalid record at {e}")).ok())
+        .collect();
+    info!("Loaded {} samples from {}", records.len(), args.input);
+    if let Some(e) = args.geohash_precision.and_then(|p| geohash::check_precision(p).err()) {
+        panic!("--geohash-precision: {e}");
+    }
+    let rewrite = Rewrite { keys: args.map_key, id_prefix: args.id_prefix, geohash_precision: args.geohash_precision };
+    let records: Vec<Record> = if rewrite.is_empty() {
+        records
+    } else {
+        let checks: Vec<(String, String)> = rewrite.keys.iter()
+            .flat_map(|m| [("--map-key source".to_string(), m.from.clone()), ("--map-key target".to_string(), m.to.clone())])
+            .collect();
+        if let Err(errors) = keys::check_all(checks.iter().map(|(o, k)| (o.as_str(), k.as_str(), KeyUse::Concrete))) {
+            for e in errors {
+                error!("{e}");
+            }
+            std::process::exit(2);
+        }
+        let records: Vec<Record> = records.into_iter().map(|r| rewrite.apply(r)).collect();
+        // a prefix may turn ids into invalid key chunks
+        let invalid: Vec<&String> = records.iter().map(|r| &r.key).filter(|k| keys::check("key", k, KeyUse::Concrete).is_err()).collect();
+        if let Some(k) = invalid.first() {
+            error!("{} rewritten keys are invalid, as '{k}'", invalid.len());
+            std::process::exit(2);
+        }
+        records
+    };
+    let signers = match &args.sign_keys {
+        Some(f) => Signers::load(f).unwrap_or_else(|e| panic!("--sign-keys: {e}")),
+        None => Signers::default()
+    };
+
+    let z = zdemo_common::open(config).await;
+    let shutdown = zdemo_common::shutdown_signal();
+    tokio::pin!(shutdown);
+    let span = match (records.first(), records.last()) {
+        (Some(first), Some(last)) => last.t.saturating_sub(first.t) + 1,
+        _ => 0,
+    };
+    for pass in 0.. {
+        let source_time = args.source_time.then_some(pass * span);
+        tokio::select! {
+            _ = &mut shutdown => break,
+            n = record::replay(&z, &records, speed, &signers, compression, source_time) => info!("Replayed {n} samples"),
+        }
+        if !args.repeat {
+            break;
+        }
+    }
+}
+
+#[derive(clap_derive::Args, Debug, Clone)]
+pub struct PublishArgs {
+    /// CSV file of "id,lat,lng,speed" lines, stdin by default or with "-".
+    pub input: Option<String>,
+    /// Positions are published on <key>/<vehicle id>.
+    #[arg(long)]
+    pub key: Option<String>,
+    /// Kind of the vehicles.
+    #[arg(long)]
+    pub kind: Option<String>,
+    /// Color of the vehicles.
+    #[arg(long)]
+    pub color: Option<String>,
+    /// Maximum positions published per second, as fast as they are read by default.
+    #[arg(long)]
+    pub rate: Option<f64>,
+    /// Publish the file again from the start at its end, forever.
+    #[arg(long = "loop")]
+    pub repeat: bool,
+    /// Publish the positions on <key>/<geohash>/<vehicle id> instead, the geohash of the
+    /// position at this precision, for trackers subscribing to a --geohash-region.
+    #[arg(long)]
+    pub geohash_precision: Option<usize>,
+}
+
+// Reads the lines of `input` into `tx`, numbered from 0 in each pass, all over
+// again with `repeat`.
+fn read_lines(input: Option<String>, repeat: bool, tx: UnboundedSender<(usize, String)>) {
+    loop {
+        let lines: Box<dyn BufRead> = match &input {
+            Some(f) => Box::new(BufReader::new(File::open(f).unwrap_or_else(|e| panic!("Unable to open {f}: {e}")))),
+            None => Box::new(std::io::stdin().lock()),
+        };
+        for (n, line) in lines.lines().enumerate() {
+            let line = match line {
+                Ok(l) => l,
+                Err(e) => return error!("Unable to read the positions: {e}"),
+            };
+            if tx.send((n, line)).is_err() {
+                return;
+            }
+        }
+        if !repeat {
+            return;
+        }
+    }
+}
+
+// Publishes the positions of "id,lat,lng,speed" lines as those of vehicles of
+// the same kind and color, until the end of the input or stopped.
+pub async fn publish_positions(args: PublishArgs, config: Config, compression: Compression, instance_id: Option<String>) {
+    let key = args.key.unwrap_or("demo/tracker/mobs".into());
+    let kind = VehicleKind::parse(args.kind.as_deref().unwrap_or("car"));
+    let color = Color::parse(args.color.as_deref().unwrap_or("blue"));
+    let (kind, color) = match (kind, color) {
+        (Ok(kind), Ok(color)) => (kind, color),
+        (Err(e), _) | (_, Err(e)) => {
+            error!("{e}");
+            std::process::exit(2);
+        },
+    };
+    if let Some(e) = args.geohash_precision.and_then(|p| geohash::check_precision(p).err()) {
+        panic!("--geohash-precision: {e}");
+    }
+    let input = args.input.filter(|f| f != "-");
+    if args.repeat && input.is_none() {
+        error!("--loop needs a CSV file, stdin cannot be read again");
+        std::process::exit(2);
+    }
+    let mut tick = match args.rate {
+        Some(r) if !(r.is_finite() && r > 0.0) => panic!("--rate must be positive"),
+        Some(r) => Some(tokio::time::interval(Duration::from_secs_f64(1.0 / r))),
+        None => None,
+    };
+    let instance = Instance::resolve("position-publisher", instance_id);
+    let z = zdemo_common::open(config).await;
+    let (tx, mut rx) = unbounded_channel();
+    std::thread::spawn(move || read_lines(input, args.repeat, tx));
+    let shutdown = zdemo_common::shutdown_signal();
+    tokio::pin!(shutdown);
+    let mut count = 0;
+    loop {
+        let (n, line) = tokio::select! {
+            _ = &mut shutdown => break,
+            line = rx.recv() => match line {
+                Some(l) => l,
+                None => break,
+            },
+        };
+        if stdin::is_skipped(n, &line) {
+            continue;
+        }
+        let (id, position, speed) = match stdin::parse_line(&line) {
+            Ok(fields) => fields,
+            Err(e) => {
+                warn!("Skipping line {}: {e}", n + 1);
+                continue;
+            }
+        };
+        let vkey = geohash::position_key(&key, args.geohash_precision, position.lat, position.lng, &id);
+        if let Err(e) = keys::check("position key", &vkey, KeyUse::Concrete) {
+            warn!("Skipping line {}: {e}", n + 1);
+            continue;
+        }
+        if let Some(t) = &mut tick {
+            t.tick().await;
+        }
+        let vi = VehicleInfo { position, speed, color: color.clone(), id, kind: kind.clone(), ts: Some(unix_ms()), ..Default::default() };
+        match z.put(&vkey, zdemo_common::json_value_with(&instance.tag(serde_json::to_value(&vi).unwrap()), compression)).res().await {
+            Ok(_) => count += 1,
+            Err(e) => warn!("Unable to publish on {vkey}: {e}"),
+        }
+    }
+    info!("Published {count} positions");
+}
+
+#[derive(clap_derive::Args, Debug, Clone)]
+pub struct SimulateArgs {
+    /// JSON file with the traffic lights, each {name, position: [lat, lng], green, yellow, red (s), offset (s)}.
+    pub lights: Option<String>,
+    /// States are published on <key>/<light name>.
+    #[arg(long)]
+    pub key: Option<String>,
+    /// Period of the publications, states are also published as soon as they change.
+    #[arg(long)]
+    pub period_ms: Option<u64>,
+    /// YAML scenario of scripted vehicles and events (see scenarios/), run along with the lights if any.
+    #[arg(long)]
+    pub scenario: Option<String>,
+    /// Positions of the scenario vehicles are published on <position_key>/<vehicle id>.
+    #[arg(long)]
+    pub position_key: Option<String>,
+    /// Routes of the scenario vehicles are assigned on <route_key>/<vehicle id>.
+    #[arg(long)]
+    pub route_key: Option<String>,
+    /// Publish the positions on <position_key>/<geohash>/<vehicle id> instead, the geohash of
+    /// the position at this precision, for trackers subscribing to a --geohash-region.
+    #[arg(long)]
+    pub geohash_precision: Option<usize>,
+    /// Write the scenario as a recording, in simulated time and without running it, for `analyze`.
+    #[arg(long)]
+    pub out: Option<String>,
+    /// Seed of the noise of the scenario positions, so that a run can be reproduced. Random by
+    /// default, and logged.
+    #[arg(long)]
+    pub seed: Option<u64>,
+}
+
+// Publishes the states of fixed cycle traffic lights and the positions of
+// scripted vehicles, until stopped or the end of the scenario.
+pub async fn simulate(args: SimulateArgs, config: Config, compression: Compression, instance_id: Option<String>) {
+    let scenario = args.scenario.as_ref().map(|f| scenario::load(f).unwrap_or_else(|e| panic!("--scenario: {e}")));
+    if let Some(e) = args.geohash_precision.and_then(|p| geohash::check_precision(p).err()) {
+        panic!("--geohash-precision: {e}");
+    }
+    let keys = ScenarioKeys {
+        position: args.position_key.clone().unwrap_or("demo/tracker/mobs".into()),
+        geohash_precision: args.geohash_precision,
+        route: args.route_key.clone().unwrap_or("demo/tracker/route".into()),
+    };
+    let seed = args.seed.unwrap_or_else(rand::random);
+    if let Some(sc) = scenario.as_ref().filter(|sc| args.seed.is_none() && !sc.noise.is_none()) {
+        info!("SCENARIO: noise of '{}' seeded with --seed {seed}", sc.name);
+    }
+    match (&args.lights, scenario) {
+        (None, None) => {
+            error!("nothing to simulate, expecting a lights file and/or a --scenario");
+            std::process::exit(2);
+        },
+        (_, Some(sc)) if args.out.is_some() => {
+            let out = args.out.unwrap();
+            write_scenario(&sc, seed, &keys, &out).unwrap_or_else(|e| panic!("Unable to write {out}: {e}"));
+        },
+        (Some(lights), None) => simulate_lights(lights, args.key, args.period_ms, config, compression, instance_id).await,
+        (None, Some(sc)) => run_scenario(sc, seed, keys, config, compression, instance_id).await,
+        (Some(lights), Some(sc)) => {
+            // the lights keep going after the end of the scenario, until stopped
+            tokio::join!(
+                simulate_lights(lights, args.key, args.period_ms, config.clone(), compression, instance_id.clone()),
+                run_scenario(sc, seed, keys, config, compression, instance_id),
+            );
+        },
+    }
+}
+
+// Where the positions and the routes of a scenario are published.
+struct ScenarioKeys {
+    position: String,
+    geohash_precision: Option<usize>,
+    route: String,
+}
+
+impl ScenarioKeys {
+    fn check(&self, sc: &Scenario) {
+        let checks: Vec<(String, String)> = sc.vehicles.iter()
+            .flat_map(|v| [(format!("vehicle '{}'", v.id), format!("{}/{}", self.position, v.id)), (format!("route of '{}'", v.id), format!("{}/{}", self.route, v.id))])
+            .collect();
+        if let Err(errors) = keys::check_all(checks.iter().map(|(o, k)| (o.as_str(), k.as_str(), KeyUse::Concrete))) {
+            for e in errors {
+                error!("{e}");
+            }
+            std::process::exit(2);
+        }
+    }
+
+    fn sample(&self, output: &scenario::Output) -> (String, serde_json::Value) {
+        match output {
+            scenario::Output::Position(vi) => {
+                let key = geohash::position_key(&self.position, self.geohash_precision, vi.position.lat, vi.position.lng, &vi.id);
+                (key, serde_json::to_value(vi).unwrap())
+            },
+            scenario::Output::Route { id, points } => (format!("{}/{id}", self.route), serde_json::to_value(points).unwrap()),
+        }
+    }
+}
+
+// Runs the scenario at the pace of its period.
+async fn run_scenario(sc: Scenario, seed: u64, keys: ScenarioKeys, config: Config, compression: Compression, instance_id: Option<String>) {
+    keys.check(&sc);
+    let instance = Instance::resolve("tracker-scenario", instance_id);
+    let z = zdemo_common::open(config).await;
+    let shm = ShmSegment::of(&z, "tracker-scenario");
+    let mut sim = Simulation::new(&sc, seed);
+    info!("Running scenario '{}' ({} vehicles, {}s)", sc.name, sc.vehicles.len(), sc.duration);
+    let shutdown = zdemo_common::shutdown_signal();
+    tokio::pin!(shutdown);
+    let mut tick = tokio::time::interval(Duration::from_millis(sim.period_ms));
+    while !sim.is_over() {
+        tokio::select! {
+            _ = &mut shutdown => return,
+            _ = tick.tick() => {}
+        }
+        for mut output in sim.step() {
+            if let scenario::Output::Position(vi) = &mut output {
+                vi.ts = Some(unix_ms());
+            }
+            let (key, value) = keys.sample(&output);
+            if let Err(e) = z.put(&key, shm.value(zdemo_common::json_value_with(&instance.tag(value), compression))).res().await {
+                warn!("Unable to publish on {key}: {e}");
+            }
+        }
+    }
+    info!("End of scenario '{}'", sc.name);
+}
+
+// Writes what the scenario would publish as a recording, timed by the
+// simulation rather than the wall clock, so that `analyze` gives the same
+// alerts on every run.
+fn write_scenario(sc: &Scenario, seed: u64, keys: &ScenarioKeys, out: &str) -> std::io::Result<()> {
+    keys.check(sc);
+    let mut w = BufWriter::new(File::create(out)?);
+    let mut sim = Simulation::new(sc, seed);
+    let start = unix_ms();
+    let mut count = 0;
+    while !sim.is_over() {
+        let t = (sim.t() * 1000.0).round() as u64;
+        for output in sim.step() {
+            let (key, value) = keys.sample(&output);
+            let r = Record { t, ts: start + t, key, delete: false, encoding: Encoding::APP_JSON.to_string(), payload: Payload::Json(value), attachment: Default::default() };
+            serde_json::to_writer(&mut w, &r)?;
+            w.write_all(b"\n")?;
+            count += 1;
+        }
+    }
+    w.flush()?;
+    info!("Wrote {count} samples of scenario '{}' to {out}", sc.name);
+    Ok(())
+}
+
+// Publishes the states of fixed cycle traffic lights, until stopped.
+async fn simulate_lights(lights: &str, key: Option<String>, period_ms: Option<u64>, config: Config, compression: Compression, instance_id: Option<String>) {
+    let key = key.unwrap_or("demo/tracker/lights".into());
+    let period = Duration::from_millis(period_ms.unwrap_or(1000));
+    let lights: Vec<TrafficLight> = lights::load(lights).unwrap_or_else(|e| panic!("{e}"));
+    let checks: Vec<(String, String)> = lights.iter().map(|l| (format!("light '{}'", l.name), format!("{key}/{}", l.name))).collect();
+    if let Err(errors) = keys::check_all(checks.iter().map(|(o, k)| (o.as_str(), k.as_str(), KeyUse::Concrete))) {
+        for e in errors {
+            error!("{e}");
+        }
+        std::process::exit(2);
+    }
+    let instance = Instance::resolve("tracker-lights", instance_id);
+
+    let z = zdemo_common::open(config).await;
+    let publishers: Vec<JsonPublisher<LightStatus>> = checks.into_iter()
+        .map(|(_, k)| JsonPublisher::new(z.clone(), k).unwrap().instance(&instance).compression(compression))
+        .collect();
+    info!("Publishing {} traffic lights on {key}/*", lights.len());
+    let start = Instant::now();
+    let mut states: Vec<Option<LightState>> = vec![None; lights.len()];
+    let mut last_put = vec![start; lights.len()];
+    let shutdown = zdemo_common::shutdown_signal();
+    tokio::pin!(shutdown);
+    // ticks often enough to follow state changes closely
+    let mut tick = tokio::time::interval(Duration::from_millis(100).min(period));
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+            _ = tick.tick() => {}
+        }
+        let now = Instant::now();
+        let t = now.duration_since(start).as_secs_f64();
+        for (i, light) in lights.iter().enumerate() {
+            let status = light.status(t);
+            let changed = states[i] != Some(status.state);
+            if !changed && now.duration_since(last_put[i]) < period {
+                continue;
+            }
+            if changed {
+                info!("LIGHT: {} is {:?}", light.name, status.state);
+            }
+            match publishers[i].put(&status).await {
+                Ok(()) => {
+                    states[i] = Some(status.state);
+                    last_put[i] = now;
+                },
+                Err(e) => warn!("Unable to publish on {}: {e}", publishers[i].key_expr()),
+            }
+        }
+    }
+}
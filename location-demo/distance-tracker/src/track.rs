@@ -0,0 +1,141 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
+use zenoh::prelude::r#async::*;
+use tracing::warn;
+use crate::record::unix_ms;
+use crate::{Position, VehicleInfo};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct TrackPoint {
+    // unix ms at which the position was accepted
+    pub t: u64,
+    #[serde(flatten)]
+    pub position: Position,
+    pub speed: f64,
+}
+
+// Trajectory of a vehicle, oldest point first.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Track {
+    pub id: String,
+    pub points: Vec<TrackPoint>,
+}
+
+// The last `length` positions of every vehicle, shared between the ingest
+// loop and the track queryable.
+#[derive(Clone)]
+pub struct Tracks {
+    buffers: Arc<Mutex<HashMap<String, VecDeque<TrackPoint>>>>,
+    length: usize,
+}
+
+impl Tracks {
+    pub fn new(length: usize) -> Self {
+        Tracks { buffers: Arc::default(), length }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.length > 0
+    }
+
+    // Only allocates for the first position of a vehicle.
+    pub fn record(&self, vi: &VehicleInfo) {
+        if !self.is_enabled() {
+            return;
+        }
+        let point = TrackPoint { t: unix_ms(), position: vi.position, speed: vi.speed };
+        let mut buffers = self.buffers.lock().unwrap();
+        let buffer = match buffers.get_mut(&vi.id) {
+            Some(b) => b,
+            None => buffers.entry(vi.id.clone()).or_insert_with(|| VecDeque::with_capacity(self.length)),
+        };
+        if buffer.len() == self.length {
+            buffer.pop_front();
+        }
+        buffer.push_back(point);
+    }
+
+    pub fn clear(&self) {
+        self.buffers.lock().unwrap().clear();
+    }
+
+    // The track of a vehicle, oldest point first.
+    pub fn get(&self, id: &str) -> Vec<TrackPoint> {
+        self.buffers.lock().unwrap().get(id).map(|b| b.iter().copied().collect()).unwrap_or_default()
+    }
+
+    // Starts the track of a vehicle with points recorded elsewhere (another
+    // tracker), unless it already has one.
+    pub fn adopt(&self, id: &str, points: &[TrackPoint]) -> bool {
+        let mut buffers = self.buffers.lock().unwrap();
+        if !self.is_enabled() || points.is_empty() || buffers.get(id).is_some_and(|b| !b.is_empty()) {
+            return false;
+        }
+        let points = points.iter().skip(points.len().saturating_sub(self.length)).copied().collect();
+        buffers.insert(id.into(), points);
+        true
+    }
+
+    // The last `n` points of the vehicles which moved since the last call,
+    // `seen` holding the time of the last point returned for each vehicle.
+    pub fn moved(&self, seen: &mut HashMap<String, u64>, n: usize) -> Vec<Track> {
+        let buffers = self.buffers.lock().unwrap();
+        buffers.iter()
+            .filter_map(|(id, b)| {
+                let last = b.back()?.t;
+                if seen.get(id).is_some_and(|t| *t >= last) {
+                    return None;
+                }
+                seen.insert(id.clone(), last);
+                Some(Track { id: id.clone(), points: b.iter().skip(b.len().saturating_sub(n)).copied().collect() })
+            })
+            .collect()
+    }
+
+    // The last `n` points of the vehicles whose track key (<prefix>/<id>)
+    // intersects `selected`.
+    fn select(&self, prefix: &str, selected: &keyexpr, n: usize) -> Vec<(OwnedKeyExpr, Track)> {
+        let buffers = self.buffers.lock().unwrap();
+        let mut tracks: Vec<(OwnedKeyExpr, Track)> = buffers.iter()
+            .filter_map(|(id, b)| {
+                let key = OwnedKeyExpr::try_from(format!("{prefix}/{id}")).ok()?;
+                key.intersects(selected).then(|| {
+                    let points = b.iter().skip(b.len().saturating_sub(n)).copied().collect();
+                    (key, Track { id: id.clone(), points })
+                })
+            })
+            .collect();
+        tracks.sort_by(|a, b| a.1.id.cmp(&b.1.id));
+        tracks
+    }
+}
+
+// Replies to <prefix>/<id>?last=<n> with the track of each vehicle selected,
+// the whole buffer when `last` is not given.
+pub async fn serve(z: Arc<Session>, prefix: String, tracks: Tracks) {
+    let queryable = z.declare_queryable(format!("{prefix}/*")).res().await.unwrap();
+    while let Ok(query) = queryable.recv_async().await {
+        let last = match query.selector().parameters_stringmap() {
+            Ok(params) => match params.get("last") {
+                Some(s) => s.parse::<usize>().map_err(|e| format!("invalid last '{s}': {e}")),
+                None => Ok(tracks.length),
+            },
+            Err(e) => Err(e.to_string()),
+        };
+        let replies = match last {
+            Ok(n) => tracks.select(&prefix, query.key_expr(), n).into_iter()
+                .map(|(key, track)| Ok(Sample::new(key, Value::from(serde_json::to_vec(&track).unwrap()).encoding(Encoding::APP_JSON))))
+                .collect(),
+            Err(e) => {
+                warn!("Invalid query {}: {e}", query.selector());
+                vec![Err(Value::from(e))]
+            }
+        };
+        for reply in replies {
+            if let Err(e) = query.reply(reply).res().await {
+                warn!("Unable to reply to query on {prefix}: {e}");
+            }
+        }
+    }
+}
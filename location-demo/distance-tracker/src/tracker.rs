@@ -0,0 +1,2747 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use zenoh::prelude::r#async::*;
+use tokio::sync::Mutex;
+use tokio::sync::mpsc::{channel, unbounded_channel, Receiver, Sender, UnboundedReceiver, UnboundedSender};
+use tokio::task;
+use zdemo_common::{Compression, Instance};
+use tracing::{debug_span, error, info, info_span, warn, Instrument};
+use crate::{admin, advisory, analyze, backfill, clock, coldstart, delay, estop::EmergencyStop, geohash, influx, keys, leader, outputs, pairing, publish, pull, query, risk, roi, routes, severity, shard, sinks, stdin, warmstart, zones};
+use crate::keys::KeyUse;
+use crate::logging::LogFormat;
+use crate::admin::{ConfigChange, SetRequest, Thresholds};
+use crate::grpc;
+use crate::http::{self, SharedHistory};
+use crate::advisory::{Advisor, Advisory};
+use crate::clusters::ClusterList;
+use crate::clock::ClockSource;
+use crate::delay::{DelayQueue, DelayStream, SharedQueue};
+use crate::diag::{DeadLetters, Diagnostics, Stage};
+use crate::digest::{self, Digest, SharedDigest};
+use crate::federation::{self, RegionStats, SharedRegionStats};
+use crate::display::Presenter;
+use crate::risk::RiskBoard;
+use crate::flagged::{self, Flagged};
+use crate::feeds::{self, Feeds};
+use crate::fleets::{self, Fleets};
+use crate::foxglove::{self, Frames};
+use crate::geo::{Coordinates, DistanceAlgo};
+use crate::handover::{self, Handover, Site};
+use crate::headway::{self, Headway, Headways};
+use crate::influx::InfluxConfig;
+use crate::ingest::{self, Downsampler, Profiles, RawVehicle};
+use crate::leader::Leadership;
+use crate::lights::LightStatus;
+use crate::matching::Matching;
+use crate::metadata::{self, MetaCache};
+use crate::runtime_state::{self, RuntimeState, RuntimeStateFile};
+use crate::pairing::PairingRules;
+use crate::outputs::Outputs;
+use crate::pacing::Pacer;
+use crate::severity::Severities;
+use crate::shard::{Border, Cells, Shard};
+use crate::metrics::{self, Metrics, SharedMetrics};
+use crate::{cbor, geojson, heatmap, matrix, poi, sectors, status, tether};
+use crate::publish::{Batching, Outgoing};
+use crate::record::{read_records, Record};
+use crate::qos::{self, CongestionArg, Level, PriorityArg, Qos, QosTable, ReliabilityArg};
+use crate::roi::Roi;
+use crate::routes::RouteUpdate;
+use crate::rules::{Alert, Rules};
+use crate::signing::{self, Keyring, SignaturePolicy, TamperAlert};
+use crate::snapshot::Snapshots;
+use crate::identity::{self, IdentityGuard};
+use crate::incidents::{self, Incidents, SharedIncidents};
+use crate::introspection::{self, Internals, SharedThresholds};
+use crate::features::{self, Features, SharedFeatures};
+use crate::rules::PrintOnly;
+use crate::summary::Summary;
+use crate::telemetry::{self, BatteryRule};
+use crate::geometry::{self, SharedDangerPairs};
+use crate::plausibility::{self, MaxSpeeds, OutlierPolicy, Plausibility};
+use crate::sinks::{SinkKind, Sinks};
+use crate::sinks::SinkConfig;
+use crate::session::DemoSession;
+use crate::storm::StormGuard;
+use crate::subscriptions::{self, SubCommand, Subscriptions};
+use crate::tether::TetherGroup;
+use crate::poi::Poi;
+use crate::sectors::Sector;
+use crate::transport::TransportArgs;
+use crate::tools::{McapArgs, PublishArgs, RecordArgs, ReplayArgs, SimulateArgs};
+use crate::track::{self, Tracks};
+use crate::{smooth, trail};
+use crate::vehicle::VehicleState;
+use crate::zones::Zone;
+use crate::{Position, VehicleInfo, VehicleKind, VehicleMap};
+
+#[derive(clap_derive::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum IngestMode {
+    /// Subscribe to every position published
+    Push,
+    /// Periodically get the latest positions from a storage
+    Pull,
+    /// Read "id,lat,lng,speed" lines from stdin, without any Zenoh publisher
+    StdinCsv,
+}
+
+#[derive(clap_derive::ValueEnum, Clone, Copy, Debug)]
+enum AlertKeyLayout {
+    /// Every alert on pub_key
+    Flat,
+    /// pub_key/<ida>/<idb>, ids in lexicographic order
+    Pair
+}
+
+impl AlertKeyLayout {
+    fn key(&self, pkey: &str, ida: &str, idb: &str) -> String {
+        match self {
+            AlertKeyLayout::Flat => pkey.into(),
+            AlertKeyLayout::Pair => {
+                let (a, b) = if ida <= idb { (ida, idb) } else { (idb, ida) };
+                let key = format!("{pkey}/{a}/{b}");
+                match keyexpr::new(&key) {
+                    Ok(_) => key,
+                    Err(_) => {
+                        warn!("ids {ida} and {idb} cannot be used as key chunks, publishing on {pkey}");
+                        pkey.into()
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Runs the tracker, or the subcommand of the options querying, analyzing or
+// generating keys for it, until interrupted.
+pub async fn run(settings: Settings) {
+    if let Some(Command::Query { what, params }) = &settings.command {
+        query_tracker(*what, params.as_deref(), &settings).await;
+        return;
+    }
+    if let Some(Command::Analyze { recording, alerts }) = &settings.command {
+        analyze_recording(recording, alerts.as_deref(), &settings);
+        return;
+    }
+    if let Some(Command::Keygen { ids, seeds, keyring }) = &settings.command {
+        signing::keygen(ids, seeds, keyring).unwrap_or_else(|e| panic!("{e}"));
+        println!("Added {} keys to {seeds}, public keys in {keyring}", ids.len());
+        return;
+    }
+    let Settings {
+        skeys,
+        mode,
+        pull_period,
+        pkey,
+        alert_key_layout,
+        outputs,
+        state_key,
+        nearest_key,
+        diag_key,
+        dead_letter_key,
+        track_key,
+        track_length,
+        trail_key,
+        trail_length,
+        trail_tolerance,
+        trail_period_ms,
+        smooth_key,
+        smooth_rate_hz,
+        metrics_key,
+        stats_key,
+        stats_period_ms,
+        status_key,
+        status_period_ms,
+        digest_key,
+        digest_period_ms,
+        recent_key,
+        alert_cache,
+        federation_key,
+        federation_period_ms,
+        federation_top,
+        meta_key,
+        max_rate,
+        min_distance,
+        max_distance,
+        distance_algo,
+        compute_period_ms,
+        auto_period,
+        speed_tolerance,
+        cutin_key,
+        cutin_range,
+        cutin_bearing_rate,
+        closing_key,
+        closing_speed,
+        closing_floor,
+        pairing,
+        severities,
+        speed_key,
+        speed_zones,
+        tethers,
+        tether_key,
+        pois,
+        poi_key,
+        sectors,
+        sector_key,
+        battery,
+        battery_key,
+        telemetry_key,
+        telemetry_period_ms,
+        route_key,
+        route_alert_key,
+        route_corridor,
+        checkpoint_radius,
+        progress_key,
+        display_key,
+        clusters_key,
+        cluster_range,
+        heatmap_key,
+        heatmap_cell,
+        risk_key,
+        risk_range,
+        geojson,
+        geojson_key,
+        matrix,
+        matrix_key,
+        matrix_cap,
+        lights_key,
+        geometry_key,
+        geometry_period_ms,
+        light_radius,
+        red_min_distance,
+        move_epsilon,
+        danger_only_closing,
+        tethered_separation,
+        print_only,
+        summary_period_s,
+        flag_key,
+        subscription_key,
+        site,
+        handover_key,
+        tracking_key,
+        admin_key,
+        introspection_key,
+        advisory_key,
+        keyring,
+        signature_policy,
+        tamper_key,
+        max_speeds,
+        headways,
+        fleets,
+        feeds,
+        outlier_policy,
+        features_key,
+        identity_key,
+        max_jump_speed,
+        delays,
+        delay_state,
+        runtime_state,
+        warm_start,
+        cold_start,
+        cold_start_max_age,
+        roi,
+        storm_key,
+        storm_rate,
+        session_duration,
+        session_key,
+        ack_key,
+        escalation_key,
+        escalation_timeout,
+        estop,
+        batching,
+        batch_key,
+        sinks,
+        leader_key,
+        shard,
+        shard_key,
+        influx,
+        http_port,
+        grpc_port,
+        foxglove_port,
+        instance,
+        qos,
+        compression,
+        sub_reliability,
+        config,
+        command_line,
+        .. } = settings;
+
+    for (k, v) in instance.resource_attributes() {
+        info!("{k}={v}");
+    }
+    if let Some(k) = &keyring {
+        info!("Verifying positions against {} publisher keys ({signature_policy:?} otherwise)", k.len());
+    }
+    let z = zdemo_common::open(config).await;
+    let metrics: SharedMetrics = Arc::new(Mutex::new(Metrics::default()));
+    let leadership = if leader_key.is_some() { Leadership::standby() } else { Leadership::default() };
+    let election = leader_key.map(|k| task::spawn(leader::elect(z.clone(), k, instance.id.clone(), leadership.clone())));
+    let (alerts, publisher) = publish::spawn(z.clone(), instance.clone(), qos, compression, metrics.clone(), batching == Batching::Concurrent, leadership.clone());
+    let notices = alerts.clone();
+    let diag = Diagnostics::new(diag_key, alerts.clone());
+    let dead_letter_space = OwnedKeyExpr::try_from(format!("{dead_letter_key}/**")).unwrap();
+    let dead_letters = DeadLetters::new(z.clone(), dead_letter_key);
+    let plausibility = (outlier_policy != OutlierPolicy::Off).then(|| Plausibility::new(max_speeds.clone()));
+    let persistence = (!delays.is_empty() && delay_state.is_some()) || runtime_state.is_some() || warm_start.is_some() || sinks.iter().any(|s| matches!(s.kind, SinkKind::File { .. }));
+    let gateway = influx.is_some() || http_port.is_some() || grpc_port.is_some() || foxglove_port.is_some() || sinks.iter().any(|s| matches!(s.kind, SinkKind::Webhook { .. } | SinkKind::Desktop | SinkKind::Audio { .. }));
+    let (sinks, mut sink_tasks) = sinks::spawn(&sinks, &instance).unwrap_or_else(|e| panic!("--sinks: {e}"));
+    let telemetry = influx.as_ref().map(|c| {
+        let (t, handle) = influx::spawn(c).unwrap_or_else(|e| panic!("--influx: {e}"));
+        sink_tasks.push(handle);
+        t
+    });
+    let mut vehicles = HashMap::<String, VehicleState>::new();
+    let mut profiles = Profiles::default();
+    if let Some(path) = &warm_start {
+        let restored = warmstart::load(path, &skeys).unwrap_or_else(|e| panic!("--warm-start: {e}"));
+        info!("WARM START: restored {} vehicles from {path}", restored.vehicles.len());
+        let now = clock::now();
+        vehicles.extend(restored.vehicles.into_iter().filter(|(vi, _)| roi.as_ref().is_none_or(|r| r.contains(&vi.position))).map(|(vi, received)| (vi.id.clone(), VehicleState::restored(vi, received, now))));
+        profiles = restored.profiles;
+    }
+    let pmap: VehicleMap = Arc::new(Snapshots::new(vehicles));
+    let tracks = Tracks::new(track_length);
+    let mut tasks = vec![
+        task::spawn(query::serve(z.clone(), state_key, pmap.clone(), query::state_page)),
+        task::spawn(query::serve(z.clone(), nearest_key, pmap.clone(), query::nearest)),
+        task::spawn(metrics::serve(z.clone(), metrics_key, metrics.clone(), instance.clone())),
+    ];
+    tasks.extend(election);
+    if tracks.is_enabled() {
+        tasks.push(task::spawn(track::serve(z.clone(), track_key, tracks.clone())));
+    }
+    if stats_period_ms > 0 {
+        tasks.push(task::spawn(metrics::publish(z.clone(), stats_key, metrics.clone(), instance.clone(), Duration::from_millis(stats_period_ms))));
+    }
+    let digest: SharedDigest = Arc::new(std::sync::Mutex::new(Digest::new(clock::unix_ms())));
+    tasks.push(task::spawn(digest::serve(z.clone(), digest_key, digest.clone(), instance.clone(), Duration::from_millis(digest_period_ms))));
+    let status_period = Duration::from_millis(status_period_ms);
+    tasks.push(task::spawn(status::run(z.clone(), status_key, metrics.clone(), instance.clone(), status_period, Duration::from_millis(compute_period_ms))));
+    let meta = MetaCache::default();
+    let saved = runtime_state.as_ref().map(|path| {
+        let restored = runtime_state::load(path).unwrap_or_else(|e| panic!("--runtime-state: {e}"));
+        info!("RUNTIME STATE: restored {} configuration sections and the metadata of {} vehicles from {}",
+            restored.rules.iter().count() + restored.zones.iter().count(), restored.meta.len(), path.display());
+        for (id, m) in &restored.meta {
+            meta.adopt(id, m.clone());
+        }
+        let state = Arc::new(std::sync::Mutex::new(RuntimeState { meta: Default::default(), ..restored }));
+        let file = Arc::new(std::sync::Mutex::new(RuntimeStateFile::new(path.clone(), state.clone(), meta.clone())));
+        tasks.push(task::spawn(runtime_state::run(file.clone())));
+        (state, file)
+    });
+    tasks.push(task::spawn(metadata::serve(z.clone(), meta_key.clone(), meta.clone())));
+    tasks.push(task::spawn(metadata::follow(z.clone(), meta_key, meta.clone(), Some(diag.clone()))));
+    tasks.push(task::spawn(handover::follow(z.clone(), handover_key.clone(), site.clone(), meta.clone(), tracks.clone(), diag.clone())));
+    let (border_tx, border_rx) = unbounded_channel::<VehicleInfo>();
+    if let Some(s) = &shard {
+        tasks.push(task::spawn(shard::follow(z.clone(), format!("{shard_key}/border"), s.clone(), border_tx)));
+        if matches!(s.cells, Cells::Elected { .. }) {
+            tasks.push(task::spawn(shard::elect(z.clone(), format!("{shard_key}/members"), s.clone())));
+        }
+    }
+    // severities may publish below the distance key
+    let distance_key = match alert_key_layout {
+        AlertKeyLayout::Flat if !severities.bands.iter().any(|b| b.key.is_some()) => pkey.clone(),
+        _ => format!("{pkey}/**"),
+    };
+    // every publication watched, for introspection
+    let mut publications = Vec::new();
+    let mut watch = |key: &str| {
+        let (m, t) = Matching::watch(z.clone(), key.into());
+        tasks.push(t);
+        publications.push(m.clone());
+        m
+    };
+    // rules with their own output are followed on its keys, the others on
+    // their keys and those of the fleets
+    let mut watch_rule = |rule: &str, key: &str| match outputs.selector(rule) {
+        Some(selector) => watch(&selector),
+        None => fleets.names().fold(watch(key), |m, f| {
+            let fleet_key = fleets.alert_key(f, rule).unwrap();
+            m.or(watch(&if key.ends_with("/**") { format!("{fleet_key}/**") } else { fleet_key }))
+        }),
+    };
+    let distance_matching = watch_rule("distance", &distance_key);
+    let cutin_matching = watch_rule("cutin", &cutin_key);
+    let closing_matching = watch_rule("closing", &closing_key);
+    let speed_matching = watch_rule("speed", &speed_key);
+    let route_matching = watch_rule("route", &route_alert_key);
+    let tether_matching = watch_rule("tether", &tether_key);
+    let poi_matching = watch_rule("poi", &format!("{poi_key}/**"));
+    let sector_matching = watch_rule("sector", &format!("{sector_key}/**"));
+    let battery_matching = watch_rule("battery", &battery_key);
+    let progress_matching = watch(&format!("{progress_key}/**"));
+    let display_matching = watch(&format!("{display_key}/**"));
+    let risk_matching = watch(&format!("{risk_key}/**"));
+    let tracking_matching = watch(&format!("{tracking_key}/**"));
+    let clusters_matching = (cluster_range > 0.0).then(|| watch(&clusters_key));
+    let heatmap_matching = (heatmap_cell > 0.0).then(|| watch(&heatmap_key));
+    let geojson_matching = geojson.then(|| watch(&geojson_key));
+    let matrix_matching = matrix.then(|| watch(&matrix_key));
+    let batch_matching = batching.batches().then(|| watch(&batch_key));
+    let escalation_matching = escalation_timeout.is_some().then(|| watch(&escalation_key));
+    let geometry_matching = watch(&format!("{geometry_key}/**"));
+    let telemetry_matching = watch(&telemetry_key);
+    let trail_matching = (trail_length > 0).then(|| watch(&format!("{trail_key}/**")));
+    let smooth_matching = (smooth_rate_hz > 0.0).then(|| watch(&format!("{smooth_key}/**")));
+    // regions publish summaries to the federation instead of their alerts
+    let region_key = federation_key.map(|k| format!("{k}/region/{}", site.name));
+    let federation_matching = region_key.as_ref().map(|k| watch(k));
+    let watched: Vec<Matching> = [&distance_matching, &cutin_matching, &closing_matching, &speed_matching, &route_matching, &tether_matching, &poi_matching, &sector_matching, &battery_matching, &progress_matching, &display_matching, &risk_matching, &tracking_matching, &geometry_matching].into_iter()
+        .chain(clusters_matching.as_ref())
+        .chain(heatmap_matching.as_ref())
+        .chain(geojson_matching.as_ref())
+        .chain(matrix_matching.as_ref())
+        .chain(batch_matching.as_ref())
+        .chain(escalation_matching.as_ref())
+        .chain(federation_matching.as_ref())
+        .cloned()
+        .collect();
+    let danger_pairs = SharedDangerPairs::default();
+    tasks.push(task::spawn(geometry::publish(z.clone(), geometry_key, danger_pairs.clone(), Duration::from_millis(geometry_period_ms), geometry_matching)));
+    if let Some(matching) = trail_matching {
+        tasks.push(task::spawn(trail::publish(z.clone(), trail_key, tracks.clone(), trail_length, trail_tolerance, Duration::from_millis(trail_period_ms), matching)));
+    }
+    tasks.push(task::spawn(telemetry::serve(z.clone(), telemetry_key, pmap.clone(), instance.clone(), Duration::from_millis(telemetry_period_ms), telemetry_matching)));
+    if let Some(matching) = smooth_matching {
+        tasks.push(task::spawn(smooth::publish(z.clone(), smooth_key, pmap.clone(), Duration::from_secs_f64(1.0 / smooth_rate_hz), matching)));
+    }
+    let region_stats = region_key.map(|key| {
+        let stats: SharedRegionStats = Arc::new(std::sync::Mutex::new(RegionStats::new(site.name.clone(), federation_top)));
+        tasks.push(task::spawn(federation::publish(z.clone(), key, stats.clone(), Duration::from_millis(federation_period_ms))));
+        stats
+    });
+    let (route_tx, route_rx) = unbounded_channel::<RouteUpdate>();
+    tasks.push(task::spawn(follow_routes(z.clone(), route_key, route_tx, diag.clone())));
+    let (light_tx, light_rx) = unbounded_channel::<LightStatus>();
+    tasks.push(task::spawn(follow_lights(z.clone(), lights_key, light_tx, diag.clone())));
+    let flagged = Flagged::default();
+    // samples of flagged vehicles, coalesced when the compute task lags behind
+    let (flag_tx, flag_rx) = channel::<String>(64);
+    tasks.push(task::spawn(follow_flags(z.clone(), flag_key, flagged.clone(), diag.clone())));
+    let (sub_tx, sub_rx) = unbounded_channel();
+    tasks.push(task::spawn(follow_subscriptions(z.clone(), subscription_key.clone(), sub_tx, diag.clone())));
+    let mut rules = Rules::new(min_distance, max_distance, distance_algo, cutin_range, cutin_bearing_rate);
+    rules.closing_speed = closing_speed;
+    rules.closing_floor = closing_floor;
+    rules.pairing = pairing;
+    rules.severities = severities.clone();
+    rules.zones = speed_zones;
+    rules.tethers = tethers;
+    rules.pois = pois;
+    rules.sectors = sectors;
+    rules.battery = battery;
+    rules.tethered_separation = tethered_separation;
+    rules.route_corridor = route_corridor;
+    rules.checkpoint_radius = checkpoint_radius;
+    rules.cluster_range = cluster_range;
+    rules.light_radius = light_radius;
+    rules.red_min_distance = red_min_distance;
+    rules.move_epsilon = move_epsilon;
+    rules.danger_only_closing = danger_only_closing;
+    rules.print = print_only;
+    rules.max_speeds = max_speeds.clone();
+    rules.headways = headways;
+    rules.fleets = fleets.clone();
+    let print_positions = print_only == PrintOnly::All;
+    let shm = *z.config().lock().transport().shared_memory().enabled();
+    let features: SharedFeatures = Arc::new(Mutex::new(Features::new(&rules, site.bbox.is_some() || roi.is_some(), cutin_range, persistence, gateway, shm)));
+    tasks.push(task::spawn(features::serve(z.clone(), features_key, features.clone(), instance.clone())));
+    let (config_tx, config_rx) = unbounded_channel::<ConfigChange>();
+    let delayed = (!delays.is_empty()).then(|| {
+        let queue: SharedQueue = Arc::new(std::sync::Mutex::new(DelayQueue::open(delay_state)));
+        tasks.push(task::spawn(delay::run(z.clone(), delays, queue.clone())));
+        queue
+    });
+    let (set_tx, set_rx) = unbounded_channel::<SetRequest>();
+    let shared_config = admin::Shared::new(admin_key, instance.id.clone(), Thresholds::of(&rules), saved.as_ref().map(|(s, _)| s.clone()));
+    tasks.push(task::spawn(admin::sync(z.clone(), shared_config, config_tx, set_rx, diag.clone())));
+    let thresholds: SharedThresholds = Arc::new(std::sync::Mutex::new(Thresholds::of(&rules)));
+    let internals = Internals {
+        vehicles: pmap.clone(), digest: digest.clone(), sinks: sinks.queues(), metrics: metrics.clone(), publications, thresholds: thresholds.clone(), args: command_line,
+    };
+    tasks.push(task::spawn(introspection::serve(z.clone(), introspection_key, instance.clone(), internals)));
+    // the recent alert queries, the HTTP API and the gRPC service share the
+    // alerts published
+    let history = SharedHistory::default();
+    let api = http_port.is_some() || grpc_port.is_some();
+    tasks.push(task::spawn(backfill::serve(z.clone(), recent_key, alert_cache, history.clone())));
+    if let Some(port) = http_port {
+        tasks.push(task::spawn(http::serve(port, pmap.clone(), history.clone(), set_tx.clone())));
+    }
+    if let Some(port) = grpc_port {
+        tasks.push(task::spawn(grpc::serve(port, pmap.clone(), history.clone(), set_tx)));
+    }
+    let foxglove_frames = foxglove_port.map(|port| {
+        let frames = foxglove::frames();
+        tasks.push(task::spawn(foxglove::serve(port, frames.clone())));
+        frames
+    });
+    let incidents = escalation_timeout.map(|timeout| {
+        let incidents: SharedIncidents = Arc::new(std::sync::Mutex::new(Incidents::new(timeout)));
+        tasks.push(task::spawn(incidents::serve(z.clone(), ack_key, incidents.clone(), diag.clone())));
+        incidents
+    });
+    let session = session_duration.map(DemoSession::new);
+    // the state of the ingestion is cleared with that of the compute loop
+    let (session_tx, session_rx) = unbounded_channel::<()>();
+    if let Some(s) = &session {
+        info!("SESSION: {} started, for {} s", s.id, s.duration().as_secs());
+        metrics.lock().await.session = Some(s.id.clone());
+    }
+    let alerting = Alerting {
+        meta: meta.clone(), fleets: fleets.clone(), shard: shard.clone(), outputs, severities, alert_key_layout, pkey,
+        cutin_key, closing_key, speed_key, tether_key, poi_key, sector_key, battery_key, route_alert_key,
+        distance_matching, cutin_matching, closing_matching, speed_matching, route_matching, tether_matching,
+        poi_matching, sector_matching, battery_matching, batch_matching, delivered: !sinks.is_empty() || api,
+        foxglove_frames,
+    };
+    let compute = Compute {
+        alerting, rules, map: pmap.clone(), alerts, z: z.clone(), instance: instance.clone(), metrics: metrics.clone(),
+        features, thresholds, danger_pairs: danger_pairs.clone(), digest, tracks: tracks.clone(), history, incidents,
+        region_stats, sinks, leadership, telemetry, influx, last_telemetry: None, session, session_tx, session_key,
+        routes: route_rx, lights: light_rx, changes: config_rx, flags: flag_rx, watched, estop,
+        storm: StormGuard::new(storm_key, storm_rate), presenter: Presenter::default(), risks: RiskBoard::default(),
+        summary: Summary::new(Duration::from_secs(summary_period_s)),
+        pacer: Pacer::new(Duration::from_millis(compute_period_ms), auto_period),
+        compute_period: Duration::from_millis(compute_period_ms), distance_algo, batching, batch_key, escalation_key,
+        geojson_key, geojson_matching, matrix_key, matrix_cap, matrix_matching, display_key, display_matching, risk_key,
+        risk_range, risk_matching, progress_key, progress_matching, tracking_key, tracking_matching, clusters_key,
+        clusters_matching, heatmap_key, heatmap_cell, heatmap_matching,
+    };
+    tasks.push(task::spawn(compute.run().instrument(info_span!("compute"))));
+
+    let ingest = Ingest {
+        z: z.clone(), mode, pull_period, reliability: sub_reliability.into(), skeys, cold_start, cold_start_max_age,
+        subscription_key, map: pmap, metrics, diag, dead_letters, dead_letter_space, notices,
+        limiter: Downsampler::new(max_rate), advisor: Advisor::default(), identities: IdentityGuard::default(),
+        plausibility, outlier_policy, profiles, meta, feeds, fleets, roi, shard, shard_key, site, handover_key, tracks,
+        danger_pairs, keyring, signature_policy, tamper_key, advisory_key, identity_key, max_speeds, max_jump_speed,
+        speed_tolerance, print_positions, flagged, flag_tx, session_rx, border_rx, sub_rx, vi: VehicleInfo::default(),
+    };
+    ingest.run().await;
+    shut_down(z, tasks, delayed, saved.map(|(_, file)| file), publisher, sink_tasks).await;
+}
+
+// Stops the tasks of the tracker, saving their state, once the pending alerts
+// are published and the ingestion is stopped.
+async fn shut_down(z: Arc<Session>, tasks: Vec<task::JoinHandle<()>>, delayed: Option<SharedQueue>, saved: Option<Arc<std::sync::Mutex<RuntimeStateFile>>>, publisher: task::JoinHandle<()>, sink_tasks: Vec<task::JoinHandle<()>>) {
+    info!("Shutting down, flushing pending alerts...");
+    for t in tasks {
+        t.abort();
+        let _ = t.await;
+    }
+    if let Some(queue) = delayed {
+        let mut queue = queue.lock().unwrap();
+        info!("Saving {} delayed samples", queue.len());
+        queue.save();
+    }
+    if let Some(file) = saved {
+        file.lock().unwrap().save();
+    }
+    let _ = publisher.await;
+    for t in sink_tasks {
+        let _ = t.await;
+    }
+    match Arc::try_unwrap(z) {
+        Ok(z) => {
+            if let Err(e) = z.close().res().await {
+                warn!("Unable to close zenoh session: {e}");
+            }
+        },
+        Err(_) => warn!("zenoh session still in use, not closing it")
+    }
+}
+
+// Where the alerts go, shared by the compute cycles and the evaluations of the
+// pairs of the flagged vehicles.
+struct Alerting {
+    meta: MetaCache,
+    fleets: Fleets,
+    shard: Option<Shard>,
+    outputs: Outputs,
+    severities: Severities,
+    alert_key_layout: AlertKeyLayout,
+    pkey: String,
+    cutin_key: String,
+    closing_key: String,
+    speed_key: String,
+    tether_key: String,
+    poi_key: String,
+    sector_key: String,
+    battery_key: String,
+    route_alert_key: String,
+    distance_matching: Matching,
+    cutin_matching: Matching,
+    closing_matching: Matching,
+    speed_matching: Matching,
+    route_matching: Matching,
+    tether_matching: Matching,
+    poi_matching: Matching,
+    sector_matching: Matching,
+    battery_matching: Matching,
+    batch_matching: Option<Matching>,
+    // sinks or the HTTP API are configured
+    delivered: bool,
+    foxglove_frames: Option<Frames>,
+}
+
+impl Alerting {
+    fn foxglove_clients(&self) -> bool {
+        self.foxglove_frames.as_ref().is_some_and(|f| f.receiver_count() > 0)
+    }
+
+    // Alerts always have somewhere to go when sinks or the HTTP API are
+    // configured, or when batches have subscribers or Foxglove clients.
+    fn wanted(&self, m: &Matching) -> bool {
+        self.delivered || m.any() || self.batch_matching.as_ref().is_some_and(|b| b.any()) || self.foxglove_clients()
+    }
+
+    fn with_meta(&self, mut out: Outgoing, ids: &[&str]) -> Outgoing {
+        self.meta.enrich(&mut out.value, ids);
+        out
+    }
+
+    // Alerts of the vehicles of a fleet are published on its keys.
+    fn fleet_key(&self, map: &HashMap<String, VehicleState>, rule: &str, ids: &[&str], key: &str) -> String {
+        fleets::common(map, ids).and_then(|f| self.fleets.alert_key(f, rule)).unwrap_or(key.into())
+    }
+
+    // The other instances of the shards publish the alerts of their vehicles.
+    fn owned(&self, a: &Alert, map: &HashMap<String, VehicleState>) -> bool {
+        self.shard.as_ref().is_none_or(|s| s.owns_alert(a, map))
+    }
+
+    // Alerts nobody subscribes to are neither serialized nor published.
+    fn outgoing(&self, alert: Alert, map: &HashMap<String, VehicleState>) -> Option<Outgoing> {
+        let rule = alert.name();
+        match alert {
+            Alert::Distance(da) if self.wanted(&self.distance_matching) => {
+                let danger = da.kind.is_danger();
+                let mut key = self.alert_key_layout.key(&self.fleet_key(map, rule, &[&da.ida, &da.idb], &self.pkey), &da.ida, &da.idb);
+                if let Some(suffix) = da.severity.as_deref().and_then(|s| self.severities.get(s)).and_then(|b| b.key.as_ref()) {
+                    key = format!("{key}/{suffix}");
+                }
+                Some(self.with_meta(Outgoing::json(key, &da).level(if danger { Level::Danger } else { Level::Alert }), &[&da.ida, &da.idb]))
+            },
+            Alert::CutIn(ca) if self.wanted(&self.cutin_matching) => Some(self.with_meta(Outgoing::json(self.fleet_key(map, rule, &[&ca.cutter, &ca.victim], &self.cutin_key), &ca).level(Level::Danger), &[&ca.cutter, &ca.victim])),
+            Alert::Closing(ca) if self.wanted(&self.closing_matching) => Some(self.with_meta(Outgoing::json(self.fleet_key(map, rule, &[&ca.ida, &ca.idb], &self.closing_key), &ca).level(Level::Danger), &[&ca.ida, &ca.idb])),
+            Alert::Speed(sa) if self.wanted(&self.speed_matching) => Some(self.with_meta(Outgoing::json(self.fleet_key(map, rule, &[&sa.id], &self.speed_key), &sa).level(Level::Alert), &[&sa.id])),
+            Alert::Tether(ta) if self.wanted(&self.tether_matching) => Some(self.with_meta(Outgoing::json(self.fleet_key(map, rule, &[&ta.id], &self.tether_key), &ta).level(Level::Alert), &[&ta.id])),
+            Alert::Poi(pa) if self.wanted(&self.poi_matching) => Some(self.with_meta(Outgoing::json(format!("{}/{}", self.fleet_key(map, rule, &[&pa.id], &self.poi_key), pa.poi), &pa).level(if pa.hazard { Level::Danger } else { Level::Alert }), &[&pa.id])),
+            Alert::Sector(sa) if self.wanted(&self.sector_matching) => Some(self.with_meta(Outgoing::json(format!("{}/{}", self.fleet_key(map, rule, &[&sa.id, &sa.other], &self.sector_key), sa.sector), &sa).level(if sa.danger { Level::Danger } else { Level::Alert }), &[&sa.id, &sa.other])),
+            Alert::Battery(ba) if self.wanted(&self.battery_matching) => Some(self.with_meta(Outgoing::json(self.fleet_key(map, rule, &[&ba.id], &self.battery_key), &ba).level(Level::Alert), &[&ba.id])),
+            Alert::Route(ra) if self.wanted(&self.route_matching) => Some(self.with_meta(Outgoing::json(self.fleet_key(map, rule, &[&ra.id], &self.route_alert_key), &ra).level(Level::Alert), &[&ra.id])),
+            _ => None,
+        }.map(|out| self.outputs.apply(rule, out))
+    }
+}
+
+// The compute loop: evaluates the rules on the fleet every period, and the
+// pairs of the flagged vehicles on each of their samples between the cycles.
+struct Compute {
+    alerting: Alerting,
+    rules: Rules,
+    map: VehicleMap,
+    alerts: Sender<Outgoing>,
+    z: Arc<Session>,
+    instance: Instance,
+    metrics: SharedMetrics,
+    features: SharedFeatures,
+    thresholds: SharedThresholds,
+    danger_pairs: SharedDangerPairs,
+    digest: SharedDigest,
+    tracks: Tracks,
+    history: SharedHistory,
+    incidents: Option<SharedIncidents>,
+    region_stats: Option<SharedRegionStats>,
+    sinks: Sinks,
+    leadership: Leadership,
+    telemetry: Option<influx::Telemetry>,
+    influx: Option<InfluxConfig>,
+    last_telemetry: Option<Instant>,
+    session: Option<DemoSession>,
+    session_tx: UnboundedSender<()>,
+    session_key: String,
+    routes: UnboundedReceiver<RouteUpdate>,
+    lights: UnboundedReceiver<LightStatus>,
+    changes: UnboundedReceiver<ConfigChange>,
+    flags: Receiver<String>,
+    watched: Vec<Matching>,
+    estop: Option<EmergencyStop>,
+    storm: StormGuard,
+    presenter: Presenter,
+    risks: RiskBoard,
+    summary: Summary,
+    pacer: Pacer,
+    compute_period: Duration,
+    distance_algo: DistanceAlgo,
+    batching: Batching,
+    batch_key: String,
+    escalation_key: String,
+    geojson_key: String,
+    geojson_matching: Option<Matching>,
+    matrix_key: String,
+    matrix_cap: Option<f64>,
+    matrix_matching: Option<Matching>,
+    display_key: String,
+    display_matching: Matching,
+    risk_key: String,
+    risk_range: f64,
+    risk_matching: Matching,
+    progress_key: String,
+    progress_matching: Matching,
+    tracking_key: String,
+    tracking_matching: Matching,
+    clusters_key: String,
+    clusters_matching: Option<Matching>,
+    heatmap_key: String,
+    heatmap_cell: f64,
+    heatmap_matching: Option<Matching>,
+}
+
+impl Compute {
+    async fn run(mut self) {
+        let (mut was_active, mut was_leading) = (true, self.leadership.leads());
+        loop {
+            let cycle_start = tokio::time::Instant::now();
+            // the displays and risks of the vehicles cleared are deleted by
+            // the next cycle
+            if self.session.as_ref().is_some_and(|s| s.is_over()) {
+                self.end_session().await;
+            }
+            // the cycle works on a copy of its own, the positions received
+            // meanwhile updating the map in place
+            let map = self.map.copy();
+            // a new leader publishes the displays and risks again, its own
+            // having been dropped on standby
+            if self.leadership.leads() != was_leading {
+                was_leading = !was_leading;
+                self.presenter = Presenter::default();
+                self.risks = RiskBoard::default();
+            }
+            self.apply_changes().await;
+            {
+                let mut m = self.metrics.lock().await;
+                m.vehicles = map.len();
+                m.last_cycle = Some(Instant::now());
+            }
+            self.export_telemetry(&map);
+            let active = self.estop.is_some() || self.watched.iter().any(|m| self.alerting.wanted(m));
+            if active != was_active {
+                info!("{}", if active { "alerts have subscribers, resuming computation" } else { "no subscribers to the alerts, suspending computation" });
+                was_active = active;
+                if !active {
+                    self.danger_pairs.lock().unwrap().set(&[], |_| None);
+                    self.digest.lock().unwrap().suspend();
+                }
+            }
+            if active {
+                self.cycle(&map).await;
+            }
+            self.summary.print_due(map.len(), Instant::now());
+            drop(map);
+            // cycles start every period, right after the last one when it overran
+            let work = cycle_start.elapsed();
+            self.pacer.cycle(work, Instant::now());
+            {
+                let mut m = self.metrics.lock().await;
+                m.compute_period_ms = self.pacer.period().as_millis() as u64;
+                m.compute_overruns = self.pacer.overruns;
+            }
+            // until the next cycle, the pairs of flagged vehicles are evaluated on every sample
+            self.follow_flagged(cycle_start + self.pacer.period(), active).await;
+        }
+    }
+
+    // Clears the vehicles and the state kept about them, and starts the next
+    // session.
+    async fn end_session(&mut self) {
+        let vehicles = self.map.update(|map| std::mem::take(map).len());
+        let _ = self.session_tx.send(());
+        self.tracks.clear();
+        self.history.lock().unwrap().clear();
+        if let Some(i) = &self.incidents {
+            i.lock().unwrap().clear();
+        }
+        *self.digest.lock().unwrap() = Digest::new(clock::unix_ms());
+        self.rules.reset();
+        self.summary.reset();
+        if let Some(stats) = &self.region_stats {
+            stats.lock().unwrap().reset();
+        }
+        // stopped vehicles are released and a storm ends with the session
+        let mut ended: Vec<Outgoing> = self.estop.as_mut().map_or(vec![], |e| e.reset());
+        ended.extend(self.storm.reset());
+        for out in ended {
+            if keyexpr::new(&out.key).is_ok() {
+                let _ = self.alerts.send(out).await;
+            }
+        }
+        let Some(s) = &mut self.session else { return };
+        let reset = s.next(clock::unix_ms(), vehicles);
+        info!("SESSION: {} over, {vehicles} vehicles cleared, {} started", reset.previous, reset.session);
+        {
+            let mut m = self.metrics.lock().await;
+            m.session = Some(reset.session.clone());
+            m.alerts.clear();
+        }
+        let _ = self.alerts.send(Outgoing::json(&self.session_key, &reset)).await;
+    }
+
+    // Applies the routes, the light states and the configuration changes
+    // received since the last cycle.
+    async fn apply_changes(&mut self) {
+        while let Ok(u) = self.routes.try_recv() {
+            self.rules.set_route(u);
+        }
+        while let Ok(l) = self.lights.try_recv() {
+            self.rules.set_light(l);
+        }
+        while let Ok(c) = self.changes.try_recv() {
+            match c {
+                ConfigChange::Thresholds(t) => t.apply(&mut self.rules),
+                ConfigChange::Zones(zones) => self.rules.zones = zones,
+            }
+            self.features.lock().await.update(&self.rules);
+            *self.thresholds.lock().unwrap() = Thresholds::of(&self.rules);
+        }
+    }
+
+    fn export_telemetry(&mut self, map: &HashMap<String, VehicleState>) {
+        let (Some(t), Some(c)) = (&self.telemetry, &self.influx) else { return };
+        let period = t.period.unwrap_or(self.compute_period);
+        if self.last_telemetry.is_none_or(|l| l.elapsed() >= period) {
+            self.last_telemetry = Some(Instant::now());
+            let lines = influx::lines(map, c, self.distance_algo, self.rules.max_distance);
+            if !lines.is_empty() {
+                t.send(lines);
+            }
+        }
+    }
+
+    // Evaluates the rules on the fleet and publishes what they raise, along
+    // with the views of the fleet which have subscribers.
+    async fn cycle(&mut self, map: &HashMap<String, VehicleState>) {
+        let (now, started) = (clock::now(), Instant::now());
+        let mut evaluated = debug_span!("cycle", vehicles = map.len()).in_scope(|| self.rules.evaluate(map, now));
+        evaluated.retain(|a| self.alerting.owned(a, map));
+        let mut m = self.metrics.lock().await;
+        m.latency.compute.record(started.elapsed());
+        self.summary.cycle(&evaluated, started.elapsed());
+        self.danger_pairs.lock().unwrap().set(&evaluated, |id| map.get(id).map(|v| v.info.position));
+        self.digest.lock().unwrap().cycle(&evaluated, now);
+        if let Some(s) = &self.region_stats {
+            s.lock().unwrap().cycle(&evaluated, map.len());
+        }
+        for a in &evaluated {
+            *m.alerts.entry(a.name().to_string()).or_default() += 1;
+        }
+        drop(m);
+        if self.geojson_matching.as_ref().is_some_and(|m| m.any()) {
+            let _ = self.alerts.send(Outgoing::json(&self.geojson_key, &geojson::feature_collection(map, &evaluated))).await;
+        }
+        if self.matrix_matching.as_ref().is_some_and(|m| m.any()) {
+            let m = matrix::compute(map, self.distance_algo, self.matrix_cap, clock::unix_ms());
+            let value = Value::from(cbor::encode(&self.instance.tag(serde_json::to_value(&m).unwrap()))).encoding(Encoding::from("application/cbor"));
+            let (z, key) = (self.z.clone(), self.matrix_key.clone());
+            // not queued with the JSON publications, nor awaited by the cycle
+            task::spawn(async move {
+                if let Err(e) = z.put(&key, value).congestion_control(CongestionControl::Drop).res().await {
+                    warn!("Unable to publish the distance matrix on {key}: {e}");
+                }
+            });
+        }
+        // displays are published again in full to the subscribers which
+        // come back
+        let (displays, gone) = if self.display_matching.any() { self.presenter.update(map, &evaluated) } else {
+            self.presenter = Presenter::default();
+            (vec![], vec![])
+        };
+        let frame = self.alerting.foxglove_clients().then(|| foxglove::frame(map, &evaluated, clock::unix_ms()));
+        let mut escalations = Vec::new();
+        if let Some(i) = &self.incidents {
+            i.lock().unwrap().update(&evaluated, now, clock::unix_ms(), |e| escalations.push(self.alerting.with_meta(Outgoing::json(&self.escalation_key, &e).level(Level::Danger), &[e.ida, e.idb])));
+        }
+        for out in self.estop.as_mut().map_or(vec![], |e| e.update(&evaluated)) {
+            if keyexpr::new(&out.key).is_ok() {
+                let _ = self.alerts.send(out).await;
+            }
+        }
+        let pending = evaluated.into_iter().filter_map(|a| self.alerting.outgoing(a, map)).collect();
+        // escalations are never digested
+        let mut filtered = self.storm.filter(pending, now);
+        filtered.extend(escalations);
+        if let (Some(f), Some(frame)) = (&self.alerting.foxglove_frames, frame) {
+            let _ = f.send(Arc::new(frame.with_alerts(&filtered)));
+        }
+        self.deliver(filtered).await;
+        if self.progress_matching.any() {
+            for p in self.rules.progress() {
+                let key = format!("{}/{}", self.progress_key, p.id);
+                if keyexpr::new(&key).is_ok() {
+                    let _ = self.alerts.send(Outgoing::json(key, p)).await;
+                }
+            }
+        }
+        for d in displays {
+            let key = format!("{}/{}", self.display_key, d.id);
+            if keyexpr::new(&key).is_ok() {
+                let _ = self.alerts.send(Outgoing::json(key, &d)).await;
+            }
+        }
+        for id in gone {
+            let key = format!("{}/{id}", self.display_key);
+            if keyexpr::new(&key).is_ok() {
+                let _ = self.z.delete(&key).res().await;
+            }
+        }
+        // like displays, risks are published again in full to the
+        // subscribers which come back
+        let (changed, gone) = if self.risk_matching.any() { self.risks.update(risk::compute(map, self.distance_algo, self.rules.min_distance, self.risk_range)) } else {
+            self.risks = RiskBoard::default();
+            (vec![], vec![])
+        };
+        for r in changed {
+            let key = format!("{}/{}", self.risk_key, r.id);
+            if keyexpr::new(&key).is_ok() {
+                let _ = self.alerts.send(Outgoing::json(key, &r)).await;
+            }
+        }
+        for id in gone {
+            let key = format!("{}/{id}", self.risk_key);
+            if keyexpr::new(&key).is_ok() {
+                let _ = self.z.delete(&key).res().await;
+            }
+        }
+        if self.clusters_matching.as_ref().is_some_and(|m| m.any()) {
+            let _ = self.alerts.send(Outgoing::json(&self.clusters_key, &ClusterList { clusters: self.rules.clusters() })).await;
+        }
+        if self.heatmap_matching.as_ref().is_some_and(|m| m.any()) {
+            let _ = self.alerts.send(Outgoing::json(&self.heatmap_key, &heatmap::compute(map, self.heatmap_cell, clock::unix_ms()))).await;
+        }
+    }
+
+    // Evaluates the pairs of the flagged vehicles on each of their samples,
+    // and publishes their tracking, until `next_cycle`.
+    async fn follow_flagged(&mut self, next_cycle: tokio::time::Instant, active: bool) {
+        loop {
+            let id = tokio::select! {
+                _ = tokio::time::sleep_until(next_cycle) => break,
+                Some(id) = self.flags.recv() => id,
+            };
+            let now = clock::now();
+            let key = format!("{}/{id}", self.tracking_key);
+            // under the lock, which is released before the publications
+            let (pending, tracking) = self.map.read(|map| {
+                let pending: Vec<Outgoing> = if active { self.rules.evaluate_pairs_of(map, &id, now).into_iter().filter(|a| self.alerting.owned(a, map)).filter_map(|a| self.alerting.outgoing(a, map)).collect() } else { vec![] };
+                let tracking = (self.tracking_matching.any() && keyexpr::new(&key).is_ok())
+                    .then(|| flagged::tracking(map, &id, self.distance_algo).map(|t| self.alerting.with_meta(Outgoing::json(key, &t), &[&id])))
+                    .flatten();
+                (pending, tracking)
+            });
+            let filtered = self.storm.filter(pending, now);
+            self.deliver(filtered).await;
+            if let Some(t) = tracking {
+                let _ = self.alerts.send(t).await;
+            }
+        }
+    }
+
+    // Hands the alerts to the sinks (on the leader), to the history of the
+    // APIs and, batched, to the publisher.
+    async fn deliver(&self, filtered: Vec<Outgoing>) {
+        if self.leadership.leads() {
+            for out in &filtered {
+                self.sinks.send(out);
+            }
+        }
+        http::record(&self.history, &filtered);
+        for out in publish::batch(filtered, &self.batch_key, self.batching) {
+            let _ = self.alerts.send(out).await;
+        }
+    }
+}
+
+// The ingestion loop: decodes, checks and applies the positions received to
+// the map, until interrupted.
+struct Ingest {
+    z: Arc<Session>,
+    mode: IngestMode,
+    pull_period: Duration,
+    reliability: Reliability,
+    skeys: Vec<String>,
+    cold_start: Option<String>,
+    cold_start_max_age: Duration,
+    subscription_key: String,
+    map: VehicleMap,
+    metrics: SharedMetrics,
+    diag: Diagnostics,
+    dead_letters: DeadLetters,
+    dead_letter_space: OwnedKeyExpr,
+    notices: Sender<Outgoing>,
+    limiter: Downsampler,
+    advisor: Advisor,
+    identities: IdentityGuard,
+    plausibility: Option<Plausibility>,
+    outlier_policy: OutlierPolicy,
+    profiles: Profiles,
+    meta: MetaCache,
+    feeds: Feeds,
+    fleets: Fleets,
+    roi: Option<Roi>,
+    shard: Option<Shard>,
+    shard_key: String,
+    site: Site,
+    handover_key: String,
+    tracks: Tracks,
+    danger_pairs: SharedDangerPairs,
+    keyring: Option<Keyring>,
+    signature_policy: SignaturePolicy,
+    tamper_key: String,
+    advisory_key: String,
+    identity_key: String,
+    max_speeds: MaxSpeeds,
+    max_jump_speed: f64,
+    speed_tolerance: f64,
+    print_positions: bool,
+    flagged: Flagged,
+    flag_tx: Sender<String>,
+    session_rx: UnboundedReceiver<()>,
+    border_rx: UnboundedReceiver<VehicleInfo>,
+    sub_rx: UnboundedReceiver<SubCommand>,
+    // decoded in place, so that the hot path does not allocate
+    vi: VehicleInfo,
+}
+
+impl Ingest {
+    async fn run(mut self) {
+        let shutdown = zdemo_common::shutdown_signal();
+        tokio::pin!(shutdown);
+        let mut flush = tokio::time::interval(self.limiter.interval().unwrap_or(Duration::from_secs(1)));
+        let (mut subs, mut srx) = self.declare(self.skeys.clone()).await;
+        self.metrics.lock().await.subscriptions = if self.mode == IngestMode::StdinCsv { vec![] } else { self.skeys.clone() };
+        // the stored positions go through the pipeline before those received since
+        // the subscriptions were declared
+        let mut stored: VecDeque<Sample> = match &self.cold_start {
+            Some(key) => coldstart::fetch(&self.z, key, self.cold_start_max_age).await.into(),
+            None => VecDeque::new(),
+        };
+        loop {
+            let sample = tokio::select! {
+                _ = &mut shutdown => break,
+                _ = flush.tick() => {
+                    self.flush().await;
+                    continue;
+                },
+                Some(()) = self.session_rx.recv() => {
+                    self.reset();
+                    continue;
+                },
+                vi = self.border_rx.recv(), if self.shard.is_some() => {
+                    if let Some(vi) = vi {
+                        self.apply_border(vi).await;
+                    }
+                    continue;
+                },
+                cmd = self.sub_rx.recv() => {
+                    if let Some(cmd) = cmd {
+                        self.change_subscriptions(subs.as_mut(), cmd).await;
+                    }
+                    continue;
+                },
+                s = async { stored.pop_front() }, if !stored.is_empty() => s,
+                s = srx.recv(), if stored.is_empty() => s
+            };
+            let (received, received_at) = (Instant::now(), SystemTime::now());
+            let Some(sample) = sample else {
+                warn!("subscriptions lost, declaring them again");
+                let keys = subs.as_ref().map_or_else(|| self.skeys.clone(), |s| s.keys());
+                drop(subs);
+                (subs, srx) = self.declare(keys).await;
+                continue;
+            };
+            self.apply(&sample, received, received_at).await;
+        }
+    }
+
+    // Only the subscriptions of the push mode can be changed at runtime.
+    async fn declare(&self, keys: Vec<String>) -> (Option<Subscriptions>, UnboundedReceiver<Sample>) {
+        match self.mode {
+            IngestMode::Push => {
+                let (subs, rx) = Subscriptions::declare(&self.z, &keys, self.reliability).await;
+                (Some(subs), rx)
+            },
+            IngestMode::Pull => (None, pull::spawn(self.z.clone(), keys, self.pull_period)),
+            IngestMode::StdinCsv => (None, stdin::spawn()),
+        }
+    }
+
+    // Applies the positions held back by the downsampler which are due.
+    async fn flush(&mut self) {
+        let (mut left, mut borders) = (vec![], vec![]);
+        {
+            let mut m = self.metrics.lock().await;
+            self.map.update(|map| self.limiter.flush(clock::now(), &mut m.ingest, |vi| {
+                self.danger_pairs.lock().unwrap().moved(&vi.id, vi.position);
+                if update(map, &self.tracks, vi, self.speed_tolerance, self.max_speeds.get(vi.kind.as_str()), self.print_positions).is_some_and(|before| self.site.left(&before, &vi.position)) {
+                    left.push(self.site.handover(vi, &self.meta, &self.tracks));
+                }
+                borders.extend(border_of(&self.shard, vi));
+            }));
+        }
+        for h in left {
+            hand_over(&self.notices, &self.handover_key, h).await;
+        }
+        for b in borders {
+            share_border(&self.notices, &self.shard_key, b).await;
+        }
+    }
+
+    // The state of the ingestion is cleared with that of the compute loop.
+    fn reset(&mut self) {
+        self.limiter.reset();
+        self.advisor.reset();
+        self.identities.reset();
+        if let Some(p) = &mut self.plausibility {
+            p.reset();
+        }
+    }
+
+    // A vehicle near the cells of the shard, sent by the instance owning it.
+    async fn apply_border(&mut self, vi: VehicleInfo) {
+        self.metrics.lock().await.ingest.from_border += 1;
+        self.danger_pairs.lock().unwrap().moved(&vi.id, vi.position);
+        self.map.update(|map| update(map, &self.tracks, &vi, self.speed_tolerance, self.max_speeds.get(vi.kind.as_str()), self.print_positions));
+    }
+
+    async fn change_subscriptions(&self, subs: Option<&mut Subscriptions>, cmd: SubCommand) {
+        let Some(subs) = subs else {
+            warn!("ignoring {cmd:?}, subscriptions can only be changed in push mode");
+            self.diag.report(Stage::Subscription, &self.subscription_key, "subscriptions can only be changed in push mode", None);
+            return;
+        };
+        let res = match &cmd {
+            // rejected positions would be received again, and republished forever
+            SubCommand::Add(k) if keyexpr::new(k).unwrap().intersects(&self.dead_letter_space) => Err(format!("{k} intersects the dead letter keys {}", self.dead_letter_space)),
+            SubCommand::Add(k) => subs.add(k).await.map(|added| if added { "subscribed to" } else { "already subscribed to" }),
+            SubCommand::Remove(k) => Ok(if subs.remove(k) { "unsubscribed from" } else { "was not subscribed to" }),
+        };
+        match res {
+            Ok(what) => {
+                let (SubCommand::Add(k) | SubCommand::Remove(k)) = &cmd;
+                info!("SUBSCRIPTION: {what} {k}");
+                self.metrics.lock().await.subscriptions = subs.keys();
+            },
+            Err(e) => {
+                warn!("Unable to change the subscriptions: {e}");
+                self.diag.report(Stage::Subscription, &self.subscription_key, e, None);
+            },
+        }
+    }
+
+    // Decodes the position of `sample`, checks it and applies it to the map.
+    async fn apply(&mut self, sample: &Sample, received: Instant, received_at: SystemTime) {
+        let payload = match zdemo_common::payload(sample) {
+            Ok(p) => p,
+            Err(e) => {
+                warn!("Unable to Deserialize:\n ${e}");
+                self.metrics.lock().await.error(format!("undecodable payload on {}: {e}", sample.key_expr));
+                self.reject(Stage::Decode, sample, &e, Some(&sample.payload.contiguous())).await;
+                return;
+            }
+        };
+        let payload = match self.feeds.of_key(&sample.key_expr).map(|f| f.normalize(&payload)) {
+            Some(Ok(normalized)) => Cow::Owned(normalized),
+            Some(Err(e)) => {
+                warn!("Unable to map the fields of the position on {}: {e}", sample.key_expr);
+                self.metrics.lock().await.error(format!("unmapped position on {}: {e}", sample.key_expr));
+                self.reject(Stage::Position, sample, &e, Some(&payload)).await;
+                return;
+            },
+            None => payload,
+        };
+        let raw = match RawVehicle::parse(&payload) {
+            Ok(raw) => raw,
+            Err(e) => {
+                warn!("Unable to Deserialize:\n ${e}");
+                self.metrics.lock().await.error(format!("invalid position on {}: {e}", sample.key_expr));
+                self.reject(Stage::Position, sample, &e, Some(&payload)).await;
+                return;
+            }
+        };
+        if let Err(e) = raw.validate() {
+            warn!("Rejecting position of {} on {}: {e}", raw.id, sample.key_expr);
+            self.reject(Stage::Validation, sample, &e, Some(&payload)).await;
+            return;
+        }
+        if let Some(keyring) = &self.keyring {
+            let signature = signing::signature(sample);
+            if let Err(reason) = keyring.verify(&raw.id, &payload, signature.as_deref()) {
+                let rejected = self.signature_policy == SignaturePolicy::Reject;
+                warn!("TAMPER: {reason:?} position of {} on {}{}", raw.id, sample.key_expr, if rejected { ", rejected" } else { "" });
+                let alert = TamperAlert { id: &raw.id, key: sample.key_expr.as_str(), reason, rejected };
+                let _ = self.notices.send(Outgoing::json(self.tamper_key.as_str(), &alert).level(Level::Danger)).await;
+                if rejected {
+                    self.reject(Stage::Signature, sample, format!("{reason:?}"), None).await;
+                    return;
+                }
+            }
+        }
+        if let Some(ts) = raw.ts {
+            clock::observe(ts);
+        }
+        let now = clock::now();
+        self.advise(&raw, sample, &payload, now).await;
+        let (kind, color) = ingest::attached(sample);
+        let (profile, invalid) = self.meta.with_profile(&raw.id, |m| self.profiles.resolve(&raw, (kind.as_deref(), color.as_deref()), m));
+        raw.write_to(&mut self.vi, profile);
+        let invalid = [invalid, raw.write_telemetry(&mut self.vi.telemetry)].into_iter().flatten().reduce(|a, b| format!("{a}, {b}"));
+        if let Some(e) = invalid {
+            self.metrics.lock().await.ingest.invalid_attributes += 1;
+            self.diag.report(Stage::Attribute, sample.key_expr.as_str(), e, Some(&payload));
+        }
+        let fleet = self.fleets.of_key(&sample.key_expr).map(|f| f.name.as_str());
+        if self.vi.fleet.as_deref() != fleet {
+            self.vi.fleet = fleet.map(String::from);
+        }
+        let vi = &self.vi;
+        if let Some(p) = &mut self.plausibility {
+            if let Err(o) = p.check(&vi.id, vi.kind.as_str(), vi.position, now, self.outlier_policy == OutlierPolicy::Flag) {
+                self.metrics.lock().await.ingest.outliers += 1;
+                if self.outlier_policy == OutlierPolicy::Drop {
+                    info!("OUTLIER: {} {o}, dropped", vi.id);
+                    self.reject(Stage::Outlier, sample, o, Some(&payload)).await;
+                    return;
+                }
+                info!("OUTLIER: {} {o}", vi.id);
+                self.diag.report(Stage::Outlier, sample.key_expr.as_str(), o.to_string(), Some(&payload));
+            }
+        }
+        if self.roi.as_ref().is_some_and(|r| !r.contains(&vi.position)) {
+            let mut m = self.metrics.lock().await;
+            m.ingest.outside_roi += 1;
+            if self.map.update(|map| map.remove(&vi.id)).is_some() {
+                info!("ROI: {} left the region of interest", vi.id);
+                self.limiter.forget(&vi.id);
+            }
+            return;
+        }
+        if let Some(s) = self.shard.as_ref().filter(|s| !s.owns(&vi.position)) {
+            self.metrics.lock().await.ingest.outside_shard += 1;
+            // the instances owning the vehicles near the cells send them
+            if !s.is_near(&vi.position) && self.map.update(|map| map.remove(&vi.id)).is_some() {
+                info!("SHARD: {} left the cells of {}", vi.id, s.me);
+                self.limiter.forget(&vi.id);
+            }
+            return;
+        }
+        let source = identity::source(sample, raw.instance.as_deref());
+        if let Some(conflict) = self.identities.check(&vi.id, source, sample.key_expr.as_str(), vi.position, now, self.max_speeds.get(vi.kind.as_str()).unwrap_or(self.max_jump_speed)) {
+            info!("IDENTITY: {} {:?} on {}, from {}", vi.id, conflict.reason, sample.key_expr, conflict.sources.join(", "));
+            let _ = self.notices.send(Outgoing::json(self.identity_key.as_str(), &conflict)).await;
+        }
+        let decoded = Instant::now();
+        let is_flagged = self.flagged.contains(&vi.id);
+        let (mut left, mut border) = (None, None);
+        {
+            let mut m = self.metrics.lock().await;
+            let accepted = if is_flagged {
+                self.limiter.pass(&vi.id, now, &mut m.ingest);
+                true
+            } else {
+                self.limiter.offer(vi, now, &mut m.ingest)
+            };
+            if accepted {
+                self.danger_pairs.lock().unwrap().moved(&vi.id, vi.position);
+                if self.map.update(|map| update(map, &self.tracks, vi, self.speed_tolerance, self.max_speeds.get(vi.kind.as_str()), self.print_positions)).is_some_and(|before| self.site.left(&before, &vi.position)) {
+                    left = Some(self.site.handover(vi, &self.meta, &self.tracks));
+                }
+                border = border_of(&self.shard, vi);
+            }
+            m.latency.ingest.record(decoded - received);
+            m.latency.map_update.record(decoded.elapsed());
+            let source = vi.ts.map(|ms| UNIX_EPOCH + Duration::from_millis(ms)).or_else(|| sample.timestamp.map(|t| t.get_time().to_system_time()));
+            if let Some(source) = source {
+                m.latency.record_end_to_end(source, received_at);
+            }
+        }
+        if let Some(h) = left {
+            hand_over(&self.notices, &self.handover_key, h).await;
+        }
+        if let Some(b) = border {
+            share_border(&self.notices, &self.shard_key, b).await;
+        }
+        if is_flagged {
+            let _ = self.flag_tx.try_send(vi.id.clone());
+        }
+    }
+
+    // Tells the publishers of positions in deprecated formats how to migrate,
+    // with an example of their position in the current one.
+    async fn advise(&mut self, raw: &RawVehicle<'_>, sample: &Sample, payload: &[u8], now: Instant) {
+        let Some(d) = raw.deprecation().filter(|d| self.advisor.due(&raw.id, *d, now)) else { return };
+        let key = format!("{}/{}", self.advisory_key, raw.id);
+        if keyexpr::new(&key).is_ok() {
+            info!("ADVISORY: {} publishes {} on {}", raw.id, d.format, sample.key_expr);
+            let mut example: serde_json::Value = serde_json::from_slice(payload).unwrap();
+            advisory::upgrade(&mut example);
+            let advisory = Advisory { publisher: &raw.id, key: sample.key_expr.as_str(), deprecated: d.format, migration: d.migration, example: &example };
+            let _ = self.notices.send(Outgoing::json(key, &advisory)).await;
+        }
+    }
+
+    // Counts the rejected position, describes it on the diagnostics key and
+    // republishes it on the dead letter key.
+    async fn reject(&self, stage: Stage, sample: &Sample, error: impl std::fmt::Display, payload: Option<&[u8]>) {
+        self.metrics.lock().await.ingest.count_rejected(stage.as_str());
+        let error = error.to_string();
+        self.diag.report(stage, sample.key_expr.as_str(), &error, payload);
+        self.dead_letters.republish(stage, sample, &error).await;
+    }
+}
+
+// Applies the position, returning the previous one.
+fn update(map: &mut HashMap<String, VehicleState>, tracks: &Tracks, vi: &VehicleInfo, speed_tolerance: f64, max_speed: Option<f64>, print: bool) -> Option<Position> {
+    if print {
+        info!("Received: {:?}", vi);
+    }
+    tracks.record(vi);
+    let now = clock::now();
+    match map.get_mut(&vi.id) {
+        Some(state) => {
+            let before = state.info.position;
+            state.update(vi, now, speed_tolerance, max_speed);
+            Some(before)
+        },
+        None => {
+            map.insert(vi.id.clone(), VehicleState::new(vi.clone(), now));
+            None
+        }
+    }
+}
+
+// Publishes the handover of a vehicle leaving the site on <prefix>/<site>/<id>.
+async fn hand_over(notices: &Sender<Outgoing>, prefix: &str, h: Handover) {
+    let key = format!("{prefix}/{}/{}", h.from, h.vehicle.id);
+    if keyexpr::new(&key).is_err() {
+        return;
+    }
+    info!("HANDOVER: {} left {}", h.vehicle.id, h.from);
+    let _ = notices.send(Outgoing::json(key, &h)).await;
+}
+
+// What the neighbors of the shard need of a vehicle near their cells.
+fn border_of(shard: &Option<Shard>, vi: &VehicleInfo) -> Option<Border> {
+    shard.as_ref().filter(|s| s.is_border(&vi.position)).map(|s| Border { from: s.me.clone(), vehicle: vi.clone() })
+}
+
+async fn share_border(notices: &Sender<Outgoing>, shard_key: &str, b: Border) {
+    let key = format!("{shard_key}/border/{}/{}", b.from, b.vehicle.id);
+    if keyexpr::new(&key).is_ok() {
+        let _ = notices.send(Outgoing::json(key, &b)).await;
+    }
+}
+
+async fn follow_lights(z: Arc<Session>, prefix: String, tx: UnboundedSender<LightStatus>, diag: Diagnostics) {
+    let keys = [format!("{prefix}/*")];
+    loop {
+        let (subs, mut rx) = zdemo_common::subscribe(&z, &keys).await;
+        while let Some(sample) = rx.recv().await {
+            match zdemo_common::decode_json::<LightStatus>(&sample) {
+                Ok(l) => {
+                    let _ = tx.send(l);
+                },
+                Err(e) => {
+                    warn!("Unable to parse traffic light state: {e}");
+                    diag.report(Stage::Light, sample.key_expr.as_str(), e, Some(&sample.payload.contiguous()));
+                },
+            }
+        }
+        warn!("traffic lights subscription lost, declaring it again");
+        drop(subs);
+    }
+}
+
+async fn follow_flags(z: Arc<Session>, prefix: String, flagged: Flagged, diag: Diagnostics) {
+    let keys = [format!("{prefix}/*")];
+    loop {
+        let (subs, mut rx) = zdemo_common::subscribe(&z, &keys).await;
+        while let Some(sample) = rx.recv().await {
+            match flagged.apply(&prefix, sample.key_expr.as_str(), sample.kind == SampleKind::Delete) {
+                Ok((id, true)) => info!("FLAG: {id} flagged for high frequency tracking"),
+                Ok((id, false)) => info!("FLAG: {id} unflagged"),
+                Err(e) => {
+                    warn!("Unable to flag vehicle: {e}");
+                    diag.report(Stage::Flag, sample.key_expr.as_str(), e, None);
+                },
+            }
+        }
+        warn!("flag subscription lost, declaring it again");
+        drop(subs);
+    }
+}
+
+async fn follow_subscriptions(z: Arc<Session>, key: String, tx: UnboundedSender<SubCommand>, diag: Diagnostics) {
+    let keys = [key];
+    loop {
+        let (subs, mut rx) = zdemo_common::subscribe(&z, &keys).await;
+        while let Some(sample) = rx.recv().await {
+            match zdemo_common::payload(&sample).and_then(|payload| subscriptions::parse(&payload)) {
+                Ok(cmd) => { let _ = tx.send(cmd); },
+                Err(e) => {
+                    warn!("Unable to parse subscription command: {e}");
+                    diag.report(Stage::Subscription, sample.key_expr.as_str(), e, Some(&sample.payload.contiguous()));
+                },
+            }
+        }
+        warn!("subscription control lost, declaring it again");
+        drop(subs);
+    }
+}
+
+async fn follow_routes(z: Arc<Session>, prefix: String, tx: UnboundedSender<RouteUpdate>, diag: Diagnostics) {
+    let keys = [format!("{prefix}/*")];
+    loop {
+        let (subs, mut rx) = zdemo_common::subscribe(&z, &keys).await;
+        while let Some(sample) = rx.recv().await {
+            let update = zdemo_common::payload(&sample)
+                .and_then(|payload| routes::parse_update(&prefix, sample.key_expr.as_str(), sample.kind == SampleKind::Delete, &payload));
+            match update {
+                Ok(u) => {
+                    info!("ROUTE: {} {}", u.id, if u.route.is_some() { "assigned" } else { "removed" });
+                    let _ = tx.send(u);
+                },
+                Err(e) => {
+                    warn!("Unable to parse route: {e}");
+                    diag.report(Stage::Route, sample.key_expr.as_str(), e, Some(&sample.payload.contiguous()));
+                },
+            }
+        }
+        warn!("route subscription lost, declaring it again");
+        drop(subs);
+    }
+}
+
+async fn query_tracker(what: QueryTarget, params: Option<&str>, s: &Settings) {
+    let key = match what {
+        QueryTarget::State => s.state_key.clone(),
+        QueryTarget::Nearest => s.nearest_key.clone(),
+        QueryTarget::Track => format!("{}/*", s.track_key),
+        QueryTarget::Metrics => s.metrics_key.clone(),
+        QueryTarget::Status => s.status_key.clone(),
+        QueryTarget::Digest => s.digest_key.clone(),
+        QueryTarget::Telemetry => s.telemetry_key.clone(),
+        QueryTarget::Recent => s.recent_key.clone(),
+        QueryTarget::Meta => format!("{}/**", s.meta_key),
+        QueryTarget::Config => format!("{}/config/*", s.admin_key),
+        QueryTarget::Internals => format!("{}/*/*", s.introspection_key),
+    };
+    let selector = match params {
+        Some(p) => format!("{key}?{p}"),
+        None => key,
+    };
+    let z = zdemo_common::open(s.config.clone()).await;
+    // chunked replies share the key of the queryable
+    let replies = z.get(&selector).consolidation(ConsolidationMode::None).res().await.unwrap_or_else(|e| panic!("Unable to query {selector}: {e}"));
+    let mut n = 0;
+    while let Ok(reply) = replies.recv_async().await {
+        match reply.sample {
+            Ok(sample) => {
+                let payload = zdemo_common::payload(&sample).unwrap_or_else(|e| e.into_bytes().into());
+                match serde_json::from_slice::<serde_json::Value>(&payload) {
+                    Ok(v) => println!("{} => {}", sample.key_expr, serde_json::to_string_pretty(&v).unwrap()),
+                    Err(_) => println!("{} => {}", sample.key_expr, String::from_utf8_lossy(&payload)),
+                }
+                n += 1;
+            },
+            Err(e) => println!("ERROR: {}", String::from_utf8_lossy(&e.payload.contiguous())),
+        }
+    }
+    if n == 0 {
+        println!("No reply on {selector}, is a tracker running with the same keys?");
+    }
+}
+
+fn analyze_recording(recording: &str, alerts: Option<&str>, s: &Settings) {
+    let file = File::open(recording).unwrap_or_else(|e| panic!("Unable to open {recording}: {e}"));
+    let records: Vec<Record> = read_records(BufReader::new(file))
+        .filter_map(|r| r.map_err(|e| warn!("Skipping invalid record at {e}")).ok())
+        .collect();
+    let keys = analyze::RecordedKeys::new(&s.skeys, &s.pkey, &s.cutin_key, &s.speed_key).unwrap()
+        .with_closing(&s.closing_key)
+        .with_routes(&s.route_key, &s.route_alert_key)
+        .with_tethers(&s.tether_key)
+        .with_pois(&s.poi_key)
+        .with_sectors(&s.sector_key)
+        .with_lights(&s.lights_key);
+    let mut rules = Rules::new(s.min_distance, s.max_distance, s.distance_algo, s.cutin_range, s.cutin_bearing_rate);
+    rules.closing_speed = s.closing_speed;
+    rules.closing_floor = s.closing_floor;
+    rules.pairing = s.pairing.clone();
+    rules.severities = s.severities.clone();
+    rules.zones = s.speed_zones.clone();
+    rules.tethers = s.tethers.clone();
+    rules.pois = s.pois.clone();
+    rules.sectors = s.sectors.clone();
+    rules.battery = s.battery.clone();
+    rules.tethered_separation = s.tethered_separation;
+    rules.route_corridor = s.route_corridor;
+    rules.checkpoint_radius = s.checkpoint_radius;
+    rules.light_radius = s.light_radius;
+    rules.red_min_distance = s.red_min_distance;
+    rules.max_speeds = s.max_speeds.clone();
+    rules.headways = s.headways.clone();
+    rules.fleets = s.fleets.clone();
+    rules.print = PrintOnly::None;
+    let period = Duration::from_millis(s.compute_period_ms);
+    let report = analyze::analyze(&records, &keys, &mut rules, period, s.speed_tolerance, alerts.is_some());
+    if let Some(path) = alerts {
+        let lines: String = report.sequence.iter().map(|l| format!("{l}\n")).collect();
+        std::fs::write(path, lines).unwrap_or_else(|e| panic!("Unable to write {path}: {e}"));
+    }
+
+    println!("{} positions replayed in {} compute cycles of {} ms", report.positions, report.cycles, s.compute_period_ms);
+    println!("{:<10} {:<30} {:>8} {:>8}", "RULE", "VEHICLES", "NOW", "RECORDED");
+    let (mut both, mut new, mut gone) = (0, 0, 0);
+    for (id, (now, recorded)) in &report.alerts {
+        let mark = match (now, recorded) {
+            (0, _) => { gone += 1; "-" },
+            (_, 0) => { new += 1; "+" },
+            _ => { both += 1; " " },
+        };
+        let vehicles = format!("{} / {}", id.a, id.b);
+        println!("{mark}{:<9} {vehicles:<30} {now:>8} {recorded:>8}", id.rule);
+    }
+    println!("{both} alerts fire in both, {new} only with the current rules (+), {gone} only in the recording (-)");
+}
+
+// What `query` asks a running tracker for.
+#[derive(clap_derive::ValueEnum, Clone, Copy, Debug)]
+pub enum QueryTarget {
+    // fleet state, paginated with offset=&limit=&token=, in one reply per page with
+    // chunked=true
+    State,
+    // closest vehicles, with lat=&lng=&k=
+    Nearest,
+    // trajectories of the vehicles, with last=
+    Track,
+    Metrics,
+    Status,
+    // alert statistics of the pairs and vehicles
+    Digest,
+    // telemetry of the vehicles, per fleet
+    Telemetry,
+    // last alerts published, with n=&since=&id=&severity=
+    Recent,
+    Meta,
+    // configuration shared by the instances
+    Config,
+    // internals of the instances
+    Internals,
+}
+
+// The tracker options come first, or after `run` and the other subcommands
+// sharing them (the Zenoh config, compression and instance id).
+#[derive(clap_derive::Subcommand)]
+pub enum Command {
+    /// Run the tracker, which is also what happens without a subcommand.
+    Run,
+    /// Publish the states of simulated traffic lights (see tracker-lights) and run a YAML
+    /// scenario of vehicles (--scenario) with seeded noise (--seed), or write the scenario as a
+    /// recording instead (--out).
+    Simulate(SimulateArgs),
+    /// Republish a recording with its original pacing (see tracker-player).
+    Replay(ReplayArgs),
+    /// Publish the positions of "id,lat,lng,speed" lines read from stdin or a CSV file (see
+    /// position-publisher).
+    Publish(PublishArgs),
+    /// Record a key expression into a JSON lines file (see tracker-recorder).
+    Record(RecordArgs),
+    /// Export the positions and alerts, live or from a recording, to MCAP files for Foxglove
+    /// Studio.
+    Mcap(McapArgs),
+    /// Query a running tracker on the keys given by the tracker options, and print the replies.
+    Query {
+        what: QueryTarget,
+        /// Selector parameters, e.g. lat=45.07&lng=7.68&k=3 for nearest.
+        params: Option<String>,
+    },
+    /// Replay the positions of a recording through the current rules, offline,
+    /// and compare the alerts that would fire with the recorded ones.
+    Analyze {
+        /// JSON lines file produced by tracker-recorder.
+        recording: String,
+        /// Write the alerts fired to this file as JSON lines of {t, rule, alert}, sorted within
+        /// each compute cycle, byte-identical on every run of the same recording and options.
+        #[arg(long)]
+        alerts: Option<String>,
+    },
+    /// Generate ed25519 keys for the given vehicles, adding their private seeds to
+    /// the seeds file (for tracker-player --sign-keys) and their public keys to the keyring.
+    Keygen {
+        ids: Vec<String>,
+        #[arg(long, default_value = "seeds.json")]
+        seeds: String,
+        #[arg(long, default_value = "keyring.json")]
+        keyring: String,
+    },
+}
+
+#[derive(clap_derive::Parser)]
+
+pub struct AppArgs {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[arg(long, global = true)]
+    sub_key: Option<String>,
+    /// Subscribe to the positions (push) or get the latest ones every pull_period_ms (pull).
+    #[arg(long, value_enum, global = true)]
+    mode: Option<IngestMode>,
+    #[arg(long, global = true)]
+    pull_period_ms: Option<u64>,
+    #[arg(long, global = true)]
+    pub_key: Option<String>,
+    /// Publish alerts on pub_key (flat) or on pub_key/<ida>/<idb> (pair).
+    #[arg(long, value_enum, global = true)]
+    alert_key_layout: Option<AlertKeyLayout>,
+    /// JSON file with the output of the rules (distance, cutin, closing, speed, route, tether,
+    /// poi, sector), each {key: template with {field} placeholders, fields: published fields of
+    /// the alert}.
+    #[arg(long, global = true)]
+    outputs: Option<String>,
+    #[arg(long, global = true)]
+    state_key: Option<String>,
+    #[arg(long, global = true)]
+    nearest_key: Option<String>,
+    /// Why samples are ignored (undecodable, invalid, rejected) is published on diag_key.
+    #[arg(long, global = true)]
+    diag_key: Option<String>,
+    /// Rejected positions are republished as received on <dead_letter_key>/<original key>, with the
+    /// stage and error attached.
+    #[arg(long, global = true)]
+    dead_letter_key: Option<String>,
+    /// Trajectories are returned by queries on <track_key>/<id>?last=<n>.
+    #[arg(long, global = true)]
+    track_key: Option<String>,
+    /// Positions kept per vehicle for the trajectories (0 disables them).
+    #[arg(long, global = true)]
+    track_length: Option<usize>,
+    /// The trail of every vehicle which moved, a simplified polyline of its last positions, is
+    /// published on <trail_key>/<id> every trail_period_ms.
+    #[arg(long, global = true)]
+    trail_key: Option<String>,
+    /// Positions of the trails, at most track_length (0 disables them).
+    #[arg(long, global = true)]
+    trail_length: Option<usize>,
+    /// Largest distance (m) of the positions left out of a trail to the polyline.
+    #[arg(long, global = true)]
+    trail_tolerance: Option<f64>,
+    #[arg(long, global = true)]
+    trail_period_ms: Option<u64>,
+    /// Estimated positions of the vehicles, extrapolated from their last fix along their heading
+    /// and flagged as such, are published on <smooth_key>/<id> smooth_rate_hz times a second
+    /// for map animations (0 disables them). The rules only use the fixes.
+    #[arg(long, global = true)]
+    smooth_key: Option<String>,
+    #[arg(long, global = true)]
+    smooth_rate_hz: Option<f64>,
+    /// Tracker counters (dropped samples...) are returned by queries on metrics_key.
+    #[arg(long, global = true)]
+    metrics_key: Option<String>,
+    /// The metrics, with the latency of each pipeline stage, are also published on stats_key.
+    #[arg(long, global = true)]
+    stats_key: Option<String>,
+    /// Period of the stats publication, 0 disables it.
+    #[arg(long, global = true)]
+    stats_period_ms: Option<u64>,
+    /// Health, uptime, vehicles, loop latency, alert counts and last error, published on
+    /// status_key and returned by queries on it.
+    #[arg(long, global = true)]
+    status_key: Option<String>,
+    /// Period of the status publication, 0 only serves it to queries.
+    #[arg(long, global = true)]
+    status_period_ms: Option<u64>,
+    /// Alert counts, shortest distances and time in alert and danger of every pair and
+    /// vehicle since the start, published on digest_key and returned by queries on it.
+    #[arg(long, global = true)]
+    digest_key: Option<String>,
+    /// Period of the digest publication, 0 only serves it to queries.
+    #[arg(long, global = true)]
+    digest_period_ms: Option<u64>,
+    /// The last alerts published are returned by queries on recent_key, the last 50 or
+    /// ?n=<n>, with since=<ms since the UNIX epoch>, id= and severity= filters.
+    #[arg(long, global = true)]
+    recent_key: Option<String>,
+    /// Key expression (e.g. demo/tracker/alert/**) whose queries are answered with the last
+    /// alerts published on the keys they select, as a publication cache would, so that
+    /// late joining querying subscribers get them.
+    #[arg(long, global = true)]
+    alert_cache: Option<String>,
+    /// Global key space of a federation of regions, the region being the site: a summary of
+    /// the alerts of every period (counts and riskiest pairs) is published on
+    /// <federation_key>/region/<site>, for tracker-aggregator to merge.
+    #[arg(long, global = true)]
+    federation_key: Option<String>,
+    /// Period of the region summaries.
+    #[arg(long, global = true)]
+    federation_period_ms: Option<u64>,
+    /// Number of pairs, the riskiest, in the region summaries.
+    #[arg(long, global = true)]
+    federation_top: Option<usize>,
+    /// Maximum positions per second applied per vehicle, the latest one is kept (0 disables).
+    #[arg(long, global = true)]
+    max_rate: Option<f64>,
+    /// Vehicles register their metadata (owner, capabilities, icon, max_speed) on <meta_key>/<id>,
+    /// it is added to their alerts and returned by queries on <meta_key>/**.
+    #[arg(long, global = true)]
+    meta_key: Option<String>,
+    /// Only subscribe to the geohash cells covering lat_min,lng_min,lat_max,lng_max.
+    /// Positions are then expected on <sub_key prefix>/<geohash>/<id>.
+    #[arg(long, global = true)]
+    geohash_region: Option<geohash::BoundingBox>,
+    #[arg(long, global = true)]
+    geohash_precision: Option<usize>,
+    #[arg(long, global = true)]
+    min_distance: Option<f64>,
+    #[arg(long, global = true)]
+    max_distance: Option<f64>,
+    /// Distance model of the rules, the great circle distance (haversine) by default. Pairs a
+    /// model gives up on get the great circle distance.
+    #[arg(long, value_enum, global = true)]
+    distance_algo: Option<DistanceAlgo>,
+    /// Coordinates of the positions, {lat, lng} by default. Distances are Euclidean in a local
+    /// frame whatever the distance algorithm.
+    #[arg(long, value_enum, global = true)]
+    pub coordinates: Option<Coordinates>,
+    /// Clock of the rate limits, speeds, TTLs and alert timings, the wall clock by default. The
+    /// data clock follows the source timestamps (ts) of the positions, for simulations and
+    /// replays faster than real time (replay --source-time).
+    #[arg(long, value_enum, global = true)]
+    pub clock: Option<ClockSource>,
+    #[arg(long, global = true)]
+    compute_period_ms: Option<u64>,
+    /// Raise the compute period when cycles take longer than it, up to 8 times
+    /// compute_period_ms, and lower it back when the load goes down. Overruns are logged
+    /// and counted in the metrics either way.
+    #[arg(long, global = true)]
+    auto_period: bool,
+    /// Maximum difference (m/s) between reported and position-implied speed.
+    #[arg(long, global = true)]
+    speed_tolerance: Option<f64>,
+    #[arg(long, global = true)]
+    cutin_key: Option<String>,
+    /// Maximum distance (m) at which cut-in manoeuvres are detected.
+    #[arg(long, global = true)]
+    cutin_range: Option<f64>,
+    /// Minimum rate (deg/s) at which a vehicle must close on another's heading
+    /// line to be considered cutting in.
+    #[arg(long, global = true)]
+    cutin_bearing_rate: Option<f64>,
+    #[arg(long, global = true)]
+    closing_key: Option<String>,
+    /// Closing speed (m/s) between two vehicles above which an alert is raised whatever
+    /// their distance (0 disables).
+    #[arg(long, global = true)]
+    closing_speed: Option<f64>,
+    /// Distance (m) below which fast approaches are left to the distance rules.
+    #[arg(long, global = true)]
+    closing_floor: Option<f64>,
+    /// JSON file restricting the pairs of vehicles raising distance, cut-in and closing alerts,
+    /// {groups: {name: [selector]}, exclude: [{a, b}], only: [{a, b}]}.
+    #[arg(long, global = true)]
+    pairing: Option<String>,
+    /// JSON file with the ordered distance bands raising alerts, [{name, below | above, color, key}],
+    /// replacing the bands derived from the min and max distances.
+    #[arg(long, global = true)]
+    severities: Option<String>,
+    #[arg(long, global = true)]
+    speed_key: Option<String>,
+    /// JSON file with the speed limited zones, each {name, speed_limit (m/s), polygon: [[lat, lng], ...]}.
+    #[arg(long, global = true)]
+    speed_zones: Option<String>,
+    /// JSON file with the groups whose members must stay close, each {name, members: [ids],
+    /// leader (optional, the centroid of the members otherwise), radius (m)}.
+    #[arg(long, global = true)]
+    tethers: Option<String>,
+    #[arg(long, global = true)]
+    tether_key: Option<String>,
+    /// JSON file with static points of interest, each {id, kind (optional), position: [lat, lng],
+    /// radius (m), hazard (optional)}. Every vehicle within the radius of one raises a PoiAlert on
+    /// <poi_key>/<id>, hazards raising dangers.
+    #[arg(long, global = true)]
+    pois: Option<String>,
+    #[arg(long, global = true)]
+    poi_key: Option<String>,
+    /// JSON file with sectors around the heading of the vehicles, each {name, kinds (optional, all
+    /// by default), from (deg), to (deg), range (m), danger (optional)}, spanning clockwise from
+    /// `from` to `to` relative to the heading (0 ahead, 90 right, 180 behind). Every vehicle within
+    /// the range in the sector of another raises a SectorAlert on <sector_key>/<name>.
+    #[arg(long, global = true)]
+    sectors: Option<String>,
+    #[arg(long, global = true)]
+    sector_key: Option<String>,
+    /// Kinds of the vehicles carrying a battery, comma separated: those reporting a telemetry
+    /// battery charge below --low-battery raise a BatteryAlert on battery_key.
+    #[arg(long, global = true)]
+    battery_kinds: Option<String>,
+    /// Battery charge (%) below which vehicles of --battery-kinds raise alerts.
+    #[arg(long, global = true)]
+    low_battery: Option<f64>,
+    #[arg(long, global = true)]
+    battery_key: Option<String>,
+    /// The telemetry fields the vehicles report (battery, temperature, payload...) are
+    /// aggregated, min, average and max of all of them and of each fleet, published on
+    /// telemetry_key while it has subscribers and returned by queries on it.
+    #[arg(long, global = true)]
+    telemetry_key: Option<String>,
+    /// Period of the telemetry publication, 0 only serves it to queries.
+    #[arg(long, global = true)]
+    telemetry_period_ms: Option<u64>,
+    /// Planned routes are assigned on <route_key>/<vehicle id>, as a JSON array of [lat, lng]
+    /// or as {points, checkpoints: [{name, position: [lat, lng], due (s)}]}.
+    #[arg(long, global = true)]
+    route_key: Option<String>,
+    #[arg(long, global = true)]
+    route_alert_key: Option<String>,
+    /// Maximum distance (m) a vehicle may stray from its assigned route.
+    #[arg(long, global = true)]
+    route_corridor: Option<f64>,
+    /// Distance (m) at which a checkpoint is considered reached.
+    #[arg(long, global = true)]
+    checkpoint_radius: Option<f64>,
+    /// Progress along the checkpoints is published on <progress_key>/<vehicle id>.
+    #[arg(long, global = true)]
+    progress_key: Option<String>,
+    /// The icon, color, z-order and label frontends draw each vehicle with, derived from its kind
+    /// and alerts, are published on <display_key>/<vehicle id> when they change.
+    #[arg(long, global = true)]
+    display_key: Option<String>,
+    #[arg(long, global = true)]
+    clusters_key: Option<String>,
+    /// Maximum distance (m) between vehicles travelling together, 0 disables clustering.
+    #[arg(long, global = true)]
+    cluster_range: Option<f64>,
+    #[arg(long, global = true)]
+    heatmap_key: Option<String>,
+    /// Size (deg) of the cells of the vehicle density grid published on heatmap_key every
+    /// compute cycle, 0 disables it (the default), e.g. 0.001 for cells of about 100 m.
+    #[arg(long, global = true)]
+    heatmap_cell: Option<f64>,
+    /// Risk scores of the vehicles, summing the contributions of their neighbors by distance,
+    /// closing speed and kind, are published on <risk_key>/<id> when they change.
+    #[arg(long, global = true)]
+    risk_key: Option<String>,
+    /// Distance (m) within which neighbors contribute to the risk score of a vehicle, 100 by default.
+    #[arg(long, global = true)]
+    risk_range: Option<f64>,
+    /// Also publish the fleet and its pairs in danger as a GeoJSON FeatureCollection on
+    /// geojson_key, every compute cycle.
+    #[arg(long, global = true)]
+    geojson: bool,
+    #[arg(long, global = true)]
+    geojson_key: Option<String>,
+    /// Also publish the distances between all the vehicles as CBOR on matrix_key, every compute
+    /// cycle.
+    #[arg(long, global = true)]
+    matrix: bool,
+    #[arg(long, global = true)]
+    matrix_key: Option<String>,
+    /// Only publish the pairs of the matrix closer than this distance (m), as [i, j, distance].
+    #[arg(long, global = true)]
+    matrix_cap: Option<f64>,
+    /// Traffic light states ({name, position, state}) are read from <lights_key>/<name>.
+    #[arg(long, global = true)]
+    lights_key: Option<String>,
+    /// The positions, midpoint, distance and bearing of every danger pair are published on
+    /// <geometry_key>/<ida>~<idb>, ids in order, for AR/3D clients drawing the line between them.
+    #[arg(long, global = true)]
+    geometry_key: Option<String>,
+    /// Shortest period between two geometries of a pair, which are only published when the
+    /// pair moved.
+    #[arg(long, global = true)]
+    geometry_period_ms: Option<u64>,
+    /// Vehicles within this distance (m) of a red light are considered queuing at it.
+    #[arg(long, global = true)]
+    light_radius: Option<f64>,
+    /// Minimum distance (m) between two vehicles queuing at red lights.
+    #[arg(long, global = true)]
+    red_min_distance: Option<f64>,
+    /// Pairs of vehicles which both moved less than this (m) since the last computation are not recomputed.
+    #[arg(long, global = true)]
+    move_epsilon: Option<f64>,
+    /// Pairs within the minimum distance raise a danger only when they are closing, an alert otherwise.
+    #[arg(long, global = true)]
+    danger_only_closing: bool,
+    /// Only the pairs of the --tethers groups (the leader and each member, or any two members of a
+    /// group without leader) raise max distance alerts, min distance alerts staying global.
+    #[arg(long, global = true)]
+    tethered_separation: bool,
+    /// Only print the alerts at or above this level instead of every evaluated pair and position.
+    #[arg(long, value_enum, global = true)]
+    print_only: Option<PrintOnly>,
+    /// Same as --print-only none.
+    #[arg(long, global = true, conflicts_with = "print_only")]
+    quiet: bool,
+    /// Period of the one line summaries of the compute cycles, 10 s by default when the output
+    /// is throttled, 0 disables them.
+    #[arg(long, global = true)]
+    summary_period_s: Option<u64>,
+    /// Vehicles are flagged for high frequency tracking by a put on <flag_key>/<vehicle id>,
+    /// and unflagged by deleting the key.
+    #[arg(long, global = true)]
+    flag_key: Option<String>,
+    /// Position key expressions are subscribed to and unsubscribed from at runtime by puts of
+    /// {"add": <key expr>} or {"remove": <key expr>} on subscription_key (push mode only).
+    #[arg(long, global = true)]
+    subscription_key: Option<String>,
+    /// Name of the site in multi-site deployments, the instance id by default.
+    #[arg(long, global = true)]
+    site: Option<String>,
+    /// Area of the site, lat_min,lng_min,lat_max,lng_max: vehicles leaving it are handed over
+    /// to the other sites on <handover_key>/<site>/<id>, with their metadata and track.
+    #[arg(long, global = true)]
+    site_bbox: Option<geohash::BoundingBox>,
+    /// Handovers of the other sites are adopted from <handover_key>/** when the vehicle enters
+    /// the site (whatever its position without --site-bbox).
+    #[arg(long, global = true)]
+    handover_key: Option<String>,
+    /// The state of flagged vehicles is published on <tracking_key>/<vehicle id> on every sample.
+    #[arg(long, global = true)]
+    tracking_key: Option<String>,
+    /// Rule thresholds and zones changed on <admin_key>/<instance id>/set/{rules,zones} are
+    /// shared with the other instances on <admin_key>/config/*.
+    #[arg(long, global = true)]
+    admin_key: Option<String>,
+    /// Internals of the instance (last seen of the vehicles, pair states, sink queue depths,
+    /// config and subscribers) are returned by queries on <introspection_key>/<instance
+    /// id>/{vehicles,pairs,sinks,config,subscribers}, read-only.
+    #[arg(long, global = true)]
+    introspection_key: Option<String>,
+    /// Publishers using deprecated payload formats are advised how to migrate on
+    /// <advisory_key>/<vehicle id>.
+    #[arg(long, global = true)]
+    advisory_key: Option<String>,
+    /// JSON file with the public keys of the publishers, {vehicle id: base64 ed25519 key}.
+    /// Positions must then be signed, the signature being carried in the "sig" attachment.
+    #[arg(long, global = true)]
+    keyring: Option<String>,
+    /// What to do with unsigned and invalid positions when a keyring is given.
+    #[arg(long, value_enum, global = true)]
+    signature_policy: Option<SignaturePolicy>,
+    #[arg(long, global = true)]
+    tamper_key: Option<String>,
+    /// JSON file with the maximum plausible speed (m/s) of each kind of vehicle, {"car": 70, "*": 90},
+    /// changing the defaults (pedestrian 4, car 70, drone 40). Positions implying a faster move
+    /// since the previous one are outliers, and derived speeds are capped.
+    #[arg(long, global = true)]
+    max_speeds: Option<String>,
+    /// Seconds of the safe following distance: the min distance of a pair becomes the speed of
+    /// its faster vehicle times this, plus --headway-margin, instead of --min-distance.
+    #[arg(long, global = true)]
+    headway: Option<f64>,
+    /// Meters added to the headway distance, kept at standstill (2 by default).
+    #[arg(long, global = true)]
+    headway_margin: Option<f64>,
+    /// JSON file with the headway of each kind of vehicle, {"truck": {"time": 4, "margin": 5},
+    /// "*": {...}}, on top of --headway. Pairs whose faster vehicle has none keep --min-distance.
+    #[arg(long, global = true)]
+    headways: Option<String>,
+    /// JSON file of independent fleets tracked together, {"north": {"key": "demo/tracker/north/mobs/**",
+    /// "alert_prefix": "demo/tracker/north/alert", "min_distance": 5, "max_distance": 500,
+    /// "cross_fleet": false}}, keys and prefixes defaulting to demo/tracker/<name>/mobs/** and
+    /// demo/tracker/<name>/alert. Vehicles of different fleets only pair when both allow it.
+    #[arg(long, global = true)]
+    fleets: Option<String>,
+    /// JSON file mapping the fields of third-party position feeds, by key expression, as JSON
+    /// pointers: {"legacy/telemetry/**": {"id": "/vehicle_id", "lat": "/latitude", "lng":
+    /// "/longitude", "speed": "/speed"}}, with optional kind, color, ts, min_distance and
+    /// max_distance. The feeds are subscribed to with the positions.
+    #[arg(long, global = true)]
+    field_mapping: Option<String>,
+    /// What to do with outliers.
+    #[arg(long, value_enum, global = true)]
+    outliers: Option<OutlierPolicy>,
+    /// Which optional subsystems (geofencing, prediction, persistence, gateway) are compiled in
+    /// and enabled is returned by queries on features_key.
+    #[arg(long, global = true)]
+    features_key: Option<String>,
+    /// Ids published by several sources (Zenoh ids, instance tags or keys), or jumping faster
+    /// than --max-jump-speed, raise an IdentityConflict on identity_key.
+    #[arg(long, global = true)]
+    identity_key: Option<String>,
+    /// m/s
+    #[arg(long, global = true)]
+    max_jump_speed: Option<f64>,
+    /// JSON file with the streams republished late, each {from: <prefix>/**, to, delay (s)}.
+    #[arg(long, global = true)]
+    delays: Option<String>,
+    /// File the delayed samples are saved in, to be published after a restart.
+    #[arg(long, global = true)]
+    delay_state: Option<String>,
+    /// File the rule thresholds and zones changed at runtime, and the metadata registered by the
+    /// vehicles, are saved in, restored at startup. Delete it to start from the command line
+    /// again.
+    #[arg(long, global = true)]
+    runtime_state: Option<String>,
+    /// Recording (tracker-recorder JSON lines) to restore the last known position of every
+    /// vehicle from at startup, stale until the vehicle publishes again.
+    #[arg(long, global = true)]
+    warm_start: Option<String>,
+    /// Key expression of a Zenoh storage holding the latest positions, fetched at startup so that
+    /// the fleet is known and raises alerts before the vehicles publish again.
+    #[arg(long, global = true)]
+    cold_start: Option<String>,
+    /// Stored positions older than this are skipped at a cold start (300 by default).
+    #[arg(long, global = true)]
+    cold_start_max_age_s: Option<u64>,
+    /// Region of interest, lat_min,lng_min,lat_max,lng_max: positions outside of it are ignored
+    /// and vehicles leaving it are dropped.
+    #[arg(long, global = true, conflicts_with = "roi_geojson")]
+    roi: Option<geohash::BoundingBox>,
+    /// GeoJSON file with the polygons of the region of interest, as --roi.
+    #[arg(long, global = true)]
+    roi_geojson: Option<String>,
+    #[arg(long, global = true)]
+    storm_key: Option<String>,
+    /// Alerts per second above which only digests are published (0 disables).
+    #[arg(long, global = true)]
+    storm_rate: Option<f64>,
+    /// Length (s) of the demo sessions of unattended installations: when one is over, the state of
+    /// the tracker is cleared, a SessionReset is published on session_key and a session with a new
+    /// id starts. Sessions never end by default.
+    #[arg(long, global = true)]
+    session_duration: Option<u64>,
+    #[arg(long, global = true)]
+    session_key: Option<String>,
+    /// Operators acknowledge the incident of a danger pair by putting {"ida", "idb", "operator"}
+    /// on ack_key, queries on which return the open incidents (with --escalation-timeout-ms).
+    #[arg(long, global = true)]
+    ack_key: Option<String>,
+    #[arg(long, global = true)]
+    escalation_key: Option<String>,
+    /// Milliseconds after which danger pairs nobody acknowledged are escalated, re-published once
+    /// on escalation_key with the urgent QoS. Disabled by default.
+    #[arg(long, global = true)]
+    escalation_timeout_ms: Option<u64>,
+    /// Publish a stop command to both vehicles of a DangerMin pair on <command_key>/<id>/stop, and
+    /// a release on <command_key>/<id>/release once they are in none. Never suspends computation.
+    #[arg(long, global = true)]
+    emergency_stop: bool,
+    #[arg(long, global = true)]
+    command_key: Option<String>,
+    /// JSON payload of the stop commands, {"id", "command": "stop", "with"} by default.
+    #[arg(long, global = true)]
+    stop_payload: Option<String>,
+    /// JSON payload of the release commands, {"id", "command": "release"} by default.
+    #[arg(long, global = true)]
+    release_payload: Option<String>,
+    /// Publish the alerts of a compute cycle on their own keys (off), also as a single batch on
+    /// batch_key (both), only as a batch (only), or on their own keys with the puts running
+    /// concurrently (concurrent).
+    #[arg(long, value_enum, global = true)]
+    batching: Option<Batching>,
+    #[arg(long, global = true)]
+    batch_key: Option<String>,
+    /// JSON file with the alert sinks used besides Zenoh, each {type: webhook|file|desktop, ...}.
+    #[arg(long, global = true)]
+    sinks: Option<String>,
+    /// JSON file with the InfluxDB line protocol telemetry of the vehicles and the distances of
+    /// the pairs, written to {url, token} or appended to {path}.
+    #[arg(long, global = true)]
+    influx: Option<String>,
+    /// Port of an HTTP API serving GET /vehicles, /vehicles/{id} and /alerts?since=<unix ms>, PUT
+    /// /config/thresholds and the /ws/alerts WebSocket, for clients without Zenoh (disabled by
+    /// default).
+    #[arg(long, global = true)]
+    http_port: Option<u16>,
+    /// Port of a gRPC service (proto/tracker.proto) with GetVehicles, the WatchAlerts stream and
+    /// SetThresholds, for gRPC clients (disabled by default).
+    #[arg(long, global = true)]
+    grpc_port: Option<u16>,
+    /// Port of a Foxglove WebSocket server showing the vehicles, the fleet and the alerts in
+    /// Foxglove Studio (disabled by default).
+    #[arg(long, global = true)]
+    foxglove_port: Option<u16>,
+    /// JSON file of the {priority, congestion} of the publications by level (danger, alert
+    /// or info) or severity band name, a band taking precedence over the level of its alerts.
+    #[arg(long, global = true)]
+    qos_table: Option<String>,
+    /// Priority and congestion control of dangers: danger level alerts, cut-ins, closing
+    /// speeds, hazards and emergency stops.
+    #[arg(long, value_enum, global = true)]
+    danger_priority: Option<PriorityArg>,
+    #[arg(long, value_enum, global = true)]
+    danger_congestion: Option<CongestionArg>,
+    /// Priority and congestion control of the other alerts.
+    #[arg(long, value_enum, global = true)]
+    alert_priority: Option<PriorityArg>,
+    #[arg(long, value_enum, global = true)]
+    alert_congestion: Option<CongestionArg>,
+    /// Priority and congestion control of every other publication (displays, risks,
+    /// notices).
+    #[arg(long, value_enum, global = true)]
+    info_priority: Option<PriorityArg>,
+    #[arg(long, value_enum, global = true)]
+    info_congestion: Option<CongestionArg>,
+    /// Compression of the publications (none, lz4 or zstd), compressed positions are always accepted.
+    #[arg(long, global = true)]
+    pub compression: Option<Compression>,
+    #[arg(long, value_enum, global = true)]
+    sub_reliability: Option<ReliabilityArg>,
+    /// Id added to every publication, generated and persisted when not given.
+    #[arg(long, global = true)]
+    pub instance_id: Option<String>,
+    /// Elect a leader among the redundant trackers declaring liveliness tokens on
+    /// <leader_key>/<instance id>, the smallest id leading: the others keep tracking but publish
+    /// nothing until they are elected, when the leader is gone.
+    #[arg(long, global = true)]
+    leader_election: bool,
+    #[arg(long, global = true)]
+    leader_key: Option<String>,
+    /// Only compute the alerts of the vehicles in these geohash cells (comma separated, of any
+    /// precision), sharing a large fleet with the instances owning the other cells: the vehicles
+    /// within --shard-border of the cells of another instance are exchanged with it on
+    /// <shard_key>/border/<instance id>/<vehicle id>, and each alert is published by the instance
+    /// owning its first vehicle in id order. With --geohash-region, only these cells are
+    /// subscribed to.
+    #[arg(long, global = true)]
+    shard_cells: Option<String>,
+    /// Share the cells of --shard-precision (6 by default) among the instances declaring liveliness
+    /// tokens on <shard_key>/members/<instance id> instead, by rendezvous hashing: the cells of an
+    /// instance which is gone go to the others.
+    #[arg(long, global = true)]
+    shard_election: bool,
+    #[arg(long, global = true)]
+    shard_precision: Option<usize>,
+    /// Distance (m) to the cells of another instance within which vehicles are exchanged with it,
+    /// pairs farther apart across instances raising no alerts (200 by default).
+    #[arg(long, global = true)]
+    shard_border: Option<f64>,
+    #[arg(long, global = true)]
+    shard_key: Option<String>,
+    /// Format of the logs, filtered by RUST_LOG (info and above by default). Alerts are logged
+    /// with the `alert` target, as structured events.
+    #[arg(long, value_enum, global = true)]
+    pub log_format: Option<LogFormat>,
+    #[command(flatten)]
+    pub transport: TransportArgs,
+}
+
+pub struct Settings {
+    command: Option<Command>,
+    skeys: Vec<String>,
+    mode: IngestMode,
+    pull_period: Duration,
+    pkey: String,
+    alert_key_layout: AlertKeyLayout,
+    outputs: Outputs,
+    state_key: String,
+    nearest_key: String,
+    diag_key: String,
+    dead_letter_key: String,
+    track_key: String,
+    track_length: usize,
+    trail_key: String,
+    trail_length: usize,
+    trail_tolerance: f64,
+    trail_period_ms: u64,
+    smooth_key: String,
+    smooth_rate_hz: f64,
+    metrics_key: String,
+    stats_key: String,
+    stats_period_ms: u64,
+    status_key: String,
+    status_period_ms: u64,
+    digest_key: String,
+    digest_period_ms: u64,
+    recent_key: String,
+    alert_cache: Option<String>,
+    federation_key: Option<String>,
+    federation_period_ms: u64,
+    federation_top: usize,
+    meta_key: String,
+    max_rate: f64,
+    min_distance: f64,
+    max_distance: f64,
+    distance_algo: DistanceAlgo,
+    compute_period_ms: u64,
+    auto_period: bool,
+    speed_tolerance: f64,
+    cutin_key: String,
+    cutin_range: f64,
+    cutin_bearing_rate: f64,
+    closing_key: String,
+    closing_speed: f64,
+    closing_floor: f64,
+    pairing: PairingRules,
+    severities: Severities,
+    speed_key: String,
+    speed_zones: Vec<Zone>,
+    tethers: Vec<TetherGroup>,
+    tether_key: String,
+    pois: Vec<Poi>,
+    poi_key: String,
+    sectors: Vec<Sector>,
+    sector_key: String,
+    battery: BatteryRule,
+    battery_key: String,
+    telemetry_key: String,
+    telemetry_period_ms: u64,
+    route_key: String,
+    route_alert_key: String,
+    route_corridor: f64,
+    checkpoint_radius: f64,
+    progress_key: String,
+    display_key: String,
+    clusters_key: String,
+    heatmap_key: String,
+    heatmap_cell: f64,
+    risk_key: String,
+    risk_range: f64,
+    cluster_range: f64,
+    geojson: bool,
+    geojson_key: String,
+    matrix: bool,
+    matrix_key: String,
+    matrix_cap: Option<f64>,
+    lights_key: String,
+    geometry_key: String,
+    geometry_period_ms: u64,
+    light_radius: f64,
+    red_min_distance: f64,
+    move_epsilon: f64,
+    danger_only_closing: bool,
+    tethered_separation: bool,
+    print_only: PrintOnly,
+    summary_period_s: u64,
+    flag_key: String,
+    subscription_key: String,
+    site: Site,
+    handover_key: String,
+    tracking_key: String,
+    admin_key: String,
+    introspection_key: String,
+    advisory_key: String,
+    keyring: Option<Keyring>,
+    signature_policy: SignaturePolicy,
+    tamper_key: String,
+    max_speeds: MaxSpeeds,
+    headways: Headways,
+    fleets: Fleets,
+    feeds: Feeds,
+    outlier_policy: OutlierPolicy,
+    features_key: String,
+    identity_key: String,
+    max_jump_speed: f64,
+    delays: Vec<DelayStream>,
+    delay_state: Option<PathBuf>,
+    runtime_state: Option<PathBuf>,
+    warm_start: Option<String>,
+    cold_start: Option<String>,
+    cold_start_max_age: Duration,
+    roi: Option<Roi>,
+    storm_key: String,
+    storm_rate: f64,
+    // clears the state of the tracker every period when set
+    session_duration: Option<Duration>,
+    session_key: String,
+    ack_key: String,
+    escalation_key: String,
+    escalation_timeout: Option<Duration>,
+    estop: Option<EmergencyStop>,
+    batching: Batching,
+    batch_key: String,
+    sinks: Vec<SinkConfig>,
+    // elects the publishing instance when set
+    leader_key: Option<String>,
+    // computes the alerts of part of the fleet when set
+    shard: Option<Shard>,
+    shard_key: String,
+    influx: Option<InfluxConfig>,
+    http_port: Option<u16>,
+    grpc_port: Option<u16>,
+    foxglove_port: Option<u16>,
+    instance: Instance,
+    qos: QosTable,
+    compression: Compression,
+    sub_reliability: ReliabilityArg,
+    config: Config,
+    // the options, as introspected
+    command_line: Vec<String>,
+}
+
+pub fn zenoh_config(transport: &TransportArgs) -> Config {
+    transport.config().unwrap_or_else(|e| {
+        error!("{e}");
+        std::process::exit(2);
+    })
+}
+
+// The settings of the options `args`, parsed from `command_line`.
+pub fn parse_args(args: AppArgs, command_line: Vec<String>) -> Settings {
+
+    let skey = args.sub_key.unwrap_or("demo/tracker/mobs/**".into());
+    let shard_cells: Option<Vec<String>> = args.shard_cells.as_deref().map(|c| c.split(',').map(|c| c.trim().to_string()).collect());
+    if let Some(e) = shard_cells.iter().flatten().find_map(|c| geohash::check(c).err()) {
+        panic!("--shard-cells: {e}");
+    }
+    let skeys = match args.geohash_region {
+        Some(bb) => {
+            let precision = args.geohash_precision.unwrap_or(5);
+            let prefix = skey.trim_end_matches("/**");
+            let cells = geohash::cover(&bb, precision).unwrap_or_else(|e| panic!("--geohash-region: {e}"));
+            // a static shard only subscribes to its own cells
+            let owned = |cell: &str| shard_cells.as_ref().is_none_or(|s| s.iter().any(|c| cell.starts_with(c.as_str()) || c.starts_with(cell)));
+            cells.iter().filter(|cell| owned(cell)).map(|cell| format!("{prefix}/{cell}/**")).collect()
+        },
+        None => vec![skey]
+    };
+    let fleets = args.fleets.map_or_else(Fleets::default, |f| fleets::load(&f).unwrap_or_else(|e| panic!("--fleets: {e}")));
+    let feeds = args.field_mapping.map_or_else(Feeds::default, |f| feeds::load(&f).unwrap_or_else(|e| panic!("--field-mapping: {e}")));
+    let skeys: Vec<String> = skeys.into_iter().chain(fleets.keys()).chain(feeds.keys()).collect();
+    let mode = args.mode.unwrap_or(IngestMode::Push);
+    let pull_period = Duration::from_millis(args.pull_period_ms.unwrap_or(1000));
+    let min_distance = args.min_distance.unwrap_or(10.0_f64);
+    let max_distance = args.max_distance.unwrap_or(1000_f64);
+    let pkey = args.pub_key.unwrap_or("demo/tracker/alert/distance".into());
+    let alert_key_layout = args.alert_key_layout.unwrap_or(AlertKeyLayout::Flat);
+    let outputs = args.outputs.map_or_else(Outputs::default, |f| outputs::load(&f).unwrap_or_else(|e| panic!("--outputs: {e}")));
+    let state_key = args.state_key.unwrap_or("demo/tracker/state".into());
+    let nearest_key = args.nearest_key.unwrap_or("demo/tracker/query/nearest".into());
+    let diag_key = args.diag_key.unwrap_or("demo/tracker/diag".into());
+    let dead_letter_key = args.dead_letter_key.unwrap_or("demo/tracker/dead-letter".into());
+    let track_key = args.track_key.unwrap_or("demo/tracker/track".into());
+    let track_length = args.track_length.unwrap_or(100);
+    let trail_key = args.trail_key.unwrap_or("demo/tracker/trail".into());
+    let trail_length = args.trail_length.unwrap_or(50.min(track_length));
+    if trail_length > track_length {
+        panic!("--trail-length: at most --track-length ({track_length}) positions are kept");
+    }
+    let trail_tolerance = args.trail_tolerance.unwrap_or(1.0);
+    if !(trail_tolerance.is_finite() && trail_tolerance >= 0.0) {
+        panic!("--trail-tolerance: must be a non negative number of meters");
+    }
+    let trail_period_ms = args.trail_period_ms.unwrap_or(1000).max(1);
+    let smooth_key = args.smooth_key.unwrap_or("demo/tracker/smooth".into());
+    let smooth_rate_hz = args.smooth_rate_hz.unwrap_or(10.0);
+    if !(smooth_rate_hz.is_finite() && (0.0..=1000.0).contains(&smooth_rate_hz)) {
+        panic!("--smooth-rate-hz must be between 0 and 1000");
+    }
+    let metrics_key = args.metrics_key.unwrap_or("demo/tracker/metrics".into());
+    let stats_key = args.stats_key.unwrap_or("demo/tracker/stats".into());
+    let stats_period_ms = args.stats_period_ms.unwrap_or(10000);
+    let status_key = args.status_key.unwrap_or("demo/tracker/status/distance-tracker".into());
+    let status_period_ms = args.status_period_ms.unwrap_or(5000);
+    let digest_key = args.digest_key.unwrap_or("demo/tracker/stats/digest".into());
+    let digest_period_ms = args.digest_period_ms.unwrap_or(60000);
+    let recent_key = args.recent_key.unwrap_or("demo/tracker/alert/recent".into());
+    let federation_period_ms = args.federation_period_ms.unwrap_or(5000);
+    if federation_period_ms == 0 {
+        panic!("--federation-period-ms must be positive");
+    }
+    let federation_top = args.federation_top.unwrap_or(10);
+    let meta_key = args.meta_key.unwrap_or("demo/tracker/meta".into());
+    let max_rate = args.max_rate.unwrap_or(20.0);
+    let distance_algo = args.distance_algo.unwrap_or(DistanceAlgo::Haversine);
+    let compute_period_ms = args.compute_period_ms.unwrap_or(500);
+    let speed_tolerance = args.speed_tolerance.unwrap_or(5.0_f64);
+    let cutin_key = args.cutin_key.unwrap_or("demo/tracker/alert/cutin".into());
+    let cutin_range = args.cutin_range.unwrap_or(50.0_f64);
+    let cutin_bearing_rate = args.cutin_bearing_rate.unwrap_or(5.0_f64);
+    let closing_key = args.closing_key.unwrap_or("demo/tracker/alert/closing".into());
+    let closing_speed = args.closing_speed.unwrap_or(15.0);
+    let closing_floor = args.closing_floor.unwrap_or(min_distance);
+    let pairing = match args.pairing {
+        Some(f) => pairing::load(&f).unwrap_or_else(|e| panic!("--pairing: {e}")),
+        None => PairingRules::default()
+    };
+    let severities = match args.severities {
+        Some(f) => severity::load(&f).unwrap_or_else(|e| panic!("--severities: {e}")),
+        None => Severities::default()
+    };
+    let speed_key = args.speed_key.unwrap_or("demo/tracker/alert/speed".into());
+    let speed_zones = match args.speed_zones {
+        Some(f) => zones::load(&f).unwrap_or_else(|e| panic!("--speed-zones: {e}")),
+        None => vec![]
+    };
+    let tethers = match args.tethers {
+        Some(f) => tether::load(&f).unwrap_or_else(|e| panic!("--tethers: {e}")),
+        None => vec![]
+    };
+    if args.tethered_separation && tethers.is_empty() {
+        warn!("--tethered-separation without --tethers, no pair raises max distance alerts");
+    }
+    let tether_key = args.tether_key.unwrap_or("demo/tracker/alert/tether".into());
+    let pois = match args.pois {
+        Some(f) => poi::load(&f).unwrap_or_else(|e| panic!("--pois: {e}")),
+        None => vec![]
+    };
+    let poi_key = args.poi_key.unwrap_or("demo/tracker/alert/poi".into());
+    let sectors = match args.sectors {
+        Some(f) => sectors::load(&f).unwrap_or_else(|e| panic!("--sectors: {e}")),
+        None => vec![]
+    };
+    let sector_key = args.sector_key.unwrap_or("demo/tracker/alert/sector".into());
+    let battery_kinds = args.battery_kinds.unwrap_or("drone".into()).split(',').filter(|k| !k.trim().is_empty())
+        .map(|k| VehicleKind::parse(k).unwrap_or_else(|e| panic!("--battery-kinds: {e}")))
+        .collect();
+    let low_battery = args.low_battery.unwrap_or(20.0);
+    if !(0.0..=100.0).contains(&low_battery) {
+        panic!("--low-battery: {low_battery} is not a charge in [0, 100] %");
+    }
+    let battery = BatteryRule { kinds: battery_kinds, low: low_battery };
+    let battery_key = args.battery_key.unwrap_or("demo/tracker/alert/battery".into());
+    let telemetry_key = args.telemetry_key.unwrap_or("demo/tracker/telemetry".into());
+    let telemetry_period_ms = args.telemetry_period_ms.unwrap_or(5000);
+    let route_key = args.route_key.unwrap_or("demo/tracker/route".into());
+    let route_alert_key = args.route_alert_key.unwrap_or("demo/tracker/alert/route".into());
+    let route_corridor = args.route_corridor.unwrap_or(25.0);
+    let checkpoint_radius = args.checkpoint_radius.unwrap_or(20.0);
+    let progress_key = args.progress_key.unwrap_or("demo/tracker/progress".into());
+    let display_key = args.display_key.unwrap_or("demo/tracker/display".into());
+    let clusters_key = args.clusters_key.unwrap_or("demo/tracker/clusters".into());
+    let cluster_range = args.cluster_range.unwrap_or(20.0);
+    let heatmap_key = args.heatmap_key.unwrap_or("demo/tracker/heatmap".into());
+    let risk_key = args.risk_key.unwrap_or("demo/tracker/risk".into());
+    let risk_range = args.risk_range.unwrap_or(100.0);
+    let heatmap_cell = args.heatmap_cell.unwrap_or(0.0);
+    if !(heatmap_cell.is_finite() && heatmap_cell >= 0.0) {
+        panic!("--heatmap-cell: must be a non negative number of degrees");
+    }
+    let geojson_key = args.geojson_key.unwrap_or("demo/tracker/geojson".into());
+    let matrix_key = args.matrix_key.unwrap_or("demo/tracker/matrix".into());
+    let lights_key = args.lights_key.unwrap_or("demo/tracker/lights".into());
+    let geometry_key = args.geometry_key.unwrap_or("demo/tracker/geometry".into());
+    let geometry_period_ms = args.geometry_period_ms.unwrap_or(100).max(1);
+    let light_radius = args.light_radius.unwrap_or(30.0);
+    let red_min_distance = args.red_min_distance.unwrap_or(2.0);
+    let move_epsilon = args.move_epsilon.unwrap_or(0.5);
+    let flag_key = args.flag_key.unwrap_or("demo/tracker/control/flag".into());
+    let subscription_key = args.subscription_key.unwrap_or("demo/tracker/control/sub".into());
+    let tracking_key = args.tracking_key.unwrap_or("demo/tracker/tracking".into());
+    let admin_key = args.admin_key.unwrap_or("demo/tracker/admin".into());
+    let introspection_key = args.introspection_key.unwrap_or("demo/tracker/@/distance-tracker".into());
+    let advisory_key = args.advisory_key.unwrap_or("demo/tracker/advisory".into());
+    let keyring = args.keyring.map(|f| Keyring::load(&f).unwrap_or_else(|e| panic!("--keyring: {e}")));
+    let signature_policy = args.signature_policy.unwrap_or(SignaturePolicy::Reject);
+    let tamper_key = args.tamper_key.unwrap_or("demo/tracker/alert/tamper".into());
+    let print_only = if args.quiet { PrintOnly::None } else { args.print_only.unwrap_or(PrintOnly::All) };
+    let summary_period_s = args.summary_period_s.unwrap_or(if print_only == PrintOnly::All { 0 } else { 10 });
+    let max_speeds = match args.max_speeds {
+        Some(f) => plausibility::load(&f).unwrap_or_else(|e| panic!("--max-speeds: {e}")),
+        None => MaxSpeeds::default(),
+    };
+    let headways = match args.headway {
+        Some(time) => Headways::uniform(Headway { time, margin: args.headway_margin.unwrap_or(2.0) }).unwrap_or_else(|e| panic!("--headway: {e}")),
+        None => Headways::default(),
+    };
+    let headways = match args.headways {
+        Some(f) => headway::load(&f, headways).unwrap_or_else(|e| panic!("--headways: {e}")),
+        None => headways,
+    };
+    let outlier_policy = args.outliers.unwrap_or(OutlierPolicy::Drop);
+    let features_key = args.features_key.unwrap_or("demo/tracker/features".into());
+    let identity_key = args.identity_key.unwrap_or("demo/tracker/diag/identity".into());
+    let max_jump_speed = args.max_jump_speed.unwrap_or(120.0_f64);
+    let cold_start_max_age = Duration::from_secs(args.cold_start_max_age_s.unwrap_or(300));
+    let delays = match args.delays {
+        Some(f) => delay::load(&f).unwrap_or_else(|e| panic!("--delays: {e}")),
+        None => vec![]
+    };
+    let storm_key = args.storm_key.unwrap_or("demo/tracker/alert/storm".into());
+    let storm_rate = args.storm_rate.unwrap_or(200.0);
+    if args.session_duration == Some(0) {
+        panic!("--session-duration: must be a positive number of seconds");
+    }
+    let session_duration = args.session_duration.map(Duration::from_secs);
+    let session_key = args.session_key.unwrap_or("demo/tracker/session".into());
+    let ack_key = args.ack_key.unwrap_or("demo/tracker/alert/ack".into());
+    let escalation_key = args.escalation_key.unwrap_or("demo/tracker/alert/escalation".into());
+    let escalation_timeout = args.escalation_timeout_ms.map(Duration::from_millis);
+    let command_key = args.command_key.unwrap_or("demo/tracker/cmd".into());
+    let payload = |arg: &str, p: Option<String>| p.map(|p| serde_json::from_str::<serde_json::Value>(&p).unwrap_or_else(|e| panic!("{arg}: {e}")));
+    let (stop_payload, release_payload) = (payload("--stop-payload", args.stop_payload), payload("--release-payload", args.release_payload));
+    let batching = args.batching.unwrap_or(Batching::Off);
+    let batch_key = args.batch_key.unwrap_or("demo/tracker/alert/batch".into());
+    let sinks = match args.sinks {
+        Some(f) => sinks::load(&f).unwrap_or_else(|e| panic!("--sinks: {e}")),
+        None => vec![]
+    };
+    let influx = args.influx.map(|f| influx::load(&f).unwrap_or_else(|e| panic!("--influx: {e}")));
+    let instance = Instance::resolve("distance-tracker", args.instance_id);
+    let roi = match (args.roi, args.roi_geojson) {
+        (Some(bb), _) => Some(Roi::Bbox(bb)),
+        (None, Some(path)) => Some(roi::load(&path).unwrap_or_else(|e| panic!("--roi-geojson: {e}"))),
+        (None, None) => None,
+    };
+    let site = Site { name: args.site.unwrap_or(instance.id.clone()), bbox: args.site_bbox };
+    let handover_key = args.handover_key.unwrap_or("demo/handover".into());
+    let site_handover_key = format!("{handover_key}/{}", site.name);
+    let delay_state = args.delay_state.map(PathBuf::from)
+        .or_else(|| zdemo_common::state_path(&format!("{}.delayed.json", instance.id)));
+    let mut qos = args.qos_table.as_deref().map_or_else(|| Ok(QosTable::default()), qos::load).unwrap_or_else(|e| panic!("--qos-table: {e}"));
+    if let Some(band) = qos.bands.keys().find(|b| severities.get(b).is_none()) {
+        panic!("--qos-table: unknown severity band '{band}', expecting danger, alert, info or a band of --severities");
+    }
+    // the flags override the table
+    let by_flags = |q: Qos, priority: Option<PriorityArg>, congestion: Option<CongestionArg>| Qos {
+        priority: priority.map_or(q.priority, Into::into),
+        congestion: congestion.map_or(q.congestion, Into::into),
+    };
+    qos.danger = by_flags(qos.danger, args.danger_priority, args.danger_congestion);
+    qos.alert = by_flags(qos.alert, args.alert_priority, args.alert_congestion);
+    qos.info = by_flags(qos.info, args.info_priority, args.info_congestion);
+    let compression = args.compression.unwrap_or_default();
+    let sub_reliability = args.sub_reliability.unwrap_or(ReliabilityArg::BestEffort);
+    let config = zenoh_config(&args.transport);
+
+    let admin_set_key = format!("{admin_key}/{}/set/rules", instance.id);
+    let introspection_item_key = format!("{introspection_key}/{}/config", instance.id);
+    let leader_key = args.leader_election.then(|| args.leader_key.unwrap_or("demo/tracker/leader".into()));
+    let leader_member_key = leader_key.as_ref().map(|k| format!("{k}/{}", instance.id));
+    let shard_key = args.shard_key.unwrap_or("demo/tracker/shard".into());
+    let shard_border = args.shard_border.unwrap_or(200.0);
+    if !(shard_border.is_finite() && shard_border >= 0.0) {
+        panic!("--shard-border: must be a non negative number of meters");
+    }
+    let shard = match (shard_cells, args.shard_election) {
+        (Some(_), true) => panic!("--shard-cells: the cells are either given or elected with --shard-election"),
+        (Some(cells), false) => Some(Shard { me: instance.id.clone(), cells: Cells::Static(cells), border: shard_border }),
+        (None, true) => {
+            let precision = args.shard_precision.unwrap_or(6);
+            if !(1..=shard::MAX_PRECISION).contains(&precision) {
+                panic!("--shard-precision: must be from 1 to {}", shard::MAX_PRECISION);
+            }
+            Some(Shard::elected(&instance.id, precision, shard_border))
+        },
+        (None, false) => None,
+    };
+    let shard_checks = shard.as_ref().map_or(vec![], |s| {
+        let mut keys = vec![("--shard-key (with --instance-id)", format!("{shard_key}/border/{}", instance.id))];
+        if matches!(s.cells, Cells::Elected { .. }) {
+            keys.push(("--shard-key (with --instance-id)", format!("{shard_key}/members/{}", instance.id)));
+        }
+        keys
+    });
+    let federation_region_key = args.federation_key.as_ref().map(|k| format!("{k}/region/{}", site.name));
+    let sub_origin = if args.geohash_region.is_some() { "--sub-key (with --geohash-region)" } else { "--sub-key" };
+    let checks = skeys.iter().map(|k| (sub_origin, k.as_str(), KeyUse::Select))
+        .chain([
+            ("--pub-key", pkey.as_str(), KeyUse::Concrete),
+            ("--state-key", state_key.as_str(), KeyUse::Concrete),
+            ("--nearest-key", nearest_key.as_str(), KeyUse::Concrete),
+            ("--diag-key", diag_key.as_str(), KeyUse::Concrete),
+            ("--dead-letter-key", dead_letter_key.as_str(), KeyUse::Concrete),
+            ("--track-key", track_key.as_str(), KeyUse::Concrete),
+            ("--trail-key", trail_key.as_str(), KeyUse::Concrete),
+            ("--smooth-key", smooth_key.as_str(), KeyUse::Concrete),
+            ("--metrics-key", metrics_key.as_str(), KeyUse::Concrete),
+            ("--stats-key", stats_key.as_str(), KeyUse::Concrete),
+            ("--status-key", status_key.as_str(), KeyUse::Concrete),
+            ("--digest-key", digest_key.as_str(), KeyUse::Concrete),
+            ("--recent-key", recent_key.as_str(), KeyUse::Concrete),
+            ("--meta-key", meta_key.as_str(), KeyUse::Concrete),
+            ("--cutin-key", cutin_key.as_str(), KeyUse::Concrete),
+            ("--closing-key", closing_key.as_str(), KeyUse::Concrete),
+            ("--speed-key", speed_key.as_str(), KeyUse::Concrete),
+            ("--tether-key", tether_key.as_str(), KeyUse::Concrete),
+            ("--poi-key", poi_key.as_str(), KeyUse::Concrete),
+            ("--sector-key", sector_key.as_str(), KeyUse::Concrete),
+            ("--battery-key", battery_key.as_str(), KeyUse::Concrete),
+            ("--telemetry-key", telemetry_key.as_str(), KeyUse::Concrete),
+            ("--route-key", route_key.as_str(), KeyUse::Concrete),
+            ("--route-alert-key", route_alert_key.as_str(), KeyUse::Concrete),
+            ("--progress-key", progress_key.as_str(), KeyUse::Concrete),
+            ("--display-key", display_key.as_str(), KeyUse::Concrete),
+            ("--clusters-key", clusters_key.as_str(), KeyUse::Concrete),
+            ("--heatmap-key", heatmap_key.as_str(), KeyUse::Concrete),
+            ("--risk-key", risk_key.as_str(), KeyUse::Concrete),
+            ("--geojson-key", geojson_key.as_str(), KeyUse::Concrete),
+            ("--matrix-key", matrix_key.as_str(), KeyUse::Concrete),
+            ("--lights-key", lights_key.as_str(), KeyUse::Concrete),
+            ("--geometry-key", geometry_key.as_str(), KeyUse::Concrete),
+            ("--flag-key", flag_key.as_str(), KeyUse::Concrete),
+            ("--subscription-key", subscription_key.as_str(), KeyUse::Concrete),
+            ("--handover-key (with --site)", site_handover_key.as_str(), KeyUse::Concrete),
+            ("--tracking-key", tracking_key.as_str(), KeyUse::Concrete),
+            ("--admin-key", admin_key.as_str(), KeyUse::Concrete),
+            ("--admin-key (with --instance-id)", admin_set_key.as_str(), KeyUse::Concrete),
+            ("--introspection-key", introspection_key.as_str(), KeyUse::Concrete),
+            ("--introspection-key (with --instance-id)", introspection_item_key.as_str(), KeyUse::Concrete),
+            ("--advisory-key", advisory_key.as_str(), KeyUse::Concrete),
+            ("--tamper-key", tamper_key.as_str(), KeyUse::Concrete),
+            ("--features-key", features_key.as_str(), KeyUse::Concrete),
+            ("--identity-key", identity_key.as_str(), KeyUse::Concrete),
+            ("--storm-key", storm_key.as_str(), KeyUse::Concrete),
+            ("--session-key", session_key.as_str(), KeyUse::Concrete),
+            ("--ack-key", ack_key.as_str(), KeyUse::Concrete),
+            ("--escalation-key", escalation_key.as_str(), KeyUse::Concrete),
+            ("--batch-key", batch_key.as_str(), KeyUse::Concrete),
+            ("--command-key", command_key.as_str(), KeyUse::Concrete),
+        ])
+        .chain(args.cold_start.as_deref().map(|k| ("--cold-start", k, KeyUse::Select)))
+        .chain(args.alert_cache.as_deref().map(|k| ("--alert-cache", k, KeyUse::Select)))
+        .chain(leader_member_key.as_deref().map(|k| ("--leader-key (with --instance-id)", k, KeyUse::Concrete)))
+        .chain(shard_checks.iter().map(|(origin, k)| (*origin, k.as_str(), KeyUse::Concrete)))
+        .chain(federation_region_key.as_deref().map(|k| ("--federation-key (with --site)", k, KeyUse::Concrete)));
+    if let Err(errors) = keys::check_all(checks) {
+        for e in errors {
+            error!("{e}");
+        }
+        std::process::exit(2);
+    }
+    let estop = args.emergency_stop.then(|| EmergencyStop::new(command_key, stop_payload, release_payload));
+    // rejected positions would be received again, and republished forever
+    let dead_letters = format!("{dead_letter_key}/**");
+    if skeys.iter().any(|k| keyexpr::new(k).unwrap().intersects(keyexpr::new(&dead_letters).unwrap())) {
+        error!("--dead-letter-key {dead_letter_key} is within the subscribed keys");
+        std::process::exit(2);
+    }
+
+    Settings {
+        command: args.command,
+        skeys,
+        mode,
+        pull_period,
+        pkey,
+        alert_key_layout,
+        outputs,
+        state_key,
+        nearest_key,
+        diag_key,
+        dead_letter_key,
+        track_key,
+        track_length,
+        trail_key,
+        trail_length,
+        trail_tolerance,
+        trail_period_ms,
+        smooth_key,
+        smooth_rate_hz,
+        metrics_key,
+        stats_key,
+        stats_period_ms,
+        status_key,
+        status_period_ms,
+        digest_key,
+        digest_period_ms,
+        recent_key,
+        alert_cache: args.alert_cache,
+        federation_key: args.federation_key,
+        federation_period_ms,
+        federation_top,
+        meta_key,
+        max_rate,
+        min_distance,
+        max_distance,
+        distance_algo,
+        compute_period_ms,
+        auto_period: args.auto_period,
+        speed_tolerance,
+        cutin_key,
+        cutin_range,
+        cutin_bearing_rate,
+        closing_key,
+        closing_speed,
+        closing_floor,
+        pairing,
+        severities,
+        speed_key,
+        speed_zones,
+        tethers,
+        tether_key,
+        pois,
+        poi_key,
+        sectors,
+        sector_key,
+        battery,
+        battery_key,
+        telemetry_key,
+        telemetry_period_ms,
+        route_key,
+        route_alert_key,
+        route_corridor,
+        checkpoint_radius,
+        progress_key,
+        display_key,
+        clusters_key,
+        cluster_range,
+        heatmap_key,
+        heatmap_cell,
+        risk_key,
+        risk_range,
+        geojson: args.geojson,
+        geojson_key,
+        matrix: args.matrix,
+        matrix_key,
+        matrix_cap: args.matrix_cap,
+        lights_key,
+        geometry_key,
+        geometry_period_ms,
+        light_radius,
+        red_min_distance,
+        move_epsilon,
+        danger_only_closing: args.danger_only_closing,
+        tethered_separation: args.tethered_separation,
+        print_only,
+        summary_period_s,
+        flag_key,
+        subscription_key,
+        site,
+        handover_key,
+        tracking_key,
+        admin_key,
+        introspection_key,
+        advisory_key,
+        keyring,
+        signature_policy,
+        tamper_key,
+        max_speeds,
+        headways,
+        fleets,
+        feeds,
+        outlier_policy,
+        features_key,
+        identity_key,
+        max_jump_speed,
+        delays,
+        delay_state,
+        runtime_state: args.runtime_state.map(PathBuf::from),
+        warm_start: args.warm_start,
+        cold_start: args.cold_start,
+        cold_start_max_age,
+        roi,
+        storm_key,
+        storm_rate,
+        session_duration,
+        session_key,
+        ack_key,
+        escalation_key,
+        escalation_timeout,
+        estop,
+        batching,
+        batch_key,
+        sinks,
+        leader_key,
+        shard,
+        shard_key,
+        influx,
+        http_port: args.http_port,
+        grpc_port: args.grpc_port,
+        foxglove_port: args.foxglove_port,
+        instance,
+        qos,
+        compression,
+        sub_reliability,
+        config,
+        command_line,
+    }
+
+}
@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use serde::Serialize;
+use zenoh::prelude::r#async::*;
+use tracing::warn;
+use crate::geo;
+use crate::matching::Matching;
+use crate::track::Tracks;
+use crate::Position;
+
+// Where a vehicle has been, oldest point first, published on <trail_key>/<id>
+// so that map UIs can draw it as a polyline without keeping any history.
+#[derive(Serialize, Debug)]
+pub struct Trail {
+    pub id: String,
+    // ms since the UNIX epoch, of the last point
+    pub ts: u64,
+    pub points: Vec<Position>,
+}
+
+// Publishes every `period`, while it has subscribers, the trails of the
+// vehicles which moved: the last `length` points of their track, simplified
+// with a tolerance of `tolerance` m.
+pub async fn publish(z: Arc<Session>, prefix: String, tracks: Tracks, length: usize, tolerance: f64, period: Duration, matching: Matching) {
+    let mut tick = tokio::time::interval(period);
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let mut seen = HashMap::new();
+    loop {
+        tick.tick().await;
+        if !matching.any() {
+            // subscribers joining later get all the trails
+            seen.clear();
+            continue;
+        }
+        for track in tracks.moved(&mut seen, length) {
+            let key = format!("{prefix}/{}", track.id);
+            if keyexpr::new(&key).is_err() {
+                continue;
+            }
+            let line: Vec<Position> = track.points.iter().map(|p| p.position).collect();
+            let ts = track.points.last().map_or(0, |p| p.t);
+            let trail = Trail { id: track.id, ts, points: geo::simplify(&line, tolerance) };
+            let value = Value::from(serde_json::to_vec(&trail).unwrap()).encoding(Encoding::APP_JSON);
+            if let Err(e) = z.put(&key, value).congestion_control(CongestionControl::Drop).res().await {
+                warn!("Unable to publish the trail on {key}: {e}");
+            }
+        }
+    }
+}
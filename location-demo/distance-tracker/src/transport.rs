@@ -0,0 +1,156 @@
+use zenoh::config::{Config, ValidatedMap, WhatAmI};
+
+// Link protocols which authenticate the other end and encrypt the traffic.
+const SECURE_PROTOCOLS: [&str; 2] = ["tls", "quic"];
+
+#[derive(clap_derive::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ZenohMode { Peer, Client, Router }
+
+impl From<ZenohMode> for WhatAmI {
+    fn from(m: ZenohMode) -> Self {
+        match m {
+            ZenohMode::Peer => WhatAmI::Peer,
+            ZenohMode::Client => WhatAmI::Client,
+            ZenohMode::Router => WhatAmI::Router,
+        }
+    }
+}
+
+// The Zenoh session options of the tracker and of the tools, the JSON5 config
+// file being overridden by the others. Alerts and every other publication go
+// through the same session as the positions, so that authenticating it covers
+// them all.
+#[derive(clap_derive::Args, Debug, Clone, Default)]
+pub struct TransportArgs {
+    /// Zenoh JSON5 config file, overridden by the other Zenoh options.
+    #[arg(long, global = true)]
+    pub config: Option<String>,
+    /// Zenoh mode, peer by default.
+    #[arg(long, value_enum, global = true)]
+    pub zenoh_mode: Option<ZenohMode>,
+    /// Zenoh endpoint to connect to, e.g. tls/router.example.com:7447, can be repeated.
+    #[arg(long, global = true)]
+    pub connect: Vec<String>,
+    /// Zenoh endpoint to listen on, e.g. tls/0.0.0.0:7447, can be repeated.
+    #[arg(long, global = true)]
+    pub listen: Vec<String>,
+    #[arg(long, global = true)]
+    pub no_multicast_scouting: bool,
+    /// PEM file of the certificate authority the certificates of the other ends are checked
+    /// against, on TLS and QUIC links.
+    #[arg(long, global = true)]
+    pub tls_root_ca: Option<String>,
+    /// PEM files of the certificate and private key presented on TLS and QUIC links: by the
+    /// listening end, and by the connecting end with --tls-mutual.
+    #[arg(long, global = true)]
+    pub tls_cert: Option<String>,
+    #[arg(long, global = true)]
+    pub tls_key: Option<String>,
+    /// Mutual TLS: listeners require a certificate from the connecting ends, which present
+    /// theirs.
+    #[arg(long, global = true)]
+    pub tls_mutual: bool,
+    /// User name the session authenticates with, the identity routers grant access to.
+    #[arg(long, global = true)]
+    pub zenoh_user: Option<String>,
+    /// File holding the password of --zenoh-user, kept out of the command line.
+    #[arg(long, global = true)]
+    pub zenoh_password_file: Option<String>,
+    /// Secure deployment: every endpoint must be TLS or QUIC with a certificate authority to
+    /// check the other ends against, listeners need a certificate, and multicast scouting,
+    /// which would connect over TCP, is disabled.
+    #[arg(long, global = true)]
+    pub secure: bool,
+    /// Enable shared memory, through which the publications reach the peers on the same host
+    /// (builds with the shm feature).
+    #[arg(long, global = true)]
+    pub shm: bool,
+}
+
+fn insert(config: &mut Config, key: &str, value: serde_json::Value) -> Result<(), String> {
+    config.insert_json5(key, &value.to_string()).map_err(|e| format!("Zenoh config {key}: {e}"))
+}
+
+impl TransportArgs {
+    pub fn config(&self) -> Result<Config, String> {
+        let mut config = match &self.config {
+            Some(f) => Config::from_file(f).map_err(|e| format!("--config {f}: {e}"))?,
+            None => Config::default(),
+        };
+        if let Some(m) = self.zenoh_mode {
+            config.set_mode(Some(m.into())).map_err(|_| "--zenoh-mode: invalid mode".to_string())?;
+        }
+        for (key, endpoints) in [("connect/endpoints", &self.connect), ("listen/endpoints", &self.listen)] {
+            if !endpoints.is_empty() {
+                insert(&mut config, key, serde_json::json!(endpoints))?;
+            }
+        }
+        if self.no_multicast_scouting || self.secure {
+            insert(&mut config, "scouting/multicast/enabled", false.into())?;
+        }
+        let tls = "transport/link/tls";
+        if let Some(ca) = &self.tls_root_ca {
+            insert(&mut config, &format!("{tls}/root_ca_certificate"), ca.as_str().into())?;
+        }
+        if let Some(cert) = &self.tls_cert {
+            insert(&mut config, &format!("{tls}/server_certificate"), cert.as_str().into())?;
+            insert(&mut config, &format!("{tls}/client_certificate"), cert.as_str().into())?;
+        }
+        if let Some(key) = &self.tls_key {
+            insert(&mut config, &format!("{tls}/server_private_key"), key.as_str().into())?;
+            insert(&mut config, &format!("{tls}/client_private_key"), key.as_str().into())?;
+        }
+        if self.tls_mutual {
+            insert(&mut config, &format!("{tls}/client_auth"), true.into())?;
+        }
+        if let Some(user) = &self.zenoh_user {
+            let file = self.zenoh_password_file.as_ref().ok_or("--zenoh-user needs a --zenoh-password-file")?;
+            let password = std::fs::read_to_string(file).map_err(|e| format!("--zenoh-password-file {file}: {e}"))?;
+            insert(&mut config, "transport/auth/usrpwd/user", user.as_str().into())?;
+            insert(&mut config, "transport/auth/usrpwd/password", password.trim().into())?;
+        }
+        if self.shm {
+            if !cfg!(feature = "shm") {
+                return Err("--shm: built without the shm feature".into());
+            }
+            insert(&mut config, "transport/shared_memory/enabled", true.into())?;
+        }
+        if self.secure {
+            check_secure(&config)?;
+        }
+        Ok(config)
+    }
+}
+
+// What --secure requires of the final config, whichever options or file set it.
+fn check_secure(config: &Config) -> Result<(), String> {
+    let mut errors = Vec::new();
+    let (connect, listen) = (config.connect().endpoints(), config.listen().endpoints());
+    for e in connect.iter().chain(listen) {
+        if !SECURE_PROTOCOLS.contains(&e.protocol().as_str()) {
+            errors.push(format!("endpoint {e} is neither TLS nor QUIC"));
+        }
+    }
+    let mode = config.mode().unwrap_or(WhatAmI::Peer);
+    if connect.is_empty() && listen.is_empty() {
+        errors.push("no endpoint to connect to or listen on, the session would rely on multicast scouting".into());
+    }
+    // peers and routers listen on TCP when given no endpoint
+    if listen.is_empty() && mode != WhatAmI::Client {
+        errors.push(format!("a {mode} listens on TCP without --listen endpoints, use --zenoh-mode client to only connect"));
+    }
+    let tls = config.transport().link().tls();
+    if tls.root_ca_certificate().is_none() {
+        errors.push("no --tls-root-ca to check the other ends against".into());
+    }
+    if !listen.is_empty() && (tls.server_certificate().is_none() || tls.server_private_key().is_none()) {
+        errors.push("listeners need a --tls-cert and a --tls-key".into());
+    }
+    if tls.client_auth().unwrap_or(false) && (tls.client_certificate().is_none() || tls.client_private_key().is_none()) {
+        errors.push("mutual TLS needs a --tls-cert and a --tls-key".into());
+    }
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(format!("--secure: {}", errors.join(", "))),
+    }
+}
@@ -0,0 +1,118 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use tracing::info;
+use crate::{Position, VehicleInfo};
+
+// Shortest interval between two fixes used to derive a speed, anything closer
+// is dominated by GPS noise.
+const MIN_SPEED_INTERVAL_S: f64 = 0.2;
+// Minimum displacement for the course over ground to be meaningful.
+const MIN_HEADING_DISPLACEMENT_M: f64 = 1.0;
+
+// The vehicles the rules apply to, leaving out the stale ones. Only copies the
+// map while some are stale, right after a warm start.
+pub fn live(map: &HashMap<String, VehicleState>) -> Cow<'_, HashMap<String, VehicleState>> {
+    if map.values().all(|v| v.stale_since.is_none()) {
+        return Cow::Borrowed(map);
+    }
+    Cow::Owned(map.iter().filter(|(_, v)| v.stale_since.is_none()).map(|(id, v)| (id.clone(), v.clone())).collect())
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataQuality { Good, SpeedMismatch }
+
+#[derive(Serialize, Debug, Clone)]
+pub struct VehicleState {
+    #[serde(flatten)]
+    pub info: VehicleInfo,
+    pub implied_speed: Option<f64>,
+    // Speed used by the safety rules: the more conservative (higher) of the
+    // reported and implied speed.
+    pub effective_speed: f64,
+    pub quality: DataQuality,
+    // Course over ground in degrees, derived from consecutive fixes.
+    pub heading: Option<f64>,
+    // Wall clock time (ms since the UNIX epoch) of the fix restored at startup,
+    // until the vehicle publishes again. Stale vehicles are left out of the
+    // rules.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stale_since: Option<u64>,
+    // Fix the implied speed is measured from.
+    #[serde(skip)]
+    anchor: (Position, Instant),
+    // when the last fix was applied
+    #[serde(skip)]
+    seen: Instant,
+}
+
+impl VehicleState {
+    pub fn new(info: VehicleInfo, now: Instant) -> Self {
+        VehicleState {
+            effective_speed: info.speed,
+            anchor: (info.position, now),
+            info,
+            implied_speed: None,
+            quality: DataQuality::Good,
+            heading: None,
+            stale_since: None,
+            seen: now,
+        }
+    }
+
+    // A vehicle restored from a fix received at `received` (ms since the UNIX
+    // epoch), before the tracker started.
+    pub fn restored(info: VehicleInfo, received: u64, now: Instant) -> Self {
+        VehicleState { stale_since: Some(received), ..VehicleState::new(info, now) }
+    }
+
+    // When the last fix was applied.
+    pub fn seen(&self) -> Instant {
+        self.seen
+    }
+
+    // Time since the last fix, received before the start for a stale vehicle.
+    pub fn age(&self, now: Instant, unix_ms: u64) -> Duration {
+        match self.stale_since {
+            Some(received) => Duration::from_millis(unix_ms.saturating_sub(received)),
+            None => now.saturating_duration_since(self.seen),
+        }
+    }
+
+    // Applies a new fix of the vehicle, copied in place, cross-checking the
+    // reported speed with the one implied by the previous fix. The implied
+    // speed is capped at the maximum plausible speed of the vehicle, if known.
+    pub fn update(&mut self, info: &VehicleInfo, now: Instant, tolerance: f64, max_speed: Option<f64>) {
+        self.info.copy_from(info);
+        self.seen = now;
+        // nothing is implied by a jump across the downtime
+        if self.stale_since.take().is_some() {
+            self.effective_speed = info.speed;
+            self.anchor = (info.position, now);
+            return;
+        }
+        let dt = now.duration_since(self.anchor.1).as_secs_f64();
+        if dt < MIN_SPEED_INTERVAL_S {
+            self.effective_speed = info.speed.max(self.implied_speed.unwrap_or(0.0));
+            return;
+        }
+        let moved = self.anchor.0.distance_haverside(&info.position);
+        let implied = (moved / dt).min(max_speed.unwrap_or(f64::INFINITY));
+        if moved >= MIN_HEADING_DISPLACEMENT_M {
+            self.heading = Some(self.anchor.0.bearing(&info.position));
+        }
+        let quality = if (implied - info.speed).abs() > tolerance {
+            DataQuality::SpeedMismatch
+        } else {
+            DataQuality::Good
+        };
+        if quality != self.quality {
+            info!("QUALITY: {} reported speed {} vs implied {implied:.2} -> {quality:?}", info.id, info.speed);
+        }
+        self.effective_speed = info.speed.max(implied);
+        self.anchor = (info.position, now);
+        self.implied_speed = Some(implied);
+        self.quality = quality;
+    }
+}
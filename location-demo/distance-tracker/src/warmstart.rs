@@ -0,0 +1,44 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use zenoh::prelude::r#async::*;
+use crate::ingest::{Profiles, RawVehicle, COLOR_ATTACHMENT, KIND_ATTACHMENT};
+use crate::record::read_records;
+use crate::VehicleInfo;
+
+// The fleet as last recorded, restored at startup so that it is complete before
+// the vehicles publish again.
+pub struct WarmStart {
+    // last position of each vehicle, with the wall clock time it was received
+    // (milliseconds since the UNIX epoch)
+    pub vehicles: Vec<(VehicleInfo, u64)>,
+    // profiles completing the next compact positions of the vehicles
+    pub profiles: Profiles,
+}
+
+// Reads the last position of every vehicle from a recording, positions being
+// the records on one of `keys`. Invalid records are skipped, as the tracker
+// does with invalid samples.
+pub fn load(path: &str, keys: &[String]) -> Result<WarmStart, String> {
+    let keys = keys.iter()
+        .map(|k| OwnedKeyExpr::new(k.as_str()).map_err(|e| format!("{k}: {e}")))
+        .collect::<Result<Vec<_>, _>>()?;
+    let file = File::open(path).map_err(|e| format!("Unable to open {path}: {e}"))?;
+    let mut profiles = Profiles::default();
+    let mut last: HashMap<String, (VehicleInfo, u64)> = HashMap::new();
+    for r in read_records(BufReader::new(file)) {
+        let Ok(r) = r else { continue };
+        if r.delete || !keyexpr::new(&r.key).is_ok_and(|key| keys.iter().any(|k| k.intersects(key))) {
+            continue;
+        }
+        let payload = r.payload.to_bytes();
+        let Some(raw) = RawVehicle::parse(&payload).ok().filter(|raw| raw.validate().is_ok()) else { continue };
+        let attached = |k: &str| r.attachment.get(k).map(String::as_str);
+        let mut vi = VehicleInfo::default();
+        raw.write_to(&mut vi, profiles.resolve(&raw, (attached(KIND_ATTACHMENT), attached(COLOR_ATTACHMENT)), None).0);
+        if last.get(&vi.id).is_none_or(|(_, ts)| *ts <= r.ts) {
+            last.insert(vi.id.clone(), (vi, r.ts));
+        }
+    }
+    Ok(WarmStart { vehicles: last.into_values().collect(), profiles })
+}
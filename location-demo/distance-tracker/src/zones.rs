@@ -0,0 +1,51 @@
+use serde::{Serialize, Deserialize};
+use crate::geo::in_polygon;
+use crate::Position;
+
+// A named area delimited by a polygon of [lat, lng] vertices.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Zone {
+    pub name: String,
+    // m/s, like the speed reported by the vehicles
+    pub speed_limit: f64,
+    pub polygon: Vec<[f64; 2]>,
+}
+
+impl Zone {
+    pub fn contains(&self, p: &Position) -> bool {
+        in_polygon(&self.polygon, p)
+    }
+}
+
+// Reads a JSON array of {name, speed_limit, polygon}.
+pub fn load(path: &str) -> Result<Vec<Zone>, String> {
+    let s = std::fs::read_to_string(path).map_err(|e| format!("Unable to read {path}: {e}"))?;
+    let zones: Vec<Zone> = serde_json::from_str(&s).map_err(|e| format!("Invalid zones file {path}: {e}"))?;
+    check(&zones)?;
+    Ok(zones)
+}
+
+pub fn check(zones: &[Zone]) -> Result<(), String> {
+    for z in zones {
+        if z.polygon.len() < 3 {
+            return Err(format!("zone '{}' needs at least 3 vertices", z.name));
+        }
+    }
+    Ok(())
+}
+
+// Most restrictive zone containing `p`, if any.
+pub fn limiting<'a>(zones: &'a [Zone], p: &Position) -> Option<&'a Zone> {
+    zones.iter()
+        .filter(|z| z.contains(p))
+        .min_by(|a, b| a.speed_limit.total_cmp(&b.speed_limit))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SpeedAlert {
+    pub id: String,
+    pub zone: String,
+    // reported speed, m/s
+    pub speed: f64,
+    pub limit: f64,
+}
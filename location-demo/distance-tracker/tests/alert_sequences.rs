@@ -0,0 +1,76 @@
+// Scripted position sequences against the tracker, run in process by the
+// harness, asserting the exact alerts it publishes, in order. The rules not
+// under test are disabled so that the vehicles jumping from one position to the
+// next raise nothing else.
+mod common;
+
+use common::{offset, put_vehicle, Harness, Seen, Timeline};
+use zdemo_common::Compression;
+
+const LAT: f64 = 45.0703;
+const LNG: f64 = 7.6869;
+const QUIET: [&str; 10] = ["--compute-period-ms", "100", "--closing-speed", "0", "--cutin-range", "0", "--outliers", "off", "--print-only", "none"];
+
+// Publishes each vehicle `north` meters from the origin.
+async fn place(z: &zenoh::Session, vehicles: &[(&str, f64)]) {
+    for (id, north) in vehicles {
+        let (lat, lng) = offset(LAT, LNG, *north, 0.0);
+        put_vehicle(z, id, lat, lng, Compression::None).await;
+    }
+}
+
+async fn tracker(h: &mut Harness, args: &[&str]) {
+    let args: Vec<&str> = QUIET.iter().chain(args).copied().collect();
+    h.tracker(&args).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn approaching_vehicles_go_from_alert_to_danger_and_back() {
+    let mut h = Harness::start().await;
+    let mut alerts = Timeline::subscribe(&h, "demo/tracker/alert/**").await;
+    tracker(&mut h, &["--min-distance", "10"]).await;
+    let z = h.session().await;
+    alerts.during(3, || place(&z, &[("a", 0.0), ("b", 30.0)])).await;
+    assert_eq!(alerts.seen, []);
+    let alert = Seen::new("distance", "AlertMin", "a", "b");
+    let danger = Seen::new("distance", "DangerMin", "a", "b");
+    alerts.until(&alert, || place(&z, &[("a", 0.0), ("b", 12.0)])).await;
+    alerts.until(&danger, || place(&z, &[("a", 0.0), ("b", 5.0)])).await;
+    alerts.until(&alert, || place(&z, &[("a", 0.0), ("b", 12.0)])).await;
+    assert_eq!(alerts.seen, [alert.clone(), danger, alert]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn drifting_apart_raises_a_separation_alert_then_danger() {
+    let mut h = Harness::start().await;
+    let mut alerts = Timeline::subscribe(&h, "demo/tracker/alert/**").await;
+    tracker(&mut h, &["--min-distance", "1", "--max-distance", "100"]).await;
+    let z = h.session().await;
+    alerts.during(3, || place(&z, &[("a", 0.0), ("b", 50.0)])).await;
+    let alert = Seen::new("distance", "AlertMax", "a", "b");
+    let danger = Seen::new("distance", "DangerMax", "a", "b");
+    alerts.until(&alert, || place(&z, &[("a", 0.0), ("b", 80.0)])).await;
+    alerts.until(&danger, || place(&z, &[("a", 0.0), ("b", 110.0)])).await;
+    assert_eq!(alerts.seen, [alert, danger]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn each_pair_raises_its_own_alerts_in_the_order_they_happen() {
+    let mut h = Harness::start().await;
+    let mut alerts = Timeline::subscribe(&h, "demo/tracker/alert/**").await;
+    tracker(&mut h, &["--min-distance", "10"]).await;
+    let z = h.session().await;
+    alerts.during(3, || place(&z, &[("a", 0.0), ("b", 40.0), ("c", 80.0)])).await;
+    assert_eq!(alerts.seen, []);
+    let ab = Seen::new("distance", "DangerMin", "a", "b");
+    let bc = Seen::new("distance", "DangerMin", "b", "c");
+    let ac = Seen::new("distance", "AlertMin", "a", "c");
+    // b closes on a, then c closes on b, coming within alert range of a
+    alerts.until(&ab, || place(&z, &[("a", 0.0), ("b", 5.0), ("c", 80.0)])).await;
+    alerts.until(&bc, || place(&z, &[("a", 0.0), ("b", 5.0), ("c", 12.0)])).await;
+    alerts.until(&ac, || place(&z, &[("a", 0.0), ("b", 5.0), ("c", 12.0)])).await;
+    assert_eq!(alerts.of("a", "b"), [&ab]);
+    assert_eq!(alerts.of("b", "c"), [&bc]);
+    assert_eq!(alerts.of("a", "c"), [&ac]);
+    assert_eq!(alerts.seen[0], ab);
+}
@@ -0,0 +1,247 @@
+// Shared by the test crates, each using part of it.
+#![allow(dead_code)]
+
+use std::fs;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use clap::Parser;
+use serde_json::{json, Value};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::task::JoinHandle;
+use zenoh::prelude::r#async::*;
+use zenoh::subscriber::Subscriber;
+use zdemo_common::Compression;
+use distance_tracker::tools;
+use distance_tracker::tracker::{self, AppArgs, Command};
+
+// How long a test waits for the tracker to start and for an expected sample.
+pub const TIMEOUT: Duration = Duration::from_secs(20);
+
+static HARNESSES: AtomicU32 = AtomicU32::new(0);
+
+// An embedded Zenoh router on a free localhost port, which the tracker and the
+// simulated publishers of a test connect to, scouting disabled so that tests
+// running in parallel (or a demo running on the host) do not see each other.
+pub struct Harness {
+    pub router: Arc<Session>,
+    dir: PathBuf,
+    client_config: PathBuf,
+    // the trackers and publishers started by the test
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Harness {
+    pub async fn start() -> Self {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let endpoint = format!("tcp/127.0.0.1:{port}");
+        let dir = std::env::temp_dir().join(format!("tracker-it-{}-{}", std::process::id(), HARNESSES.fetch_add(1, Ordering::Relaxed)));
+        fs::create_dir_all(&dir).unwrap();
+        let config = |name: &str, mode: &str, endpoints: &str| {
+            let path = dir.join(name);
+            let mut config = json!({ "mode": mode, "scouting": { "multicast": { "enabled": false } } });
+            config[endpoints] = json!({ "endpoints": [endpoint] });
+            fs::write(&path, config.to_string()).unwrap();
+            path
+        };
+        let router_config = config("router.json5", "router", "listen");
+        let client_config = config("client.json5", "peer", "connect");
+        let router = Arc::new(zenoh::open(Config::from_file(&router_config).unwrap()).res().await.unwrap());
+        Harness { router, dir, client_config, tasks: vec![] }
+    }
+
+    // A session of a simulated publisher or consumer.
+    pub async fn session(&self) -> Arc<Session> {
+        Arc::new(zenoh::open(Config::from_file(&self.client_config).unwrap()).res().await.unwrap())
+    }
+
+    // Starts a tracker with the options `args` on a task of the test runtime,
+    // waiting until it answers queries.
+    pub async fn tracker(&mut self, args: &[&str]) {
+        let config = self.client_config.to_str().unwrap();
+        let command_line: Vec<String> = ["DistanceAlert", "--config", config].iter().chain(args).map(|a| a.to_string()).collect();
+        let settings = tracker::parse_args(AppArgs::parse_from(&command_line), command_line);
+        self.tasks.push(tokio::spawn(distance_tracker::run(settings)));
+        let ready = async {
+            while self.get_json("demo/tracker/features").await.is_none() {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        };
+        tokio::time::timeout(TIMEOUT, ready).await.expect("tracker not ready");
+    }
+
+    // Starts a position publisher publishing the "id,lat,lng,speed" `lines`
+    // with the options `args` (those of `DistanceAlert publish`), over and over.
+    pub fn publisher(&mut self, lines: &str, args: &[&str]) {
+        let input = self.dir.join(format!("positions-{}.csv", self.tasks.len()));
+        fs::write(&input, lines).unwrap();
+        let command_line = ["DistanceAlert", "publish", input.to_str().unwrap(), "--loop"].into_iter().chain(args.iter().copied());
+        let Some(Command::Publish(publish)) = AppArgs::parse_from(command_line).command else { unreachable!() };
+        let config = Config::from_file(&self.client_config).unwrap();
+        self.tasks.push(tokio::spawn(tools::publish_positions(publish, config, Compression::None, None)));
+    }
+
+    // The first reply to a query, as JSON.
+    pub async fn get_json(&self, selector: &str) -> Option<Value> {
+        let replies = self.router.get(selector).timeout(Duration::from_secs(1)).res().await.unwrap();
+        let reply = replies.recv_async().await.ok()?;
+        let sample = reply.sample.ok()?;
+        zdemo_common::decode_json(&sample).ok()
+    }
+
+    pub async fn subscribe(&self, key: &str) -> Subscription {
+        let (subs, rx) = zdemo_common::subscribe(&self.router, &[key.to_string()]).await;
+        Subscription { _subs: subs, rx }
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        for t in &self.tasks {
+            t.abort();
+        }
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+pub struct Subscription {
+    _subs: Vec<Subscriber<'static, ()>>,
+    rx: UnboundedReceiver<Sample>,
+}
+
+// Publishes the position of a simulated vehicle.
+pub async fn put_vehicle(z: &Session, id: &str, lat: f64, lng: f64, compression: Compression) {
+    let payload = json!({ "v": 2, "id": id, "kind": "car", "color": "red", "speed": 1.0, "position": { "lat": lat, "lng": lng } });
+    let value = compression.encode(payload.to_string().into_bytes(), Encoding::APP_JSON);
+    z.put(format!("demo/tracker/mobs/{id}"), value).res().await.unwrap();
+}
+
+// Waits for a sample whose JSON payload satisfies `accept`, republishing the
+// positions with `publish` meanwhile, as the tracker only starts computing once
+// it sees the subscriber.
+pub async fn expect<F, Fut>(sub: &mut Subscription, accept: impl Fn(&Value) -> bool, publish: F) -> Value
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = ()>,
+{
+    let wait = async {
+        loop {
+            publish().await;
+            let deadline = tokio::time::sleep(Duration::from_millis(500));
+            tokio::pin!(deadline);
+            loop {
+                tokio::select! {
+                    _ = &mut deadline => break,
+                    Some(sample) = sub.rx.recv() => {
+                        if let Ok(v) = zdemo_common::decode_json::<Value>(&sample) {
+                            if accept(&v) {
+                                return v;
+                            }
+                        }
+                    },
+                }
+            }
+        }
+    };
+    tokio::time::timeout(TIMEOUT, wait).await.expect("expected sample not received")
+}
+
+// The position `north` and `east` meters away from lat, lng, close enough for
+// the flat earth approximation.
+pub fn offset(lat: f64, lng: f64, north: f64, east: f64) -> (f64, f64) {
+    const M_PER_DEG: f64 = 6_371_000.0 * std::f64::consts::PI / 180.0;
+    (lat + north / M_PER_DEG, lng + east / (M_PER_DEG * lat.to_radians().cos()))
+}
+
+// An alert as the assertions see it: the last chunk of its key, its kind and
+// the pair (or vehicle) it is about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Seen {
+    pub rule: String,
+    pub kind: String,
+    pub ids: (String, String),
+}
+
+impl Seen {
+    pub fn new(rule: &str, kind: &str, ida: &str, idb: &str) -> Self {
+        Seen { rule: rule.into(), kind: kind.into(), ids: (ida.into(), idb.into()) }
+    }
+}
+
+// Every alert received on a subscription, in order, the repeats of the last
+// alert of a pair collapsed, as the tracker publishes it again on every
+// compute cycle while it holds.
+pub struct Timeline {
+    sub: Subscription,
+    pub seen: Vec<Seen>,
+}
+
+impl Timeline {
+    pub async fn subscribe(h: &Harness, key: &str) -> Self {
+        Timeline { sub: h.subscribe(key).await, seen: vec![] }
+    }
+
+    fn record(&mut self, sample: &Sample) {
+        let Ok(v) = zdemo_common::decode_json::<Value>(sample) else { return };
+        let text = |k: &str| v[k].as_str().unwrap_or_default().to_string();
+        let rule = sample.key_expr.as_str().rsplit('/').next().unwrap_or_default();
+        let seen = Seen { rule: rule.into(), kind: text("kind"), ids: (text("ida"), text("idb")) };
+        if self.last(&seen.ids) != Some(&seen) {
+            self.seen.push(seen);
+        }
+    }
+
+    // The last alert recorded for a pair.
+    pub fn last(&self, ids: &(String, String)) -> Option<&Seen> {
+        self.seen.iter().rev().find(|s| &s.ids == ids)
+    }
+
+    // The alerts recorded for a pair, in order.
+    pub fn of(&self, ida: &str, idb: &str) -> Vec<&Seen> {
+        self.seen.iter().filter(|s| s.ids.0 == ida && s.ids.1 == idb).collect()
+    }
+
+    // Republishes the positions with `publish` until `expected` is the last
+    // alert recorded for its pair.
+    pub async fn until<F, Fut>(&mut self, expected: &Seen, publish: F)
+    where
+        F: Fn() -> Fut,
+        Fut: std::future::Future<Output = ()>,
+    {
+        let wait = async {
+            while self.last(&expected.ids) != Some(expected) {
+                publish().await;
+                self.drain(Duration::from_millis(500)).await;
+            }
+        };
+        if tokio::time::timeout(TIMEOUT, wait).await.is_err() {
+            panic!("{expected:?} not received, got {:?}", self.seen);
+        }
+    }
+
+    // Publishes the positions with `publish` for `cycles` half seconds,
+    // recording whatever they raise.
+    pub async fn during<F, Fut>(&mut self, cycles: u32, publish: F)
+    where
+        F: Fn() -> Fut,
+        Fut: std::future::Future<Output = ()>,
+    {
+        for _ in 0..cycles {
+            publish().await;
+            self.drain(Duration::from_millis(500)).await;
+        }
+    }
+
+    async fn drain(&mut self, period: Duration) {
+        let deadline = tokio::time::sleep(period);
+        tokio::pin!(deadline);
+        loop {
+            tokio::select! {
+                _ = &mut deadline => return,
+                Some(sample) = self.sub.rx.recv() => self.record(&sample),
+            }
+        }
+    }
+}
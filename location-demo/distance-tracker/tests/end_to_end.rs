@@ -0,0 +1,64 @@
+// End-to-end tests of the tracker, run in process, over real Zenoh pub/sub,
+// through the embedded router of the harness.
+mod common;
+
+use common::{expect, put_vehicle, Harness};
+use zdemo_common::Compression;
+
+const LAT: f64 = 45.0703;
+const LNG: f64 = 7.6869;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn close_vehicles_raise_a_danger() {
+    let mut h = Harness::start().await;
+    let mut alerts = h.subscribe("demo/tracker/alert/distance").await;
+    h.tracker(&["--min-distance", "10", "--compute-period-ms", "100"]).await;
+    let z = h.session().await;
+    let alert = expect(&mut alerts, |v| v["kind"] == "DangerMin", || async {
+        put_vehicle(&z, "a", LAT, LNG, Compression::None).await;
+        put_vehicle(&z, "b", LAT + 2e-5, LNG, Compression::None).await;
+    }).await;
+    assert_eq!((alert["ida"].as_str(), alert["idb"].as_str()), (Some("a"), Some("b")));
+    assert!(alert["distance"].as_f64().unwrap() < 10.0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn vehicles_far_apart_raise_a_separation_danger() {
+    let mut h = Harness::start().await;
+    let mut alerts = h.subscribe("demo/tracker/alert/distance").await;
+    h.tracker(&["--max-distance", "100", "--compute-period-ms", "100"]).await;
+    let z = h.session().await;
+    let alert = expect(&mut alerts, |v| v["kind"] == "DangerMax", || async {
+        put_vehicle(&z, "a", LAT, LNG, Compression::None).await;
+        put_vehicle(&z, "b", LAT + 2e-3, LNG, Compression::None).await;
+    }).await;
+    assert!(alert["distance"].as_f64().unwrap() > 100.0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn compressed_positions_reach_the_state_queryable() {
+    let mut h = Harness::start().await;
+    // the computation, and thus the state, only runs with alert subscribers
+    let mut alerts = h.subscribe("demo/tracker/alert/distance").await;
+    h.tracker(&["--compute-period-ms", "100"]).await;
+    let z = h.session().await;
+    expect(&mut alerts, |v| v["kind"] == "DangerMin", || async {
+        put_vehicle(&z, "lz4-a", LAT, LNG, Compression::Lz4).await;
+        put_vehicle(&z, "lz4-b", LAT + 2e-5, LNG, Compression::Lz4).await;
+    }).await;
+    let state = h.get_json("demo/tracker/state").await.expect("no reply from the state queryable");
+    assert_eq!(state["total"], 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn positions_on_geohash_keys_reach_a_region_subscription() {
+    let mut h = Harness::start().await;
+    let mut alerts = h.subscribe("demo/tracker/alert/distance").await;
+    let region = format!("{},{},{},{}", LAT - 0.01, LNG - 0.01, LAT + 0.01, LNG + 0.01);
+    h.tracker(&["--min-distance", "10", "--compute-period-ms", "100", "--geohash-region", &region, "--geohash-precision", "6"]).await;
+    // published on demo/tracker/mobs/<geohash>/<id>, which only the cells of
+    // the region subscribe to
+    h.publisher(&format!("a,{LAT},{LNG},1\nb,{},{LNG},1\n", LAT + 2e-5), &["--geohash-precision", "6", "--rate", "20"]);
+    let alert = expect(&mut alerts, |v| v["kind"] == "DangerMin", || async {}).await;
+    assert_eq!((alert["ida"].as_str(), alert["idb"].as_str()), (Some("a"), Some("b")));
+}
@@ -0,0 +1,56 @@
+// Replays of the bundled scenarios, with a fixed seed, through the analyzer,
+// comparing the alerts fired with the expected ones checked in tests/golden/.
+// UPDATE_GOLDEN=1 writes them instead, after a change of the rules.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const SEED: &str = "7";
+
+fn run(args: &[&str]) {
+    let out = Command::new(env!("CARGO_BIN_EXE_DistanceAlert")).args(args).output().unwrap();
+    assert!(out.status.success(), "{args:?} failed: {}", String::from_utf8_lossy(&out.stderr));
+}
+
+// The alerts of `scenario`, replayed twice, which must give the same bytes.
+fn alerts(scenario: &Path, dir: &Path) -> String {
+    let name = scenario.file_stem().unwrap().to_str().unwrap();
+    let runs: Vec<String> = (0..2).map(|i| {
+        let recording = dir.join(format!("{name}-{i}.jsonl"));
+        let alerts = dir.join(format!("{name}-{i}.alerts.jsonl"));
+        run(&["simulate", "--scenario", scenario.to_str().unwrap(), "--seed", SEED, "--out", recording.to_str().unwrap()]);
+        run(&["analyze", recording.to_str().unwrap(), "--alerts", alerts.to_str().unwrap()]);
+        fs::read_to_string(alerts).unwrap()
+    }).collect();
+    assert_eq!(runs[0], runs[1], "two replays of {name} differ");
+    runs[0].clone()
+}
+
+#[test]
+fn bundled_scenarios_replay_their_golden_alerts() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let dir = std::env::temp_dir().join(format!("tracker-golden-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut scenarios: Vec<PathBuf> = fs::read_dir(root.join("scenarios")).unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "yaml"))
+        .collect();
+    scenarios.sort();
+    assert!(!scenarios.is_empty());
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    for scenario in &scenarios {
+        let alerts = alerts(scenario, &dir);
+        let golden = root.join("tests/golden").join(scenario.file_stem().unwrap()).with_extension("alerts.jsonl");
+        if update {
+            fs::create_dir_all(golden.parent().unwrap()).unwrap();
+            fs::write(&golden, &alerts).unwrap();
+            continue;
+        }
+        let expected = fs::read_to_string(&golden).unwrap_or_else(|e| panic!("{}: {e}, run with UPDATE_GOLDEN=1 to write it", golden.display()));
+        if let Some((i, (got, want))) = alerts.lines().zip(expected.lines()).enumerate().find(|(_, (a, b))| a != b) {
+            panic!("{}: line {} differs\n  got  {got}\n  want {want}", golden.display(), i + 1);
+        }
+        assert_eq!(alerts.lines().count(), expected.lines().count(), "{}: alert count differs", golden.display());
+    }
+    let _ = fs::remove_dir_all(&dir);
+}
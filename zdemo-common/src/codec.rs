@@ -0,0 +1,109 @@
+use std::borrow::Cow;
+use std::str::FromStr;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use zenoh::prelude::r#async::*;
+
+/// Largest payload accepted once decompressed, so that a forged size cannot
+/// make receivers allocate gigabytes.
+const MAX_DECOMPRESSED_SIZE: usize = 16 << 20;
+
+/// Compression of published payloads. The codec is carried by the encoding
+/// suffix (`application/json;lz4` or `application/json;zstd`) and every
+/// decoding helper of this crate accepts compressed and plain payloads alike,
+/// so receivers can be upgraded before publishers start compressing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    Lz4,
+    Zstd,
+}
+
+impl Compression {
+    fn suffix(&self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Lz4 => Some(";lz4"),
+            Compression::Zstd => Some(";zstd"),
+        }
+    }
+
+    /// Compresses `payload`, returning it with its encoding.
+    pub fn encode(&self, payload: Vec<u8>, encoding: Encoding) -> Value {
+        let Some(suffix) = self.suffix() else {
+            return Value::from(payload).encoding(encoding);
+        };
+        let compressed = match self {
+            Compression::Zstd => zstd::bulk::compress(&payload, zstd::DEFAULT_COMPRESSION_LEVEL).unwrap(),
+            _ => lz4_flex::compress_prepend_size(&payload),
+        };
+        Value::from(compressed).encoding(encoding.with_suffix(suffix).unwrap())
+    }
+}
+
+/// The compression signalled by `encoding`, and the encoding of the payload
+/// once decompressed.
+pub fn split_encoding(encoding: &Encoding) -> (Compression, Encoding) {
+    let encoding_str = encoding.to_string();
+    for compression in [Compression::Lz4, Compression::Zstd] {
+        if let Some(plain) = encoding_str.strip_suffix(compression.suffix().unwrap()) {
+            return (compression, Encoding::from(plain.to_string()));
+        }
+    }
+    (Compression::None, encoding.clone())
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "none" => Ok(Compression::None),
+            "lz4" => Ok(Compression::Lz4),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(format!("unknown compression '{s}', expecting none, lz4 or zstd")),
+        }
+    }
+}
+
+/// Serializes `v` as a JSON value tagged with the `application/json` encoding.
+pub fn json_value<T: Serialize>(v: &T) -> Value {
+    Value::from(serde_json::to_vec(v).unwrap()).encoding(Encoding::APP_JSON)
+}
+
+/// Serializes `v` as JSON, compressed with `compression`.
+pub fn json_value_with<T: Serialize>(v: &T, compression: Compression) -> Value {
+    compression.encode(serde_json::to_vec(v).unwrap(), Encoding::APP_JSON)
+}
+
+/// The payload of a sample, decompressed according to its encoding suffix.
+pub fn payload(sample: &Sample) -> Result<Cow<'_, [u8]>, String> {
+    let payload = sample.payload.contiguous();
+    let limit = |codec: &str, size: usize| if size > MAX_DECOMPRESSED_SIZE {
+        Err(format!("{codec} payload of {size} bytes exceeds the {MAX_DECOMPRESSED_SIZE} bytes limit"))
+    } else {
+        Ok(size)
+    };
+    match split_encoding(&sample.encoding).0 {
+        Compression::None => Ok(payload),
+        Compression::Lz4 => {
+            let size = payload.get(..4).map(|s| u32::from_le_bytes(s.try_into().unwrap()) as usize).ok_or("truncated lz4 payload")?;
+            let size = limit("lz4", size)?;
+            lz4_flex::decompress(&payload[4..], size).map(Cow::Owned).map_err(|e| format!("invalid lz4 payload: {e}"))
+        },
+        Compression::Zstd => {
+            // the size is in the frame header, as written by zstd::bulk::compress
+            let size = zstd::zstd_safe::get_frame_content_size(&payload).ok().flatten()
+                .ok_or("zstd payload without its decompressed size")?;
+            let size = limit("zstd", usize::try_from(size).unwrap_or(usize::MAX))?;
+            zstd::bulk::decompress(&payload, size).map(Cow::Owned).map_err(|e| format!("invalid zstd payload: {e}"))
+        },
+    }
+}
+
+/// Decodes the JSON payload of a sample, decompressing it if needed.
+pub fn decode_json<T: DeserializeOwned>(sample: &Sample) -> serde_json::Result<T> {
+    let payload = payload(sample).map_err(serde::de::Error::custom)?;
+    serde_json::from_slice::<T>(payload.as_ref())
+}
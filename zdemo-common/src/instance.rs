@@ -0,0 +1,99 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// Identity of a running demo process. It is added to everything the process
+/// publishes so that multi-process deployments can be correlated.
+#[derive(Debug, Clone)]
+pub struct Instance {
+    pub service: String,
+    pub id: String,
+    pub host: String,
+}
+
+impl Instance {
+    /// Resolves the instance id of `service`, in order: `explicit` (usually a
+    /// command line flag), the `ZDEMO_INSTANCE_ID` environment variable, the id
+    /// persisted by a previous run, and finally a newly generated one which is
+    /// persisted for the next runs.
+    ///
+    /// Ids are persisted per service in `$ZDEMO_STATE_DIR`, defaulting to
+    /// `$XDG_STATE_HOME/zdemo` or `~/.local/state/zdemo`, so several instances of
+    /// the same service on one host need an explicit id.
+    pub fn resolve(service: &str, explicit: Option<String>) -> Instance {
+        let id = explicit
+            .or_else(|| std::env::var("ZDEMO_INSTANCE_ID").ok())
+            .filter(|id| !id.is_empty())
+            .unwrap_or_else(|| persisted_id(service));
+        Instance { service: service.into(), id, host: hostname() }
+    }
+
+    /// OpenTelemetry resource attributes describing this instance.
+    pub fn resource_attributes(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("service.name", self.service.clone()),
+            ("service.instance.id", self.id.clone()),
+            ("host.name", self.host.clone()),
+        ]
+    }
+
+    /// Adds an `instance` field to JSON objects, other values are returned as is.
+    pub fn tag(&self, mut v: serde_json::Value) -> serde_json::Value {
+        if let serde_json::Value::Object(m) = &mut v {
+            m.insert("instance".into(), self.id.clone().into());
+        }
+        v
+    }
+}
+
+/// Path of `name` in the directory where instance ids are persisted, for other
+/// state that must survive restarts.
+pub fn state_path(name: &str) -> Option<PathBuf> {
+    state_dir().map(|d| d.join(name))
+}
+
+fn state_dir() -> Option<PathBuf> {
+    let env = |k| std::env::var_os(k).filter(|v| !v.is_empty()).map(PathBuf::from);
+    env("ZDEMO_STATE_DIR")
+        .or_else(|| env("XDG_STATE_HOME").map(|d| d.join("zdemo")))
+        .or_else(|| env("HOME").map(|d| d.join(".local/state/zdemo")))
+}
+
+fn persisted_id(service: &str) -> String {
+    let file = state_dir().map(|d| d.join(format!("{service}.id")));
+    if let Some(id) = file.as_ref().and_then(|f| std::fs::read_to_string(f).ok()) {
+        let id = id.trim();
+        if !id.is_empty() {
+            return id.into();
+        }
+    }
+    let id = format!("{service}-{:016x}", random_u64());
+    match &file {
+        Some(f) => {
+            let r = f.parent().map_or(Ok(()), std::fs::create_dir_all).and_then(|_| std::fs::write(f, &id));
+            if let Err(e) = r {
+                warn!("unable to persist instance id in {}: {e}", f.display());
+            }
+        },
+        None => warn!("no state directory, instance id {id} will not be persisted"),
+    }
+    id
+}
+
+fn random_u64() -> u64 {
+    // RandomState is randomly seeded per process, good enough for an id
+    let mut h = RandomState::new().build_hasher();
+    h.write_u128(SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0));
+    h.write_u32(std::process::id());
+    h.finish()
+}
+
+fn hostname() -> String {
+    std::env::var("HOSTNAME").ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "unknown".into())
+}
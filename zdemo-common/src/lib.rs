@@ -0,0 +1,15 @@
+//! Zenoh boilerplate shared by the demos: resilient session and subscriber
+//! handling, JSON encoding helpers with optional compression, typed publishers,
+//! instance identity and graceful shutdown.
+
+pub mod codec;
+pub mod instance;
+pub mod session;
+pub mod shutdown;
+pub mod typed;
+
+pub use codec::{decode_json, json_value, json_value_with, payload, split_encoding, Compression};
+pub use instance::{state_path, Instance};
+pub use session::{open, subscribe, subscribe_with, with_backoff};
+pub use shutdown::shutdown_signal;
+pub use typed::JsonPublisher;
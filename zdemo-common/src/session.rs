@@ -0,0 +1,70 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use zenoh::prelude::r#async::*;
+use zenoh::subscriber::Subscriber;
+use tracing::{info, warn};
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Retries `op` with exponential backoff until it succeeds or `attempts` run
+/// out (0 means forever), returning the last error in that case. Runs in a
+/// debug span named after `what`.
+#[tracing::instrument(level = "debug", skip(op))]
+pub async fn with_backoff<T, E, F, Fut>(what: &str, attempts: u32, mut op: F) -> Result<T, E>
+where
+    E: std::fmt::Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut delay = INITIAL_BACKOFF;
+    let mut n = 0;
+    loop {
+        match op().await {
+            Ok(v) => return Ok(v),
+            Err(e) => {
+                n += 1;
+                if attempts != 0 && n >= attempts {
+                    return Err(e);
+                }
+                warn!("{what} failed ({e}), retrying in {delay:?}");
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+/// Opens a session, retrying until it succeeds.
+pub async fn open(config: Config) -> Arc<Session> {
+    let s = with_backoff("opening zenoh session", 0, || zenoh::open(config.clone()).res()).await;
+    Arc::new(s.unwrap())
+}
+
+/// Declares one subscriber per key expression, all feeding the same channel.
+/// The channel closes once every subscriber is gone, which is how consumers
+/// learn that they have to subscribe again.
+pub async fn subscribe(z: &Arc<Session>, keys: &[String]) -> (Vec<Subscriber<'static, ()>>, UnboundedReceiver<Sample>) {
+    subscribe_with(z, keys, Reliability::default()).await
+}
+
+/// Same as [`subscribe`], with the given reliability.
+pub async fn subscribe_with(z: &Arc<Session>, keys: &[String], reliability: Reliability) -> (Vec<Subscriber<'static, ()>>, UnboundedReceiver<Sample>) {
+    let (tx, rx) = unbounded_channel::<Sample>();
+    let mut subs = Vec::with_capacity(keys.len());
+    for key in keys {
+        let tx = tx.clone();
+        let sub = with_backoff(&format!("subscribing to {key}"), 0, || {
+            let tx = tx.clone();
+            z.declare_subscriber(key)
+                .reliability(reliability)
+                .callback(move |sample| { let _ = tx.send(sample); })
+                .res()
+        }).await.unwrap();
+        subs.push(sub);
+    }
+    info!("Subscribed to {} key expression(s)", subs.len());
+    (subs, rx)
+}
@@ -0,0 +1,56 @@
+use std::marker::PhantomData;
+use std::sync::Arc;
+use serde::Serialize;
+use zenoh::prelude::r#async::*;
+use zenoh::Result as ZResult;
+use crate::codec::{json_value_with, Compression};
+use crate::instance::Instance;
+use crate::session::with_backoff;
+
+/// Publishes values of type `T` as JSON on a fixed key expression.
+pub struct JsonPublisher<T> {
+    z: Arc<Session>,
+    key: KeyExpr<'static>,
+    attempts: u32,
+    instance: Option<Instance>,
+    compression: Compression,
+    _t: PhantomData<fn(&T)>,
+}
+
+impl<T: Serialize> JsonPublisher<T> {
+    pub fn new(z: Arc<Session>, key: impl TryInto<KeyExpr<'static>, Error = zenoh::Error>) -> ZResult<Self> {
+        Ok(JsonPublisher { z, key: key.try_into()?, attempts: 1, instance: None, compression: Compression::None, _t: PhantomData })
+    }
+
+    /// Retries each put up to `attempts` times with backoff.
+    pub fn retries(mut self, attempts: u32) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    /// Tags every published value with the id of `instance`.
+    pub fn instance(mut self, instance: &Instance) -> Self {
+        self.instance = Some(instance.clone());
+        self
+    }
+
+    /// Compresses every published value, see [`Compression`].
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn key_expr(&self) -> &KeyExpr<'static> {
+        &self.key
+    }
+
+    pub async fn put(&self, v: &T) -> ZResult<()> {
+        let value = match &self.instance {
+            Some(i) => json_value_with(&i.tag(serde_json::to_value(v).unwrap()), self.compression),
+            None => json_value_with(v, self.compression),
+        };
+        with_backoff(&format!("put on {}", self.key), self.attempts, || {
+            self.z.put(&self.key, value.clone()).res()
+        }).await
+    }
+}